@@ -0,0 +1,155 @@
+# English messages for ditrive. This is the source catalog; other catalogs
+# fall back to it for any message they don't translate.
+
+## Errors
+
+error-prefix = Error
+error-help = Use 'ditrive --help' for usage information.
+error-config = Configuration error: { $details }
+error-git = Git error: { $details }
+error-io = IO error: { $details }
+error-json = JSON error: { $details }
+error-http = HTTP request error: { $details }
+error-walkdir = Directory walk error: { $details }
+error-drive = Storage error: { $details }
+error-github = GitHub error: { $details }
+error-file-not-found = File not found: { $details }
+error-not-git-repo = Not a git repository: { $details }
+error-auth = Authentication error: { $details }
+error-quota-exceeded = Download quota exceeded: { $details }
+error-access-denied = Access denied: { $details }
+error-storage-full = Not enough storage space: { $details }
+error-quarantined = Download failed malware scan: { $details }
+error-signature = Tracker signature check failed: { $details }
+error-lfs = Git LFS error: { $details }
+error-locked = Repository is locked: { $details }
+error-check-failed = Check failed: { $details }
+error-cancelled = Operation cancelled by user
+error-retry-exhausted = Retry exhausted after { $attempts } attempts: { $details }
+
+## Configure
+
+not-set = <not set>
+config-title = Ditrive Configuration
+config-github-heading = GitHub Configuration:
+prompt-forge-api-url = API URL of a self-hosted Gitea/Forgejo or GitHub Enterprise, e.g. https://git.example.com/api/v1 (blank keeps, "-" uses GitHub.com) [{ $current }]
+prompt-forge-kind = Which forge is this?
+prompt-github-username = GitHub username [{ $current }]
+prompt-github-token = GitHub personal access token (leave blank to keep current)
+prompt-github-visibility = Default repository visibility (public/private) [{ $current }]
+config-storage-heading = Storage Configuration:
+config-auth-heading = Authentication method:
+config-auth-oauth = 1. OAuth (recommended for collaboration - each user logs in with their Google account)
+config-auth-service-account = 2. Service Account (for automation/CI)
+config-auth-onedrive = 3. OneDrive / SharePoint (Microsoft 365 account instead of Google Drive)
+config-auth-local = 4. Local path (mounted NAS share or external drive, no cloud account)
+config-auth-b2 = 5. Backblaze B2 bucket (S3-compatible API)
+config-auth-r2 = 6. Cloudflare R2 bucket (S3-compatible API)
+config-auth-releases = 7. GitHub Releases (public projects; anyone can download without an account)
+prompt-auth-method = Choose auth method (1-7) [{ $current }]
+config-oauth-heading = OAuth Configuration:
+config-oauth-hint = (Get these from Google Cloud Console > APIs & Services > Credentials)
+prompt-oauth-client-id = OAuth Client ID [{ $current }]
+prompt-oauth-client-secret = OAuth Client Secret (leave blank to keep current)
+prompt-service-account-file = Service account file path [{ $current }]
+config-onedrive-heading = OneDrive Configuration:
+config-onedrive-hint =
+    (Register an app in Microsoft Entra admin center > App registrations,
+    with redirect URI http://localhost:8085 as a mobile/desktop platform)
+prompt-onedrive-client-id = Application (client) ID [{ $current }]
+prompt-onedrive-client-secret = Client secret (only for confidential clients, leave blank to keep current)
+prompt-onedrive-tenant = Tenant [{ $current }]
+prompt-onedrive-drive-id = SharePoint drive ID (blank for your own OneDrive) [{ $current }]
+prompt-onedrive-root-path = Root folder path [{ $current }]
+prompt-local-root-path = Storage directory [{ $current }]
+config-b2-heading = Backblaze B2 Configuration:
+config-b2-hint =
+    (Create an application key restricted to the bucket under App Keys;
+    the region is part of the bucket's S3 endpoint, e.g. us-west-004)
+prompt-b2-region = Bucket region [{ $current }]
+prompt-b2-key-id = Application key ID [{ $current }]
+prompt-b2-key = Application key (leave blank to keep current)
+config-r2-heading = Cloudflare R2 Configuration:
+config-r2-hint =
+    (Create an R2 API token with Object Read & Write permission;
+    the account ID is shown on the R2 overview page)
+prompt-r2-account-id = Account ID [{ $current }]
+prompt-r2-key-id = Access key ID [{ $current }]
+prompt-r2-key = Secret access key (leave blank to keep current)
+prompt-releases-repository = Releases repository as owner/name (blank for the origin remote) [{ $current }]
+prompt-releases-tag = Release tag [{ $current }]
+prompt-bucket-name = Bucket name [{ $current }]
+prompt-bucket-prefix = Key prefix [{ $current }]
+prompt-root-folder-id = Root folder ID [{ $current }]
+prompt-shared-drive-id = Shared Drive ID (blank for My Drive) [{ $current }]
+config-settings-heading = Application Settings:
+prompt-threshold = Large file threshold in MB [{ $current }]
+config-saved = Configuration saved!
+config-checking = Checking the configuration...
+config-check-forge-ok = ✓ { $forge } accepted the token
+config-check-forge-rejected = ✗ { $forge } rejected the token. Check it and run 'ditrive configure' again.
+config-check-forge-error = ✗ Couldn't check the token with { $forge }: { $error }
+config-check-drive-ok = ✓ Google Drive accepted the credentials
+config-check-drive-failed = ✗ Couldn't sign in to Google Drive: { $error }
+config-check-drive-login = Run 'ditrive login' to check access to Google Drive.
+config-check-folder-ok = ✓ Found the root folder { $folder }
+config-check-folder-missing = ✗ Root folder { $folder } wasn't found or is in the trash. Check the ID and that the folder is shared with this account.
+config-complete = Configuration is complete. You can now use 'quick-setup' to create a new repository.
+config-login-hint = Run 'ditrive login' to authenticate with { $service }.
+config-incomplete = Configuration is incomplete. Please fill in all required fields.
+
+## Ignored large files
+
+ignored-large-file = Large file { $path } is already in .gitignore.
+ignored-what-to-do = What would you like to do?
+ignored-manage = Manage it with Ditrive (upload to Drive)
+ignored-skip = Skip it (keep it ignored)
+prompt-choice = Enter your choice
+
+## Status
+
+status-title = Ditrive Status
+status-configuration = Configuration:
+status-github-username = GitHub username: { $value }
+status-github-token = GitHub token: { $value }
+status-github-app = GitHub App: { $value }
+status-auth-type = Drive auth type: { $value }
+auth-name-oauth = OAuth
+auth-name-service-account = Service Account
+auth-name-onedrive = OneDrive
+auth-name-local = Local path
+auth-name-b2 = Backblaze B2
+auth-name-r2 = Cloudflare R2
+auth-name-releases = GitHub Releases
+status-oauth-client-id = OAuth client ID: { $value }
+status-service-account = Service account: { $value }
+status-onedrive-client-id = OneDrive client ID: { $value }
+status-onedrive-drive = OneDrive drive: { $value }
+status-onedrive-personal = personal OneDrive
+status-storage-directory = Storage directory: { $value }
+status-bucket = Bucket: { $value }
+status-access-key-id = Access key ID: { $value }
+status-key-prefix = Key prefix: { $value }
+status-releases-repository = Releases repository: { $value }
+status-release-tag = Release tag: { $value }
+status-root-folder-path = Root folder path: { $value }
+status-root-folder-id = Root folder ID: { $value }
+status-shared-drive-id = Shared Drive: { $value }
+status-storage = Storage: { $used } of { $limit } used
+status-storage-unlimited = Storage: { $used } used (unlimited)
+status-auth-profile = Auth profile: { $value }
+status-logged-in = Login status: ✓ Logged in
+status-logged-out = Login status: ✗ Not logged in (run 'ditrive login')
+status-repository = Repository:
+status-git-initialized = Git initialized: ✓
+status-git-missing = Git initialized: ✗
+status-repo-name = Repository name: { $value }
+status-tracked = Large files tracked: { $count }
+status-pending = Large files pending upload: { $count }
+status-files = Managed files:
+status-remote-unchecked = (not signed in, so remote copies weren't checked)
+status-file-up-to-date = ✓ { $path }
+status-file-modified = M { $path } (modified, needs upload)
+status-file-missing-locally = ! { $path } (missing locally, run 'ditrive pull')
+status-file-missing-remotely = ✗ { $path } (missing remotely)
+status-file-missing-everywhere = ✗ { $path } (missing locally and remotely; only a backup can restore it)
@@ -0,0 +1,155 @@
+# Indonesian messages for ditrive. Messages missing here fall back to English;
+# run `ditrive messages id` to list them.
+
+## Errors
+
+error-prefix = Galat
+error-help = Gunakan 'ditrive --help' untuk informasi penggunaan.
+error-config = Galat konfigurasi: { $details }
+error-git = Galat Git: { $details }
+error-io = Galat IO: { $details }
+error-json = Galat JSON: { $details }
+error-http = Galat permintaan HTTP: { $details }
+error-walkdir = Galat saat menelusuri direktori: { $details }
+error-drive = Galat penyimpanan: { $details }
+error-github = Galat GitHub: { $details }
+error-file-not-found = Berkas tidak ditemukan: { $details }
+error-not-git-repo = Bukan repositori git: { $details }
+error-auth = Galat autentikasi: { $details }
+error-quota-exceeded = Kuota unduhan terlampaui: { $details }
+error-access-denied = Akses ditolak: { $details }
+error-storage-full = Ruang penyimpanan tidak cukup: { $details }
+error-quarantined = Unduhan gagal pemindaian malware: { $details }
+error-signature = Pemeriksaan tanda tangan tracker gagal: { $details }
+error-lfs = Galat Git LFS: { $details }
+error-locked = Repositori sedang dikunci: { $details }
+error-check-failed = Pemeriksaan gagal: { $details }
+error-cancelled = Operasi dibatalkan oleh pengguna
+error-retry-exhausted = Percobaan ulang habis setelah { $attempts } kali: { $details }
+
+## Configure
+
+not-set = <belum diatur>
+config-title = Konfigurasi Ditrive
+config-github-heading = Konfigurasi GitHub:
+prompt-forge-api-url = URL API Gitea/Forgejo atau GitHub Enterprise yang di-host sendiri, mis. https://git.example.com/api/v1 (kosong mempertahankan, "-" memakai GitHub.com) [{ $current }]
+prompt-forge-kind = Forge apa ini?
+prompt-github-username = Nama pengguna GitHub [{ $current }]
+prompt-github-token = Token akses pribadi GitHub (kosongkan untuk mempertahankan yang sekarang)
+prompt-github-visibility = Visibilitas repositori bawaan (public/private) [{ $current }]
+config-storage-heading = Konfigurasi Penyimpanan:
+config-auth-heading = Metode autentikasi:
+config-auth-oauth = 1. OAuth (disarankan untuk kolaborasi - setiap pengguna masuk dengan akun Google masing-masing)
+config-auth-service-account = 2. Service Account (untuk otomatisasi/CI)
+config-auth-onedrive = 3. OneDrive / SharePoint (akun Microsoft 365 sebagai pengganti Google Drive)
+config-auth-local = 4. Path lokal (share NAS yang di-mount atau drive eksternal, tanpa akun cloud)
+config-auth-b2 = 5. Bucket Backblaze B2 (API kompatibel S3)
+config-auth-r2 = 6. Bucket Cloudflare R2 (API kompatibel S3)
+config-auth-releases = 7. GitHub Releases (proyek publik; siapa pun bisa mengunduh tanpa akun)
+prompt-auth-method = Pilih metode autentikasi (1-7) [{ $current }]
+config-oauth-heading = Konfigurasi OAuth:
+config-oauth-hint = (Dapatkan dari Google Cloud Console > APIs & Services > Credentials)
+prompt-oauth-client-id = OAuth Client ID [{ $current }]
+prompt-oauth-client-secret = OAuth Client Secret (kosongkan untuk mempertahankan yang sekarang)
+prompt-service-account-file = Path berkas service account [{ $current }]
+config-onedrive-heading = Konfigurasi OneDrive:
+config-onedrive-hint =
+    (Daftarkan aplikasi di Microsoft Entra admin center > App registrations,
+    dengan redirect URI http://localhost:8085 sebagai platform mobile/desktop)
+prompt-onedrive-client-id = ID aplikasi (client) [{ $current }]
+prompt-onedrive-client-secret = Client secret (hanya untuk confidential client, kosongkan untuk mempertahankan yang sekarang)
+prompt-onedrive-tenant = Tenant [{ $current }]
+prompt-onedrive-drive-id = ID drive SharePoint (kosongkan untuk OneDrive Anda sendiri) [{ $current }]
+prompt-onedrive-root-path = Path folder root [{ $current }]
+prompt-local-root-path = Direktori penyimpanan [{ $current }]
+config-b2-heading = Konfigurasi Backblaze B2:
+config-b2-hint =
+    (Buat application key yang dibatasi ke bucket di menu App Keys;
+    region adalah bagian dari endpoint S3 bucket, misalnya us-west-004)
+prompt-b2-region = Region bucket [{ $current }]
+prompt-b2-key-id = Application key ID [{ $current }]
+prompt-b2-key = Application key (kosongkan untuk mempertahankan yang sekarang)
+config-r2-heading = Konfigurasi Cloudflare R2:
+config-r2-hint =
+    (Buat token API R2 dengan izin Object Read & Write;
+    ID akun ditampilkan di halaman ringkasan R2)
+prompt-r2-account-id = ID akun [{ $current }]
+prompt-r2-key-id = Access key ID [{ $current }]
+prompt-r2-key = Secret access key (kosongkan untuk mempertahankan yang sekarang)
+prompt-releases-repository = Repositori rilis, owner/nama (kosong untuk remote origin) [{ $current }]
+prompt-releases-tag = Tag rilis [{ $current }]
+prompt-bucket-name = Nama bucket [{ $current }]
+prompt-bucket-prefix = Prefiks key [{ $current }]
+prompt-root-folder-id = ID folder root [{ $current }]
+prompt-shared-drive-id = ID Shared Drive (kosongkan untuk My Drive) [{ $current }]
+config-settings-heading = Pengaturan Aplikasi:
+prompt-threshold = Ambang batas berkas besar dalam MB [{ $current }]
+config-saved = Konfigurasi tersimpan!
+config-checking = Memeriksa konfigurasi...
+config-check-forge-ok = ✓ { $forge } menerima token
+config-check-forge-rejected = ✗ { $forge } menolak token. Periksa token lalu jalankan 'ditrive configure' lagi.
+config-check-forge-error = ✗ Tidak dapat memeriksa token dengan { $forge }: { $error }
+config-check-drive-ok = ✓ Google Drive menerima kredensial
+config-check-drive-failed = ✗ Tidak dapat masuk ke Google Drive: { $error }
+config-check-drive-login = Jalankan 'ditrive login' untuk memeriksa akses ke Google Drive.
+config-check-folder-ok = ✓ Folder root { $folder } ditemukan
+config-check-folder-missing = ✗ Folder root { $folder } tidak ditemukan atau ada di tempat sampah. Periksa ID dan pastikan folder dibagikan ke akun ini.
+config-complete = Konfigurasi sudah lengkap. Sekarang Anda dapat menggunakan 'quick-setup' untuk membuat repositori baru.
+config-login-hint = Jalankan 'ditrive login' untuk autentikasi dengan { $service }.
+config-incomplete = Konfigurasi belum lengkap. Silakan isi semua kolom yang wajib.
+
+## Ignored large files
+
+ignored-large-file = Berkas besar { $path } sudah ada di .gitignore.
+ignored-what-to-do = Apa yang ingin Anda lakukan?
+ignored-manage = Kelola dengan Ditrive (unggah ke Drive)
+ignored-skip = Lewati (biarkan tetap diabaikan)
+prompt-choice = Masukkan pilihan Anda
+
+## Status
+
+status-title = Status Ditrive
+status-configuration = Konfigurasi:
+status-github-username = Nama pengguna GitHub: { $value }
+status-github-token = Token GitHub: { $value }
+status-github-app = Aplikasi GitHub: { $value }
+status-auth-type = Jenis autentikasi penyimpanan: { $value }
+auth-name-oauth = OAuth
+auth-name-service-account = Service Account
+auth-name-onedrive = OneDrive
+auth-name-local = Path lokal
+auth-name-b2 = Backblaze B2
+auth-name-r2 = Cloudflare R2
+auth-name-releases = GitHub Releases
+status-oauth-client-id = OAuth client ID: { $value }
+status-service-account = Service account: { $value }
+status-onedrive-client-id = OneDrive client ID: { $value }
+status-onedrive-drive = Drive OneDrive: { $value }
+status-onedrive-personal = OneDrive pribadi
+status-storage-directory = Direktori penyimpanan: { $value }
+status-bucket = Bucket: { $value }
+status-access-key-id = Access key ID: { $value }
+status-key-prefix = Prefiks key: { $value }
+status-releases-repository = Repositori rilis: { $value }
+status-release-tag = Tag rilis: { $value }
+status-root-folder-path = Path folder root: { $value }
+status-root-folder-id = ID folder root: { $value }
+status-shared-drive-id = Shared Drive: { $value }
+status-storage = Penyimpanan: { $used } dari { $limit } terpakai
+status-storage-unlimited = Penyimpanan: { $used } terpakai (tanpa batas)
+status-auth-profile = Profil autentikasi: { $value }
+status-logged-in = Status login: ✓ Sudah masuk
+status-logged-out = Status login: ✗ Belum masuk (jalankan 'ditrive login')
+status-repository = Repositori:
+status-git-initialized = Git terinisialisasi: ✓
+status-git-missing = Git terinisialisasi: ✗
+status-repo-name = Nama repositori: { $value }
+status-tracked = Berkas besar yang dilacak: { $count }
+status-pending = Berkas besar yang menunggu diunggah: { $count }
+status-files = Berkas yang dikelola:
+status-remote-unchecked = (belum masuk, jadi salinan di penyimpanan tidak diperiksa)
+status-file-up-to-date = ✓ { $path }
+status-file-modified = M { $path } (berubah, perlu diunggah)
+status-file-missing-locally = ! { $path } (tidak ada di lokal, jalankan 'ditrive pull')
+status-file-missing-remotely = ✗ { $path } (tidak ada di penyimpanan)
+status-file-missing-everywhere = ✗ { $path } (tidak ada di lokal maupun di penyimpanan; hanya cadangan yang bisa memulihkannya)
@@ -0,0 +1,306 @@
+//! Storage backend abstraction for managed file content
+
+use async_trait::async_trait;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::drive::FileMetadata;
+use crate::error::Result;
+
+/// A file found when listing a repository's folder in a backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteEntry {
+    pub id: String,
+    /// Path under the repository folder, with forward slashes, as stored
+    pub path: String,
+    pub size: u64,
+    /// SHA-256 of the content, if the backend reports one
+    pub hash: Option<String>,
+    /// Last modification as a Unix timestamp
+    pub modified_at: i64,
+}
+
+/// Share of the storage left after an upload below which sync warns
+const LOW_SPACE_FRACTION: f64 = 0.1;
+
+/// Storage used and available in the backend's account
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StorageQuota {
+    pub usage: u64,
+    /// None for unlimited storage
+    pub limit: Option<u64>,
+}
+
+/// Whether pending uploads fit in the remaining storage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuotaCheck {
+    Fits,
+    /// They fit, but leave less than `LOW_SPACE_FRACTION` of the storage free
+    LowSpace { remaining_after: u64 },
+    Insufficient { remaining: u64 },
+}
+
+impl StorageQuota {
+    /// Bytes left, or None for unlimited storage
+    pub fn remaining(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.usage))
+    }
+
+    /// Check whether `pending` more bytes fit
+    pub fn check(&self, pending: u64) -> QuotaCheck {
+        let (Some(limit), Some(remaining)) = (self.limit, self.remaining()) else {
+            return QuotaCheck::Fits;
+        };
+        if pending > remaining {
+            return QuotaCheck::Insufficient { remaining };
+        }
+        let remaining_after = remaining - pending;
+        if (remaining_after as f64) < limit as f64 * LOW_SPACE_FRACTION {
+            QuotaCheck::LowSpace { remaining_after }
+        } else {
+            QuotaCheck::Fits
+        }
+    }
+}
+
+/// A remote store that managed files are uploaded to and downloaded from
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Human-readable backend name
+    fn name(&self) -> &'static str;
+
+    /// ID of the repository's folder in the backend
+    fn repo_folder_id(&self) -> &str;
+
+    /// Upload a new file, mirroring its repository-relative path
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata>;
+
+    /// Replace the content of an already uploaded file, keeping its ID
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata>;
+
+    /// Download a file's content to a local path
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()>;
+
+    /// Download the content a tracker entry was uploaded as, even if the file
+    /// has changed in the backend since, for backends that keep versions
+    async fn download_version(&self, metadata: &FileMetadata, destination: &Path) -> Result<()> {
+        self.download_file(&metadata.id, destination).await
+    }
+
+    /// Check if a file exists in the backend
+    async fn file_exists(&self, file_id: &str) -> bool;
+
+    /// Check if a file exists by asking the backend itself, for backends
+    /// whose `file_exists` may answer from a cache
+    async fn file_exists_uncached(&self, file_id: &str) -> bool {
+        self.file_exists(file_id).await
+    }
+
+    /// Check several files, answering in the order given, for backends that
+    /// can do better than one request after another
+    async fn files_exist(&self, file_ids: &[String]) -> Vec<bool> {
+        let mut found = Vec::with_capacity(file_ids.len());
+        for file_id in file_ids {
+            found.push(self.file_exists(file_id).await);
+        }
+        found
+    }
+
+    /// Remove a file from the backend
+    async fn delete_file(&self, file_id: &str) -> Result<()>;
+
+    /// Remove a file without going through the backend's trash, for
+    /// backends whose `delete_file` only trashes it
+    async fn delete_permanently(&self, file_id: &str) -> Result<()> {
+        self.delete_file(file_id).await
+    }
+
+    /// Move a stored file to `rel_path` in the repository folder, returning
+    /// its ID there. Backends that can move or copy files themselves override
+    /// this; the fallback downloads the file and uploads it again.
+    async fn move_file(&mut self, file_id: &str, rel_path: &str) -> Result<String> {
+        relocate(self, file_id, rel_path).await
+    }
+
+    /// Set a file aside at `trash_path` in the repository folder until it is
+    /// restored or purged, returning its ID there
+    async fn move_to_trash(&mut self, file_id: &str, trash_path: &str) -> Result<String> {
+        self.move_file(file_id, trash_path).await
+    }
+
+    /// Bring a trashed file back to `rel_path`, returning its ID there
+    async fn restore_from_trash(&mut self, trash_id: &str, rel_path: &str) -> Result<String> {
+        self.move_file(trash_id, rel_path).await
+    }
+
+    /// Get the folders for uploads into these repository-relative directories
+    /// ready ahead of time, for backends that can resolve them in bulk
+    async fn prepare_folders(&mut self, _rel_dirs: &[PathBuf]) -> Result<()> {
+        Ok(())
+    }
+
+    /// SHA-256 of the content stored for a file, if the backend can tell
+    /// without downloading it
+    async fn content_hash(&self, _file_id: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Every file stored in the repository's folder, or None if the backend
+    /// can't list it
+    async fn list_files(&self) -> Result<Option<Vec<RemoteEntry>>> {
+        Ok(None)
+    }
+
+    /// Make sure the content stored under `metadata` survives the next update,
+    /// returning the version ID it is kept under if the backend has one
+    async fn pin_current_version(&self, _metadata: &FileMetadata) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Storage used and available, or None if the backend can't tell
+    async fn storage_quota(&self) -> Result<Option<StorageQuota>> {
+        Ok(None)
+    }
+}
+
+/// One backend handed out to every operation of a long-running process, so
+/// sign-in and the backend's folder cache are paid for once.
+///
+/// Calls are serialized through a lock; each holds it for that call only.
+#[derive(Clone)]
+pub struct SharedBackend {
+    inner: Arc<Mutex<Box<dyn StorageBackend>>>,
+    name: &'static str,
+    repo_folder_id: String,
+}
+
+impl SharedBackend {
+    pub fn new(backend: Box<dyn StorageBackend>) -> Self {
+        Self {
+            name: backend.name(),
+            repo_folder_id: backend.repo_folder_id().to_string(),
+            inner: Arc::new(Mutex::new(backend)),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SharedBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        &self.repo_folder_id
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        self.inner.lock().await.upload_file(file_path, repo_path).await
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        self.inner.lock().await.update_file(file_id, file_path).await
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        self.inner.lock().await.download_file(file_id, destination).await
+    }
+
+    async fn download_version(&self, metadata: &FileMetadata, destination: &Path) -> Result<()> {
+        self.inner.lock().await.download_version(metadata, destination).await
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        self.inner.lock().await.file_exists(file_id).await
+    }
+
+    async fn file_exists_uncached(&self, file_id: &str) -> bool {
+        self.inner.lock().await.file_exists_uncached(file_id).await
+    }
+
+    async fn files_exist(&self, file_ids: &[String]) -> Vec<bool> {
+        self.inner.lock().await.files_exist(file_ids).await
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        self.inner.lock().await.delete_file(file_id).await
+    }
+
+    async fn delete_permanently(&self, file_id: &str) -> Result<()> {
+        self.inner.lock().await.delete_permanently(file_id).await
+    }
+
+    async fn move_file(&mut self, file_id: &str, rel_path: &str) -> Result<String> {
+        self.inner.lock().await.move_file(file_id, rel_path).await
+    }
+
+    async fn move_to_trash(&mut self, file_id: &str, trash_path: &str) -> Result<String> {
+        self.inner.lock().await.move_to_trash(file_id, trash_path).await
+    }
+
+    async fn restore_from_trash(&mut self, trash_id: &str, rel_path: &str) -> Result<String> {
+        self.inner.lock().await.restore_from_trash(trash_id, rel_path).await
+    }
+
+    async fn prepare_folders(&mut self, rel_dirs: &[PathBuf]) -> Result<()> {
+        self.inner.lock().await.prepare_folders(rel_dirs).await
+    }
+
+    async fn content_hash(&self, file_id: &str) -> Result<Option<String>> {
+        self.inner.lock().await.content_hash(file_id).await
+    }
+
+    async fn list_files(&self) -> Result<Option<Vec<RemoteEntry>>> {
+        self.inner.lock().await.list_files().await
+    }
+
+    async fn pin_current_version(&self, metadata: &FileMetadata) -> Result<Option<String>> {
+        self.inner.lock().await.pin_current_version(metadata).await
+    }
+
+    async fn storage_quota(&self) -> Result<Option<StorageQuota>> {
+        self.inner.lock().await.storage_quota().await
+    }
+}
+
+/// Move a file to another path in the repository folder by downloading and
+/// re-uploading it, for backends that can't move files in place
+pub async fn relocate<B: StorageBackend + ?Sized>(backend: &mut B, file_id: &str, rel_path: &str) -> Result<String> {
+    let staging = std::env::temp_dir().join(format!(
+        "ditrive-relocate-{}-{}",
+        std::process::id(),
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    ));
+    let local_path = staging.join(rel_path);
+
+    let result = async {
+        backend.download_file(file_id, &local_path).await?;
+        let moved = backend.upload_file(&local_path, &staging).await?;
+        backend.delete_permanently(file_id).await?;
+        Ok(moved.id)
+    }
+    .await;
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_check() {
+        let gb = 1024 * 1024 * 1024;
+        let quota = StorageQuota { usage: 12 * gb, limit: Some(15 * gb) };
+        assert_eq!(quota.remaining(), Some(3 * gb));
+        assert_eq!(quota.check(gb), QuotaCheck::Fits);
+        assert_eq!(quota.check(2 * gb), QuotaCheck::LowSpace { remaining_after: gb });
+        assert_eq!(quota.check(4 * gb), QuotaCheck::Insufficient { remaining: 3 * gb });
+
+        let unlimited = StorageQuota { usage: 12 * gb, limit: None };
+        assert_eq!(unlimited.check(100 * gb), QuotaCheck::Fits);
+    }
+}
@@ -0,0 +1,110 @@
+//! Transfer benchmarking against the configured storage backend
+//!
+//! `ditrive bench` uploads and downloads synthetic files to measure latency,
+//! throughput per file size, and how throughput scales with parallel
+//! transfers. The helpers here generate the files and summarize the timings;
+//! the transfers themselves run in `Ditrive::bench`.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::Result;
+
+/// Bytes in a megabyte, as used for sizes and throughput
+pub const MB: u64 = 1024 * 1024;
+
+/// Round trips used to measure latency
+pub const LATENCY_ROUNDS: usize = 3;
+
+/// A higher concurrency level must beat the best lower one by this much to be recommended
+const CONCURRENCY_GAIN: f64 = 1.10;
+
+/// Write `size` bytes of incompressible pseudo-random data to `path`.
+///
+/// Each `seed` yields different content, so backends that deduplicate
+/// uploads can't skip the transfer.
+pub fn write_synthetic_file(path: &Path, size: u64, seed: u64) -> Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    // xorshift64 needs a non-zero state
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut remaining = size;
+
+    while remaining > 0 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let bytes = state.to_le_bytes();
+        let n = remaining.min(bytes.len() as u64) as usize;
+        writer.write_all(&bytes[..n])?;
+        remaining -= n as u64;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Throughput in MB/s for `bytes` moved in `elapsed`
+pub fn throughput(bytes: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds == 0.0 {
+        return 0.0;
+    }
+    bytes as f64 / MB as f64 / seconds
+}
+
+/// Median of a set of timings
+pub fn median(mut samples: Vec<Duration>) -> Duration {
+    if samples.is_empty() {
+        return Duration::ZERO;
+    }
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+/// Pick the concurrency level to recommend from `(level, MB/s)` results.
+///
+/// More parallel transfers cost memory and risk rate limits, so a level is
+/// only worth it if it is clearly faster than every lower level.
+pub fn recommend_concurrency(results: &[(usize, f64)]) -> usize {
+    let mut sorted = results.to_vec();
+    sorted.sort_by_key(|(level, _)| *level);
+
+    let mut best: Option<(usize, f64)> = None;
+    for (level, rate) in sorted {
+        match best {
+            Some((_, best_rate)) if rate < best_rate * CONCURRENCY_GAIN => {}
+            _ => best = Some((level, rate)),
+        }
+    }
+
+    best.map(|(level, _)| level).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_synthetic_files_have_size_and_differ_by_seed() {
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("a.bin");
+        let second = dir.path().join("b.bin");
+
+        write_synthetic_file(&first, 1000, 1).unwrap();
+        write_synthetic_file(&second, 1000, 2).unwrap();
+
+        assert_eq!(fs::metadata(&first).unwrap().len(), 1000);
+        assert_ne!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+    }
+
+    #[test]
+    fn test_recommend_concurrency_needs_a_clear_gain() {
+        assert_eq!(recommend_concurrency(&[(1, 10.0), (2, 19.0), (4, 20.0)]), 2);
+        assert_eq!(recommend_concurrency(&[(4, 10.5), (1, 10.0), (2, 10.4)]), 1);
+        assert_eq!(recommend_concurrency(&[(1, 10.0), (8, 40.0)]), 8);
+        assert_eq!(recommend_concurrency(&[]), 1);
+    }
+}
@@ -0,0 +1,211 @@
+//! Adaptive chunk sizes for resumable uploads
+//!
+//! A `ChunkTuner` starts at 8 MiB, doubles the chunk size while chunks go
+//! through quickly and reliably, and halves it when a chunk is slow or fails.
+//! The size it settles on is remembered per backend and network profile in
+//! `~/.ditrive/chunk_sizes.json`, so the next upload on the same network
+//! starts from it instead of relearning it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::GlobalConfig;
+use crate::error::Result;
+
+const MIB: usize = 1024 * 1024;
+
+/// Chunk size used on a network we haven't learned yet
+pub const INITIAL_CHUNK_SIZE: usize = 8 * MIB;
+
+/// Largest chunk size the tuner grows to
+pub const MAX_CHUNK_SIZE: usize = 128 * MIB;
+
+/// Chunks finishing faster than this count towards growing the size
+const FAST_CHUNK: Duration = Duration::from_secs(5);
+
+/// Chunks taking longer than this shrink the size
+const SLOW_CHUNK: Duration = Duration::from_secs(30);
+
+/// Consecutive fast chunks needed before growing, so one lucky chunk doesn't count
+const STABLE_STREAK: u32 = 2;
+
+/// Environment variable naming the network profile explicitly
+const NETWORK_PROFILE_ENV: &str = "DITRIVE_NETWORK_PROFILE";
+
+/// Learns a good chunk size for one upload session
+#[derive(Debug)]
+pub struct ChunkTuner {
+    key: String,
+    size: usize,
+    min: usize,
+    max: usize,
+    align: usize,
+    fast_streak: u32,
+}
+
+impl ChunkTuner {
+    /// Create a tuner within `[min, max]`, keeping sizes a multiple of `align`
+    pub fn new(key: &str, initial: usize, min: usize, max: usize, align: usize) -> Self {
+        let mut tuner = Self {
+            key: key.to_string(),
+            size: initial,
+            min,
+            max,
+            align: align.max(1),
+            fast_streak: 0,
+        };
+        tuner.size = tuner.clamp(initial);
+        tuner
+    }
+
+    /// Create a tuner for `backend` starting from the size learned on this network
+    pub fn load(backend: &str, min: usize, max: usize, align: usize) -> Self {
+        let key = format!("{}@{}", backend, network_profile());
+        let initial = read_learned_sizes()
+            .get(&key)
+            .copied()
+            .unwrap_or(INITIAL_CHUNK_SIZE);
+        debug!("Starting {} uploads with {} byte chunks", key, initial);
+        Self::new(&key, initial, min, max, align)
+    }
+
+    /// Size of the next chunk
+    pub fn chunk_size(&self) -> usize {
+        self.size
+    }
+
+    /// Record a chunk that uploaded successfully in `elapsed`
+    pub fn record_success(&mut self, elapsed: Duration) {
+        if elapsed > SLOW_CHUNK {
+            self.fast_streak = 0;
+            self.size = self.clamp(self.size / 2);
+        } else if elapsed < FAST_CHUNK {
+            self.fast_streak += 1;
+            if self.fast_streak >= STABLE_STREAK {
+                self.fast_streak = 0;
+                self.size = self.clamp(self.size.saturating_mul(2));
+            }
+        } else {
+            self.fast_streak = 0;
+        }
+    }
+
+    /// Record a chunk that failed and will be retried
+    pub fn record_failure(&mut self) {
+        self.fast_streak = 0;
+        self.size = self.clamp(self.size / 2);
+    }
+
+    /// Remember the current size for the next upload on this network
+    pub fn save(&self) {
+        let mut sizes = read_learned_sizes();
+        sizes.insert(self.key.clone(), self.size);
+        if let Err(e) = write_learned_sizes(&sizes) {
+            warn!("Failed to save learned chunk size: {}", e);
+        }
+    }
+
+    /// Keep a size within the limits and on an alignment boundary
+    fn clamp(&self, size: usize) -> usize {
+        let size = size.clamp(self.min, self.max);
+        (size / self.align).max(1) * self.align
+    }
+}
+
+/// Identify the current network, so each network keeps its own chunk size.
+///
+/// Uses `DITRIVE_NETWORK_PROFILE` when set, otherwise the local address's
+/// subnet (found without sending anything), falling back to "default".
+pub fn network_profile() -> String {
+    if let Ok(profile) = std::env::var(NETWORK_PROFILE_ENV) {
+        if !profile.is_empty() {
+            return profile;
+        }
+    }
+
+    let local_ip = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("8.8.8.8:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip());
+
+    match local_ip {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        _ => "default".to_string(),
+    }
+}
+
+/// Path of the learned chunk size store
+fn store_path() -> Result<PathBuf> {
+    Ok(GlobalConfig::config_dir()?.join("chunk_sizes.json"))
+}
+
+/// Learned chunk sizes by backend and network profile
+fn read_learned_sizes() -> HashMap<String, usize> {
+    store_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_learned_sizes(sizes: &HashMap<String, usize>) -> Result<()> {
+    let path = store_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(sizes)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuner_grows_on_stable_links_and_shrinks_on_errors() {
+        let mut tuner = ChunkTuner::new("test", INITIAL_CHUNK_SIZE, MIB, MAX_CHUNK_SIZE, MIB);
+
+        // One fast chunk isn't enough to grow
+        tuner.record_success(Duration::from_secs(1));
+        assert_eq!(tuner.chunk_size(), 8 * MIB);
+        tuner.record_success(Duration::from_secs(1));
+        assert_eq!(tuner.chunk_size(), 16 * MIB);
+
+        for _ in 0..20 {
+            tuner.record_success(Duration::from_millis(500));
+        }
+        assert_eq!(tuner.chunk_size(), MAX_CHUNK_SIZE);
+
+        tuner.record_failure();
+        assert_eq!(tuner.chunk_size(), 64 * MIB);
+        tuner.record_success(Duration::from_secs(60));
+        assert_eq!(tuner.chunk_size(), 32 * MIB);
+    }
+
+    #[test]
+    fn test_tuner_respects_alignment_and_limits() {
+        let align = 320 * 1024;
+        let mut tuner = ChunkTuner::new("test", INITIAL_CHUNK_SIZE, align, 60 * MIB, align);
+        assert_eq!(tuner.chunk_size() % align, 0);
+
+        for _ in 0..20 {
+            tuner.record_failure();
+        }
+        assert_eq!(tuner.chunk_size(), align);
+
+        for _ in 0..40 {
+            tuner.record_success(Duration::from_millis(100));
+        }
+        assert!(tuner.chunk_size() <= 60 * MIB);
+        assert_eq!(tuner.chunk_size() % align, 0);
+    }
+}
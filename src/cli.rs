@@ -1,8 +1,11 @@
 //! Command-line interface definitions
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::net::IpAddr;
 use std::path::PathBuf;
 
+use crate::history::HistoryAction;
+
 #[derive(Parser, Debug)]
 #[command(
     name = "ditrive",
@@ -29,6 +32,29 @@ pub struct Cli {
     /// Enable verbose logging
     #[arg(short, long)]
     pub verbose: bool,
+
+    /// Print JSON instead of human-readable output (status, list, sync, diff, stats)
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Never wait for input: answer prompts from config defaults and fail
+    /// when a step needs the user (e.g. signing in)
+    #[arg(short = 'y', long = "yes", visible_alias = "non-interactive", global = true)]
+    pub non_interactive: bool,
+
+    /// Log every API call and per-phase timings to a trace file
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "ditrive-trace.log"
+    )]
+    pub trace_api: Option<PathBuf>,
+
+    /// Also write debug-level JSON logs to a rotating file, whatever the console shows
+    #[arg(long, value_name = "FILE", global = true)]
+    pub log_file: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -36,11 +62,30 @@ pub enum Commands {
     /// Configure global settings for GitHub and Google Drive
     Configure,
 
+    /// Read or change a single setting without the interactive wizard
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
     /// Login to Google Drive using OAuth
-    Login,
+    Login {
+        /// Sign in to a named profile, e.g. "work" (tokens under ~/.ditrive/profiles/)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Don't open a browser or listen for the redirect; paste the address
+        /// the browser ends up on instead (for SSH sessions, WSL and containers)
+        #[arg(long)]
+        no_browser: bool,
+    },
 
     /// Logout from Google Drive (clear stored tokens)
-    Logout,
+    Logout {
+        /// Sign out of a named profile instead of the repository's
+        #[arg(long)]
+        profile: Option<String>,
+    },
 
     /// Quick setup for a new repository with GitHub and Google Drive
     #[command(name = "quick-setup")]
@@ -56,23 +101,535 @@ pub enum Commands {
         /// Create as public repository (default: private)
         #[arg(long)]
         public: bool,
+
+        /// Create the repository under this organization (default: from config, else your account)
+        #[arg(long)]
+        org: Option<String>,
+
+        /// Give an organization team access, as <team> or <team>:<permission> (repeatable)
+        #[arg(long = "team", value_name = "TEAM")]
+        teams: Vec<String>,
     },
 
     /// Initialize Ditrive for an existing repository
-    Init,
+    Init {
+        /// Commit pointer files through a git filter instead of ignoring large files
+        #[arg(long)]
+        pointers: bool,
+
+        /// Settings for the repository, saved in its new config
+        #[command(flatten)]
+        overrides: SettingOverrides,
+    },
 
     /// Synchronize files between the repository and Google Drive
-    Sync,
+    Sync {
+        /// Fetch and fast-forward the current branch first, so new tracker entries are downloaded too
+        /// (default: the repo's pull_before_sync setting)
+        #[arg(long)]
+        pull: bool,
+
+        /// Push the current branch to origin afterwards (default: the repo's push_after_sync setting)
+        #[arg(long)]
+        push: bool,
+
+        /// Also download files changed or added remotely by other clones, even ones present here
+        /// (default: the repo's two_way_sync setting)
+        #[arg(long)]
+        two_way: bool,
+
+        /// Remove the stored copies of managed files deleted here since the last sync, as the
+        /// deletion policy says; without it they are downloaded again
+        #[arg(long)]
+        prune: bool,
+
+        /// Commit the tracker and .gitignore changes afterwards (default: the repo's auto_commit setting)
+        #[arg(long)]
+        commit: bool,
+
+        #[command(flatten)]
+        overrides: SettingOverrides,
+    },
 
     /// Show status of Ditrive configuration and login
     Status,
 
+    /// Show what sync would upload, download and remove, without changing anything
+    Diff {
+        /// Plan as `sync --prune` would, removing the stored copies of files deleted here
+        #[arg(long)]
+        prune: bool,
+
+        #[command(flatten)]
+        overrides: SettingOverrides,
+    },
+
     /// Download missing files from Google Drive
-    Pull,
+    Pull {
+        /// Only download files under these paths, relative to the repository root
+        paths: Vec<PathBuf>,
+
+        /// Only download files matching this glob, like .gitattributes patterns (repeatable)
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// Leave out files matching this glob (repeatable)
+        #[arg(long)]
+        exclude: Vec<String>,
+    },
+
+    /// Replace `.ditrive` placeholders with the content they stand for
+    Hydrate {
+        /// Placeholders, managed files, or folders to hydrate, relative to the repository root
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Browse managed files in a read-only folder that downloads each file on first read
+    /// (needs a build with the "mount" feature and FUSE)
+    Mount {
+        /// Empty folder to mount the files at; unmount with 'fusermount -u' (or 'umount' on macOS)
+        mountpoint: PathBuf,
+    },
 
     /// Upload new/changed large files to Google Drive
-    Push,
+    Push {
+        /// Only upload files under these paths, relative to the repository root
+        paths: Vec<PathBuf>,
+
+        /// Upload managed files again even if the tracker says they're current,
+        /// e.g. after their stored copy was corrupted or deleted
+        #[arg(long)]
+        force: bool,
+
+        /// Commit the tracker and .gitignore changes afterwards (default: the repo's auto_commit setting)
+        #[arg(long)]
+        commit: bool,
+
+        #[command(flatten)]
+        overrides: SettingOverrides,
+    },
+
+    /// Delete managed files and stop managing them; the stored copies follow the repo's deletion_policy
+    Rm {
+        /// Paths of the managed files, relative to the repository root
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// List, restore, or empty files set aside by the "trash" deletion policy
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+
+    /// Manage every file matching a pattern such as "*.psd", whatever its size
+    Track {
+        /// Patterns to add, matched like .gitattributes (lists the current ones if omitted)
+        patterns: Vec<String>,
+    },
+
+    /// Watch the repository and keep the index behind `status` and `list` current,
+    /// handing new large files to a running `serve --rpc` daemon to push
+    Watch,
 
     /// List all managed files
+    List {
+        /// Show preview metadata for each file
+        #[arg(short, long)]
+        long: bool,
+    },
+
+    /// List the files actually stored for this repository, next to what the trackers hold them as
+    #[command(name = "ls-remote")]
+    LsRemote,
+
+    /// Track files already in the storage folder that no tracker knows about,
+    /// matched by relative path; missing ones are downloaded
+    Adopt {
+        /// Commit the tracker and .gitignore changes afterwards (default: the repo's auto_commit setting)
+        #[arg(long)]
+        commit: bool,
+    },
+
+    /// Summarize storage: managed bytes, the largest directories and files, and quota use
+    Stats {
+        /// Number of largest files to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+
+    /// List the version history of a managed file
+    Versions {
+        /// Path of the managed file, relative to the repository root
+        path: PathBuf,
+    },
+
+    /// Download a specific version of a managed file
+    Restore {
+        /// Path of the managed file, relative to the repository root
+        path: PathBuf,
+
+        /// Version number to restore (see 'ditrive versions')
+        #[arg(long)]
+        version: usize,
+
+        /// Restore to this path instead of overwriting the managed file
+        #[arg(long)]
+        to: Option<PathBuf>,
+    },
+
+    /// Export a manifest of download links and hashes for all managed files
+    #[command(name = "export-links")]
+    ExportLinks {
+        /// Manifest format
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Write the manifest to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Make each file readable by anyone with the link
+        #[arg(long, conflicts_with = "email")]
+        share: bool,
+
+        /// Share each file with this email address instead
+        #[arg(long)]
+        email: Option<String>,
+
+        /// Expire the email share after this many days
+        #[arg(long, requires = "email")]
+        expires_in_days: Option<u32>,
+    },
+
+    /// Publish the managed files to a read-only public mirror
+    Publish {
+        /// Google Drive folder to publish into, by ID or https://drive.google.com link
+        #[arg(long)]
+        target: String,
+    },
+
+    /// Give teammates access to the repository's Drive folder
+    #[command(group(clap::ArgGroup::new("action").required(true).args(["email", "list", "revoke"])))]
+    Share {
+        /// Grant access to this email address
+        #[arg(long)]
+        email: Option<String>,
+
+        /// Access to grant
+        #[arg(long, value_enum, default_value_t = ShareRole::Reader)]
+        role: ShareRole,
+
+        /// List who has access to the folder
+        #[arg(long)]
+        list: bool,
+
+        /// Remove this email address's access
+        #[arg(long, value_name = "EMAIL")]
+        revoke: Option<String>,
+    },
+
+    /// Restore a managed file's Drive copy from the Drive trash
+    #[command(name = "restore-trashed")]
+    RestoreTrashed {
+        /// Path of the managed file, relative to the repository root
+        path: PathBuf,
+    },
+
+    /// Move the repository's Drive folder under another root folder using server-side copies
+    #[command(name = "migrate-folder")]
+    MigrateFolder {
+        /// Google Drive folder ID to move the repository folder under
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Move every managed file to another storage backend
+    #[command(name = "migrate-backend")]
+    MigrateBackend {
+        /// Backend the files are stored in now
+        #[arg(long, value_enum)]
+        from: BackendKind,
+
+        /// Backend to move the files to
+        #[arg(long, value_enum)]
+        to: BackendKind,
+    },
+
+    /// Move files stored in Git LFS to the storage backend and stop using LFS for them
+    ImportLfs,
+
+    /// Hand every managed file back to Git LFS and stop managing it
+    ExportLfs,
+
+    /// Upgrade tracker entries from older versions, filling in missing hashes and sizes
+    Migrate {
+        /// Also move every entry into a single .ditrive/manifest.json
+        #[arg(long)]
+        manifest: bool,
+    },
+
+    /// Switch between per-folder .woilah files and a single .ditrive/manifest.json
+    #[command(name = "migrate-tracker")]
+    MigrateTracker {
+        /// Tracker format to switch to
+        #[arg(long, value_enum)]
+        to: TrackerFormat,
+    },
+
+    /// Remove managed files whose expire rule has run out, remotely and from the trackers
+    Gc {
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Guided repair after a bad merge: fsck, check-remote, then reconcile, confirming each step
+    Recover,
+
+    /// Fail if a file staged for commit is over the large file threshold but not managed,
+    /// for pre-commit hooks and CI
+    Check {
+        /// Install a pre-commit hook in this repository that runs the check
+        #[arg(long)]
+        install_hook: bool,
+    },
+
+    /// Summarize monthly usage from the local operation journal
+    Report {
+        /// Number of most recent months to show
+        #[arg(long, default_value_t = 6)]
+        months: usize,
+
+        /// Include every repository on this machine, not just this one
+        #[arg(long)]
+        all_repos: bool,
+    },
+
+    /// Show the audit log of uploads, downloads, deletions and sharing
+    Log {
+        /// Only entries whose path matches this pattern, e.g. "art/*.psd"
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Only these actions; repeat for several
+        #[arg(long, value_enum)]
+        action: Vec<HistoryAction>,
+
+        /// Only entries by users containing this text
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Only entries this recent, e.g. 12h, 7d or 2w
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Include every repository on this machine, not just this one
+        #[arg(long)]
+        all_repos: bool,
+
+        /// Show at most this many of the most recent entries
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+
+    /// Benchmark uploads and downloads against the configured storage backend
+    Bench {
+        /// Comma-separated file sizes to test, in MB
+        #[arg(long, value_delimiter = ',', default_values_t = [1, 10, 50])]
+        sizes: Vec<u64>,
+
+        /// Comma-separated numbers of parallel uploads to test
+        #[arg(long, value_delimiter = ',', default_values_t = [1, 2, 4, 8])]
+        concurrency: Vec<usize>,
+    },
+
+    /// Serve managed files from a local cache in front of the storage backend,
+    /// or with --rpc, run sync/push/pull/status for local clients over JSON-RPC
+    Serve {
+        /// Address to listen on, such as 0.0.0.0 to serve other machines as their team cache
+        /// [default: 127.0.0.1]
+        #[arg(long, conflicts_with = "rpc")]
+        bind: Option<IpAddr>,
+
+        /// Port to listen on [default: 7878, or 7879 with --rpc]
+        #[arg(long)]
+        port: Option<u16>,
+
+        /// Directory to keep cached files in [default: ~/.ditrive/team-cache]
+        #[arg(long, conflicts_with = "rpc")]
+        cache_dir: Option<PathBuf>,
+
+        /// Stay signed in and answer JSON-RPC requests on 127.0.0.1 instead of serving files
+        #[arg(long)]
+        rpc: bool,
+    },
+
+    /// Show or empty ~/.ditrive/cache, the downloads shared by every repository
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+
+    /// Post or update a pull request comment summarizing managed-file changes (for CI)
+    #[command(name = "pr-comment")]
+    PrComment {
+        /// Pull request number; defaults to the one in GITHUB_REF
+        #[arg(long)]
+        pr: Option<u64>,
+
+        /// Branch or commit the pull request merges into; defaults to origin/$GITHUB_BASE_REF, then origin/main
+        #[arg(long)]
+        base: Option<String>,
+
+        /// GitHub repository as owner/name; defaults to GITHUB_REPOSITORY, then the repository's GitHub URL
+        #[arg(long)]
+        repository: Option<String>,
+    },
+
+    /// Git clean filter: upload a managed file and print its pointer (run by git)
+    #[command(name = "filter-clean", hide = true)]
+    FilterClean {
+        /// Path of the file being staged, relative to the repository root
+        path: PathBuf,
+    },
+
+    /// Git smudge filter: print the content a pointer stands for (run by git)
+    #[command(name = "filter-smudge", hide = true)]
+    FilterSmudge {
+        /// Path of the file being checked out, relative to the repository root
+        path: PathBuf,
+    },
+
+    /// Print the catalog entries a language still lacks, as a template for translators
+    Messages {
+        /// Language code such as "id"
+        language: String,
+    },
+}
+
+/// What `ditrive trash` does
+#[derive(Subcommand, Debug)]
+pub enum TrashCommand {
+    /// List trashed files and when they expire
     List,
+
+    /// Bring a trashed file back and manage it again
+    Restore {
+        /// Path the file had, relative to the repository root
+        path: PathBuf,
+    },
+
+    /// Permanently delete trashed files older than the retention period
+    Empty {
+        /// Delete everything in the trash, however recent
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// What `ditrive cache` does
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Show how many files the download cache holds, and its size limit
+    Stats,
+
+    /// Remove everything from the download cache
+    Clean,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print a setting, named by its dotted path, e.g. "drive.root_folder_id"
+    Get {
+        key: String,
+
+        /// Read the repository config instead of the global one
+        #[arg(long)]
+        repo: bool,
+    },
+
+    /// Change a setting, e.g. "settings.large_file_threshold_mb 50"
+    Set {
+        key: String,
+
+        /// New value; lists take comma-separated items or a JSON array
+        value: String,
+
+        /// Change the repository config instead of the global one
+        #[arg(long)]
+        repo: bool,
+    },
+
+    /// Put a setting back to its default
+    Unset {
+        key: String,
+
+        /// Change the repository config instead of the global one
+        #[arg(long)]
+        repo: bool,
+    },
+}
+
+/// Repository settings to use for one run instead of the configured ones
+#[derive(Args, Debug, Clone, Default)]
+pub struct SettingOverrides {
+    /// Manage files larger than this many MB
+    #[arg(long, value_name = "MB")]
+    pub threshold: Option<u64>,
+
+    /// Also ignore files matching this pattern (repeatable)
+    #[arg(long = "ignore-pattern", value_name = "PATTERN")]
+    pub ignore_patterns: Vec<String>,
+
+    /// Any other setting, as "KEY=VALUE" with a key like 'config set' takes (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub settings: Vec<String>,
+}
+
+/// Output format for exported manifests
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Access `ditrive share` grants on the repository folder
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ShareRole {
+    /// Download managed files
+    Reader,
+    /// Download and upload managed files
+    Writer,
+}
+
+impl ShareRole {
+    /// Role name in the Drive permissions API
+    pub fn as_api_role(self) -> &'static str {
+        match self {
+            ShareRole::Reader => "reader",
+            ShareRole::Writer => "writer",
+        }
+    }
+}
+
+/// Where managed files are recorded
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum TrackerFormat {
+    /// A .woilah file in every folder with managed files
+    Woilah,
+    /// One .ditrive/manifest.json for the whole repository
+    Manifest,
+}
+
+/// Storage backend named on the command line
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum BackendKind {
+    Drive,
+    Onedrive,
+    Local,
+    B2,
+    R2,
+    Releases,
 }
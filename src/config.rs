@@ -1,35 +1,303 @@
 //! Configuration management for ditrive
 //! 
 //! Handles both global configuration (~/.ditrive/config.json) and
-//! repository-specific configuration (.woilah-config.json)
+//! repository-specific configuration (.woilah-config.json). Either can be
+//! written in TOML instead, as ~/.ditrive/config.toml or .ditrive.toml in the
+//! repository; a TOML file takes precedence over its JSON counterpart.
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
+use crate::download_cache;
+use crate::drive;
+use crate::drive_cache;
 use crate::error::{DitriveError, Result};
+use crate::git;
+use crate::oauth;
+use crate::trash;
+
+/// File format of a config file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Format to write a config file in, by its extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// Format of config file content. Our JSON configs are objects, so they start with `{`.
+    pub fn detect(content: &str) -> Self {
+        if content.trim_start().starts_with('{') {
+            ConfigFormat::Json
+        } else {
+            ConfigFormat::Toml
+        }
+    }
+
+    /// Parse config content read from `path`
+    fn parse<T: DeserializeOwned>(self, content: &str, path: &Path) -> Result<T> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::from_str(content)?),
+            ConfigFormat::Toml => toml::from_str(content)
+                .map_err(|e| DitriveError::Config(format!("Invalid {}: {}", path.display(), e))),
+        }
+    }
+
+    /// Serialize a config in this format
+    fn render<T: Serialize>(self, config: &T) -> Result<String> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(config)?),
+            ConfigFormat::Toml => toml::to_string_pretty(config)
+                .map_err(|e| DitriveError::Config(format!("Failed to write TOML config: {}", e))),
+        }
+    }
+}
+
+/// Read a config file in whichever format its content is in
+fn read_config<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path)?;
+    ConfigFormat::detect(&content).parse(&content, path)
+}
+
+/// Write a config file in the format its extension names
+fn write_config<T: Serialize>(path: &Path, config: &T) -> Result<()> {
+    let content = ConfigFormat::from_path(path).render(config)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Look up a dotted key such as "settings.large_file_threshold_mb" in a config
+pub fn get_key<T: Serialize>(config: &T, key: &str) -> Result<serde_json::Value> {
+    let value = serde_json::to_value(config)?;
+    key.split('.')
+        .try_fold(&value, |value, part| value.get(part))
+        .cloned()
+        .ok_or_else(|| DitriveError::Config(format!("Unknown config key '{}'", key)))
+}
+
+/// Set a dotted key in a config, reading `raw` as the type the key already
+/// holds: a number, true/false, a comma-separated or JSON list, or text
+pub fn set_key<T: Serialize + DeserializeOwned>(config: &T, key: &str, raw: &str) -> Result<T> {
+    let current = get_key(config, key).ok();
+    let new_value = match current {
+        Some(serde_json::Value::String(_)) => serde_json::Value::String(raw.to_string()),
+        Some(serde_json::Value::Bool(_)) => match raw {
+            "true" => serde_json::Value::Bool(true),
+            "false" => serde_json::Value::Bool(false),
+            _ => return Err(DitriveError::Config(format!("{} must be true or false", key))),
+        },
+        Some(serde_json::Value::Number(_)) => serde_json::from_str(raw)
+            .ok()
+            .filter(serde_json::Value::is_number)
+            .ok_or_else(|| DitriveError::Config(format!("{} must be a number", key)))?,
+        Some(serde_json::Value::Array(_)) => match serde_json::from_str(raw) {
+            Ok(list @ serde_json::Value::Array(_)) => list,
+            _ => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| serde_json::Value::String(item.to_string()))
+                .collect(),
+        },
+        // Tables, unset optional values and new map entries take JSON, else text
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string())),
+    };
+    replace_key(config, key, Some(new_value))
+}
+
+/// Put a dotted key back to its value in `defaults`
+pub fn unset_key<T: Serialize + DeserializeOwned>(config: &T, defaults: &T, key: &str) -> Result<T> {
+    get_key(config, key)?;
+    replace_key(config, key, get_key(defaults, key).ok())
+}
+
+/// Replace or remove the value at a dotted key, checking the result is still
+/// a valid config and the key is one it keeps
+fn replace_key<T: Serialize + DeserializeOwned>(
+    config: &T,
+    key: &str,
+    new_value: Option<serde_json::Value>,
+) -> Result<T> {
+    let unknown = || DitriveError::Config(format!("Unknown config key '{}'", key));
+    let mut value = serde_json::to_value(config)?;
+    let (parent_key, field) = key.rsplit_once('.').map_or(("", key), |(parent, field)| (parent, field));
+    let parent = parent_key
+        .split('.')
+        .filter(|part| !part.is_empty())
+        .try_fold(&mut value, |value, part| value.get_mut(part))
+        .and_then(serde_json::Value::as_object_mut)
+        .ok_or_else(unknown)?;
+    let expected = new_value.clone();
+    match new_value {
+        Some(new_value) => parent.insert(field.to_string(), new_value),
+        None => parent.remove(field),
+    };
+
+    let updated: T = serde_json::from_value(value)
+        .map_err(|e| DitriveError::Config(format!("Invalid value for {}: {}", key, e)))?;
+    // Keys a config doesn't have are dropped on the way back in
+    if expected.is_some() && get_key(&updated, key).is_err() {
+        return Err(unknown());
+    }
+    Ok(updated)
+}
+
+/// How OAuth token files are protected at rest
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenEncryption {
+    /// Plaintext, readable by the owner only
+    #[default]
+    Off,
+    /// Sealed with a key bound to this machine and user
+    Machine,
+    /// Sealed with the passphrase in DITRIVE_TOKEN_PASSPHRASE
+    Passphrase,
+}
 
 /// Authentication type for Google Drive
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DriveAuthType {
     /// OAuth2 user authentication (for collaboration)
+    #[default]
     OAuth,
     /// Service account authentication (for automation)
     ServiceAccount,
+    /// Microsoft OneDrive / SharePoint via the Graph API
+    OneDrive,
+    /// Local directory, e.g. a mounted NAS share or external drive
+    Local,
+    /// Backblaze B2 bucket via its S3-compatible API
+    B2,
+    /// Cloudflare R2 bucket via its S3-compatible API
+    R2,
+    /// Assets of a GitHub release, downloadable without credentials
+    Releases,
 }
 
-impl Default for DriveAuthType {
-    fn default() -> Self {
-        DriveAuthType::OAuth
+impl DriveAuthType {
+    /// Human-readable name of the storage service
+    pub fn service_name(&self) -> &'static str {
+        match self {
+            DriveAuthType::OAuth | DriveAuthType::ServiceAccount => "Google Drive",
+            DriveAuthType::OneDrive => "OneDrive",
+            DriveAuthType::Local => "local storage",
+            DriveAuthType::B2 => "Backblaze B2",
+            DriveAuthType::R2 => "Cloudflare R2",
+            DriveAuthType::Releases => "GitHub Releases",
+        }
+    }
+
+    /// Whether the service names files by their path rather than by an opaque ID,
+    /// so the same file can have the same ID in two such services
+    pub fn ids_are_paths(&self) -> bool {
+        matches!(
+            self,
+            DriveAuthType::Local | DriveAuthType::B2 | DriveAuthType::R2 | DriveAuthType::Releases
+        )
     }
 }
 
+/// What to do with thumbnails of image and video assets on push
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailMode {
+    /// Don't generate thumbnails
+    #[default]
+    Off,
+    /// Write thumbnails into the repository so they get committed
+    Commit,
+    /// Upload thumbnails to a `thumbnails` subfolder of the remote folder
+    Upload,
+}
+
+/// What sync does with the stored copy of a managed file deleted locally
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletionPolicy {
+    /// Leave it alone; the file is downloaded again on the next sync
+    #[default]
+    Keep,
+    /// Set it aside in the trash for `trash_retention_days` (see `ditrive trash`), and stop managing the file
+    Trash,
+    /// Delete it for good, and stop managing the file
+    Delete,
+}
+
+/// How repository scans treat symbolic links
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Leave links out of scans
+    #[default]
+    Skip,
+    /// Follow links into the folders and files they point to
+    Follow,
+    /// Manage a link to a file as a copy of that file, without descending into linked folders
+    TreatAsFile,
+}
+
+/// Tool that signs and verifies tracker entries
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningTool {
+    /// Don't sign or check signatures
+    #[default]
+    Off,
+    /// Detached, armored `gpg` signatures
+    Gpg,
+    /// `minisign` signatures
+    Minisign,
+}
+
+/// How stored copies are arranged in the backend
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLayout {
+    /// Folders that mirror the repository's directories
+    #[default]
+    Mirror,
+    /// Content-addressed objects under `objects/<hash prefix>/<hash>`, with paths kept only in the trackers
+    Flat,
+}
+
 /// Global configuration shared across all repositories
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalConfig {
     pub github: GitHubGlobalConfig,
     pub drive: DriveGlobalConfig,
     pub settings: GlobalSettings,
+    #[serde(default)]
+    pub network: NetworkConfig,
+}
+
+/// Proxy and TLS settings for networks that need them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxy for all requests, e.g. "http://proxy.corp:3128"; empty uses HTTPS_PROXY and friends
+    #[serde(default)]
+    pub proxy_url: String,
+    /// Username, for proxies that require authentication
+    #[serde(default)]
+    pub proxy_username: String,
+    #[serde(default)]
+    pub proxy_password: String,
+    /// Comma-separated hosts that bypass the proxy, like NO_PROXY; empty uses NO_PROXY
+    #[serde(default)]
+    pub no_proxy: String,
+    /// PEM files of extra root certificates to trust, e.g. a corporate CA
+    #[serde(default)]
+    pub ca_certificates: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +305,72 @@ pub struct GitHubGlobalConfig {
     pub username: String,
     pub token: String,
     pub default_visibility: String,
+    /// Which API the forge speaks: "github" or "gitea" (also Forgejo)
+    #[serde(default)]
+    pub forge: ForgeKind,
+    /// Organization new repositories are created under; empty for the user's account
+    #[serde(default)]
+    pub default_org: String,
+    /// Teams given access to new organization repositories, as "team" or "team:permission"
+    #[serde(default)]
+    pub default_teams: Vec<String>,
+    /// API base URL of a self-hosted forge, e.g. "https://git.example.com/api/v1"; empty for GitHub.com
+    #[serde(default)]
+    pub api_url: String,
+    /// GitHub App to authenticate as instead of a personal access token
+    #[serde(default, skip_serializing_if = "GitHubAppConfig::is_empty")]
+    pub app: GitHubAppConfig,
+}
+
+/// GitHub App installation credentials
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GitHubAppConfig {
+    pub app_id: String,
+    /// Path to the app's private key (.pem) downloaded from GitHub
+    pub private_key_file: String,
+    /// Installation of the app on the account or organization repositories live in
+    pub installation_id: String,
+}
+
+impl GitHubAppConfig {
+    /// Whether every field needed to get installation tokens is set
+    pub fn is_configured(&self) -> bool {
+        !self.app_id.is_empty() && !self.private_key_file.is_empty() && !self.installation_id.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.app_id.is_empty() && self.private_key_file.is_empty() && self.installation_id.is_empty()
+    }
+}
+
+/// Kind of Git forge repositories are hosted on
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    /// GitHub.com or GitHub Enterprise Server
+    #[default]
+    GitHub,
+    /// Gitea or Forgejo
+    Gitea,
+}
+
+impl ForgeKind {
+    /// Guess the forge from its API base URL; Gitea and Forgejo serve theirs under /api/v1
+    pub fn detect(api_url: &str) -> Self {
+        if api_url.trim_end_matches('/').ends_with("/api/v1") {
+            ForgeKind::Gitea
+        } else {
+            ForgeKind::GitHub
+        }
+    }
+
+    /// Name shown to users
+    pub fn display_name(self) -> &'static str {
+        match self {
+            ForgeKind::GitHub => "GitHub",
+            ForgeKind::Gitea => "Gitea/Forgejo",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,14 +381,72 @@ pub struct DriveGlobalConfig {
     /// OAuth client ID (for OAuth auth)
     #[serde(default)]
     pub client_id: String,
-    /// OAuth client secret (for OAuth auth)
+    /// OAuth client secret (for OAuth auth); empty for public clients
     #[serde(default)]
     pub client_secret: String,
-    /// Service account file path (for service account auth)
+    /// Service account key file (for service account auth); "-" reads it from standard input,
+    /// and empty falls back to $DITRIVE_SERVICE_ACCOUNT_JSON or $GOOGLE_APPLICATION_CREDENTIALS
     #[serde(default)]
     pub service_account_file: String,
     /// Root folder ID in Google Drive
     pub root_folder_id: String,
+    /// Shared Drive that holds the root folder; empty uses My Drive (for Google Drive)
+    #[serde(default)]
+    pub shared_drive_id: String,
+    /// Application (client) ID registered in Microsoft Entra (for OneDrive)
+    #[serde(default)]
+    pub onedrive_client_id: String,
+    /// Client secret, only needed for confidential client registrations (for OneDrive)
+    #[serde(default)]
+    pub onedrive_client_secret: String,
+    /// Entra tenant ID or "common" (for OneDrive)
+    #[serde(default)]
+    pub onedrive_tenant: String,
+    /// SharePoint document library drive ID; empty uses the user's OneDrive
+    #[serde(default)]
+    pub onedrive_drive_id: String,
+    /// Folder path under the drive root that holds repository folders (for OneDrive)
+    #[serde(default)]
+    pub onedrive_root_path: String,
+    /// Directory that holds repository folders (for local storage)
+    #[serde(default)]
+    pub local_root_path: String,
+    /// Bucket name (for B2 / R2)
+    #[serde(default)]
+    pub bucket_name: String,
+    /// Access key ID; for B2 this is the application key ID (for B2 / R2)
+    #[serde(default)]
+    pub bucket_key_id: String,
+    /// Secret access key; for B2 this is the application key (for B2 / R2)
+    #[serde(default)]
+    pub bucket_secret_key: String,
+    /// Key prefix that holds repository folders inside the bucket (for B2 / R2)
+    #[serde(default)]
+    pub bucket_prefix: String,
+    /// Bucket region such as "us-west-004" (for B2)
+    #[serde(default)]
+    pub b2_region: String,
+    /// Cloudflare account ID (for R2)
+    #[serde(default)]
+    pub r2_account_id: String,
+    /// Repository whose releases hold the files, as "owner/name"; empty uses the origin remote (for GitHub Releases)
+    #[serde(default)]
+    pub releases_repository: String,
+    /// Tag of the release new files are uploaded to; empty means "ditrive-assets" (for GitHub Releases)
+    #[serde(default)]
+    pub releases_tag: String,
+}
+
+impl DriveGlobalConfig {
+    /// Google Drive folder that holds repository folders. A Shared Drive's
+    /// top level has the drive's own ID, so the root folder may be left out.
+    pub fn google_root_folder_id(&self) -> &str {
+        if self.root_folder_id.is_empty() {
+            &self.shared_drive_id
+        } else {
+            &self.root_folder_id
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +454,46 @@ pub struct GlobalSettings {
     pub large_file_threshold_mb: u64,
     pub handle_ignored_large_files: String,
     pub managed_files_marker: String,
+    /// Language for messages such as "id"; empty follows the system locale
+    #[serde(default)]
+    pub language: String,
+    /// Team cache server to pull from before the backend, e.g. "http://10.0.0.5:7878"
+    #[serde(default)]
+    pub team_cache_url: String,
+    /// Secret shared by a team cache server and its clients; the server refuses requests without it
+    #[serde(default)]
+    pub team_cache_token: String,
+    /// Record transfers in ~/.ditrive/journal.jsonl for `ditrive report` (local only)
+    #[serde(default)]
+    pub usage_journal: bool,
+    /// Record uploads, downloads, deletions and sharing in ~/.ditrive/history.log for `ditrive log`
+    #[serde(default = "default_history_log")]
+    pub history_log: bool,
+    /// Encrypt OAuth token files at rest: "off", "machine", or "passphrase"
+    #[serde(default)]
+    pub token_encryption: TokenEncryption,
+    /// Command to scan downloads with before they reach the working tree, e.g. "clamscan --no-summary {}"
+    #[serde(default)]
+    pub scan_command: String,
+    /// Write debug-level JSON logs here on every run, like `--log-file`; empty for none
+    #[serde(default)]
+    pub log_file: String,
+    /// Size at which the log file is rotated, in MB
+    #[serde(default = "default_log_file_max_mb")]
+    pub log_file_max_mb: u64,
+    /// Port `login` listens on for the browser's redirect. Set 0 to pick a free
+    /// one when the OAuth client accepts any localhost port.
+    #[serde(default = "default_oauth_callback_port")]
+    pub oauth_callback_port: u16,
+    /// How long Drive folder IDs and file lookups are reused between runs, in minutes; 0 to always ask Drive
+    #[serde(default = "default_drive_cache_ttl_minutes")]
+    pub drive_cache_ttl_minutes: u64,
+    /// Size limit of ~/.ditrive/cache, the downloads all repositories share, in MB; 0 turns it off
+    #[serde(default = "default_download_cache_max_mb")]
+    pub download_cache_max_mb: u64,
+    /// Key pushes sign tracker entries with: a GPG key ID, or a minisign secret key file; empty uses the tool's default
+    #[serde(default)]
+    pub signing_key: String,
 }
 
 impl Default for GlobalConfig {
@@ -71,6 +503,11 @@ impl Default for GlobalConfig {
                 username: String::new(),
                 token: String::new(),
                 default_visibility: "private".to_string(),
+                default_org: String::new(),
+                default_teams: Vec::new(),
+                forge: ForgeKind::GitHub,
+                api_url: String::new(),
+                app: GitHubAppConfig::default(),
             },
             drive: DriveGlobalConfig {
                 auth_type: DriveAuthType::OAuth,
@@ -78,12 +515,41 @@ impl Default for GlobalConfig {
                 client_secret: String::new(),
                 service_account_file: String::new(),
                 root_folder_id: String::new(),
+                shared_drive_id: String::new(),
+                onedrive_client_id: String::new(),
+                onedrive_client_secret: String::new(),
+                onedrive_tenant: "common".to_string(),
+                onedrive_drive_id: String::new(),
+                onedrive_root_path: "Ditrive".to_string(),
+                local_root_path: String::new(),
+                bucket_name: String::new(),
+                bucket_key_id: String::new(),
+                bucket_secret_key: String::new(),
+                bucket_prefix: "ditrive".to_string(),
+                b2_region: String::new(),
+                r2_account_id: String::new(),
+                releases_repository: String::new(),
+                releases_tag: String::new(),
             },
             settings: GlobalSettings {
                 large_file_threshold_mb: 10,
                 handle_ignored_large_files: "ask".to_string(),
                 managed_files_marker: "# Managed by Git Drive Sync".to_string(),
+                language: String::new(),
+                team_cache_url: String::new(),
+                team_cache_token: String::new(),
+                usage_journal: false,
+                history_log: true,
+                token_encryption: TokenEncryption::Off,
+                scan_command: String::new(),
+                log_file: String::new(),
+                log_file_max_mb: 10,
+                oauth_callback_port: oauth::DEFAULT_CALLBACK_PORT,
+                drive_cache_ttl_minutes: drive_cache::DEFAULT_TTL_MINUTES,
+                download_cache_max_mb: download_cache::DEFAULT_MAX_MB,
+                signing_key: String::new(),
             },
+            network: NetworkConfig::default(),
         }
     }
 }
@@ -96,9 +562,15 @@ impl GlobalConfig {
             .ok_or_else(|| DitriveError::Config("Could not find home directory".to_string()))
     }
 
-    /// Get the global config file path
+    /// Get the global config file path: config.toml if present, else config.json
     pub fn config_path() -> Result<PathBuf> {
-        Ok(Self::config_dir()?.join("config.json"))
+        let config_dir = Self::config_dir()?;
+        let toml_path = config_dir.join("config.toml");
+        if toml_path.exists() {
+            Ok(toml_path)
+        } else {
+            Ok(config_dir.join("config.json"))
+        }
     }
 
     /// Load global configuration from file or create default
@@ -106,9 +578,7 @@ impl GlobalConfig {
         let config_path = Self::config_path()?;
         
         if config_path.exists() {
-            let content = fs::read_to_string(&config_path)?;
-            let config: GlobalConfig = serde_json::from_str(&content)?;
-            Ok(config)
+            read_config(&config_path)
         } else {
             let config = GlobalConfig::default();
             config.save()?;
@@ -126,43 +596,42 @@ impl GlobalConfig {
             fs::create_dir_all(&config_dir)?;
         }
 
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&config_path, content)?;
-        Ok(())
+        write_config(&config_path, self)
     }
 
     /// Check if the configuration is complete
     pub fn is_configured(&self) -> bool {
-        let github_ok = !self.github.token.is_empty();
-        let drive_ok = match self.drive.auth_type {
-            DriveAuthType::OAuth => {
-                !self.drive.client_id.is_empty() 
-                    && !self.drive.client_secret.is_empty()
-                    && !self.drive.root_folder_id.is_empty()
-            }
-            DriveAuthType::ServiceAccount => {
-                !self.drive.service_account_file.is_empty()
-                    && !self.drive.root_folder_id.is_empty()
-            }
-        };
-        github_ok && drive_ok
+        let github_ok = !self.github.token.is_empty() || self.github.app.is_configured();
+        github_ok && self.is_drive_configured()
     }
 
     /// Check if Drive is configured (without GitHub)
     pub fn is_drive_configured(&self) -> bool {
         match self.drive.auth_type {
+            // Public clients have no secret; sign-in uses PKCE either way
             DriveAuthType::OAuth => {
-                !self.drive.client_id.is_empty() 
-                    && !self.drive.client_secret.is_empty()
-                    && !self.drive.root_folder_id.is_empty()
+                !self.drive.client_id.is_empty() && !self.drive.google_root_folder_id().is_empty()
             }
             DriveAuthType::ServiceAccount => {
-                !self.drive.service_account_file.is_empty()
-                    && !self.drive.root_folder_id.is_empty()
+                (!self.drive.service_account_file.is_empty() || drive::service_account_env().is_some())
+                    && !self.drive.google_root_folder_id().is_empty()
             }
+            DriveAuthType::OneDrive => !self.drive.onedrive_client_id.is_empty(),
+            DriveAuthType::Local => !self.drive.local_root_path.is_empty(),
+            DriveAuthType::B2 => self.is_bucket_configured() && !self.drive.b2_region.is_empty(),
+            DriveAuthType::R2 => self.is_bucket_configured() && !self.drive.r2_account_id.is_empty(),
+            // Downloads need no credentials and the repository defaults to the origin remote
+            DriveAuthType::Releases => true,
         }
     }
 
+    /// Check if the bucket name and keys shared by B2 and R2 are set
+    fn is_bucket_configured(&self) -> bool {
+        !self.drive.bucket_name.is_empty()
+            && !self.drive.bucket_key_id.is_empty()
+            && !self.drive.bucket_secret_key.is_empty()
+    }
+
     /// Update a specific field and save
     pub fn update<F>(&mut self, updater: F) -> Result<()>
     where
@@ -186,7 +655,6 @@ pub struct GitHubRepoConfig {
     pub repository_url: String,
     pub branch: String,
     pub username: String,
-    pub token: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,10 +670,163 @@ pub struct RepoSettings {
     pub additional_ignore_patterns: Vec<String>,
     pub handle_ignored_large_files: String,
     pub managed_files_marker: String,
+    /// Per-extension MIME type overrides used on upload (e.g. "glb" -> "model/gltf-binary")
+    #[serde(default)]
+    pub mime_overrides: HashMap<String, String>,
+    /// Extract preview metadata (dimensions, duration, polycount) on upload
+    #[serde(default)]
+    pub extract_preview_metadata: bool,
+    /// Generate thumbnails for image and video assets on push
+    #[serde(default)]
+    pub thumbnails: ThumbnailMode,
+    /// Suffix uploaded file names with a short content hash to avoid collisions
+    #[serde(default)]
+    pub hash_suffix_names: bool,
+    /// Mirror the repository's folders in storage, or store flat content-addressed objects that identical files share
+    #[serde(default)]
+    pub storage_layout: StorageLayout,
+    /// Commit pointer files through a clean/smudge filter instead of ignoring managed files
+    #[serde(default)]
+    pub pointer_files: bool,
+    /// Storage backend for this repository, overriding the global choice (set by `migrate-backend`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_backend: Option<DriveAuthType>,
+    /// Rules such as "scratch/** expires 30d" for `gc` to prune old uploads by
+    #[serde(default)]
+    pub expire_rules: Vec<String>,
+    /// Fetch and fast-forward the current branch from origin before every sync
+    #[serde(default)]
+    pub pull_before_sync: bool,
+    /// Push the current branch to origin after every sync
+    #[serde(default)]
+    pub push_after_sync: bool,
+    /// Download content uploaded from other clones on every sync, not just missing files
+    #[serde(default)]
+    pub two_way_sync: bool,
+    /// Patterns such as "*.psd" managed whatever their size (see `ditrive track`)
+    #[serde(default)]
+    pub tracked_patterns: Vec<String>,
+    /// Thresholds for files under a path or matching a pattern, overriding `large_file_threshold_mb`
+    #[serde(default)]
+    pub threshold_rules: Vec<ThresholdRule>,
+    /// What sync and `ditrive rm` do with the stored copy of a deleted managed file
+    #[serde(default)]
+    pub deletion_policy: DeletionPolicy,
+    /// Days `ditrive trash empty` keeps trashed copies before deleting them.
+    /// Google Drive deletes trashed files after 30 days regardless.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// Auth profile whose tokens this repository signs in with (see `ditrive login --profile`)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
+    /// Whether scans skip, follow, or treat symbolic links as files; links out of the repository are always skipped
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    /// Sign tracker entries on push, and refuse to pull content without a valid signature
+    #[serde(default)]
+    pub signing: SigningTool,
+    /// Minisign public key, or fingerprint of the GPG key, that pulls require signatures from
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signing_public_key: String,
+    /// Commit the tracker and .gitignore changes a sync or push makes
+    #[serde(default)]
+    pub auto_commit: bool,
+    /// Message for those commits; "{summary}" becomes e.g. "manage assets/big.bin"
+    #[serde(default = "default_auto_commit_message")]
+    pub auto_commit_message: String,
+}
+
+/// A size threshold for some of the repository's files.
+///
+/// `{"path": "assets/**", "threshold_mb": 1}` matches from the repository
+/// root, `{"pattern": "*.mp4", "threshold_mb": 0}` matches file names at any
+/// depth. A rule giving both must match both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    pub threshold_mb: u64,
+}
+
+impl ThresholdRule {
+    /// Check a repository-relative path against the rule
+    pub fn matches(&self, rel_path: &str) -> bool {
+        let path_matches = self
+            .path
+            .as_ref()
+            .is_none_or(|path| git::pattern_matches(&format!("/{}", path.trim_start_matches('/')), rel_path));
+        let pattern_matches = self
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| git::pattern_matches(pattern, rel_path));
+        path_matches && pattern_matches
+    }
+}
+
+/// What makes a file large enough to manage, resolved from the repository settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LargeFileRules {
+    pub threshold_bytes: u64,
+    pub threshold_rules: Vec<ThresholdRule>,
+    pub tracked_patterns: Vec<String>,
+}
+
+impl LargeFileRules {
+    /// Threshold for a repository-relative path; when several rules match, the last one wins
+    pub fn threshold_for(&self, rel_path: &str) -> u64 {
+        self.threshold_rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(rel_path))
+            .map_or(self.threshold_bytes, |rule| rule.threshold_mb * 1024 * 1024)
+    }
+
+    /// Check if a path matches a pattern managed regardless of size
+    pub fn is_tracked(&self, rel_path: &str) -> bool {
+        self.tracked_patterns
+            .iter()
+            .any(|pattern| git::pattern_matches(pattern, rel_path))
+    }
+
+    /// Check if a file of this size at this path should be managed
+    pub fn is_large(&self, rel_path: &str, size: u64) -> bool {
+        self.is_tracked(rel_path) || size > self.threshold_for(rel_path)
+    }
+}
+
+fn default_trash_retention_days() -> u32 {
+    trash::DEFAULT_RETENTION_DAYS
+}
+
+fn default_auto_commit_message() -> String {
+    "ditrive: {summary}".to_string()
+}
+
+fn default_history_log() -> bool {
+    true
+}
+
+fn default_log_file_max_mb() -> u64 {
+    10
+}
+
+fn default_oauth_callback_port() -> u16 {
+    oauth::DEFAULT_CALLBACK_PORT
+}
+
+fn default_drive_cache_ttl_minutes() -> u64 {
+    drive_cache::DEFAULT_TTL_MINUTES
+}
+
+fn default_download_cache_max_mb() -> u64 {
+    download_cache::DEFAULT_MAX_MB
 }
 
 impl RepoConfig {
     const CONFIG_FILENAME: &'static str = ".woilah-config.json";
+    const TOML_CONFIG_FILENAME: &'static str = ".ditrive.toml";
 
     /// Create a new repo config with defaults from global config
     pub fn new_with_global(global: &GlobalConfig) -> Self {
@@ -214,7 +835,6 @@ impl RepoConfig {
                 repository_url: String::new(),
                 branch: "main".to_string(),
                 username: global.github.username.clone(),
-                token: global.github.token.clone(),
             },
             drive: DriveRepoConfig {
                 service_account_file: global.drive.service_account_file.clone(),
@@ -226,13 +846,44 @@ impl RepoConfig {
                 additional_ignore_patterns: vec!["*.tmp".to_string(), "*.log".to_string()],
                 handle_ignored_large_files: global.settings.handle_ignored_large_files.clone(),
                 managed_files_marker: global.settings.managed_files_marker.clone(),
+                mime_overrides: HashMap::new(),
+                extract_preview_metadata: false,
+                thumbnails: ThumbnailMode::Off,
+                hash_suffix_names: false,
+                storage_layout: StorageLayout::Mirror,
+                pointer_files: false,
+                storage_backend: None,
+                expire_rules: Vec::new(),
+                pull_before_sync: false,
+                push_after_sync: false,
+                two_way_sync: false,
+                tracked_patterns: Vec::new(),
+                threshold_rules: Vec::new(),
+                deletion_policy: DeletionPolicy::Keep,
+                trash_retention_days: trash::DEFAULT_RETENTION_DAYS,
+                profile: String::new(),
+                symlinks: SymlinkPolicy::Skip,
+                signing: SigningTool::Off,
+                signing_public_key: String::new(),
+                auto_commit: false,
+                auto_commit_message: default_auto_commit_message(),
             },
         }
     }
 
-    /// Get the config file path for a repository
+    /// Get the config file path for a repository: .ditrive.toml if present, else .woilah-config.json
     pub fn config_path(repo_path: &Path) -> PathBuf {
-        repo_path.join(Self::CONFIG_FILENAME)
+        let toml_path = repo_path.join(Self::TOML_CONFIG_FILENAME);
+        if toml_path.exists() {
+            toml_path
+        } else {
+            repo_path.join(Self::CONFIG_FILENAME)
+        }
+    }
+
+    /// Check if a file name is one of the repository config files
+    pub fn is_config_file(filename: &str) -> bool {
+        filename == Self::CONFIG_FILENAME || filename == Self::TOML_CONFIG_FILENAME
     }
 
     /// Load repository configuration from file or create default
@@ -240,9 +891,7 @@ impl RepoConfig {
         let config_path = Self::config_path(repo_path);
         
         if config_path.exists() {
-            let content = fs::read_to_string(&config_path)?;
-            let config: RepoConfig = serde_json::from_str(&content)?;
-            Ok(config)
+            read_config(&config_path)
         } else {
             let global = GlobalConfig::load()?;
             let config = RepoConfig::new_with_global(&global);
@@ -253,10 +902,7 @@ impl RepoConfig {
 
     /// Save repository configuration to file
     pub fn save(&self, repo_path: &Path) -> Result<()> {
-        let config_path = Self::config_path(repo_path);
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&config_path, content)?;
-        Ok(())
+        write_config(&Self::config_path(repo_path), self)
     }
 
     /// Update a specific field and save
@@ -272,12 +918,20 @@ impl RepoConfig {
     pub fn large_file_threshold_bytes(&self) -> u64 {
         self.settings.large_file_threshold_mb * 1024 * 1024
     }
+
+    /// Threshold rules and tracked patterns, ready to check files against
+    pub fn large_file_rules(&self) -> LargeFileRules {
+        LargeFileRules {
+            threshold_bytes: self.large_file_threshold_bytes(),
+            threshold_rules: self.settings.threshold_rules.clone(),
+            tracked_patterns: self.settings.tracked_patterns.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempdir;
 
     #[test]
     fn test_global_config_default() {
@@ -286,6 +940,31 @@ mod tests {
         assert!(!config.is_configured());
     }
 
+    #[test]
+    fn test_shared_drive_stands_in_for_root_folder() {
+        let mut config = GlobalConfig::default();
+        config.drive.client_id = "client".to_string();
+        config.drive.client_secret = "secret".to_string();
+        assert!(!config.is_drive_configured());
+
+        config.drive.shared_drive_id = "0AExampleDrive".to_string();
+        assert!(config.is_drive_configured());
+        assert_eq!(config.drive.google_root_folder_id(), "0AExampleDrive");
+
+        config.drive.root_folder_id = "folder".to_string();
+        assert_eq!(config.drive.google_root_folder_id(), "folder");
+    }
+
+    #[test]
+    fn test_public_oauth_client_needs_no_secret() {
+        let mut config = GlobalConfig::default();
+        config.github.token = "token".to_string();
+        config.drive.client_id = "client".to_string();
+        config.drive.root_folder_id = "folder".to_string();
+        assert!(config.is_drive_configured());
+        assert!(config.is_configured());
+    }
+
     #[test]
     fn test_repo_config_inherits_global() {
         let mut global = GlobalConfig::default();
@@ -294,6 +973,89 @@ mod tests {
         
         let repo = RepoConfig::new_with_global(&global);
         assert_eq!(repo.github.username, "testuser");
-        assert_eq!(repo.github.token, "testtoken");
+        // The repo config gets committed, so the token stays in the global config
+        assert!(!serde_json::to_string(&repo).unwrap().contains("testtoken"));
+    }
+
+    #[test]
+    fn test_large_file_rules() {
+        let mut config = RepoConfig::new_with_global(&GlobalConfig::default());
+        config.settings.threshold_rules = serde_json::from_str(
+            r#"[{"path": "assets/**", "threshold_mb": 1}, {"pattern": "*.mp4", "threshold_mb": 0}]"#,
+        )
+        .unwrap();
+        config.settings.tracked_patterns = vec!["*.psd".to_string()];
+        let rules = config.large_file_rules();
+        let mb = 1024 * 1024;
+
+        assert_eq!(rules.threshold_for("docs/manual.pdf"), 10 * mb);
+        assert_eq!(rules.threshold_for("assets/deep/level.bin"), mb);
+        assert_eq!(rules.threshold_for("other/assets/level.bin"), 10 * mb);
+        // The later rule wins where both match
+        assert_eq!(rules.threshold_for("assets/intro.mp4"), 0);
+        assert!(rules.is_large("intro.mp4", 1));
+        assert!(!rules.is_large("assets/level.bin", mb));
+        assert!(rules.is_large("art/hero.psd", 0));
+    }
+
+    #[test]
+    fn test_dotted_keys() {
+        let config = GlobalConfig::default();
+        assert_eq!(get_key(&config, "settings.large_file_threshold_mb").unwrap(), 10);
+        assert!(get_key(&config, "settings.no_such_key").is_err());
+
+        let config = set_key(&config, "drive.root_folder_id", "XYZ").unwrap();
+        let config = set_key(&config, "settings.large_file_threshold_mb", "50").unwrap();
+        let config = set_key(&config, "settings.usage_journal", "true").unwrap();
+        let config = set_key(&config, "github.default_teams", "art, audio").unwrap();
+        let config = set_key(&config, "drive.auth_type", "local").unwrap();
+        assert_eq!(config.drive.root_folder_id, "XYZ");
+        assert_eq!(config.settings.large_file_threshold_mb, 50);
+        assert!(config.settings.usage_journal);
+        assert_eq!(config.github.default_teams, ["art", "audio"]);
+        assert_eq!(config.drive.auth_type, DriveAuthType::Local);
+
+        assert!(set_key(&config, "settings.large_file_threshold_mb", "big").is_err());
+        assert!(set_key(&config, "drive.auth_type", "carrier-pigeon").is_err());
+        assert!(set_key(&config, "drive.no_such_key", "1").is_err());
+
+        let config = unset_key(&config, &GlobalConfig::default(), "settings.large_file_threshold_mb").unwrap();
+        assert_eq!(config.settings.large_file_threshold_mb, 10);
+        assert_eq!(config.drive.root_folder_id, "XYZ");
+    }
+
+    #[test]
+    fn test_dotted_keys_reach_maps_and_options() {
+        let config = RepoConfig::new_with_global(&GlobalConfig::default());
+        let config = set_key(&config, "settings.mime_overrides.psd", "image/vnd.adobe.photoshop").unwrap();
+        let config = set_key(&config, "settings.storage_backend", "b2").unwrap();
+        assert_eq!(config.settings.mime_overrides["psd"], "image/vnd.adobe.photoshop");
+        assert_eq!(config.settings.storage_backend, Some(DriveAuthType::B2));
+
+        let defaults = RepoConfig::new_with_global(&GlobalConfig::default());
+        let config = unset_key(&config, &defaults, "settings.mime_overrides.psd").unwrap();
+        let config = unset_key(&config, &defaults, "settings.storage_backend").unwrap();
+        assert!(config.settings.mime_overrides.is_empty());
+        assert_eq!(config.settings.storage_backend, None);
+    }
+
+    #[test]
+    fn test_repo_config_prefers_toml_and_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = RepoConfig::new_with_global(&GlobalConfig::default());
+        config.settings.large_file_threshold_mb = 42;
+        config.settings.storage_backend = Some(DriveAuthType::B2);
+
+        config.save(dir.path()).unwrap();
+        assert!(RepoConfig::config_path(dir.path()).ends_with(".woilah-config.json"));
+
+        let toml_path = dir.path().join(".ditrive.toml");
+        fs::write(&toml_path, ConfigFormat::Toml.render(&config).unwrap()).unwrap();
+        assert_eq!(RepoConfig::config_path(dir.path()), toml_path);
+
+        let loaded = RepoConfig::load(dir.path()).unwrap();
+        assert_eq!(loaded.settings.large_file_threshold_mb, 42);
+        assert_eq!(loaded.settings.storage_backend, Some(DriveAuthType::B2));
+        assert_eq!(ConfigFormat::detect("{\n  \"github\": {}\n}"), ConfigFormat::Json);
     }
 }
@@ -0,0 +1,149 @@
+//! Detecting managed files changed both locally and remotely
+//!
+//! Before a modified file is re-uploaded, sync compares three hashes: the one
+//! in the tracker, the local file's, and the backend's checksum. A local copy
+//! matching an older version in the tracker's history was never edited, it
+//! just wasn't downloaded after a teammate's update. A backend checksum that
+//! differs from the tracker means a teammate uploaded since this tracker was
+//! written. Either way, uploading would silently overwrite their work.
+
+use std::path::{Path, PathBuf};
+
+use crate::drive::FileMetadata;
+
+/// Characters of the local hash put in a kept-both file's name
+const NAME_HASH_LEN: usize = 8;
+
+/// Why a modified file can't simply be uploaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// The local copy is an older version the tracker has moved past
+    StaleLocal,
+    /// The backend holds content the tracker doesn't know about
+    RemoteChanged { remote_hash: String },
+}
+
+impl Conflict {
+    /// Hash of the content "take remote" would download
+    pub fn remote_hash<'a>(&'a self, metadata: &'a FileMetadata) -> &'a str {
+        match self {
+            Conflict::StaleLocal => &metadata.hash,
+            Conflict::RemoteChanged { remote_hash } => remote_hash,
+        }
+    }
+
+    /// One-line explanation for the conflict prompt
+    pub fn describe(&self, rel_path: &str) -> String {
+        match self {
+            Conflict::StaleLocal => format!(
+                "{} was updated by someone else, and your copy is the version before their change",
+                rel_path
+            ),
+            Conflict::RemoteChanged { .. } => format!(
+                "{} was changed here and also re-uploaded by someone else since your last pull",
+                rel_path
+            ),
+        }
+    }
+}
+
+/// How to settle a conflict
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    KeepLocal,
+    TakeRemote,
+    /// Move the local copy aside under a new name, then take the remote one
+    KeepBoth,
+}
+
+impl Resolution {
+    /// Choices offered in the prompt, in `from_choice` order
+    pub const CHOICES: [&'static str; 3] = [
+        "Keep mine (upload it over theirs)",
+        "Take theirs (replace my copy)",
+        "Keep both (save mine under a new name, then take theirs)",
+    ];
+
+    /// Index into `CHOICES` of the default, which never loses content
+    pub const DEFAULT_CHOICE: usize = 2;
+
+    pub fn from_choice(choice: usize) -> Self {
+        match choice {
+            0 => Resolution::KeepLocal,
+            1 => Resolution::TakeRemote,
+            _ => Resolution::KeepBoth,
+        }
+    }
+}
+
+/// Check a modified file for a conflict before re-uploading it.
+///
+/// `remote_hash` is None when the backend can't report a checksum, in which
+/// case only stale local copies are detected.
+pub fn detect(metadata: &FileMetadata, local_hash: &str, remote_hash: Option<&str>) -> Option<Conflict> {
+    if let Some(remote_hash) = remote_hash.filter(|h| *h != metadata.hash && *h != local_hash) {
+        return Some(Conflict::RemoteChanged {
+            remote_hash: remote_hash.to_string(),
+        });
+    }
+    metadata
+        .versions
+        .iter()
+        .any(|version| version.hash == local_hash)
+        .then_some(Conflict::StaleLocal)
+}
+
+/// Where "keep both" moves the local copy: `model.conflict-1a2b3c4d.bin`
+pub fn kept_copy_path(path: &Path, local_hash: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let short_hash = local_hash.get(..NAME_HASH_LEN).unwrap_or(local_hash);
+    let name = match path.extension() {
+        Some(ext) => format!("{}.conflict-{}.{}", stem, short_hash, ext.to_string_lossy()),
+        None => format!("{}.conflict-{}", stem, short_hash),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drive::FileVersion;
+
+    #[test]
+    fn test_detect() {
+        let metadata = FileMetadata {
+            id: "id-1".to_string(),
+            hash: "theirs".to_string(),
+            versions: vec![FileVersion {
+                revision_id: "r1".to_string(),
+                hash: "base".to_string(),
+                size: 1,
+                uploaded_at: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // An ordinary edit on top of what the tracker knows
+        assert_eq!(detect(&metadata, "mine", Some("theirs")), None);
+        assert_eq!(detect(&metadata, "mine", None), None);
+        // Never re-downloaded after a teammate's update
+        assert_eq!(detect(&metadata, "base", Some("theirs")), Some(Conflict::StaleLocal));
+        // Someone uploaded after this tracker was written
+        assert_eq!(
+            detect(&metadata, "mine", Some("newer")),
+            Some(Conflict::RemoteChanged { remote_hash: "newer".to_string() })
+        );
+        // The remote already has exactly this content
+        assert_eq!(detect(&metadata, "mine", Some("mine")), None);
+    }
+
+    #[test]
+    fn test_kept_copy_path() {
+        assert_eq!(
+            kept_copy_path(Path::new("/repo/art/hero.psd"), "1a2b3c4d5e6f"),
+            Path::new("/repo/art/hero.conflict-1a2b3c4d.psd")
+        );
+        assert_eq!(kept_copy_path(Path::new("/repo/blob"), "abc"), Path::new("/repo/blob.conflict-abc"));
+    }
+}
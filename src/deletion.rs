@@ -0,0 +1,62 @@
+//! Propagating local deletions of managed files
+//!
+//! A managed file missing from the working tree usually just hasn't been
+//! downloaded yet. To tell a deletion apart, sync records which managed files
+//! it left in place in `.git/ditrive/synced.json`: one recorded there but gone
+//! now was deleted in this clone. The repository's `deletion_policy` decides
+//! what happens to the stored copy, and the file stops being managed, so the
+//! deletion reaches other clones once the trackers are committed. This only
+//! happens with `sync --prune`; a plain sync downloads the file again, since
+//! a `git clean -fdX` looks no different from a deliberate deletion.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::error::Result;
+
+/// Record location, relative to the repository root
+const SYNCED_PATH: &str = ".git/ditrive/synced.json";
+
+/// Repo-relative paths of the managed files present after the last sync
+pub fn load_synced(repo_path: &Path) -> BTreeSet<String> {
+    fs::read_to_string(repo_path.join(SYNCED_PATH))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Record the managed files present after a sync.
+///
+/// Outside a git repository nothing is recorded, so no deletions are detected.
+pub fn save_synced(repo_path: &Path, files: &BTreeSet<String>) -> Result<()> {
+    if !repo_path.join(".git").is_dir() {
+        return Ok(());
+    }
+
+    let synced_path = repo_path.join(SYNCED_PATH);
+    if let Some(parent) = synced_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&synced_path, serde_json::to_string(files)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_synced_files_round_trip() {
+        let dir = tempdir().unwrap();
+        let files: BTreeSet<String> = ["assets/hero.psd", "level.bin"].into_iter().map(String::from).collect();
+
+        save_synced(dir.path(), &files).unwrap();
+        assert!(load_synced(dir.path()).is_empty());
+
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        save_synced(dir.path(), &files).unwrap();
+        assert_eq!(load_synced(dir.path()), files);
+    }
+}
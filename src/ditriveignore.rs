@@ -0,0 +1,96 @@
+//! Paths left out of large-file scans, from `.ditriveignore`
+//!
+//! `.ditriveignore` at the repository root takes the same patterns as
+//! `.gitignore`: negation, anchoring, character classes, `**`, and
+//! directory-only patterns such as `target/` or `node_modules/`. Files it
+//! matches are never picked up as large files, and folders it matches aren't
+//! walked at all, so build output and dependency caches cost nothing to skip.
+//! It has no effect on git, and files already managed stay managed.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::paths;
+
+/// Name of the ignore file, at the repository root
+pub const DITRIVEIGNORE_FILENAME: &str = ".ditriveignore";
+
+/// The compiled patterns of a repository's `.ditriveignore`
+#[derive(Debug, Clone, Default)]
+pub struct DitriveIgnore {
+    repo_path: PathBuf,
+    /// None when there is no `.ditriveignore`
+    matcher: Option<Gitignore>,
+}
+
+impl DitriveIgnore {
+    /// Read the repository's `.ditriveignore`, skipping invalid lines
+    pub fn load(repo_path: &Path) -> Self {
+        let path = repo_path.join(DITRIVEIGNORE_FILENAME);
+        let matcher = path.is_file().then(|| {
+            let mut builder = GitignoreBuilder::new(repo_path);
+            if let Some(e) = builder.add(&path) {
+                warn!("Invalid pattern in {:?}: {}", path, e);
+            }
+            builder
+                .build()
+                .map_err(|e| warn!("Failed to read ignore rules from {:?}: {}", path, e))
+                .ok()
+        });
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            matcher: matcher.flatten(),
+        }
+    }
+
+    /// Check whether a file or folder, or a folder it is in, is left out of scans
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(ref matcher) = self.matcher else {
+            return false;
+        };
+        match paths::relative(&self.repo_path, path) {
+            Some(rel_path) if !rel_path.is_empty() => matcher
+                .matched_path_or_any_parents(Path::new(&rel_path), is_dir)
+                .is_ignore(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::write(
+            repo.join(DITRIVEIGNORE_FILENAME),
+            "target/\nnode_modules/\n/build\n**/cache/*.bin\n*.iso\n!keep.iso\n",
+        )
+        .unwrap();
+        fs::create_dir_all(repo.join("target/debug")).unwrap();
+        let ignore = DitriveIgnore::load(repo);
+
+        assert!(ignore.is_excluded(&repo.join("target"), true));
+        assert!(ignore.is_excluded(&repo.join("target/debug/game"), false));
+        assert!(ignore.is_excluded(&repo.join("web/node_modules/pkg/big.wasm"), false));
+        assert!(ignore.is_excluded(&repo.join("build/out.pak"), false));
+        assert!(!ignore.is_excluded(&repo.join("art/build/out.pak"), false));
+        assert!(ignore.is_excluded(&repo.join("a/b/cache/blob.bin"), false));
+        assert!(ignore.is_excluded(&repo.join("disc.iso"), false));
+        assert!(!ignore.is_excluded(&repo.join("keep.iso"), false));
+        assert!(!ignore.is_excluded(&repo.join("art/hero.psd"), false));
+    }
+
+    #[test]
+    fn test_missing_file_excludes_nothing() {
+        let dir = tempdir().unwrap();
+        let ignore = DitriveIgnore::load(dir.path());
+        assert!(!ignore.is_excluded(&dir.path().join("target/game"), false));
+    }
+}
@@ -0,0 +1,352 @@
+//! Downloaded content shared by every repository on this machine
+//!
+//! Each download is also kept in `~/.ditrive/cache`, named by its SHA-256, and
+//! consulted before the team cache or the backend. A second checkout of a
+//! repository, or another repository with the same assets, then copies the
+//! bytes from disk instead of downloading them again. Content is checked
+//! against its hash on the way in and on the way out, so a damaged entry is
+//! dropped rather than served. Once the cache grows past
+//! `download_cache_max_mb`, the least recently used files are evicted; files
+//! bigger than the whole cache are never kept.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::drive::calculate_file_hash;
+use crate::error::Result;
+
+/// Cache directory name in the global config directory
+pub const DOWNLOAD_CACHE_DIR: &str = "cache";
+
+/// Size limit unless the global settings say otherwise
+pub const DEFAULT_MAX_MB: u64 = 10 * 1024;
+
+/// Extension of content still being copied in
+const PARTIAL_EXTENSION: &str = "partial";
+
+/// Age after which a partial copy is taken to be left over from a run that died
+const STALE_PARTIAL_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Number of files and bytes, in the cache or removed from it
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl CacheStats {
+    fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+    }
+}
+
+/// A cached file, for eviction
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Content-addressed store of downloads
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Bytes in the cache, counted on the first store and kept up to date
+    /// after, so storing doesn't walk the whole cache each time
+    total: Arc<Mutex<Option<u64>>>,
+}
+
+impl DownloadCache {
+    pub fn new(dir: PathBuf, max_mb: u64) -> Self {
+        Self {
+            dir,
+            max_bytes: max_mb * 1024 * 1024,
+            total: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Directory the cache lives in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Size the cache is evicted down to, in bytes
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Where content with this hash is kept: `<dir>/<first two characters>/<hash>`.
+    /// None for anything but a SHA-256 in hex, so a tracker can't point outside the cache.
+    pub fn path_for(&self, hash: &str) -> Option<PathBuf> {
+        let is_sha256 = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        is_sha256.then(|| self.dir.join(&hash[..2]).join(hash))
+    }
+
+    /// Copy cached content to `destination`, returning whether it was there
+    pub fn fetch(&self, hash: &str, destination: &Path) -> Result<bool> {
+        let Some(path) = self.path_for(hash).filter(|path| path.is_file()) else {
+            return Ok(false);
+        };
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&path, destination)?;
+        if calculate_file_hash(destination)? != hash {
+            warn!("Cached content {} is damaged; dropping it", hash);
+            let _ = fs::remove_file(destination);
+            let _ = fs::remove_file(&path);
+            return Ok(false);
+        }
+
+        touch(&path);
+        debug!("Copied {} from the download cache", hash);
+        Ok(true)
+    }
+
+    /// Path of cached content to read in place, after checking it against its hash
+    #[cfg(feature = "mount")]
+    pub fn lookup(&self, hash: &str) -> Result<Option<PathBuf>> {
+        let Some(path) = self.path_for(hash).filter(|path| path.is_file()) else {
+            return Ok(None);
+        };
+        if calculate_file_hash(&path)? != hash {
+            warn!("Cached content {} is damaged; dropping it", hash);
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+
+        touch(&path);
+        Ok(Some(path))
+    }
+
+    /// Keep a copy of downloaded content, then evict down to the size limit.
+    /// Content that doesn't match `hash`, or that wouldn't fit, is never cached.
+    pub fn store(&self, hash: &str, source: &Path) -> Result<()> {
+        let Some(path) = self.path_for(hash) else {
+            return Ok(());
+        };
+        if path.is_file() {
+            touch(&path);
+            return Ok(());
+        }
+        let size = fs::metadata(source)?.len();
+        if size > self.max_bytes {
+            debug!("Not caching {:?}: it is bigger than the whole cache", source);
+            return Ok(());
+        }
+        if calculate_file_hash(source)? != hash {
+            debug!("Not caching {:?}: it doesn't match {}", source, hash);
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Copy next to the entry and rename, so other runs never see a partial file
+        let partial = path.with_extension(format!("{}-{}", PARTIAL_EXTENSION, std::process::id()));
+        fs::copy(source, &partial)?;
+        fs::rename(&partial, &path)?;
+        debug!("Cached {}", hash);
+
+        let over_limit = {
+            let mut total = self.total.lock().unwrap_or_else(|e| e.into_inner());
+            let bytes = match *total {
+                Some(bytes) => bytes + size,
+                None => self.stats()?.bytes,
+            };
+            *total = Some(bytes);
+            bytes > self.max_bytes
+        };
+        if over_limit {
+            let evicted = self.evict()?;
+            if evicted.files > 0 {
+                debug!("Evicted {} files ({} bytes) from the download cache", evicted.files, evicted.bytes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything in the cache, least recently used first. Partial copies
+    /// left behind by runs that died are removed on the way.
+    fn entries(&self) -> Result<Vec<CachedFile>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in WalkDir::new(&self.dir).min_depth(2).max_depth(2) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if entry.path().extension().is_some() {
+                remove_if_stale_partial(entry.path());
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            files.push(CachedFile {
+                path: entry.into_path(),
+                size: metadata.len(),
+                last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+        files.sort_by_key(|file| file.last_used);
+        Ok(files)
+    }
+
+    /// Files and bytes in the cache
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for file in self.entries()? {
+            stats.add(file.size);
+        }
+        Ok(stats)
+    }
+
+    /// Remove the least recently used files until the cache fits its limit
+    pub fn evict(&self) -> Result<CacheStats> {
+        let files = self.entries()?;
+        let mut total: u64 = files.iter().map(|file| file.size).sum();
+        let mut evicted = CacheStats::default();
+        for file in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&file.path)?;
+            total -= file.size;
+            evicted.add(file.size);
+        }
+        *self.total.lock().unwrap_or_else(|e| e.into_inner()) = Some(total);
+        Ok(evicted)
+    }
+
+    /// Remove everything from the cache
+    pub fn clean(&self) -> Result<CacheStats> {
+        let removed = self.stats()?;
+        if self.dir.is_dir() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        *self.total.lock().unwrap_or_else(|e| e.into_inner()) = Some(0);
+        Ok(removed)
+    }
+}
+
+/// Remove a partial copy that has sat untouched long enough that no run is
+/// still writing it
+fn remove_if_stale_partial(path: &Path) {
+    let is_partial = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.starts_with(PARTIAL_EXTENSION));
+    let is_stale = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_PARTIAL_AGE);
+    if is_partial && is_stale {
+        match fs::remove_file(path) {
+            Ok(()) => debug!("Removed leftover partial copy {:?}", path),
+            Err(e) => debug!("Failed to remove leftover partial copy {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Mark a cached file as just used. Modification times double as use times,
+/// since access times are often not kept.
+fn touch(path: &Path) {
+    let result = fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = result {
+        debug!("Failed to mark {:?} as used: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_store_and_fetch() {
+        let dir = tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("cache"), DEFAULT_MAX_MB);
+        let source = dir.path().join("level.bin");
+        fs::write(&source, b"level data").unwrap();
+        let hash = calculate_file_hash(&source).unwrap();
+
+        let restored = dir.path().join("checkout/level.bin");
+        assert!(!cache.fetch(&hash, &restored).unwrap());
+
+        cache.store(&hash, &source).unwrap();
+        assert!(cache.fetch(&hash, &restored).unwrap());
+        assert_eq!(fs::read(&restored).unwrap(), b"level data");
+        assert_eq!(cache.stats().unwrap(), CacheStats { files: 1, bytes: 10 });
+
+        // Content that doesn't match its hash, and hashes that aren't hashes, stay out
+        cache.store(&"0".repeat(64), &source).unwrap();
+        cache.store("../../escape", &source).unwrap();
+        assert_eq!(cache.stats().unwrap().files, 1);
+
+        assert_eq!(cache.clean().unwrap(), CacheStats { files: 1, bytes: 10 });
+        assert!(!cache.fetch(&hash, &restored).unwrap());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("cache"), DEFAULT_MAX_MB);
+        let mut hashes = Vec::new();
+        for (index, seed) in [b'a', b'b', b'c'].into_iter().enumerate() {
+            let source = dir.path().join(format!("{}.bin", index));
+            fs::write(&source, vec![seed; 400 * 1024]).unwrap();
+            let hash = calculate_file_hash(&source).unwrap();
+            cache.store(&hash, &source).unwrap();
+            // Give each file a distinct use time, oldest first
+            let used = SystemTime::now() - Duration::from_secs(100 - index as u64 * 10);
+            let file = fs::File::options().write(true).open(cache.path_for(&hash).unwrap()).unwrap();
+            file.set_modified(used).unwrap();
+            hashes.push(hash);
+        }
+
+        // Using the first file again makes the second the one to go
+        assert!(cache.fetch(&hashes[0], &dir.path().join("restored.bin")).unwrap());
+        let evicted = DownloadCache::new(dir.path().join("cache"), 1).evict().unwrap();
+        assert_eq!(evicted.files, 1);
+        assert!(cache.path_for(&hashes[0]).unwrap().is_file());
+        assert!(!cache.path_for(&hashes[1]).unwrap().is_file());
+        assert!(cache.path_for(&hashes[2]).unwrap().is_file());
+    }
+
+    #[test]
+    fn test_skips_oversized_files_and_clears_stale_partials() {
+        let dir = tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("cache"), 1);
+        let source = dir.path().join("big.bin");
+        fs::write(&source, vec![b'a'; 2 * 1024 * 1024]).unwrap();
+        let hash = calculate_file_hash(&source).unwrap();
+        cache.store(&hash, &source).unwrap();
+        assert!(!cache.path_for(&hash).unwrap().is_file());
+
+        let shard = dir.path().join("cache/ab");
+        fs::create_dir_all(&shard).unwrap();
+        let stale = shard.join(format!("{}.partial-1", "ab".repeat(32)));
+        let fresh = shard.join(format!("{}.partial-2", "ab".repeat(32)));
+        fs::write(&stale, b"left over").unwrap();
+        fs::write(&fresh, b"in progress").unwrap();
+        let file = fs::File::options().write(true).open(&stale).unwrap();
+        file.set_modified(SystemTime::now() - 2 * STALE_PARTIAL_AGE).unwrap();
+
+        cache.evict().unwrap();
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+}
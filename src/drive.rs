@@ -1,38 +1,273 @@
 //! Google Drive manager for file uploads and downloads using REST API
 
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{header, multipart, Client};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use reqwest::{header, multipart, Client, IntoUrl, Method, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use md5::Md5;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::io::Read;
-use std::path::Path;
-use tracing::info;
-
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::backend::{RemoteEntry, StorageBackend, StorageQuota};
+use crate::config::StorageLayout;
+use crate::drive_cache::DriveCache;
 use crate::error::{DitriveError, Result};
-use crate::oauth::{OAuthCredentials, OAuthManager};
+use crate::http;
+use crate::jwt;
+use crate::oauth::{OAuthCredentials, OAuthManager, REFRESH_MARGIN_SECS};
+use crate::paths;
+use crate::progress::Transfer;
+use crate::trace::{self, TracedSend};
 
 /// Authentication method for Google Drive
 #[derive(Debug, Clone)]
 pub enum AuthMethod {
     /// OAuth2 user authentication (for collaboration)
     OAuth(OAuthCredentials),
-    /// Service account authentication (for automation)
-    ServiceAccount(String), // Path to service account JSON file
+    /// Service account authentication (for automation), with the key read up front
+    ServiceAccount(ServiceAccountKey),
+}
+
+/// Environment variable holding a service account key as JSON, raw or base64-encoded
+pub const SERVICE_ACCOUNT_JSON_ENV: &str = "DITRIVE_SERVICE_ACCOUNT_JSON";
+
+/// Google's usual environment variable naming a service account key file
+pub const GOOGLE_CREDENTIALS_ENV: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+
+/// `service_account_file` value that reads the key from standard input
+pub const SERVICE_ACCOUNT_STDIN: &str = "-";
+
+/// The environment variable a service account key is taken from when no file is configured
+pub fn service_account_env() -> Option<&'static str> {
+    [SERVICE_ACCOUNT_JSON_ENV, GOOGLE_CREDENTIALS_ENV]
+        .into_iter()
+        .find(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()))
+}
+
+/// Read the service account key `source` points at: a key file, "-" for
+/// standard input, or nothing to use `$DITRIVE_SERVICE_ACCOUNT_JSON` or the
+/// file `$GOOGLE_APPLICATION_CREDENTIALS` names, in that order
+fn load_service_account_key(source: &str) -> Result<ServiceAccountKey> {
+    let content = match source {
+        SERVICE_ACCOUNT_STDIN => {
+            // Standard input can only be read once, and every DriveManager needs the key
+            static STDIN_KEY: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+            STDIN_KEY
+                .get_or_init(|| {
+                    let mut content = String::new();
+                    let _ = std::io::stdin().read_to_string(&mut content);
+                    content
+                })
+                .clone()
+        }
+        "" => match std::env::var(SERVICE_ACCOUNT_JSON_ENV).ok().filter(|value| !value.is_empty()) {
+            Some(content) => content,
+            None => match std::env::var(GOOGLE_CREDENTIALS_ENV).ok().filter(|value| !value.is_empty()) {
+                Some(path) => fs::read_to_string(path)?,
+                None => {
+                    return Err(DitriveError::Config(format!(
+                        "No service account key: set drive.service_account_file, {} or {}",
+                        SERVICE_ACCOUNT_JSON_ENV, GOOGLE_CREDENTIALS_ENV
+                    )))
+                }
+            },
+        },
+        path => fs::read_to_string(path)?,
+    };
+    parse_service_account_key(&content)
+}
+
+/// Parse a service account key given as JSON or as base64-encoded JSON
+fn parse_service_account_key(content: &str) -> Result<ServiceAccountKey> {
+    use base64::Engine;
+
+    let content = content.trim();
+    let json = if content.starts_with('{') {
+        content.to_string()
+    } else {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(content.split_whitespace().collect::<String>())
+            .map_err(|e| DitriveError::Auth(format!("Service account key is neither JSON nor base64: {}", e)))?;
+        String::from_utf8(decoded)
+            .map_err(|e| DitriveError::Auth(format!("Service account key is neither JSON nor base64: {}", e)))?
+    };
+    serde_json::from_str(&json).map_err(|e| DitriveError::Auth(format!("Failed to parse service account key: {}", e)))
+}
+
+/// An access token and when it expires (Unix seconds)
+#[derive(Debug, Clone)]
+struct CachedToken {
+    value: String,
+    expires_at: i64,
+}
+
+impl CachedToken {
+    /// Whether the token is close enough to expiry to need replacing
+    fn needs_refresh(&self, now: i64) -> bool {
+        self.expires_at <= now + REFRESH_MARGIN_SECS
+    }
+}
+
+/// Supplies access tokens for Drive requests, fetching a new one shortly
+/// before the current one expires so long syncs outlive a single token
+struct TokenProvider {
+    auth_method: AuthMethod,
+    cached: tokio::sync::Mutex<CachedToken>,
+}
+
+impl TokenProvider {
+    async fn new(client: &Client, auth_method: AuthMethod) -> Result<Self> {
+        let token = Self::fetch(client, &auth_method).await?;
+        Ok(Self {
+            auth_method,
+            cached: tokio::sync::Mutex::new(token),
+        })
+    }
+
+    /// A token valid for at least the refresh margin
+    async fn token(&self, client: &Client) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if cached.needs_refresh(chrono::Utc::now().timestamp()) {
+            debug!("Drive access token is about to expire, refreshing");
+            *cached = Self::fetch(client, &self.auth_method).await?;
+        }
+        Ok(cached.value.clone())
+    }
+
+    async fn fetch(client: &Client, auth_method: &AuthMethod) -> Result<CachedToken> {
+        match auth_method {
+            AuthMethod::OAuth(credentials) => {
+                let tokens = OAuthManager::new(credentials.clone()).get_tokens().await?;
+                Ok(CachedToken {
+                    value: tokens.access_token,
+                    expires_at: tokens.expires_at,
+                })
+            }
+            AuthMethod::ServiceAccount(key) => DriveManager::get_service_account_token(client, key).await,
+        }
+    }
 }
 
 /// File metadata stored in .woilah files
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub id: String,
     pub hash: String,
     pub size: u64,
     pub uploaded_at: i64,
+    /// Drive revision holding this content. Downloads fetch this revision
+    /// rather than the file's current content, so edits made to the file in
+    /// Drive itself don't change what a checkout gets.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub revision_id: String,
+    /// MD5 of the content as Drive reported it after upload
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub md5: String,
+    /// Who uploaded this content, as "Name <email>" from their git config
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub uploaded_by: String,
+    /// Commit checked out when this content was uploaded
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commit: String,
+    /// Release the content is an asset of (GitHub Releases backend)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub release_tag: String,
+    /// Previously uploaded versions, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<FileVersion>,
+    /// Thumbnail of this content: its path in the repository when committed,
+    /// or the ID it was stored under when uploaded
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub thumbnail: String,
+    /// Preview metadata such as image dimensions or media duration
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl FileMetadata {
+    /// The stored content's ID, then its thumbnail's if it has one
+    pub fn stored_ids(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain((!self.thumbnail.is_empty()).then_some(self.thumbnail.as_str()))
+    }
+
+    /// Whatever is stored under `id` right now, expected to hash to `hash`
+    pub fn current(id: &str, hash: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            hash: hash.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A prior version of a managed file, kept as a Drive revision
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileVersion {
+    pub revision_id: String,
+    pub hash: String,
+    pub size: u64,
+    pub uploaded_at: i64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub uploaded_by: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commit: String,
+}
+
+impl From<&FileMetadata> for FileVersion {
+    fn from(metadata: &FileMetadata) -> Self {
+        Self {
+            revision_id: metadata.revision_id.clone(),
+            hash: metadata.hash.clone(),
+            size: metadata.size,
+            uploaded_at: metadata.uploaded_at,
+            uploaded_by: metadata.uploaded_by.clone(),
+            commit: metadata.commit.clone(),
+        }
+    }
+}
+
+/// MIME type prefix of native Google Docs/Sheets/Slides formats
+const GOOGLE_APPS_MIME_PREFIX: &str = "application/vnd.google-apps.";
+
+/// Resolve the content type a file is uploaded with, honouring per-extension overrides.
+///
+/// Google Apps types are never returned: uploading with one makes Drive convert
+/// the file into a native document and the original bytes are lost.
+pub fn resolve_mime_type(path: &Path, overrides: &HashMap<String, String>) -> String {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let override_type = overrides.iter().find_map(|(ext, mime)| {
+        (ext.trim_start_matches('.').to_lowercase() == extension).then(|| mime.clone())
+    });
+
+    let mime_type = override_type.unwrap_or_else(|| {
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string()
+    });
+
+    if mime_type.starts_with(GOOGLE_APPS_MIME_PREFIX) {
+        warn!(
+            "Refusing to upload {:?} as '{}' (would be converted by Drive), using binary instead",
+            path, mime_type
+        );
+        return "application/octet-stream".to_string();
+    }
+
+    mime_type
 }
 
 /// Calculate SHA-256 hash of a file
 pub fn calculate_file_hash(path: &Path) -> Result<String> {
+    let _timer = trace::phase("hash", path.display().to_string());
     let mut file = fs::File::open(path)?;
     let mut hasher = Sha256::new();
     let mut buffer = [0u8; 8192];
@@ -48,28 +283,257 @@ pub fn calculate_file_hash(path: &Path) -> Result<String> {
     Ok(hex::encode(hasher.finalize()))
 }
 
+/// Compare the MD5 Drive computed for an upload with the one of the bytes
+/// sent, so content corrupted on the way is caught now rather than on a
+/// later pull. Returns Drive's checksum.
+fn verify_upload_md5(file_name: &str, sent_md5: &str, reported: Option<String>) -> Result<String> {
+    match reported {
+        Some(md5) if md5.eq_ignore_ascii_case(sent_md5) => Ok(md5),
+        Some(md5) => Err(DitriveError::Drive(format!(
+            "Upload of {} was corrupted: Drive stored content with MD5 {}, but {} was sent",
+            file_name, md5, sent_md5
+        ))),
+        None => {
+            warn!("Drive didn't report an MD5 checksum for {}, so the upload can't be verified", file_name);
+            Ok(String::new())
+        }
+    }
+}
+
+/// Error reason Drive reports when a file was downloaded too often
+const DOWNLOAD_QUOTA_REASON: &str = "downloadQuotaExceeded";
+
+/// Pauses before retrying a download whose quota was exceeded and couldn't be copied
+const QUOTA_RETRY_DELAYS: [std::time::Duration; 2] = [
+    std::time::Duration::from_secs(30),
+    std::time::Duration::from_secs(120),
+];
+
+/// Whether a failed download was refused because of the file's download quota
+fn is_download_quota_error(status: reqwest::StatusCode, body: &str) -> bool {
+    status == reqwest::StatusCode::FORBIDDEN && body.contains(DOWNLOAD_QUOTA_REASON)
+}
+
+/// Length of the content-hash suffix added by `upload_name`
+const NAME_HASH_LEN: usize = 6;
+
+/// Folder the flat layout keeps its objects in, under the repository folder
+pub const OBJECTS_DIR: &str = "objects";
+
+/// Hash characters naming each folder under `objects/`, so no folder holds every object
+const OBJECT_PREFIX_LEN: usize = 2;
+
+/// Requests in flight at once when looking up many files or folders
+const CONCURRENT_LOOKUPS: usize = 8;
+
+/// Files at least this large are downloaded as several byte ranges at once,
+/// since a single connection to Drive rarely fills the line
+const RANGED_DOWNLOAD_MIN_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Size of each range of a ranged download
+const RANGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Ranges of one file downloaded at once
+const CONCURRENT_RANGES: usize = 4;
+
+/// Split a file into inclusive byte ranges of at most `range_size` bytes
+fn byte_ranges(file_size: u64, range_size: u64) -> Vec<(u64, u64)> {
+    (0..file_size)
+        .step_by(range_size as usize)
+        .map(|start| (start, (start + range_size).min(file_size) - 1))
+        .collect()
+}
+
+/// Every folder the given directories need, grouped by depth, so each level
+/// can be resolved once its parents are known
+fn folder_levels(rel_dirs: &[PathBuf]) -> Vec<BTreeSet<PathBuf>> {
+    let mut levels: Vec<BTreeSet<PathBuf>> = Vec::new();
+    for dir in rel_dirs {
+        let mut prefix = PathBuf::new();
+        let names = dir.components().filter(|c| matches!(c, std::path::Component::Normal(_)));
+        for (depth, component) in names.enumerate() {
+            prefix.push(component);
+            if levels.len() <= depth {
+                levels.push(BTreeSet::new());
+            }
+            levels[depth].insert(prefix.clone());
+        }
+    }
+    levels
+}
+
+/// Name to store a file under in the backend.
+///
+/// With `hash_suffix` set, a short content hash is appended (`model.bin.3fa2c1`)
+/// so same-named files from repos or branches sharing a folder don't collide.
+/// The tracker always keeps the clean name.
+pub fn upload_name(file_name: &str, hash: &str, hash_suffix: bool) -> String {
+    match hash.get(..NAME_HASH_LEN) {
+        Some(short_hash) if hash_suffix => format!("{}.{}", file_name, short_hash),
+        _ => file_name.to_string(),
+    }
+}
+
+/// Where the flat layout stores content: `objects/<hash prefix>/<hash>`.
+///
+/// Identical content uploaded under different paths lands on the same object,
+/// which is only ever written once.
+pub fn object_path(hash: &str) -> String {
+    let prefix = hash.get(..OBJECT_PREFIX_LEN).unwrap_or(hash);
+    format!("{}/{}/{}", OBJECTS_DIR, prefix, hash)
+}
+
+/// The clean name behind a stored name, dropping a suffix `upload_name` added for `hash`
+pub fn clean_name<'a>(stored_name: &'a str, hash: &str) -> &'a str {
+    match (hash.get(..NAME_HASH_LEN), stored_name.rsplit_once('.')) {
+        (Some(short_hash), Some((name, suffix))) if suffix == short_hash => name,
+        _ => stored_name,
+    }
+}
+
 /// Service account key structure
-#[derive(Debug, Deserialize)]
-struct ServiceAccountKey {
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccountKey {
     client_email: String,
     private_key: String,
     token_uri: String,
 }
 
+impl std::fmt::Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .field("token_uri", &self.token_uri)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Google API token response
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
     access_token: String,
     expires_in: u64,
-    token_type: String,
 }
 
 /// Drive file response
 #[derive(Debug, Deserialize)]
 struct DriveFileResponse {
     id: Option<String>,
-    name: Option<String>,
     size: Option<String>,
+    #[serde(rename = "headRevisionId")]
+    head_revision_id: Option<String>,
+    #[serde(rename = "md5Checksum")]
+    md5_checksum: Option<String>,
+}
+
+/// Remote state of a Drive file as reported by the API
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteFile {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub size: Option<String>,
+    #[serde(default)]
+    pub trashed: bool,
+    pub sha256_checksum: Option<String>,
+    pub head_revision_id: Option<String>,
+    #[serde(default)]
+    pub mime_type: String,
+    pub modified_time: Option<String>,
+}
+
+impl RemoteFile {
+    /// Fields requested from the API when fetching a RemoteFile
+    const FIELDS: &'static str = "id,name,size,trashed,sha256Checksum,headRevisionId,mimeType,modifiedTime";
+
+    /// MIME type Drive gives folders
+    const FOLDER_MIME_TYPE: &'static str = "application/vnd.google-apps.folder";
+
+    pub fn is_folder(&self) -> bool {
+        self.mime_type == Self::FOLDER_MIME_TYPE
+    }
+
+    /// `modifiedTime` as a Unix timestamp, 0 if missing or unparsable
+    pub fn modified_at(&self) -> i64 {
+        self.modified_time
+            .as_deref()
+            .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.timestamp())
+            .unwrap_or(0)
+    }
+}
+
+/// Drive files list response with full remote state
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteFileList {
+    #[serde(default)]
+    files: Vec<RemoteFile>,
+    next_page_token: Option<String>,
+}
+
+/// Who can access a Drive file, and how
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Permission {
+    pub id: String,
+    /// "user", "group", "domain" or "anyone"
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub role: String,
+    pub email_address: Option<String>,
+    pub display_name: Option<String>,
+}
+
+impl Permission {
+    const FIELDS: &'static str = "id,type,role,emailAddress,displayName";
+
+    /// Whether this permission was granted to an email address
+    pub fn is_for(&self, email: &str) -> bool {
+        self.email_address
+            .as_deref()
+            .is_some_and(|address| address.eq_ignore_ascii_case(email))
+    }
+}
+
+/// Drive permissions list response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PermissionList {
+    #[serde(default)]
+    permissions: Vec<Permission>,
+    next_page_token: Option<String>,
+}
+
+/// Drive about response, limited to the storage quota
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AboutResponse {
+    storage_quota: DriveStorageQuota,
+}
+
+/// Drive reports quota figures as decimal strings, and no limit for unlimited storage
+#[derive(Debug, Deserialize)]
+struct DriveStorageQuota {
+    limit: Option<String>,
+    #[serde(default)]
+    usage: String,
+}
+
+impl From<DriveStorageQuota> for StorageQuota {
+    fn from(quota: DriveStorageQuota) -> Self {
+        Self {
+            usage: quota.usage.parse().unwrap_or(0),
+            limit: quota.limit.and_then(|limit| limit.parse().ok()),
+        }
+    }
+}
+
+/// Drive file parents response
+#[derive(Debug, Deserialize)]
+struct DriveParentsResponse {
+    parents: Option<Vec<String>>,
 }
 
 /// Drive files list response
@@ -81,83 +545,85 @@ struct DriveFilesListResponse {
 /// Google Drive manager using REST API
 pub struct DriveManager {
     client: Client,
-    access_token: String,
+    tokens: TokenProvider,
     root_folder_id: String,
+    /// Shared Drive searches are scoped to, if the folders live in one
+    shared_drive_id: Option<String>,
     repo_name: String,
     repo_folder_id: String,
-    folder_cache: HashMap<String, String>,
-    auth_method: AuthMethod,
+    /// Folder IDs and file existence, possibly remembered from earlier runs
+    cache: DriveCache,
+    mime_overrides: HashMap<String, String>,
+    hash_suffix_names: bool,
+    layout: StorageLayout,
 }
 
 impl DriveManager {
     const API_BASE: &'static str = "https://www.googleapis.com/drive/v3";
     const UPLOAD_BASE: &'static str = "https://www.googleapis.com/upload/drive/v3";
 
-    /// Create a new DriveManager with OAuth authentication (for collaboration)
+    /// Create a new DriveManager with OAuth authentication (for collaboration).
+    ///
+    /// Call `open_repo_folder` before uploading or downloading.
     pub async fn with_oauth(
         credentials: OAuthCredentials,
         root_folder_id: &str,
         repo_name: &str,
     ) -> Result<Self> {
-        let client = Client::new();
+        let client = http::client();
         
         // Get access token via OAuth
-        let oauth = OAuthManager::new(credentials.clone());
-        let access_token = oauth.get_access_token().await?;
+        let tokens = TokenProvider::new(&client, AuthMethod::OAuth(credentials)).await?;
 
-        let mut manager = Self {
+        let manager = Self {
             client,
-            access_token,
+            tokens,
             root_folder_id: root_folder_id.to_string(),
+            shared_drive_id: None,
             repo_name: repo_name.to_string(),
             repo_folder_id: String::new(),
-            folder_cache: HashMap::new(),
-            auth_method: AuthMethod::OAuth(credentials),
+            cache: DriveCache::in_memory(),
+            mime_overrides: HashMap::new(),
+            hash_suffix_names: false,
+            layout: StorageLayout::Mirror,
         };
 
-        // Get or create repository folder
-        manager.repo_folder_id = manager
-            .get_or_create_folder(repo_name, root_folder_id)
-            .await?;
-
-        info!(
-            "DriveManager (OAuth) initialized for repo '{}' with folder ID: {}",
-            repo_name, manager.repo_folder_id
-        );
+        debug!("DriveManager (OAuth) authenticated for repo '{}'", repo_name);
 
         Ok(manager)
     }
 
-    /// Create a new DriveManager with service account (legacy/automation)
+    /// Create a new DriveManager with service account (legacy/automation).
+    ///
+    /// Call `open_repo_folder` before uploading or downloading.
+    ///
+    /// `service_account_file` is a key file, "-" to read the key from standard
+    /// input, or empty to take it from the environment (see `load_service_account_key`).
     pub async fn with_service_account(
         service_account_file: &str,
         root_folder_id: &str,
         repo_name: &str,
     ) -> Result<Self> {
-        let client = Client::new();
+        let client = http::client();
         
         // Get access token via service account
-        let access_token = Self::get_service_account_token(&client, service_account_file).await?;
+        let auth_method = AuthMethod::ServiceAccount(load_service_account_key(service_account_file)?);
+        let tokens = TokenProvider::new(&client, auth_method).await?;
 
-        let mut manager = Self {
+        let manager = Self {
             client,
-            access_token,
+            tokens,
             root_folder_id: root_folder_id.to_string(),
+            shared_drive_id: None,
             repo_name: repo_name.to_string(),
             repo_folder_id: String::new(),
-            folder_cache: HashMap::new(),
-            auth_method: AuthMethod::ServiceAccount(service_account_file.to_string()),
+            cache: DriveCache::in_memory(),
+            mime_overrides: HashMap::new(),
+            hash_suffix_names: false,
+            layout: StorageLayout::Mirror,
         };
 
-        // Get or create repository folder
-        manager.repo_folder_id = manager
-            .get_or_create_folder(repo_name, root_folder_id)
-            .await?;
-
-        info!(
-            "DriveManager (ServiceAccount) initialized for repo '{}' with folder ID: {}",
-            repo_name, manager.repo_folder_id
-        );
+        debug!("DriveManager (ServiceAccount) authenticated for repo '{}'", repo_name);
 
         Ok(manager)
     }
@@ -168,15 +634,14 @@ impl DriveManager {
         root_folder_id: &str,
         repo_name: &str,
     ) -> Result<Self> {
-        Self::with_service_account(service_account_file, root_folder_id, repo_name).await
+        let mut manager =
+            Self::with_service_account(service_account_file, root_folder_id, repo_name).await?;
+        manager.open_repo_folder(None).await?;
+        Ok(manager)
     }
 
     /// Get access token using service account
-    async fn get_service_account_token(client: &Client, service_account_file: &str) -> Result<String> {
-        let key_content = fs::read_to_string(service_account_file)?;
-        let key: ServiceAccountKey = serde_json::from_str(&key_content)
-            .map_err(|e| DitriveError::Auth(format!("Failed to parse service account key: {}", e)))?;
-
+    async fn get_service_account_token(client: &Client, key: &ServiceAccountKey) -> Result<CachedToken> {
         // Create JWT
         let now = chrono::Utc::now().timestamp();
         let claims = serde_json::json!({
@@ -187,15 +652,7 @@ impl DriveManager {
             "exp": now + 3600,
         });
 
-        // Sign JWT with RS256
-        let header = base64_url_encode(r#"{"alg":"RS256","typ":"JWT"}"#.as_bytes());
-        let payload = base64_url_encode(claims.to_string().as_bytes());
-        let signing_input = format!("{}.{}", header, payload);
-        
-        let signature = sign_rs256(&signing_input, &key.private_key)
-            .map_err(|e| DitriveError::Auth(format!("Failed to sign JWT: {}", e)))?;
-        
-        let jwt = format!("{}.{}", signing_input, signature);
+        let jwt = jwt::sign_rs256(&claims, &key.private_key)?;
 
         // Exchange JWT for access token
         let response = client
@@ -204,7 +661,7 @@ impl DriveManager {
                 ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
                 ("assertion", &jwt),
             ])
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Auth(format!("Failed to get access token: {}", e)))?;
 
@@ -216,7 +673,15 @@ impl DriveManager {
         let token_response: TokenResponse = response.json().await
             .map_err(|e| DitriveError::Auth(format!("Failed to parse token response: {}", e)))?;
 
-        Ok(token_response.access_token)
+        Ok(CachedToken {
+            value: token_response.access_token,
+            expires_at: now + token_response.expires_in as i64,
+        })
+    }
+
+    /// Access token for the next request, refreshed if it is about to expire
+    async fn access_token(&self) -> Result<String> {
+        self.tokens.token(&self.client).await
     }
 
     /// Get the repository folder ID
@@ -224,27 +689,140 @@ impl DriveManager {
         &self.repo_folder_id
     }
 
-    /// Get or create a folder in Drive
-    async fn get_or_create_folder(&mut self, name: &str, parent_id: &str) -> Result<String> {
-        let cache_key = format!("{}/{}", parent_id, name);
+    /// Open the repository folder, using a known folder ID or looking it up by name under the root
+    pub async fn open_repo_folder(&mut self, repo_folder_id: Option<&str>) -> Result<()> {
+        self.repo_folder_id = match repo_folder_id {
+            Some(id) => id.to_string(),
+            None => {
+                let name = self.repo_name.clone();
+                let root = self.root_folder_id.clone();
+                self.get_or_create_folder(&name, &root).await?
+            }
+        };
+
+        info!(
+            "DriveManager initialized for repo '{}' with folder ID: {}",
+            self.repo_name, self.repo_folder_id
+        );
+        Ok(())
+    }
+
+    /// Check if a folder is reachable and not trashed
+    pub async fn folder_exists(&self, folder_id: &str) -> bool {
+        if self.cache.exists(folder_id) {
+            return true;
+        }
+        let exists = self
+            .get_remote_file(folder_id)
+            .await
+            .map(|f| !f.trashed)
+            .unwrap_or(false);
+        if exists {
+            self.cache.set_exists(folder_id);
+        }
+        exists
+    }
+
+    /// Get the first parent folder of a Drive file
+    pub async fn get_parent_folder(&self, file_id: &str) -> Result<Option<String>> {
+        let response = self
+            .request(Method::GET, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("fields", "parents")])
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to get file parents: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to get file parents: {}", error)));
+        }
+
+        let parents: DriveParentsResponse = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        Ok(parents.parents.and_then(|p| p.into_iter().next()))
+    }
+
+    /// Set the per-extension MIME type overrides used on upload
+    pub fn set_mime_overrides(&mut self, overrides: HashMap<String, String>) {
+        self.mime_overrides = overrides;
+    }
+
+    /// Search inside a Shared Drive instead of My Drive
+    pub fn set_shared_drive(&mut self, drive_id: &str) {
+        self.shared_drive_id = (!drive_id.is_empty()).then(|| drive_id.to_string());
+    }
+
+    /// Start a request to the Drive API. Files in Shared Drives are reported
+    /// as not found unless the request opts in to them.
+    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        self.client.request(method, url).query(&[("supportsAllDrives", "true")])
+    }
+
+    /// Parameters scoping a file search to the configured drive
+    fn search_scope(&self) -> Vec<(&'static str, &str)> {
+        match &self.shared_drive_id {
+            Some(drive_id) => vec![
+                ("corpora", "drive"),
+                ("driveId", drive_id),
+                ("includeItemsFromAllDrives", "true"),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// Use a cache of Drive lookups, such as one loaded from disk
+    pub fn set_cache(&mut self, cache: DriveCache) {
+        self.cache = cache;
+    }
+
+    /// Append a short content hash to uploaded file names (see `upload_name`)
+    pub fn set_hash_suffix_names(&mut self, enabled: bool) {
+        self.hash_suffix_names = enabled;
+    }
 
+    /// Mirror the repository's folders, or store content-addressed objects (see `object_path`)
+    pub fn set_storage_layout(&mut self, layout: StorageLayout) {
+        self.layout = layout;
+    }
+
+    /// Folder the flat layout keeps the object for some content in, created if needed
+    async fn object_folder(&self, hash: &str) -> Result<String> {
+        let object = object_path(hash);
+        let object_dir = Path::new(&object).parent().unwrap_or(Path::new(OBJECTS_DIR));
+        self.ensure_folder_path(&self.repo_folder_id, object_dir).await
+    }
+
+    /// Find the flat layout's object for some content, if it has been uploaded
+    pub async fn find_object(&self, hash: &str) -> Result<Option<RemoteFile>> {
+        let folder_id = self.object_folder(hash).await?;
+        // A stored copy whose checksum disagrees with its name is never reused
+        Ok(self
+            .find_file(hash, &folder_id)
+            .await?
+            .filter(|file| file.sha256_checksum.as_deref().is_none_or(|checksum| checksum == hash)))
+    }
+
+    /// Get or create a folder in Drive
+    async fn get_or_create_folder(&self, name: &str, parent_id: &str) -> Result<String> {
         // Check cache first
-        if let Some(id) = self.folder_cache.get(&cache_key) {
-            return Ok(id.clone());
+        if let Some(id) = self.cache.folder(parent_id, name) {
+            return Ok(id);
         }
 
         // Search for existing folder
         let query = format!(
             "name='{}' and '{}' in parents and mimeType='application/vnd.google-apps.folder' and trashed=false",
-            name, parent_id
+            escape_query_value(name), parent_id
         );
 
         let response = self
-            .client
-            .get(&format!("{}/files", Self::API_BASE))
-            .bearer_auth(&self.access_token)
+            .request(Method::GET, format!("{}/files", Self::API_BASE))
+            .bearer_auth(self.access_token().await?)
             .query(&[("q", &query), ("fields", &"files(id,name)".to_string())])
-            .send()
+            .query(&self.search_scope())
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Drive(format!("Failed to list folders: {}", e)))?;
 
@@ -262,7 +840,7 @@ impl DriveManager {
         };
 
         // Update cache
-        self.folder_cache.insert(cache_key, folder_id.clone());
+        self.cache.set_folder(parent_id, name, &folder_id);
 
         Ok(folder_id)
     }
@@ -276,12 +854,11 @@ impl DriveManager {
         });
 
         let response = self
-            .client
-            .post(&format!("{}/files", Self::API_BASE))
-            .bearer_auth(&self.access_token)
+            .request(Method::POST, format!("{}/files", Self::API_BASE))
+            .bearer_auth(self.access_token().await?)
             .header(header::CONTENT_TYPE, "application/json")
             .json(&metadata)
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Drive(format!("Failed to create folder: {}", e)))?;
 
@@ -300,34 +877,204 @@ impl DriveManager {
     }
 
     /// Get the folder ID for a file path, creating folders as needed
-    async fn get_folder_for_path(&mut self, file_path: &Path, repo_path: &Path) -> Result<String> {
-        let rel_path = file_path
-            .strip_prefix(repo_path)
-            .map_err(|_| DitriveError::FileNotFound(file_path.display().to_string()))?;
-
-        let mut current_folder_id = self.repo_folder_id.clone();
-
-        // Create or get each folder in the path (excluding filename)
-        if let Some(parent) = rel_path.parent() {
-            for component in parent.components() {
-                if let std::path::Component::Normal(name) = component {
-                    let name_str = name.to_string_lossy();
-                    current_folder_id = self
-                        .get_or_create_folder(&name_str, &current_folder_id)
-                        .await?;
-                }
+    async fn get_folder_for_path(&self, file_path: &Path, repo_path: &Path) -> Result<String> {
+        let rel_path = paths::relative(repo_path, file_path)
+            .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?;
+
+        let repo_folder_id = self.repo_folder_id.clone();
+        match Path::new(&rel_path).parent() {
+            Some(parent) => self.ensure_folder_path(&repo_folder_id, parent).await,
+            None => Ok(repo_folder_id),
+        }
+    }
+
+    /// Get or create the nested folders for a relative directory under a base folder
+    pub async fn ensure_folder_path(&self, base_folder_id: &str, rel_dir: &Path) -> Result<String> {
+        let mut current_folder_id = base_folder_id.to_string();
+
+        for component in rel_dir.components() {
+            if let std::path::Component::Normal(name) = component {
+                let name_str = name.to_string_lossy();
+                current_folder_id = self
+                    .get_or_create_folder(&name_str, &current_folder_id)
+                    .await?;
             }
         }
 
         Ok(current_folder_id)
     }
 
+    /// Look up or create the folders for many repository-relative
+    /// directories, one round of concurrent requests per level of nesting
+    /// rather than one request per folder in turn. Results land in the folder
+    /// cache, so later uploads into these directories skip the lookups.
+    pub async fn resolve_folders(&self, rel_dirs: &[PathBuf]) -> Result<()> {
+        let mut folder_ids = HashMap::from([(PathBuf::new(), self.repo_folder_id.clone())]);
+        for level in folder_levels(rel_dirs) {
+            let resolved: Vec<Result<(PathBuf, String)>> = stream::iter(level)
+                .map(|dir| {
+                    let parent_id = dir.parent().and_then(|parent| folder_ids.get(parent)).cloned();
+                    async move {
+                        let parent_id = parent_id.ok_or_else(|| DitriveError::Drive(format!("No parent folder for {:?}", dir)))?;
+                        let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        let folder_id = self.get_or_create_folder(&name, &parent_id).await?;
+                        Ok((dir, folder_id))
+                    }
+                })
+                .buffer_unordered(CONCURRENT_LOOKUPS)
+                .collect()
+                .await;
+            for result in resolved {
+                let (dir, folder_id) = result?;
+                folder_ids.insert(dir, folder_id);
+            }
+        }
+
+        debug!("Resolved {} folders", folder_ids.len() - 1);
+        Ok(())
+    }
+
+    /// Find a non-trashed file by name in a folder
+    pub async fn find_file(&self, name: &str, parent_id: &str) -> Result<Option<RemoteFile>> {
+        let query = format!(
+            "name='{}' and '{}' in parents and trashed=false",
+            escape_query_value(name),
+            parent_id
+        );
+
+        let response = self
+            .request(Method::GET, format!("{}/files", Self::API_BASE))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("q", query), ("fields", format!("files({})", RemoteFile::FIELDS))])
+            .query(&self.search_scope())
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to list files: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to list files: {}", error)));
+        }
+
+        let list: RemoteFileList = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        Ok(list.files.into_iter().next())
+    }
+
+    /// Everything directly inside a folder, following pagination
+    pub async fn list_folder(&self, folder_id: &str) -> Result<Vec<RemoteFile>> {
+        let query = format!("'{}' in parents and trashed=false", folder_id);
+        let fields = format!("nextPageToken,files({})", RemoteFile::FIELDS);
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .request(Method::GET, format!("{}/files", Self::API_BASE))
+                .bearer_auth(self.access_token().await?)
+                .query(&[("q", query.as_str()), ("fields", fields.as_str()), ("pageSize", "1000")])
+                .query(&self.search_scope());
+            if let Some(ref token) = page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+
+            let response = request
+                .send_traced()
+                .await
+                .map_err(|e| DitriveError::Drive(format!("Failed to list files: {}", e)))?;
+
+            if !response.status().is_success() {
+                let error = response.text().await.unwrap_or_default();
+                return Err(DitriveError::Drive(format!("Failed to list files: {}", error)));
+            }
+
+            let list: RemoteFileList = response.json().await
+                .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+            files.extend(list.files);
+
+            match list.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    /// Every file under the repository folder, with paths relative to it
+    pub async fn list_repo_files(&self) -> Result<Vec<RemoteEntry>> {
+        let mut entries = Vec::new();
+        let mut folders = vec![(self.repo_folder_id.clone(), String::new())];
+
+        while let Some((folder_id, prefix)) = folders.pop() {
+            for file in self.list_folder(&folder_id).await? {
+                let path = format!("{}{}", prefix, file.name);
+                if file.is_folder() {
+                    folders.push((file.id, format!("{}/", path)));
+                    continue;
+                }
+                entries.push(RemoteEntry {
+                    size: file.size.as_deref().and_then(|s| s.parse().ok()).unwrap_or(0),
+                    modified_at: file.modified_at(),
+                    hash: file.sha256_checksum,
+                    id: file.id,
+                    path,
+                });
+            }
+        }
+
+        debug!("Listed {} files in the repository folder", entries.len());
+        Ok(entries)
+    }
+
+    /// Copy a Drive file into another folder server-side
+    pub async fn copy_file(&self, file_id: &str, name: &str, parent_id: &str) -> Result<String> {
+        let response = self
+            .request(Method::POST, format!("{}/files/{}/copy", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("fields", "id")])
+            .json(&serde_json::json!({ "name": name, "parents": [parent_id] }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to copy file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to copy file: {}", error)));
+        }
+
+        let copied: DriveFileResponse = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        copied.id.ok_or_else(|| DitriveError::Drive("No file ID returned".to_string()))
+    }
+
+    /// Move a file to the Drive trash
+    pub async fn trash_file(&self, file_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::PATCH, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .json(&serde_json::json!({ "trashed": true }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to trash file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to trash file: {}", error)));
+        }
+
+        self.cache.forget(file_id);
+        debug!("Moved {} to the Drive trash", file_id);
+        Ok(())
+    }
+
     /// Upload a file to Drive with progress indication
     pub async fn upload_file(
         &mut self,
         file_path: &Path,
         repo_path: &Path,
     ) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
         let file_name = file_path
             .file_name()
             .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?
@@ -336,31 +1083,44 @@ impl DriveManager {
 
         let file_size = fs::metadata(file_path)?.len();
         let file_hash = calculate_file_hash(file_path)?;
-        let mime_type = mime_guess::from_path(file_path)
-            .first_or_octet_stream()
-            .to_string();
-
-        // Get the folder for this file
-        let folder_id = self.get_folder_for_path(file_path, repo_path).await?;
+        let mime_type = resolve_mime_type(file_path, &self.mime_overrides);
+
+        let (folder_id, stored_name) = match self.layout {
+            StorageLayout::Mirror => (
+                self.get_folder_for_path(file_path, repo_path).await?,
+                upload_name(&file_name, &file_hash, self.hash_suffix_names),
+            ),
+            StorageLayout::Flat => {
+                // Content already stored, under this path or any other, isn't sent again
+                if let Some(existing) = self.find_object(&file_hash).await? {
+                    info!("{} is already stored as {}", file_name, existing.id);
+                    return Ok(FileMetadata {
+                        id: existing.id,
+                        hash: file_hash,
+                        size: file_size,
+                        uploaded_at: chrono::Utc::now().timestamp(),
+                        revision_id: existing.head_revision_id.unwrap_or_default(),
+                        ..Default::default()
+                    });
+                }
+                (self.object_folder(&file_hash).await?, file_hash.clone())
+            }
+        };
 
         // Create progress bar
-        let pb = ProgressBar::new(file_size);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-        pb.set_message(format!("Uploading {}", file_name));
+        let pb = transfer_progress_bar(file_size, format!("Uploading {}", file_name));
 
         // Read file content
         let file_content = fs::read(file_path)?;
+        let sent_md5 = hex::encode(Md5::digest(&file_content));
         pb.set_position(file_size / 3);
 
-        // Create metadata part
+        // Create metadata part. Pinning mimeType keeps Drive from converting
+        // the upload into a Google Docs format.
         let metadata = serde_json::json!({
-            "name": file_name,
-            "parents": [folder_id]
+            "name": stored_name,
+            "parents": [folder_id],
+            "mimeType": mime_type
         });
 
         // Use multipart upload
@@ -371,11 +1131,15 @@ impl DriveManager {
         pb.set_position(file_size * 2 / 3);
 
         let response = self
-            .client
-            .post(&format!("{}/files?uploadType=multipart", Self::UPLOAD_BASE))
-            .bearer_auth(&self.access_token)
+            .request(Method::POST, format!("{}/files", Self::UPLOAD_BASE))
+            .query(&[
+                ("uploadType", "multipart"),
+                ("keepRevisionForever", "true"),
+                ("fields", "id,headRevisionId,md5Checksum"),
+            ])
+            .bearer_auth(self.access_token().await?)
             .multipart(form)
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Drive(format!("Failed to upload file: {}", e)))?;
 
@@ -394,6 +1158,17 @@ impl DriveManager {
         let drive_id = file_response.id
             .ok_or_else(|| DitriveError::Drive("No file ID returned".to_string()))?;
 
+        // A corrupted upload is removed so a retry starts clean
+        let md5 = match verify_upload_md5(&file_name, &sent_md5, file_response.md5_checksum) {
+            Ok(md5) => md5,
+            Err(e) => {
+                if let Err(delete_error) = self.delete_permanently(&drive_id).await {
+                    warn!("Failed to delete the corrupted upload of {}: {}", file_name, delete_error);
+                }
+                return Err(e);
+            }
+        };
+
         info!("Uploaded {} ({} bytes) to Drive", file_name, file_size);
 
         Ok(FileMetadata {
@@ -401,11 +1176,79 @@ impl DriveManager {
             hash: file_hash,
             size: file_size,
             uploaded_at: chrono::Utc::now().timestamp(),
+            revision_id: file_response.head_revision_id.unwrap_or_default(),
+            md5,
+            ..Default::default()
+        })
+    }
+
+    /// Replace the content of an existing Drive file, keeping its file ID.
+    ///
+    /// Drive has no partial content update, so the whole file is sent; keeping
+    /// the ID means tracker entries and shared links stay valid. The new content
+    /// becomes a revision that is kept forever so older versions can be restored.
+    pub async fn update_file(&self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        let file_name = file_path
+            .file_name()
+            .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?
+            .to_string_lossy()
+            .to_string();
+
+        let file_size = fs::metadata(file_path)?.len();
+        let file_hash = calculate_file_hash(file_path)?;
+        let mime_type = resolve_mime_type(file_path, &self.mime_overrides);
+
+        // Create progress bar
+        let pb = transfer_progress_bar(file_size, format!("Updating {}", file_name));
+
+        let file_content = fs::read(file_path)?;
+        let sent_md5 = hex::encode(Md5::digest(&file_content));
+        pb.set_position(file_size / 2);
+
+        let response = self
+            .request(Method::PATCH, format!("{}/files/{}", Self::UPLOAD_BASE, file_id))
+            .query(&[
+                ("uploadType", "media"),
+                ("keepRevisionForever", "true"),
+                ("fields", "id,headRevisionId,md5Checksum"),
+            ])
+            .bearer_auth(self.access_token().await?)
+            .header(header::CONTENT_TYPE, mime_type)
+            .body(file_content)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to update file: {}", e)))?;
+
+        pb.set_position(file_size);
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Update failed: {}", error)));
+        }
+
+        let file_response: DriveFileResponse = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        pb.finish_with_message(format!("Updated {}", file_name));
+        // The tracker keeps the old entry on failure, so the next push sends the file again
+        let md5 = verify_upload_md5(&file_name, &sent_md5, file_response.md5_checksum)?;
+        info!("Updated {} ({} bytes) in Drive", file_name, file_size);
+
+        Ok(FileMetadata {
+            id: file_id.to_string(),
+            hash: file_hash,
+            size: file_size,
+            uploaded_at: chrono::Utc::now().timestamp(),
+            revision_id: file_response.head_revision_id.unwrap_or_default(),
+            md5,
+            ..Default::default()
         })
     }
 
     /// Download a file from Drive with progress indication
     pub async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let _timer = trace::phase("download", destination.display().to_string());
         // Ensure parent directory exists
         if let Some(parent) = destination.parent() {
             fs::create_dir_all(parent)?;
@@ -420,43 +1263,168 @@ impl DriveManager {
 
         // Get file metadata for size
         let meta_response = self
-            .client
-            .get(&format!("{}/files/{}", Self::API_BASE, file_id))
-            .bearer_auth(&self.access_token)
+            .request(Method::GET, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
             .query(&[("fields", "size,name")])
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Drive(format!("Failed to get file metadata: {}", e)))?;
 
         let file_meta: DriveFileResponse = meta_response.json().await.unwrap_or(DriveFileResponse {
             id: None,
-            name: None,
             size: None,
+            head_revision_id: None,
+            md5_checksum: None,
         });
 
         let file_size = file_meta.size.and_then(|s| s.parse::<u64>().ok()).unwrap_or(0);
 
         // Create progress bar
-        let pb = ProgressBar::new(file_size);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-        pb.set_message(format!("Downloading {}", file_name));
+        let pb = transfer_progress_bar(file_size, format!("Downloading {}", file_name));
 
         // Download file content
+        let url = format!("{}/files/{}?alt=media&supportsAllDrives=true", Self::API_BASE, file_id);
+        match self.fetch_media(&url, file_size, destination).await {
+            Err(DitriveError::QuotaExceeded(_)) => {
+                warn!("Drive's download quota for {} is exceeded, downloading through a private copy", file_name);
+                self.download_over_quota(file_id, &file_name, destination).await?;
+            }
+            result => result?,
+        }
+        pb.finish_with_message(format!("Downloaded {}", file_name));
+
+        info!("Downloaded {} to {:?}", file_name, destination);
+        Ok(())
+    }
+
+    /// Download the content of a specific revision of a Drive file
+    pub async fn download_revision(
+        &self,
+        file_id: &str,
+        revision_id: &str,
+        file_size: u64,
+        destination: &Path,
+    ) -> Result<()> {
+        let _timer = trace::phase("download", destination.display().to_string());
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file_name = destination
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+
+        let pb = transfer_progress_bar(file_size, format!("Downloading {} ({})", file_name, revision_id));
+
+        let url = format!(
+            "{}/files/{}/revisions/{}?alt=media",
+            Self::API_BASE, file_id, revision_id
+        );
+        self.fetch_media(&url, file_size, destination).await?;
+        pb.finish_with_message(format!("Downloaded {}", file_name));
+
+        info!("Downloaded revision {} of {} to {:?}", revision_id, file_name, destination);
+        Ok(())
+    }
+
+    /// Fetch media content of a known size, in concurrent ranges when it is
+    /// large. If Drive won't serve ranges, fall back to a single request.
+    async fn fetch_media(&self, url: &str, file_size: u64, destination: &Path) -> Result<()> {
+        if file_size < RANGED_DOWNLOAD_MIN_SIZE {
+            return self.download_media(url, destination).await;
+        }
+
+        match self.download_ranges(url, file_size, destination).await {
+            Err(e @ DitriveError::QuotaExceeded(_)) => Err(e),
+            Err(e) => {
+                warn!("Ranged download failed, retrying over a single connection: {}", e);
+                self.download_media(url, destination).await
+            }
+            result => result,
+        }
+    }
+
+    /// Download media content as concurrent byte ranges written in place
+    async fn download_ranges(&self, url: &str, file_size: u64, destination: &Path) -> Result<()> {
+        fs::File::create(destination)?.set_len(file_size)?;
+        let ranges = byte_ranges(file_size, RANGE_SIZE);
+        debug!("Downloading {} bytes as {} ranges", file_size, ranges.len());
+
+        stream::iter(ranges.into_iter().map(Ok))
+            .try_for_each_concurrent(CONCURRENT_RANGES, |(start, end)| {
+                self.download_range(url, start, end, destination)
+            })
+            .await
+    }
+
+    /// Download the inclusive byte range `start..=end` into the same place in `destination`
+    async fn download_range(&self, url: &str, start: u64, end: u64, destination: &Path) -> Result<()> {
+        let mut response = self
+            .client
+            .get(url)
+            .bearer_auth(self.access_token().await?)
+            .header(header::RANGE, format!("bytes={}-{}", start, end))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to download range: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error = response.text().await.unwrap_or_default();
+            if is_download_quota_error(status, &error) {
+                return Err(DitriveError::QuotaExceeded(url.to_string()));
+            }
+            if status == reqwest::StatusCode::FORBIDDEN {
+                return Err(DitriveError::AccessDenied(error));
+            }
+            return Err(DitriveError::Drive(format!("Range download failed: {}", error)));
+        }
+        // Anything but a partial response is the whole file; don't read it
+        if status != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(DitriveError::Drive(format!("Drive ignored the byte range request ({})", status)));
+        }
+
+        let mut file = fs::OpenOptions::new().write(true).open(destination)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut written = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to read response: {}", e)))?
+        {
+            file.write_all(&chunk)?;
+            written += chunk.len() as u64;
+        }
+
+        if written != end - start + 1 {
+            return Err(DitriveError::Drive(format!(
+                "Range {}-{} ended after {} bytes",
+                start, end, written
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fetch media content from a Drive URL and write it to disk
+    async fn download_media(&self, url: &str, destination: &Path) -> Result<()> {
         let response = self
             .client
-            .get(&format!("{}/files/{}?alt=media", Self::API_BASE, file_id))
-            .bearer_auth(&self.access_token)
-            .send()
+            .get(url)
+            .bearer_auth(self.access_token().await?)
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Drive(format!("Failed to download file: {}", e)))?;
 
-        if !response.status().is_success() {
+        let status = response.status();
+        if !status.is_success() {
             let error = response.text().await.unwrap_or_default();
+            if is_download_quota_error(status, &error) {
+                return Err(DitriveError::QuotaExceeded(url.to_string()));
+            }
+            if status == reqwest::StatusCode::FORBIDDEN {
+                return Err(DitriveError::AccessDenied(error));
+            }
             return Err(DitriveError::Drive(format!("Download failed: {}", error)));
         }
 
@@ -464,42 +1432,475 @@ impl DriveManager {
             .map_err(|e| DitriveError::Drive(format!("Failed to read response: {}", e)))?;
 
         fs::write(destination, &bytes)?;
-        pb.finish_with_message(format!("Downloaded {}", file_name));
+        Ok(())
+    }
+
+    /// Download a file whose download quota is exhausted.
+    ///
+    /// The quota is per file, so a server-side copy in the user's own Drive
+    /// can still be downloaded; the copy is deleted afterwards. If Drive
+    /// refuses the copy too, retry the original after a pause.
+    async fn download_over_quota(&self, file_id: &str, file_name: &str, destination: &Path) -> Result<()> {
+        let copy_name = format!("{} (ditrive download)", file_name);
+        match self.copy_file(file_id, &copy_name, "root").await {
+            Ok(copy_id) => {
+                let url = format!("{}/files/{}?alt=media&supportsAllDrives=true", Self::API_BASE, copy_id);
+                let result = self.download_media(&url, destination).await;
+                if let Err(e) = self.delete_permanently(&copy_id).await {
+                    warn!("Failed to delete temporary copy '{}': {}", copy_name, e);
+                }
+                match result {
+                    Err(DitriveError::QuotaExceeded(_)) => {}
+                    result => return result,
+                }
+            }
+            Err(e) => warn!("Could not copy {} to work around its download quota: {}", file_name, e),
+        }
+
+        let url = format!("{}/files/{}?alt=media&supportsAllDrives=true", Self::API_BASE, file_id);
+        for delay in QUOTA_RETRY_DELAYS {
+            info!("Retrying download of {} in {} seconds...", file_name, delay.as_secs());
+            tokio::time::sleep(delay).await;
+            match self.download_media(&url, destination).await {
+                Err(DitriveError::QuotaExceeded(_)) => continue,
+                result => return result,
+            }
+        }
+
+        Err(DitriveError::RetryExhausted {
+            attempts: QUOTA_RETRY_DELAYS.len() as u32 + 1,
+            message: format!(
+                "Google Drive's download quota for {} is exceeded. It usually resets within 24 hours; try again later",
+                file_name
+            ),
+        })
+    }
+
+    /// Delete a file outright, skipping the trash
+    pub async fn delete_permanently(&self, file_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to delete file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to delete file: {}", error)));
+        }
+
+        self.cache.forget(file_id);
+        debug!("Deleted {}", file_id);
+        Ok(())
+    }
+
+    /// Get the remote state of a Drive file, including trashed files
+    pub async fn get_remote_file(&self, file_id: &str) -> Result<RemoteFile> {
+        let response = self
+            .request(Method::GET, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("fields", RemoteFile::FIELDS)])
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to get file metadata: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to get file metadata: {}", error)));
+        }
+
+        response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))
+    }
+
+    /// Mark a revision to be kept forever instead of expiring after 30 days
+    pub async fn keep_revision(&self, file_id: &str, revision_id: &str) -> Result<()> {
+        let response = self
+            .client
+            .patch(format!("{}/files/{}/revisions/{}", Self::API_BASE, file_id, revision_id))
+            .bearer_auth(self.access_token().await?)
+            .json(&serde_json::json!({ "keepForever": true }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to update revision: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to update revision: {}", error)));
+        }
 
-        info!("Downloaded {} to {:?}", file_name, destination);
         Ok(())
     }
 
-    /// Check if a file exists in Drive
+    /// Make a file readable by anyone with the link
+    pub async fn share_with_anyone(&self, file_id: &str) -> Result<()> {
+        self.create_permission(file_id, serde_json::json!({ "type": "anyone", "role": "reader" }))
+            .await
+    }
+
+    /// Grant a user access to a file, optionally expiring at the given time
+    pub async fn share_with_user(
+        &self,
+        file_id: &str,
+        email: &str,
+        role: &str,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<()> {
+        let mut permission = serde_json::json!({
+            "type": "user",
+            "role": role,
+            "emailAddress": email,
+        });
+        if let Some(expires_at) = expires_at {
+            permission["expirationTime"] = serde_json::json!(expires_at.to_rfc3339());
+        }
+        self.create_permission(file_id, permission).await
+    }
+
+    /// Create a permission on a Drive file
+    async fn create_permission(&self, file_id: &str, permission: serde_json::Value) -> Result<()> {
+        let response = self
+            .request(Method::POST, format!("{}/files/{}/permissions", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("sendNotificationEmail", "false")])
+            .json(&permission)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to create permission: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to create permission: {}", error)));
+        }
+
+        debug!("Created permission on {}", file_id);
+        Ok(())
+    }
+
+    /// Everyone with access to a Drive file, following pagination
+    pub async fn list_permissions(&self, file_id: &str) -> Result<Vec<Permission>> {
+        let fields = format!("nextPageToken,permissions({})", Permission::FIELDS);
+        let mut permissions = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .request(Method::GET, format!("{}/files/{}/permissions", Self::API_BASE, file_id))
+                .bearer_auth(self.access_token().await?)
+                .query(&[("fields", fields.as_str()), ("pageSize", "100")]);
+            if let Some(ref token) = page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+
+            let response = request
+                .send_traced()
+                .await
+                .map_err(|e| DitriveError::Drive(format!("Failed to list permissions: {}", e)))?;
+
+            if !response.status().is_success() {
+                let error = response.text().await.unwrap_or_default();
+                return Err(DitriveError::Drive(format!("Failed to list permissions: {}", error)));
+            }
+
+            let list: PermissionList = response.json().await
+                .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+            permissions.extend(list.permissions);
+
+            match list.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => return Ok(permissions),
+            }
+        }
+    }
+
+    /// Remove a permission from a Drive file
+    pub async fn delete_permission(&self, file_id: &str, permission_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, format!("{}/files/{}/permissions/{}", Self::API_BASE, file_id, permission_id))
+            .bearer_auth(self.access_token().await?)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to delete permission: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to delete permission: {}", error)));
+        }
+
+        debug!("Deleted permission {} on {}", permission_id, file_id);
+        Ok(())
+    }
+
+    /// Move a trashed file back out of the Drive trash
+    pub async fn untrash_file(&self, file_id: &str) -> Result<RemoteFile> {
+        let response = self
+            .request(Method::PATCH, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("fields", RemoteFile::FIELDS)])
+            .json(&serde_json::json!({ "trashed": false }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to untrash file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to untrash file: {}", error)));
+        }
+
+        info!("Restored file {} from Drive trash", file_id);
+        response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))
+    }
+
+    /// Storage used and available in the signed-in account. Shared Drives
+    /// don't count against it, so there is nothing to report for them.
+    pub async fn storage_quota(&self) -> Result<Option<StorageQuota>> {
+        if self.shared_drive_id.is_some() {
+            return Ok(None);
+        }
+
+        let response = self
+            .request(Method::GET, format!("{}/about", Self::API_BASE))
+            .bearer_auth(self.access_token().await?)
+            .query(&[("fields", "storageQuota(limit,usage)")])
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to get storage quota: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to get storage quota: {}", error)));
+        }
+
+        let about: AboutResponse = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+        Ok(Some(about.storage_quota.into()))
+    }
+
+    /// Check many files at once, answering in the order given. Each ID is
+    /// asked about once, with several requests in flight.
+    pub async fn files_exist(&self, file_ids: &[String]) -> Vec<bool> {
+        let unique: HashSet<String> = file_ids.iter().cloned().collect();
+        let found: HashMap<String, bool> = stream::iter(unique)
+            .map(|id| async move {
+                let exists = self.file_exists(&id).await;
+                (id, exists)
+            })
+            .buffer_unordered(CONCURRENT_LOOKUPS)
+            .collect()
+            .await;
+        file_ids.iter().map(|id| found[id]).collect()
+    }
+
+    /// Check if a file exists in Drive. Files seen recently are taken on trust.
     pub async fn file_exists(&self, file_id: &str) -> bool {
-        self.client
-            .get(&format!("{}/files/{}", Self::API_BASE, file_id))
-            .bearer_auth(&self.access_token)
+        self.cache.exists(file_id) || self.file_exists_uncached(file_id).await
+    }
+
+    /// Check if a file exists in Drive, asking Drive even if it was seen recently
+    pub async fn file_exists_uncached(&self, file_id: &str) -> bool {
+        let Ok(access_token) = self.access_token().await else {
+            return false;
+        };
+        let exists = self
+            .request(Method::GET, format!("{}/files/{}", Self::API_BASE, file_id))
+            .bearer_auth(access_token)
             .query(&[("fields", "id")])
-            .send()
+            .send_traced()
             .await
             .map(|r| r.status().is_success())
-            .unwrap_or(false)
+            .unwrap_or(false);
+        if exists {
+            self.cache.set_exists(file_id);
+        }
+        exists
+    }
+}
+
+#[async_trait]
+impl StorageBackend for DriveManager {
+    fn name(&self) -> &'static str {
+        "Google Drive"
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        DriveManager::repo_folder_id(self)
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        DriveManager::upload_file(self, file_path, repo_path).await
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        match self.layout {
+            StorageLayout::Mirror => DriveManager::update_file(self, file_id, file_path).await,
+            // Objects are never rewritten, since other paths and older versions
+            // may point at them; the repository path only places mirrored uploads
+            StorageLayout::Flat => DriveManager::upload_file(self, file_path, Path::new("")).await,
+        }
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        DriveManager::download_file(self, file_id, destination).await
+    }
+
+    async fn download_version(&self, metadata: &FileMetadata, destination: &Path) -> Result<()> {
+        // Entries written before revisions were recorded, or taken from the
+        // file's current content, have no revision to hold to
+        if metadata.revision_id.is_empty() {
+            return DriveManager::download_file(self, &metadata.id, destination).await;
+        }
+        pinned_or_current(
+            metadata,
+            destination,
+            self.download_revision(&metadata.id, &metadata.revision_id, metadata.size, destination),
+            DriveManager::download_file(self, &metadata.id, destination),
+        )
+        .await
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        DriveManager::file_exists(self, file_id).await
+    }
+
+    async fn file_exists_uncached(&self, file_id: &str) -> bool {
+        DriveManager::file_exists_uncached(self, file_id).await
+    }
+
+    async fn files_exist(&self, file_ids: &[String]) -> Vec<bool> {
+        DriveManager::files_exist(self, file_ids).await
+    }
+
+    async fn prepare_folders(&mut self, rel_dirs: &[PathBuf]) -> Result<()> {
+        match self.layout {
+            StorageLayout::Mirror => self.resolve_folders(rel_dirs).await,
+            StorageLayout::Flat => Ok(()),
+        }
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        // Trash rather than delete outright, so a mistake stays recoverable
+        self.trash_file(file_id).await
+    }
+
+    async fn delete_permanently(&self, file_id: &str) -> Result<()> {
+        DriveManager::delete_permanently(self, file_id).await
     }
+
+    async fn move_to_trash(&mut self, file_id: &str, _trash_path: &str) -> Result<String> {
+        // Drive's own trash keeps the file where it was, under the same ID
+        self.trash_file(file_id).await?;
+        Ok(file_id.to_string())
+    }
+
+    async fn restore_from_trash(&mut self, trash_id: &str, _rel_path: &str) -> Result<String> {
+        Ok(self.untrash_file(trash_id).await?.id)
+    }
+
+    async fn content_hash(&self, file_id: &str) -> Result<Option<String>> {
+        Ok(self.get_remote_file(file_id).await?.sha256_checksum)
+    }
+
+    async fn list_files(&self) -> Result<Option<Vec<RemoteEntry>>> {
+        Ok(Some(self.list_repo_files().await?))
+    }
+
+    async fn pin_current_version(&self, metadata: &FileMetadata) -> Result<Option<String>> {
+        if !metadata.revision_id.is_empty() {
+            return Ok(Some(metadata.revision_id.clone()));
+        }
+
+        // Entries written before version tracking don't know their revision
+        let remote = self.get_remote_file(&metadata.id).await?;
+        match remote.head_revision_id {
+            Some(revision_id) => {
+                self.keep_revision(&metadata.id, &revision_id).await?;
+                Ok(Some(revision_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn storage_quota(&self) -> Result<Option<StorageQuota>> {
+        self.storage_quota().await
+    }
+}
+
+/// Download pinned content with `pinned`. When Drive won't serve the revision,
+/// as for collaborators with the reader role or once its download quota is
+/// used up, fall back to `current`, the file's current content, which has to
+/// match the tracked hash so an edit made in Drive still can't slip through.
+async fn pinned_or_current(
+    metadata: &FileMetadata,
+    destination: &Path,
+    pinned: impl Future<Output = Result<()>>,
+    current: impl Future<Output = Result<()>>,
+) -> Result<()> {
+    match pinned.await {
+        Err(e @ (DitriveError::AccessDenied(_) | DitriveError::QuotaExceeded(_))) => {
+            warn!(
+                "Can't download revision {} of {} ({}); downloading its current content instead",
+                metadata.revision_id, metadata.id, e
+            );
+        }
+        result => return result,
+    }
+
+    current.await?;
+    if !metadata.hash.is_empty() && calculate_file_hash(destination)? != metadata.hash {
+        let _ = fs::remove_file(destination);
+        return Err(DitriveError::Drive(format!(
+            "{} was changed in Drive since it was uploaded, and its pinned revision can't be downloaded",
+            metadata.id
+        )));
+    }
+    Ok(())
 }
 
-/// Base64 URL-safe encoding without padding
-fn base64_url_encode(data: &[u8]) -> String {
-    base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, data)
+/// Drive folder ID named by a publish target: a bare folder ID, or the
+/// folder's `https://drive.google.com` link. Other URLs are refused.
+pub fn folder_id_from_target(target: &str) -> Result<String> {
+    let is_folder_id = |id: &str| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !target.contains("://") {
+        return if is_folder_id(target) {
+            Ok(target.to_string())
+        } else {
+            Err(DitriveError::Config(format!("'{}' is not a Google Drive folder ID", target)))
+        };
+    }
+
+    let url = reqwest::Url::parse(target)
+        .map_err(|e| DitriveError::Config(format!("Invalid publish target '{}': {}", target, e)))?;
+    if url.scheme() != "https" || url.host_str() != Some("drive.google.com") {
+        return Err(DitriveError::Config(format!(
+            "Publishing to '{}' is not supported; use a Google Drive folder ID or https://drive.google.com link",
+            target
+        )));
+    }
+
+    // Links look like /drive/folders/<id>, /drive/u/0/folders/<id> or /open?id=<id>
+    let segments: Vec<&str> = url.path_segments().map(Iterator::collect).unwrap_or_default();
+    let from_path = segments
+        .iter()
+        .position(|segment| *segment == "folders")
+        .and_then(|index| segments.get(index + 1))
+        .map(|id| id.to_string());
+    let from_query = || url.query_pairs().find(|(key, _)| key == "id").map(|(_, id)| id.into_owned());
+    from_path
+        .or_else(from_query)
+        .filter(|id| is_folder_id(id))
+        .ok_or_else(|| DitriveError::Config(format!("'{}' doesn't link to a Google Drive folder", target)))
 }
 
-/// Sign data with RS256 (RSA-SHA256)
-fn sign_rs256(data: &str, private_key_pem: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
-    use sha2::Sha256;
-    use rsa::{RsaPrivateKey, pkcs8::DecodePrivateKey};
-    use rsa::signature::{SignatureEncoding, Signer};
-    use rsa::pkcs1v15::SigningKey;
+/// Escape a value for use inside a quoted Drive search query
+fn escape_query_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
 
-    let private_key = RsaPrivateKey::from_pkcs8_pem(private_key_pem)?;
-    let signing_key = SigningKey::<Sha256>::new(private_key);
-    let signature = signing_key.sign(data.as_bytes());
-    
-    Ok(base64_url_encode(&signature.to_bytes()))
+/// Create a byte progress bar for a file transfer
+pub fn transfer_progress_bar(total: u64, message: String) -> Transfer {
+    Transfer::start(total, message)
 }
 
 #[cfg(test)]
@@ -507,6 +1908,54 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_folder_id_from_target() {
+        assert_eq!(folder_id_from_target("1AbC-d_E").unwrap(), "1AbC-d_E");
+        assert_eq!(
+            folder_id_from_target("https://drive.google.com/drive/u/0/folders/1AbC?usp=sharing").unwrap(),
+            "1AbC"
+        );
+        assert_eq!(folder_id_from_target("https://drive.google.com/open?id=1AbC").unwrap(), "1AbC");
+        assert!(folder_id_from_target("s3://bucket/mirror").is_err());
+        assert!(folder_id_from_target("http://drive.google.com/drive/folders/1AbC").is_err());
+        assert!(folder_id_from_target("https://example.com/drive/folders/1AbC").is_err());
+        assert!(folder_id_from_target("https://drive.google.com/drive/my-drive").is_err());
+        assert!(folder_id_from_target("../escape").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_revision_falls_back_to_matching_current_content() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("level.bin");
+        let source = dir.path().join("source.bin");
+        fs::write(&source, b"uploaded").unwrap();
+        let metadata = FileMetadata {
+            id: "file".to_string(),
+            hash: calculate_file_hash(&source).unwrap(),
+            revision_id: "rev".to_string(),
+            ..Default::default()
+        };
+        let current = |content: &'static [u8]| {
+            let destination = destination.clone();
+            async move { fs::write(destination, content).map_err(DitriveError::from) }
+        };
+
+        // A reader can't fetch revisions, but unchanged content is fine
+        let denied = async { Err(DitriveError::AccessDenied("insufficientFilePermissions".to_string())) };
+        pinned_or_current(&metadata, &destination, denied, current(b"uploaded")).await.unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), b"uploaded");
+
+        // Content edited in Drive since the upload is refused
+        let quota = async { Err(DitriveError::QuotaExceeded("revision".to_string())) };
+        assert!(pinned_or_current(&metadata, &destination, quota, current(b"edited")).await.is_err());
+        assert!(!destination.exists());
+
+        // Other failures aren't papered over
+        let broken = async { Err(DitriveError::Drive("revision deleted".to_string())) };
+        assert!(pinned_or_current(&metadata, &destination, broken, current(b"uploaded")).await.is_err());
+        assert!(!destination.exists());
+    }
+
     #[test]
     fn test_calculate_file_hash() {
         let dir = tempdir().unwrap();
@@ -517,4 +1966,114 @@ mod tests {
         assert!(!hash.is_empty());
         assert_eq!(hash.len(), 64); // SHA-256 produces 64 hex characters
     }
+
+    #[test]
+    fn test_remote_file_deserialize() {
+        let json = r#"{"id":"abc","name":"a.bin","size":"42","trashed":true,"sha256Checksum":"ff"}"#;
+        let remote: RemoteFile = serde_json::from_str(json).unwrap();
+        assert!(remote.trashed);
+        assert_eq!(remote.sha256_checksum.as_deref(), Some("ff"));
+
+        let remote: RemoteFile = serde_json::from_str(r#"{"id":"abc"}"#).unwrap();
+        assert!(!remote.trashed);
+        assert_eq!(remote.modified_at(), 0);
+
+        let json = r#"{"id":"f","mimeType":"application/vnd.google-apps.folder","modifiedTime":"2024-01-02T03:04:05.000Z"}"#;
+        let folder: RemoteFile = serde_json::from_str(json).unwrap();
+        assert!(folder.is_folder());
+        assert_eq!(folder.modified_at(), 1704164645);
+    }
+
+    #[test]
+    fn test_parse_service_account_key() {
+        use base64::Engine;
+
+        let json = r#"{"client_email":"bot@proj.iam.gserviceaccount.com","private_key":"secret","token_uri":"https://oauth2.googleapis.com/token"}"#;
+        let key = parse_service_account_key(json).unwrap();
+        assert_eq!(key.client_email, "bot@proj.iam.gserviceaccount.com");
+        assert!(!format!("{:?}", key).contains("secret"));
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(json);
+        let (head, tail) = encoded.split_at(20);
+        let key = parse_service_account_key(&format!("{}\n{}\n", head, tail)).unwrap();
+        assert_eq!(key.token_uri, "https://oauth2.googleapis.com/token");
+
+        assert!(parse_service_account_key("not a key").is_err());
+    }
+
+    #[test]
+    fn test_escape_query_value() {
+        assert_eq!(escape_query_value("it's"), "it\\'s");
+        assert_eq!(escape_query_value("a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn test_resolve_mime_type_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert(".GLB".to_string(), "model/gltf-binary".to_string());
+        overrides.insert("docx".to_string(), "application/vnd.google-apps.document".to_string());
+
+        assert_eq!(resolve_mime_type(Path::new("a/scene.glb"), &overrides), "model/gltf-binary");
+        assert_eq!(resolve_mime_type(Path::new("notes.docx"), &overrides), "application/octet-stream");
+        assert_eq!(resolve_mime_type(Path::new("image.png"), &overrides), "image/png");
+    }
+
+    #[test]
+    fn test_upload_name_hash_suffix() {
+        let hash = "3fa2c1d9e8b7";
+        assert_eq!(upload_name("model.bin", hash, true), "model.bin.3fa2c1");
+        assert_eq!(upload_name("model.bin", hash, false), "model.bin");
+        assert_eq!(upload_name("model.bin", "", true), "model.bin");
+
+        assert_eq!(clean_name("model.bin.3fa2c1", hash), "model.bin");
+        assert_eq!(clean_name("model.bin", hash), "model.bin");
+        assert_eq!(clean_name("model.bin.3fa2c1", "ffffff"), "model.bin.3fa2c1");
+    }
+
+    #[test]
+    fn test_folder_levels() {
+        let dirs = [PathBuf::from("assets/textures"), PathBuf::from("assets/audio"), PathBuf::from("")];
+        let levels = folder_levels(&dirs);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].iter().collect::<Vec<_>>(), [Path::new("assets")]);
+        assert_eq!(
+            levels[1].iter().collect::<Vec<_>>(),
+            [Path::new("assets/audio"), Path::new("assets/textures")]
+        );
+    }
+
+    #[test]
+    fn test_verify_upload_md5() {
+        let sent = hex::encode(Md5::digest(b"level data"));
+        assert_eq!(verify_upload_md5("a.bin", &sent, Some(sent.to_uppercase())).unwrap(), sent.to_uppercase());
+        assert!(verify_upload_md5("a.bin", &sent, Some("0".repeat(32))).is_err());
+        assert_eq!(verify_upload_md5("a.bin", &sent, None).unwrap(), "");
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        assert_eq!(byte_ranges(10, 4), [(0, 3), (4, 7), (8, 9)]);
+        assert_eq!(byte_ranges(8, 4), [(0, 3), (4, 7)]);
+        assert_eq!(byte_ranges(3, 4), [(0, 2)]);
+        assert!(byte_ranges(0, 4).is_empty());
+    }
+
+    #[test]
+    fn test_is_download_quota_error() {
+        let body = r#"{"error":{"errors":[{"domain":"usageLimits","reason":"downloadQuotaExceeded"}],"code":403}}"#;
+        assert!(is_download_quota_error(reqwest::StatusCode::FORBIDDEN, body));
+        assert!(!is_download_quota_error(reqwest::StatusCode::FORBIDDEN, r#"{"error":{"code":403}}"#));
+        assert!(!is_download_quota_error(reqwest::StatusCode::TOO_MANY_REQUESTS, body));
+    }
+
+    #[test]
+    fn test_token_refreshes_within_margin() {
+        let token = CachedToken {
+            value: "ya29.token".to_string(),
+            expires_at: 10_000,
+        };
+        assert!(!token.needs_refresh(10_000 - REFRESH_MARGIN_SECS - 1));
+        assert!(token.needs_refresh(10_000 - REFRESH_MARGIN_SECS));
+        assert!(token.needs_refresh(20_000));
+    }
 }
@@ -0,0 +1,328 @@
+//! Drive lookups remembered between runs
+//!
+//! Every command used to look the repository folder up again, resolve each
+//! subfolder by name, and ask Drive about every managed file. Folder IDs and
+//! confirmed file existence are kept in `~/.ditrive/drive-cache.json` for
+//! `drive_cache_ttl_minutes` (10 by default), so `status`, `list` and repeated
+//! syncs skip those calls. Entries are kept per account and root folder, so
+//! one file serves every repository without a different sign-in answering
+//! from another's lookups. Saves take a lock and merge with what other runs
+//! wrote meanwhile. Content hashes are never cached: conflict checks always
+//! ask Drive.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::error::Result;
+use crate::tracker;
+
+/// Cache file name in the global config directory
+pub const DRIVE_CACHE_FILE: &str = "drive-cache.json";
+
+/// How long lookups are trusted unless the global settings say otherwise
+pub const DEFAULT_TTL_MINUTES: u64 = 10;
+
+/// A looked-up value and when it was fetched, as a Unix timestamp
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry<T> {
+    value: T,
+    fetched_at: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheData {
+    /// Folder IDs by "parent ID/name"
+    #[serde(default)]
+    folders: HashMap<String, Entry<String>>,
+    /// Files and folders known to exist and not be trashed, by ID
+    #[serde(default)]
+    existing: HashMap<String, Entry<()>>,
+    /// IDs forgotten this run, dropped from what other runs saved too
+    #[serde(skip)]
+    forgotten: HashSet<String>,
+}
+
+impl CacheData {
+    fn forget(&mut self, id: &str) {
+        self.existing.remove(id);
+        self.folders.retain(|_, entry| entry.value != id);
+    }
+
+    /// Take in entries from `other` looked up since `since`, keeping the
+    /// newer of two lookups
+    fn merge(&mut self, other: &CacheData, since: i64) {
+        for (key, entry) in other.folders.iter().filter(|(_, entry)| entry.fetched_at >= since) {
+            match self.folders.get(key) {
+                Some(ours) if ours.fetched_at >= entry.fetched_at => {}
+                _ => {
+                    self.folders.insert(key.clone(), entry.clone());
+                }
+            }
+        }
+        for (key, entry) in other.existing.iter().filter(|(_, entry)| entry.fetched_at >= since) {
+            match self.existing.get(key) {
+                Some(ours) if ours.fetched_at >= entry.fetched_at => {}
+                _ => {
+                    self.existing.insert(key.clone(), entry.clone());
+                }
+            }
+        }
+    }
+
+    fn retain_newer_than(&mut self, cutoff: i64) {
+        self.folders.retain(|_, entry| entry.fetched_at > cutoff);
+        self.existing.retain(|_, entry| entry.fetched_at > cutoff);
+    }
+}
+
+/// The cache file: lookups by account and root folder
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    #[serde(default)]
+    scopes: HashMap<String, CacheData>,
+}
+
+impl CacheFile {
+    fn read(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Folder IDs and file existence, shared by a DriveManager's requests
+#[derive(Debug)]
+pub struct DriveCache {
+    /// Where the cache is saved; None keeps it in memory for this run only
+    path: Option<PathBuf>,
+    /// Account and root folder the lookups belong to
+    scope: String,
+    /// Entries older than this came from the file and are already in it
+    loaded_at: i64,
+    ttl_secs: i64,
+    data: Mutex<CacheData>,
+    changed: AtomicBool,
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+impl DriveCache {
+    /// A cache for this run only, as when persisting is turned off
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            scope: String::new(),
+            loaded_at: 0,
+            ttl_secs: i64::MAX,
+            data: Mutex::new(CacheData::default()),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Load the lookups for `scope` from the cache at `path`, trusting
+    /// entries for `ttl_minutes`; 0 turns persisting off
+    pub fn load(path: PathBuf, ttl_minutes: u64, scope: &str) -> Self {
+        if ttl_minutes == 0 {
+            return Self::in_memory();
+        }
+        let data = CacheFile::read(&path).scopes.remove(scope).unwrap_or_default();
+        Self {
+            path: Some(path),
+            scope: scope.to_string(),
+            loaded_at: now(),
+            ttl_secs: i64::try_from(ttl_minutes * 60).unwrap_or(i64::MAX),
+            data: Mutex::new(data),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    fn data(&self) -> std::sync::MutexGuard<'_, CacheData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_fresh<T>(&self, entry: &Entry<T>) -> bool {
+        now().saturating_sub(entry.fetched_at) < self.ttl_secs
+    }
+
+    /// ID of the folder `name` under `parent_id`, if looked up recently
+    pub fn folder(&self, parent_id: &str, name: &str) -> Option<String> {
+        let data = self.data();
+        data.folders
+            .get(&format!("{}/{}", parent_id, name))
+            .filter(|entry| self.is_fresh(entry))
+            .map(|entry| entry.value.clone())
+    }
+
+    pub fn set_folder(&self, parent_id: &str, name: &str, folder_id: &str) {
+        let entry = Entry { value: folder_id.to_string(), fetched_at: now() };
+        self.data().folders.insert(format!("{}/{}", parent_id, name), entry);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a file or folder was recently confirmed to exist
+    pub fn exists(&self, id: &str) -> bool {
+        self.data().existing.get(id).is_some_and(|entry| self.is_fresh(entry))
+    }
+
+    pub fn set_exists(&self, id: &str) {
+        self.data().existing.insert(id.to_string(), Entry { value: (), fetched_at: now() });
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Drop everything known about an ID, after it was trashed or deleted
+    pub fn forget(&self, id: &str) {
+        let mut data = self.data();
+        data.forget(id);
+        data.forgotten.insert(id.to_string());
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Save the cache if it changed, leaving out expired entries.
+    ///
+    /// The file is locked while it is re-read and rewritten, so lookups
+    /// saved by another run in the meantime are merged rather than lost.
+    pub fn save(&self) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        if !self.changed.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock = File::create(path.with_extension("json.lock"))?;
+        lock.lock()?;
+
+        let mut file = CacheFile::read(path);
+        let cutoff = now().saturating_sub(self.ttl_secs);
+        let data = self.data();
+        let scope = file.scopes.entry(self.scope.clone()).or_default();
+        for id in &data.forgotten {
+            scope.forget(id);
+        }
+        scope.merge(&data, self.loaded_at);
+        for scope in file.scopes.values_mut() {
+            scope.retain_newer_than(cutoff);
+        }
+        file.scopes.retain(|_, scope| !scope.folders.is_empty() || !scope.existing.is_empty());
+        tracker::write_atomically(path, serde_json::to_string(&file)?.as_bytes())?;
+        lock.unlock()?;
+
+        debug!("Saved Drive cache with {} folders and {} files", data.folders.len(), data.existing.len());
+        Ok(())
+    }
+}
+
+impl Drop for DriveCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            warn!("Couldn't save the Drive cache: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lookups_survive_a_reload() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DRIVE_CACHE_FILE);
+
+        let cache = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "account");
+        cache.set_folder("root", "assets", "folder-1");
+        cache.set_exists("file-1");
+        cache.set_exists("file-2");
+        cache.forget("file-2");
+        drop(cache);
+
+        let cache = DriveCache::load(path, DEFAULT_TTL_MINUTES, "account");
+        assert_eq!(cache.folder("root", "assets").as_deref(), Some("folder-1"));
+        assert_eq!(cache.folder("root", "other"), None);
+        assert!(cache.exists("file-1"));
+        assert!(!cache.exists("file-2"));
+    }
+
+    #[test]
+    fn test_expired_entries_are_not_trusted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DRIVE_CACHE_FILE);
+        let stale = now() - 3600;
+        let content = serde_json::json!({"scopes": {"account": {
+            "folders": {"root/assets": {"value": "folder-1", "fetched_at": stale}},
+            "existing": {"file-1": {"value": null, "fetched_at": stale}},
+        }}});
+        std::fs::write(&path, content.to_string()).unwrap();
+
+        let cache = DriveCache::load(path, 10, "account");
+        assert_eq!(cache.folder("root", "assets"), None);
+        assert!(!cache.exists("file-1"));
+    }
+
+    #[test]
+    fn test_zero_ttl_keeps_nothing_on_disk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DRIVE_CACHE_FILE);
+
+        let cache = DriveCache::load(path.clone(), 0, "account");
+        cache.set_folder("root", "assets", "folder-1");
+        assert_eq!(cache.folder("root", "assets").as_deref(), Some("folder-1"));
+        drop(cache);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_accounts_keep_separate_lookups_in_one_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DRIVE_CACHE_FILE);
+
+        let work = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "work");
+        let home = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "home");
+        work.set_exists("file-1");
+        home.set_exists("file-2");
+        drop(work);
+        drop(home);
+
+        let work = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "work");
+        assert!(work.exists("file-1"));
+        assert!(!work.exists("file-2"));
+        let home = DriveCache::load(path, DEFAULT_TTL_MINUTES, "home");
+        assert!(home.exists("file-2"));
+        assert!(!home.exists("file-1"));
+    }
+
+    #[test]
+    fn test_concurrent_saves_merge() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DRIVE_CACHE_FILE);
+
+        let seed = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "account");
+        seed.set_exists("file-3");
+        drop(seed);
+
+        let first = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "account");
+        let second = DriveCache::load(path.clone(), DEFAULT_TTL_MINUTES, "account");
+        first.set_exists("file-1");
+        second.set_exists("file-2");
+        second.forget("file-3");
+        drop(first);
+        drop(second);
+
+        let cache = DriveCache::load(path, DEFAULT_TTL_MINUTES, "account");
+        assert!(cache.exists("file-1"));
+        assert!(cache.exists("file-2"));
+        assert!(!cache.exists("file-3"));
+    }
+}
@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::i18n::tr_with;
+
 /// Main error type for ditrive operations
 #[derive(Error, Debug)]
 pub enum DitriveError {
@@ -38,6 +40,30 @@ pub enum DitriveError {
     #[error("Authentication error: {0}")]
     Auth(String),
 
+    #[error("Download quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Not enough storage space: {0}")]
+    StorageFull(String),
+
+    #[error("Download failed malware scan: {0}")]
+    Quarantined(String),
+
+    #[error("Tracker signature check failed: {0}")]
+    Signature(String),
+
+    #[error("Git LFS error: {0}")]
+    Lfs(String),
+
+    #[error("Repository is locked: {0}")]
+    Locked(String),
+
+    #[error("Check failed: {0}")]
+    CheckFailed(String),
+
     #[error("Operation cancelled by user")]
     Cancelled,
 
@@ -45,4 +71,39 @@ pub enum DitriveError {
     RetryExhausted { attempts: u32, message: String },
 }
 
+impl DitriveError {
+    /// The error message in the configured language
+    pub fn localized(&self) -> String {
+        let (id, details) = match self {
+            DitriveError::Config(d) => ("error-config", d.clone()),
+            DitriveError::Git(e) => ("error-git", e.to_string()),
+            DitriveError::Io(e) => ("error-io", e.to_string()),
+            DitriveError::Json(e) => ("error-json", e.to_string()),
+            DitriveError::Http(e) => ("error-http", e.to_string()),
+            DitriveError::WalkDir(e) => ("error-walkdir", e.to_string()),
+            DitriveError::Drive(d) => ("error-drive", d.clone()),
+            DitriveError::GitHub(d) => ("error-github", d.clone()),
+            DitriveError::FileNotFound(d) => ("error-file-not-found", d.clone()),
+            DitriveError::NotGitRepo(d) => ("error-not-git-repo", d.clone()),
+            DitriveError::Auth(d) => ("error-auth", d.clone()),
+            DitriveError::QuotaExceeded(d) => ("error-quota-exceeded", d.clone()),
+            DitriveError::AccessDenied(d) => ("error-access-denied", d.clone()),
+            DitriveError::StorageFull(d) => ("error-storage-full", d.clone()),
+            DitriveError::Quarantined(d) => ("error-quarantined", d.clone()),
+            DitriveError::Signature(d) => ("error-signature", d.clone()),
+            DitriveError::Lfs(d) => ("error-lfs", d.clone()),
+            DitriveError::Locked(d) => ("error-locked", d.clone()),
+            DitriveError::CheckFailed(d) => ("error-check-failed", d.clone()),
+            DitriveError::Cancelled => ("error-cancelled", String::new()),
+            DitriveError::RetryExhausted { attempts, message } => {
+                return tr_with(
+                    "error-retry-exhausted",
+                    &[("attempts", (*attempts).into()), ("details", message.as_str().into())],
+                );
+            }
+        };
+        tr_with(id, &[("details", details.into())])
+    }
+}
+
 pub type Result<T> = std::result::Result<T, DitriveError>;
@@ -0,0 +1,114 @@
+//! Time-based expiry rules for scratch assets
+//!
+//! Rules such as `scratch/** expires 30d` live in the repository config's
+//! `expire_rules`. `ditrive gc` removes managed files whose last upload is
+//! older than the first rule matching their path, so throwaway renders and
+//! intermediate data don't pile up in remote storage forever.
+
+use glob::{MatchOptions, Pattern};
+
+use crate::error::{DitriveError, Result};
+
+/// Keyword separating a rule's glob from its time to live
+const EXPIRES_KEYWORD: &str = "expires";
+
+/// A glob and how long files matching it are kept
+#[derive(Debug, Clone)]
+pub struct ExpireRule {
+    pattern: Pattern,
+    /// Time to live in seconds
+    ttl: i64,
+}
+
+impl ExpireRule {
+    /// Parse a rule of the form `<glob> expires <duration>`, e.g. `renders/*.exr expires 2w`
+    pub fn parse(rule: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            DitriveError::Config(format!("Invalid expire rule '{}': {}", rule, reason))
+        };
+
+        let parts: Vec<&str> = rule.split_whitespace().collect();
+        let [glob, keyword, ttl] = parts[..] else {
+            return Err(invalid("expected '<glob> expires <duration>'"));
+        };
+        if keyword != EXPIRES_KEYWORD {
+            return Err(invalid("expected '<glob> expires <duration>'"));
+        }
+
+        Ok(Self {
+            pattern: Pattern::new(glob).map_err(|e| invalid(&e.to_string()))?,
+            ttl: parse_ttl(ttl).ok_or_else(|| invalid("durations look like 90m, 12h, 30d or 2w"))?,
+        })
+    }
+
+    /// Whether the rule applies to a repository-relative path
+    pub fn matches(&self, rel_path: &str) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.pattern.matches_with(rel_path, options)
+    }
+}
+
+/// Parse every configured rule, failing on the first invalid one
+pub fn parse_rules(rules: &[String]) -> Result<Vec<ExpireRule>> {
+    rules.iter().map(|rule| ExpireRule::parse(rule)).collect()
+}
+
+/// Parse a duration such as `30d` into seconds
+pub fn parse_ttl(text: &str) -> Option<i64> {
+    let unit = text.chars().last()?;
+    let amount: i64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    amount.checked_mul(seconds).filter(|ttl| *ttl > 0)
+}
+
+/// When a file uploaded at `uploaded_at` expires under the first matching rule.
+///
+/// Files with no recorded upload time never expire, since their age is unknown.
+pub fn expires_at(rules: &[ExpireRule], rel_path: &str, uploaded_at: i64) -> Option<i64> {
+    if uploaded_at <= 0 {
+        return None;
+    }
+    rules
+        .iter()
+        .find(|rule| rule.matches(rel_path))
+        .map(|rule| uploaded_at.saturating_add(rule.ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_matching_rule_sets_expiry() {
+        let rules = parse_rules(&[
+            "scratch/keep/** expires 52w".to_string(),
+            "scratch/** expires 30d".to_string(),
+        ])
+        .unwrap();
+
+        let day = 24 * 60 * 60;
+        assert_eq!(expires_at(&rules, "scratch/render.exr", 1000), Some(1000 + 30 * day));
+        assert_eq!(expires_at(&rules, "scratch/keep/ref.exr", 1000), Some(1000 + 52 * 7 * day));
+        assert_eq!(expires_at(&rules, "assets/hero.png", 1000), None);
+        assert_eq!(expires_at(&rules, "scratch/render.exr", 0), None);
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        assert_eq!(parse_ttl("90m"), Some(90 * 60));
+        assert_eq!(parse_ttl("0d"), None);
+        assert_eq!(parse_ttl("30"), None);
+        assert!(ExpireRule::parse("scratch/** 30d").is_err());
+        assert!(ExpireRule::parse("scratch/** expires soon").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Link manifests for handing managed files to people who don't run ditrive
+
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// One managed file in an exported link manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkEntry {
+    pub path: String,
+    pub id: String,
+    pub size: u64,
+    pub sha256: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+impl LinkEntry {
+    /// Browser download link, usable once the file is shared
+    pub fn shared_url(file_id: &str) -> String {
+        format!("https://drive.google.com/uc?id={}&export=download", file_id)
+    }
+
+    /// Drive web page for a file, for people with access to it
+    pub fn view_url(file_id: &str) -> String {
+        format!("https://drive.google.com/file/d/{}/view", file_id)
+    }
+
+    /// API download link, which requires an authenticated request
+    pub fn api_url(file_id: &str) -> String {
+        format!("https://www.googleapis.com/drive/v3/files/{}?alt=media", file_id)
+    }
+}
+
+/// Render a manifest as pretty-printed JSON
+pub fn render_json(entries: &[LinkEntry]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(entries)?)
+}
+
+/// Render a manifest as CSV with a header row
+pub fn render_csv(entries: &[LinkEntry]) -> String {
+    let mut out = String::from("path,id,size,sha256,url,expires_at\n");
+
+    for entry in entries {
+        let fields = [
+            csv_field(&entry.path),
+            csv_field(&entry.id),
+            entry.size.to_string(),
+            csv_field(&entry.sha256),
+            csv_field(&entry.url),
+            csv_field(entry.expires_at.as_deref().unwrap_or_default()),
+        ];
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Quote a CSV field if it contains separators, quotes or newlines
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_csv_escapes_fields() {
+        let entries = vec![LinkEntry {
+            path: "assets/a,b \"final\".bin".to_string(),
+            id: "abc".to_string(),
+            size: 42,
+            sha256: "ff".to_string(),
+            url: LinkEntry::shared_url("abc"),
+            expires_at: None,
+        }];
+
+        let csv = render_csv(&entries);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("\"assets/a,b \"\"final\"\".bin\",abc,42,ff,"));
+        assert!(row.ends_with(','));
+    }
+}
@@ -0,0 +1,194 @@
+//! Local cache of file hashes keyed on size and modification time
+//!
+//! Hashing every managed file on each run is slow once files reach several
+//! GB. `.git/ditrive/hash-cache.json` remembers each file's size, mtime and
+//! hash, so only files whose size or mtime changed are read again. Files
+//! modified in the last couple of seconds aren't cached, since a write in the
+//! same clock tick could leave the mtime unchanged. Outside a git repository
+//! nothing is cached.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::drive::calculate_file_hash;
+use crate::error::Result;
+use crate::paths;
+use crate::tracker;
+
+/// Cache location, relative to the repository root
+pub const HASH_CACHE_PATH: &str = ".git/ditrive/hash-cache.json";
+
+/// Files modified more recently than this are hashed but not cached
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// What a file looked like when it was hashed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedHash {
+    size: u64,
+    /// Modification time in nanoseconds since the epoch
+    mtime_ns: u64,
+    hash: String,
+}
+
+/// Hashes of repository files, by repository-relative path
+#[derive(Debug, Default)]
+pub struct HashCache {
+    repo_path: PathBuf,
+    entries: BTreeMap<String, CachedHash>,
+    changed: bool,
+}
+
+/// Size and mtime of a file, as the cache compares them
+fn stat(path: &Path) -> Result<(u64, SystemTime)> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.len(), metadata.modified()?))
+}
+
+fn nanos_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+impl HashCache {
+    /// Load a repository's hash cache; a missing or unreadable one is empty
+    pub fn load(repo_path: &Path) -> Self {
+        let entries = fs::read_to_string(repo_path.join(HASH_CACHE_PATH))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Hash files, reading only those whose size or mtime changed since they
+    /// were last hashed. Results come back in the order given.
+    pub fn hashes<P: AsRef<Path> + Sync>(&mut self, files: &[P]) -> Vec<Result<String>> {
+        let now = SystemTime::now();
+        let looked_up: Vec<_> = files
+            .par_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let rel_path = paths::repo_relative(&self.repo_path, path);
+                let (size, mtime) = stat(path)?;
+                let mtime_ns = nanos_since_epoch(mtime);
+                let cached = self
+                    .entries
+                    .get(&rel_path)
+                    .filter(|entry| entry.size == size && entry.mtime_ns == mtime_ns)
+                    .map(|entry| entry.hash.clone());
+                let hash = match cached {
+                    Some(hash) => return Ok((rel_path, None, hash)),
+                    None => calculate_file_hash(path)?,
+                };
+                let settled = now.duration_since(mtime).is_ok_and(|age| age >= RACY_WINDOW);
+                let entry = settled.then(|| CachedHash { size, mtime_ns, hash: hash.clone() });
+                Ok((rel_path, entry, hash))
+            })
+            .collect();
+
+        looked_up
+            .into_iter()
+            .map(|result: Result<_>| {
+                let (rel_path, entry, hash) = result?;
+                if let Some(entry) = entry {
+                    self.entries.insert(rel_path, entry);
+                    self.changed = true;
+                }
+                Ok(hash)
+            })
+            .collect()
+    }
+
+    /// Hash one file through the cache
+    pub fn hash(&mut self, path: &Path) -> Result<String> {
+        self.hashes(&[path]).pop().unwrap_or_else(|| Ok(String::new()))
+    }
+
+    /// Write the cache back if it changed, dropping files that no longer exist
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed || !self.repo_path.join(".git").is_dir() {
+            return Ok(());
+        }
+
+        let repo_path = &self.repo_path;
+        self.entries.retain(|rel_path, _| repo_path.join(rel_path).exists());
+        let cache_path = repo_path.join(HASH_CACHE_PATH);
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        tracker::write_atomically(&cache_path, serde_json::to_string(&self.entries)?.as_bytes())?;
+        self.changed = false;
+        debug!("Saved {} cached hashes", self.entries.len());
+        Ok(())
+    }
+}
+
+/// Hash files through the repository's cache, saving it afterwards
+pub fn cached_hashes<P: AsRef<Path> + Sync>(repo_path: &Path, files: &[P]) -> Vec<Result<String>> {
+    let mut cache = HashCache::load(repo_path);
+    let hashes = cache.hashes(files);
+    if let Err(e) = cache.save() {
+        warn!("Couldn't save the hash cache: {}", e);
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::FileTimes;
+    use tempfile::tempdir;
+
+    /// Backdate a file's mtime past the racy window, so its hash is cached
+    fn settle(path: &Path, secs_since_epoch: u64) {
+        let mtime = UNIX_EPOCH + Duration::from_secs(secs_since_epoch);
+        let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.set_times(FileTimes::new().set_modified(mtime)).unwrap();
+    }
+
+    #[test]
+    fn test_unchanged_files_come_from_the_cache() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        let file = dir.path().join("level.bin");
+        fs::write(&file, "original").unwrap();
+        settle(&file, 1_700_000_000);
+        let original = calculate_file_hash(&file).unwrap();
+
+        let hashes = cached_hashes(dir.path(), &[&file]);
+        assert_eq!(hashes[0].as_ref().unwrap(), &original);
+        assert!(dir.path().join(HASH_CACHE_PATH).is_file());
+
+        // Same size and mtime: the cached hash is trusted without reading the file
+        fs::write(&file, "replaced").unwrap();
+        settle(&file, 1_700_000_000);
+        assert_eq!(cached_hashes(dir.path(), &[&file])[0].as_ref().unwrap(), &original);
+
+        // A new mtime means the file is read again
+        settle(&file, 1_700_000_100);
+        assert_eq!(
+            cached_hashes(dir.path(), &[&file])[0].as_ref().unwrap(),
+            &calculate_file_hash(&file).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recently_modified_files_are_not_cached() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        let file = dir.path().join("level.bin");
+        fs::write(&file, "fresh").unwrap();
+
+        let mut cache = HashCache::load(dir.path());
+        assert!(cache.hashes(&[&file])[0].is_ok());
+        assert!(cache.entries.is_empty());
+        assert!(cache.hashes(&[dir.path().join("missing.bin")])[0].is_err());
+    }
+}
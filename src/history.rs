@@ -0,0 +1,247 @@
+//! Audit log of what ditrive did to stored files
+//!
+//! Every upload, download, deletion and sharing change is appended to
+//! `~/.ditrive/history.log`, one JSON object per line, with who did it and
+//! the ID of the stored file, so a team can reconstruct what happened to an
+//! asset. `ditrive log` reads it back with filters. Unlike the usage journal
+//! it is on by default; set `history_log` to false in the global settings to
+//! stop recording. Past `MAX_BYTES` the log rotates like `--log-file` does,
+//! so the oldest entries are eventually dropped.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::config::GlobalConfig;
+use crate::error::Result;
+use crate::git;
+use crate::log_file::{self, RotatingFile, ROTATED_FILES};
+use crate::report::{Action, SyncReport};
+
+/// What was done to a stored file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryAction {
+    Upload,
+    /// New content for an already stored file
+    Update,
+    Download,
+    /// The stored copy was deleted or moved to the trash
+    Delete,
+    /// Someone was given access
+    Share,
+    /// Someone's access was revoked
+    Unshare,
+}
+
+/// One recorded action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: i64,
+    /// Git user email of whoever ran ditrive, else their login name
+    pub user: String,
+    pub repository: String,
+    pub action: HistoryAction,
+    /// Repository-relative path; empty for the repository folder itself
+    pub path: String,
+    /// ID of the file or folder in the backend
+    pub id: String,
+    pub bytes: u64,
+    /// E.g. who was given access, and what role
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+}
+
+impl HistoryEntry {
+    /// Entries for the transfers and deletions in a sync report
+    pub fn from_report(user: &str, repository: &str, report: &SyncReport, timestamp: i64) -> Vec<Self> {
+        report
+            .actions
+            .iter()
+            .filter_map(|action| {
+                let kind = match action.action {
+                    Action::Uploaded => HistoryAction::Upload,
+                    Action::Updated => HistoryAction::Update,
+                    Action::Downloaded => HistoryAction::Download,
+                    Action::Deleted => HistoryAction::Delete,
+                    Action::Tracked | Action::Adopted | Action::Skipped => return None,
+                };
+                Some(Self {
+                    timestamp,
+                    user: user.to_string(),
+                    repository: repository.to_string(),
+                    action: kind,
+                    path: action.path.clone(),
+                    id: action.id.clone().unwrap_or_default(),
+                    bytes: action.size.unwrap_or(0),
+                    detail: String::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Who to record as doing things in a repository: the git user email, else the login name
+pub fn current_user(repo_path: &Path) -> String {
+    git2::Repository::open(repo_path)
+        .and_then(|repo| repo.config())
+        .and_then(|config| config.get_string("user.email"))
+        .ok()
+        .filter(|email| !email.is_empty())
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Who to credit for an upload: "Name <email>" from the git config, else `current_user`
+pub fn uploader(repo_path: &Path) -> String {
+    git2::Repository::open(repo_path)
+        .and_then(|repo| repo.signature())
+        .ok()
+        .and_then(|signature| match (signature.name(), signature.email()) {
+            (Some(name), Some(email)) if !email.is_empty() => Some(format!("{} <{}>", name, email)),
+            _ => None,
+        })
+        .unwrap_or_else(|| current_user(repo_path))
+}
+
+/// Size at which the history log is rotated
+const MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Path of the history log
+fn history_path() -> Result<PathBuf> {
+    Ok(GlobalConfig::config_dir()?.join("history.log"))
+}
+
+/// Append entries to the history log
+pub fn append(entries: &[HistoryEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = RotatingFile::open(&history_path()?, MAX_BYTES)?;
+    for entry in entries {
+        // One write per line, so rotation never splits an entry
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read every entry, rotated files included, oldest first, skipping lines that don't parse
+pub fn read_all() -> Result<Vec<HistoryEntry>> {
+    let path = history_path()?;
+    let files = (1..=ROTATED_FILES)
+        .rev()
+        .map(|index| log_file::rotated_path(&path, index))
+        .chain(std::iter::once(path.clone()));
+
+    let mut entries = Vec::new();
+    for file in files.filter(|file| file.exists()) {
+        let content = fs::read_to_string(file)?;
+        entries.extend(
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Skipping unreadable history line: {}", e);
+                        None
+                    }
+                }),
+        );
+    }
+    Ok(entries)
+}
+
+/// Which entries `ditrive log` shows
+#[derive(Debug, Default)]
+pub struct HistoryFilter {
+    /// Only this repository; None for every repository
+    pub repository: Option<String>,
+    /// `.gitattributes`-style pattern the path must match
+    pub path: Option<String>,
+    pub actions: Vec<HistoryAction>,
+    /// Substring of the user
+    pub user: Option<String>,
+    /// Only entries at or after this Unix timestamp
+    pub since: Option<i64>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.repository.as_ref().is_none_or(|repository| entry.repository == *repository)
+            && self.path.as_ref().is_none_or(|pattern| git::pattern_matches(pattern, &entry.path))
+            && (self.actions.is_empty() || self.actions.contains(&entry.action))
+            && self.user.as_ref().is_none_or(|user| entry.user.contains(user.as_str()))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: i64, action: HistoryAction, path: &str, user: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            user: user.to_string(),
+            repository: "game".to_string(),
+            action,
+            path: path.to_string(),
+            id: format!("id-{}", path),
+            bytes: 10,
+            detail: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let entries = [
+            entry(100, HistoryAction::Upload, "art/hero.psd", "ana@example.com"),
+            entry(200, HistoryAction::Download, "art/hero.psd", "bo@example.com"),
+            entry(300, HistoryAction::Delete, "level.bin", "ana@example.com"),
+        ];
+        let shown = |filter: HistoryFilter| {
+            entries.iter().filter(|e| filter.matches(e)).map(|e| e.timestamp).collect::<Vec<_>>()
+        };
+
+        assert_eq!(shown(HistoryFilter::default()), vec![100, 200, 300]);
+        assert_eq!(
+            shown(HistoryFilter {
+                path: Some("*.psd".to_string()),
+                ..Default::default()
+            }),
+            vec![100, 200]
+        );
+        assert_eq!(
+            shown(HistoryFilter {
+                user: Some("ana".to_string()),
+                since: Some(150),
+                ..Default::default()
+            }),
+            vec![300]
+        );
+        assert_eq!(
+            shown(HistoryFilter {
+                actions: vec![HistoryAction::Download],
+                repository: Some("other".to_string()),
+                ..Default::default()
+            }),
+            Vec::<i64>::new()
+        );
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let line = serde_json::to_string(&entry(100, HistoryAction::Share, "", "ana@example.com")).unwrap();
+        assert!(line.contains(r#""action":"share""#));
+        assert!(!line.contains("detail"));
+        let parsed: HistoryEntry = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.action, HistoryAction::Share);
+    }
+}
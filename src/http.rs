@@ -0,0 +1,120 @@
+//! Proxy and TLS settings shared by every HTTP client
+//!
+//! reqwest already honors `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and
+//! `NO_PROXY`. The `network` section of the global config adds a proxy that
+//! needs credentials, and extra root certificates for networks that intercept
+//! TLS with a corporate CA. Clients are built with `client` or
+//! `client_builder` so that they all pick these up.
+
+use reqwest::{Certificate, Client, ClientBuilder, NoProxy, Proxy};
+use std::fs;
+use std::sync::OnceLock;
+
+use crate::config::NetworkConfig;
+use crate::error::{DitriveError, Result};
+
+static NETWORK: OnceLock<Network> = OnceLock::new();
+
+/// Network settings, parsed and ready to apply to a client
+#[derive(Debug, Clone, Default)]
+struct Network {
+    proxy: Option<Proxy>,
+    certificates: Vec<Certificate>,
+}
+
+impl Network {
+    fn from_config(config: &NetworkConfig) -> Result<Self> {
+        let proxy = match config.proxy_url.as_str() {
+            "" => None,
+            url => {
+                let mut proxy = Proxy::all(url)
+                    .map_err(|e| DitriveError::Config(format!("Invalid proxy URL '{}': {}", url, e)))?;
+                if !config.proxy_username.is_empty() {
+                    proxy = proxy.basic_auth(&config.proxy_username, &config.proxy_password);
+                }
+                // A configured proxy replaces the environment's, so keep its exceptions
+                let no_proxy = match config.no_proxy.as_str() {
+                    "" => NoProxy::from_env(),
+                    hosts => NoProxy::from_string(hosts),
+                };
+                Some(proxy.no_proxy(no_proxy))
+            }
+        };
+
+        let mut certificates = Vec::new();
+        for path in &config.ca_certificates {
+            let pem = fs::read(path)
+                .map_err(|e| DitriveError::Config(format!("Failed to read CA certificate '{}': {}", path, e)))?;
+            let bundle = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| DitriveError::Config(format!("Invalid CA certificate '{}': {}", path, e)))?;
+            if bundle.is_empty() {
+                return Err(DitriveError::Config(format!("No certificates found in '{}'", path)));
+            }
+            certificates.extend(bundle);
+        }
+
+        Ok(Self { proxy, certificates })
+    }
+
+    fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &self.certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    }
+}
+
+/// Use `config`'s proxy and certificates for the rest of the process
+pub fn init(config: &NetworkConfig) -> Result<()> {
+    let network = Network::from_config(config)?;
+    // Surface TLS setup problems now rather than on the first request
+    network.apply(Client::builder()).build()?;
+    let _ = NETWORK.set(network);
+    Ok(())
+}
+
+/// A client builder with the configured proxy and certificates
+pub fn client_builder() -> ClientBuilder {
+    match NETWORK.get() {
+        Some(network) => network.apply(Client::builder()),
+        None => Client::builder(),
+    }
+}
+
+/// A client with the configured proxy and certificates
+pub fn client() -> Client {
+    client_builder()
+        .build()
+        .expect("HTTP client settings are checked by http::init")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_from_config() {
+        let network = Network::from_config(&NetworkConfig::default()).unwrap();
+        assert!(network.proxy.is_none() && network.certificates.is_empty());
+
+        let config = NetworkConfig {
+            proxy_url: "http://proxy.corp:3128".to_string(),
+            proxy_username: "builder".to_string(),
+            proxy_password: "secret".to_string(),
+            no_proxy: "localhost,.corp".to_string(),
+            ..Default::default()
+        };
+        let network = Network::from_config(&config).unwrap();
+        assert!(network.proxy.is_some());
+        assert!(network.apply(Client::builder()).build().is_ok());
+
+        let missing_ca = NetworkConfig {
+            ca_certificates: vec!["/nonexistent/corp-ca.pem".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(Network::from_config(&missing_ca), Err(DitriveError::Config(_))));
+    }
+}
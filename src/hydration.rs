@@ -0,0 +1,130 @@
+//! Download order for `pull` from `hydration-order.toml`
+//!
+//! A repository can commit a `hydration-order.toml` listing globs in the
+//! order their files are needed:
+//!
+//! ```toml
+//! order = ["configs/**", "models/**", "videos/**"]
+//! ```
+//!
+//! Missing files are downloaded group by group, so a CI job can start its
+//! first build steps while the large, late files are still arriving. Files
+//! matching none of the globs come last.
+
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::error::{DitriveError, Result};
+
+/// Name of the hydration order file at the repository root
+pub const HYDRATION_ORDER_FILE: &str = "hydration-order.toml";
+
+#[derive(Debug, Default, Deserialize)]
+struct HydrationOrderFile {
+    #[serde(default)]
+    order: Vec<String>,
+}
+
+/// Priority groups for downloading missing files
+#[derive(Debug, Default)]
+pub struct HydrationOrder {
+    patterns: Vec<Pattern>,
+}
+
+impl HydrationOrder {
+    /// Load the repository's hydration order, or an empty one if it has none
+    pub fn load(repo_path: &Path) -> Result<Self> {
+        let path = repo_path.join(HYDRATION_ORDER_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse the contents of a hydration order file
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: HydrationOrderFile = toml::from_str(content).map_err(|e| {
+            DitriveError::Config(format!("Invalid {}: {}", HYDRATION_ORDER_FILE, e))
+        })?;
+
+        let patterns = file
+            .order
+            .iter()
+            .filter_map(|glob| match Pattern::new(glob) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    warn!("Ignoring invalid hydration glob '{}': {}", glob, e);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Self { patterns })
+    }
+
+    /// Whether any priorities are configured
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Group of a repository-relative path; lower groups are downloaded first
+    pub fn rank(&self, rel_path: &str) -> usize {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.patterns
+            .iter()
+            .position(|pattern| pattern.matches_with(rel_path, options))
+            .unwrap_or(self.patterns.len())
+    }
+
+    /// Stable-sort `items` by the group of the path `key` returns for each
+    pub fn sort_by_priority<T>(&self, items: &mut [T], key: impl Fn(&T) -> String) {
+        if self.is_empty() {
+            return;
+        }
+        items.sort_by_cached_key(|item| self.rank(&key(item)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_are_ordered_by_first_matching_glob() {
+        let order = HydrationOrder::parse(r#"order = ["configs/**", "models/*.onnx", "videos/**"]"#).unwrap();
+
+        let mut files = vec![
+            "videos/intro.mp4",
+            "README.bin",
+            "models/nested/big.onnx",
+            "models/small.onnx",
+            "configs/app/settings.bin",
+        ];
+        order.sort_by_priority(&mut files, |path| path.to_string());
+
+        assert_eq!(
+            files,
+            vec![
+                "configs/app/settings.bin",
+                "models/small.onnx",
+                "videos/intro.mp4",
+                "README.bin",
+                "models/nested/big.onnx",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_order_keeps_files_in_place() {
+        let order = HydrationOrder::parse("").unwrap();
+        assert!(order.is_empty());
+        assert_eq!(order.rank("anything.bin"), 0);
+        assert!(HydrationOrder::parse("order = 3").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Localized user-facing messages using Fluent
+//!
+//! Catalogs live in `locales/<language>.ftl` and are compiled into the binary.
+//! English is the source catalog: messages missing from a translation fall
+//! back to it, and `ditrive messages <language>` prints the English entries a
+//! translation still lacks as a starting point for translators.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use std::sync::OnceLock;
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// Language of the source catalog
+pub const SOURCE_LANGUAGE: &str = "en";
+
+/// Bundled catalogs by language
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("id", include_str!("../locales/id.ftl")),
+];
+
+/// Environment variable that overrides the configured language
+const LANGUAGE_ENV: &str = "DITRIVE_LANG";
+
+static LOCALIZER: OnceLock<Localizer> = OnceLock::new();
+
+/// Selected catalog plus the English fallback
+struct Localizer {
+    bundle: FluentBundle<FluentResource>,
+    fallback: FluentBundle<FluentResource>,
+}
+
+impl Localizer {
+    fn new(language: &str) -> Self {
+        Self {
+            bundle: load_bundle(language),
+            fallback: load_bundle(SOURCE_LANGUAGE),
+        }
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        [&self.bundle, &self.fallback]
+            .into_iter()
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = Vec::new();
+                let text = bundle.format_pattern(pattern, args, &mut errors);
+                Some(text.into_owned())
+            })
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+/// Build a bundle for one bundled catalog
+fn load_bundle(language: &str) -> FluentBundle<FluentResource> {
+    let source = catalog(language).unwrap_or_else(|| catalog(SOURCE_LANGUAGE).unwrap_or_default());
+    let langid: LanguageIdentifier = language.parse().unwrap_or_default();
+
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Unicode isolation marks around arguments show up as junk in terminals
+    bundle.set_use_isolating(false);
+
+    let resource = FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, errors)| {
+        warn!("Catalog '{}' has {} syntax errors", language, errors.len());
+        resource
+    });
+    if let Err(errors) = bundle.add_resource(resource) {
+        warn!("Catalog '{}' has {} duplicate messages", language, errors.len());
+    }
+    bundle
+}
+
+/// Source text of a bundled catalog
+fn catalog(language: &str) -> Option<&'static str> {
+    CATALOGS
+        .iter()
+        .find(|(lang, _)| *lang == language)
+        .map(|(_, source)| *source)
+}
+
+/// Pick the language to use.
+///
+/// `DITRIVE_LANG` wins over the configured language, which wins over the
+/// usual POSIX locale variables. Unknown languages fall back to English.
+pub fn resolve_language(configured: &str) -> String {
+    let candidates = [
+        std::env::var(LANGUAGE_ENV).ok(),
+        Some(configured.to_string()),
+        std::env::var("LC_ALL").ok(),
+        std::env::var("LC_MESSAGES").ok(),
+        std::env::var("LANG").ok(),
+    ];
+
+    candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|value| match_language(&value))
+        .next()
+        .unwrap_or(SOURCE_LANGUAGE)
+        .to_string()
+}
+
+/// Match a locale string such as "id_ID.UTF-8" to a bundled catalog
+fn match_language(value: &str) -> Option<&'static str> {
+    let language = value
+        .split(['.', '@'])
+        .next()?
+        .split(['_', '-'])
+        .next()?
+        .to_lowercase();
+
+    CATALOGS
+        .iter()
+        .map(|(lang, _)| *lang)
+        .find(|lang| *lang == language)
+}
+
+/// Select the language for this process. Later calls have no effect.
+pub fn init(configured: &str) {
+    let language = resolve_language(configured);
+    let _ = LOCALIZER.set(Localizer::new(&language));
+}
+
+/// Localized text of a message
+pub fn tr(id: &str) -> String {
+    localizer().format(id, None)
+}
+
+/// Localized text of a message with arguments
+pub fn tr_with(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    localizer().format(id, Some(&fluent_args))
+}
+
+/// The active localizer, defaulting to English if `init` was never called
+fn localizer() -> &'static Localizer {
+    LOCALIZER.get_or_init(|| Localizer::new(SOURCE_LANGUAGE))
+}
+
+/// Languages with a bundled catalog
+pub fn available_languages() -> Vec<&'static str> {
+    CATALOGS.iter().map(|(lang, _)| *lang).collect()
+}
+
+/// English catalog entries that `language` doesn't translate yet, as FTL source
+pub fn untranslated_template(language: &str) -> Option<String> {
+    let translated = catalog(language)?;
+    let translated_ids: Vec<&str> = message_blocks(translated).map(|(id, _)| id).collect();
+
+    let template = message_blocks(catalog(SOURCE_LANGUAGE)?)
+        .filter(|(id, _)| !translated_ids.contains(id))
+        .map(|(_, block)| block)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(template)
+}
+
+/// Split FTL source into `(message id, source block)` pairs.
+///
+/// A block is the `id = ...` line plus its indented continuation lines.
+fn message_blocks(source: &str) -> impl Iterator<Item = (&str, String)> {
+    let mut blocks: Vec<(&str, String)> = Vec::new();
+
+    for line in source.lines() {
+        let is_continuation = line.starts_with(' ') || line.starts_with('\t');
+        if is_continuation {
+            if let Some((_, block)) = blocks.last_mut() {
+                block.push('\n');
+                block.push_str(line);
+            }
+        } else if let Some((id, _)) = line.split_once(" =") {
+            if !id.starts_with('#') && !id.is_empty() {
+                blocks.push((id.trim(), line.to_string()));
+            }
+        }
+    }
+
+    blocks.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_language_and_fallback() {
+        assert_eq!(match_language("id_ID.UTF-8"), Some("id"));
+        assert_eq!(match_language("en-GB"), Some("en"));
+        assert_eq!(match_language("xx_YY"), None);
+
+        let localizer = Localizer::new("id");
+        let text = localizer.format("error-prefix", None);
+        assert!(!text.is_empty() && text != "error-prefix");
+        assert_eq!(localizer.format("no-such-message", None), "no-such-message");
+    }
+
+    #[test]
+    fn test_catalogs_cover_source_messages() {
+        // Every message used in the source must exist in the English catalog
+        let english: Vec<(&str, String)> = message_blocks(catalog(SOURCE_LANGUAGE).unwrap()).collect();
+        let sources = [
+            include_str!("app.rs"),
+            include_str!("error.rs"),
+            include_str!("main.rs"),
+        ];
+
+        for source in sources {
+            for call in ["tr(\"", "tr_with(\""] {
+                for (start, _) in source.match_indices(call) {
+                    let preceding = source[..start].chars().last().unwrap_or(' ');
+                    if preceding.is_alphanumeric() || preceding == '_' {
+                        continue;
+                    }
+                    let rest = &source[start + call.len()..];
+                    let id = &rest[..rest.find('"').unwrap()];
+                    assert!(english.iter().any(|(known, _)| *known == id), "missing message '{}'", id);
+                }
+            }
+        }
+
+        // And every bundled catalog must parse cleanly
+        for (language, source) in CATALOGS {
+            assert!(FluentResource::try_new(source.to_string()).is_ok(), "{} catalog has errors", language);
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Persistent index of large-file candidates and tracker state
+//!
+//! `status` and `list` read this index instead of hashing files and parsing
+//! every `.woilah` on each invocation. The index is kept current either by
+//! `ditrive watch`, which applies filesystem events as they happen, or by a
+//! stat-only refresh that re-reads just the `.woilah` files that changed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, UNIX_EPOCH};
+use notify::{RecursiveMode, Watcher};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::config::{LargeFileRules, RepoConfig};
+use crate::ditriveignore::{DitriveIgnore, DITRIVEIGNORE_FILENAME};
+use crate::drive::FileMetadata;
+use crate::error::{DitriveError, Result};
+use crate::paths;
+use crate::trace;
+use crate::tracker::{WoilahTracker, MANIFEST_PATH, WOILAH_FILENAME};
+
+/// Index location, relative to the repository root
+const INDEX_PATH: &str = ".git/ditrive/index.json";
+
+/// How long a watcher heartbeat keeps the index trusted without a refresh
+const HEARTBEAT_TTL_SECS: i64 = 15;
+
+/// How often a running watcher renews its heartbeat
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Quiet period used to batch bursts of filesystem events into one save
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Size and modification time of an indexed file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: i64,
+}
+
+impl FileStamp {
+    /// Stat a file, returning `None` if it no longer exists
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or_default();
+        Some(Self { size: metadata.len(), modified })
+    }
+}
+
+/// Parsed contents of one `.woilah` file or the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrackerEntry {
+    stamp: FileStamp,
+    files: BTreeMap<String, FileMetadata>,
+}
+
+/// On-disk index of a repository's large files and tracker state
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RepoIndex {
+    /// Rules the candidates were collected with
+    rules: LargeFileRules,
+    /// Files large enough to manage, keyed by repo-relative path
+    candidates: BTreeMap<String, FileStamp>,
+    /// `.woilah` files, keyed by repo-relative folder path
+    trackers: BTreeMap<String, TrackerEntry>,
+    /// The repository manifest, keyed by repo-relative file path
+    #[serde(default)]
+    manifest: Option<TrackerEntry>,
+    /// Last time a running watcher vouched for the index
+    #[serde(default)]
+    heartbeat: i64,
+    #[serde(skip)]
+    repo_path: PathBuf,
+    /// Paths never counted as candidates
+    #[serde(skip)]
+    ditriveignore: DitriveIgnore,
+}
+
+impl RepoIndex {
+    /// Load the index and bring it up to date.
+    ///
+    /// While a watcher is running the stored index is used as-is; otherwise
+    /// the tree is re-stat'ed and only changed `.woilah` files are re-read.
+    pub fn open(repo_path: &Path, rules: LargeFileRules) -> Result<Self> {
+        let mut index = Self::load(repo_path);
+
+        if index.rules != rules {
+            index.candidates.clear();
+            index.rules = rules;
+        } else if index.is_watched() {
+            debug!("Using watcher-maintained index");
+            return Ok(index);
+        }
+
+        index.refresh()?;
+        index.save()?;
+        Ok(index)
+    }
+
+    /// Load the stored index, or an empty one if missing or unreadable
+    pub fn load(repo_path: &Path) -> Self {
+        let index_path = repo_path.join(INDEX_PATH);
+        let mut index: Self = fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        index.repo_path = repo_path.to_path_buf();
+        index.ditriveignore = DitriveIgnore::load(repo_path);
+        index
+    }
+
+    /// Write the index to disk.
+    ///
+    /// Outside a git repository the index lives only in memory.
+    pub fn save(&self) -> Result<()> {
+        if !self.repo_path.join(".git").is_dir() {
+            return Ok(());
+        }
+
+        let index_path = self.repo_path.join(INDEX_PATH);
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&index_path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Check if a watcher refreshed its heartbeat recently
+    fn is_watched(&self) -> bool {
+        chrono::Utc::now().timestamp() - self.heartbeat < HEARTBEAT_TTL_SECS
+    }
+
+    /// Record that a running watcher is keeping the index current
+    pub fn touch_heartbeat(&mut self) {
+        self.heartbeat = chrono::Utc::now().timestamp();
+    }
+
+    /// Drop the heartbeat so the next reader refreshes the index itself
+    pub fn clear_heartbeat(&mut self) {
+        self.heartbeat = 0;
+    }
+
+    /// Re-stat the whole tree, re-reading only `.woilah` files that changed
+    pub fn refresh(&mut self) -> Result<()> {
+        let _timer = trace::phase("scan", "index refresh");
+        let mut candidates = BTreeMap::new();
+        let mut trackers = BTreeMap::new();
+        let mut manifest = None;
+        let git_dir = self.repo_path.join(".git");
+
+        // Excluded folders are never descended into, so ignoring a large
+        // build output keeps the refresh from stat'ing everything under it
+        let ditriveignore = self.ditriveignore.clone();
+        for entry in WalkDir::new(&self.repo_path).into_iter().filter_entry(|e| {
+            let excluded = e.file_type().is_dir() && ditriveignore.is_excluded(e.path(), true);
+            !e.path().starts_with(&git_dir) && !excluded
+        }) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            let Some(stamp) = FileStamp::of(path) else { continue };
+
+            if self.relative(path) == MANIFEST_PATH {
+                manifest = match self.manifest.take() {
+                    Some(existing) if existing.stamp == stamp => Some(existing),
+                    _ => Some(self.read_tracker(path, stamp)?),
+                };
+            } else if entry.file_name() == WOILAH_FILENAME {
+                let folder = self.relative(path.parent().unwrap_or(path));
+                let tracker = match self.trackers.remove(&folder) {
+                    Some(existing) if existing.stamp == stamp => existing,
+                    _ => self.read_tracker(path, stamp)?,
+                };
+                trackers.insert(folder, tracker);
+            } else if self.is_candidate(path, stamp) {
+                candidates.insert(self.relative(path), stamp);
+            }
+        }
+
+        self.candidates = candidates;
+        self.trackers = trackers;
+        self.manifest = manifest;
+        Ok(())
+    }
+
+    /// Apply a change to a single path, as reported by the watcher
+    pub fn update_path(&mut self, path: &Path) -> Result<()> {
+        if path.starts_with(self.repo_path.join(".git")) {
+            return Ok(());
+        }
+
+        // A directory moved into place produces a single event; index its contents
+        if path.is_dir() {
+            for entry in WalkDir::new(path).min_depth(1) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    self.update_path(entry.path())?;
+                }
+            }
+            return Ok(());
+        }
+
+        // New patterns can change any candidate, so start over
+        if self.relative(path) == DITRIVEIGNORE_FILENAME {
+            self.ditriveignore = DitriveIgnore::load(&self.repo_path);
+            return self.refresh();
+        }
+
+        let stamp = FileStamp::of(path);
+
+        if self.relative(path) == MANIFEST_PATH {
+            self.manifest = stamp.map(|stamp| self.read_tracker(path, stamp)).transpose()?;
+            return Ok(());
+        }
+
+        if path.file_name().is_some_and(|n| n == WOILAH_FILENAME) {
+            let folder = self.relative(path.parent().unwrap_or(path));
+            match stamp {
+                Some(stamp) => {
+                    let tracker = self.read_tracker(path, stamp)?;
+                    self.trackers.insert(folder, tracker);
+                }
+                None => {
+                    self.trackers.remove(&folder);
+                }
+            }
+            return Ok(());
+        }
+
+        let rel_path = self.relative(path);
+        match stamp {
+            Some(stamp) if self.is_candidate(path, stamp) => {
+                self.candidates.insert(rel_path, stamp);
+            }
+            _ => {
+                // Removed, shrunk below the threshold, or a whole directory went away
+                self.candidates.remove(&rel_path);
+                let prefix = format!("{}/", rel_path);
+                self.candidates.retain(|p, _| !p.starts_with(&prefix));
+                self.trackers.retain(|p, _| p != &rel_path && !p.starts_with(&prefix));
+            }
+        }
+        Ok(())
+    }
+
+    /// All managed files, as recorded in the indexed `.woilah` files and manifest
+    pub fn managed_files(&self) -> Vec<(PathBuf, FileMetadata)> {
+        let manifest_files = self.manifest.iter().flat_map(|manifest| {
+            manifest
+                .files
+                .iter()
+                .map(|(rel_path, metadata)| (self.repo_path.join(rel_path), metadata.clone()))
+        });
+        self.trackers
+            .iter()
+            .flat_map(|(folder, tracker)| {
+                let folder_path = self.repo_path.join(folder);
+                tracker
+                    .files
+                    .iter()
+                    .map(move |(name, metadata)| (folder_path.join(name), metadata.clone()))
+            })
+            .chain(manifest_files)
+            .collect()
+    }
+
+    /// Files large enough to manage that aren't managed yet
+    pub fn pending_files(&self) -> Vec<PathBuf> {
+        self.candidates
+            .keys()
+            .filter(|rel_path| !self.is_managed(rel_path))
+            .map(|rel_path| self.repo_path.join(rel_path))
+            .collect()
+    }
+
+    /// Check if a repo-relative path has a tracker entry
+    fn is_managed(&self, rel_path: &str) -> bool {
+        let (folder, name) = rel_path.rsplit_once('/').unwrap_or(("", rel_path));
+        self.manifest.as_ref().is_some_and(|manifest| manifest.files.contains_key(rel_path))
+            || self
+                .trackers
+                .get(folder)
+                .is_some_and(|tracker| tracker.files.contains_key(name))
+    }
+
+    /// Check if a file should be listed as a large-file candidate
+    fn is_candidate(&self, path: &Path, stamp: FileStamp) -> bool {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        self.rules.is_large(&self.relative(path), stamp.size)
+            && !RepoConfig::is_config_file(&filename)
+            && !self.ditriveignore.is_excluded(path, false)
+    }
+
+    /// Parse a `.woilah` file or the manifest into an index entry
+    fn read_tracker(&self, tracker_path: &Path, stamp: FileStamp) -> Result<TrackerEntry> {
+        debug!("Indexing {:?}", tracker_path);
+        let content = fs::read_to_string(tracker_path)?;
+        let files = WoilahTracker::parse_woilah(&content, tracker_path).into_iter().collect();
+        Ok(TrackerEntry { stamp, files })
+    }
+
+    /// Repo-relative path with forward slashes
+    fn relative(&self, path: &Path) -> String {
+        paths::repo_relative(&self.repo_path, path)
+    }
+}
+
+/// Keep the index current by applying filesystem events until interrupted,
+/// calling `on_change` after each batch of events
+pub fn watch(repo_path: &Path, rules: LargeFileRules, mut on_change: impl FnMut(&RepoIndex)) -> Result<()> {
+    let mut index = RepoIndex::open(repo_path, rules)?;
+    index.touch_heartbeat();
+    index.save()?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .map_err(|e| DitriveError::Config(format!("Failed to start file watcher: {}", e)))?;
+    watcher
+        .watch(repo_path, RecursiveMode::Recursive)
+        .map_err(|e| DitriveError::Config(format!("Failed to watch {:?}: {}", repo_path, e)))?;
+
+    info!("Watching {:?} for changes (Ctrl+C to stop)", repo_path);
+
+    loop {
+        let mut changed = Vec::new();
+        match rx.recv_timeout(HEARTBEAT_INTERVAL) {
+            Ok(event) => changed.push(event),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            changed.push(event);
+        }
+        let had_events = !changed.is_empty();
+
+        for event in changed {
+            match event {
+                Ok(event) => {
+                    for path in &event.paths {
+                        index.update_path(path)?;
+                    }
+                }
+                // The event queue overflowed or similar; fall back to a full refresh
+                Err(e) => {
+                    warn!("File watcher error, rescanning: {}", e);
+                    index.refresh()?;
+                }
+            }
+        }
+
+        index.touch_heartbeat();
+        index.save()?;
+        if had_events {
+            on_change(&index);
+        }
+    }
+
+    index.clear_heartbeat();
+    index.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{ManifestTracker, Tracker};
+    use tempfile::tempdir;
+
+    fn threshold(bytes: u64) -> LargeFileRules {
+        LargeFileRules { threshold_bytes: bytes, ..Default::default() }
+    }
+
+    #[test]
+    fn test_index_tracks_pending_and_managed_files() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("assets")).unwrap();
+        fs::write(repo.join("assets/big.bin"), vec![0u8; 64]).unwrap();
+        fs::write(repo.join("assets/small.txt"), "hi").unwrap();
+
+        let index = RepoIndex::open(repo, threshold(16)).unwrap();
+        assert_eq!(index.pending_files(), vec![repo.join("assets/big.bin")]);
+        assert!(index.managed_files().is_empty());
+
+        let tracker = WoilahTracker::new(repo);
+        let metadata = FileMetadata { id: "id-1".to_string(), ..Default::default() };
+        tracker.add_file_mapping(&repo.join("assets"), "big.bin", metadata).unwrap();
+
+        let index = RepoIndex::open(repo, threshold(16)).unwrap();
+        assert!(index.pending_files().is_empty());
+        assert_eq!(index.managed_files().len(), 1);
+
+        // Entries in the manifest count as managed too
+        fs::write(repo.join("level.bin"), vec![0u8; 64]).unwrap();
+        let metadata = FileMetadata { id: "id-2".to_string(), ..Default::default() };
+        ManifestTracker::new(repo).add_file_mapping(repo, "level.bin", metadata).unwrap();
+
+        let index = RepoIndex::open(repo, threshold(16)).unwrap();
+        assert!(index.pending_files().is_empty());
+        assert_eq!(index.managed_files().len(), 2);
+    }
+
+    #[test]
+    fn test_ditriveignore_leaves_out_candidates() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("target/debug")).unwrap();
+        fs::write(repo.join("target/debug/game"), vec![0u8; 64]).unwrap();
+        fs::write(repo.join("level.bin"), vec![0u8; 64]).unwrap();
+
+        let mut index = RepoIndex::open(repo, threshold(16)).unwrap();
+        assert_eq!(index.pending_files().len(), 2);
+
+        fs::write(repo.join(DITRIVEIGNORE_FILENAME), "target/\n").unwrap();
+        index.update_path(&repo.join(DITRIVEIGNORE_FILENAME)).unwrap();
+        assert_eq!(index.pending_files(), vec![repo.join("level.bin")]);
+    }
+
+    #[test]
+    fn test_update_path_removes_deleted_files() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        let big = repo.join("big.bin");
+        fs::write(&big, vec![0u8; 64]).unwrap();
+
+        let mut index = RepoIndex::open(repo, threshold(16)).unwrap();
+        assert_eq!(index.pending_files().len(), 1);
+
+        fs::remove_file(&big).unwrap();
+        index.update_path(&big).unwrap();
+        assert!(index.pending_files().is_empty());
+    }
+}
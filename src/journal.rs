@@ -0,0 +1,200 @@
+//! Local operation journal and the usage report built from it
+//!
+//! With `usage_journal` enabled in the global settings, every upload,
+//! download, expiry and deletion is appended to `~/.ditrive/journal.jsonl`.
+//! `ditrive report` aggregates it into monthly stats for storage-budget
+//! discussions. The journal is opt-in and never leaves the machine.
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::config::GlobalConfig;
+use crate::error::Result;
+use crate::report::{Action, SyncReport};
+
+/// What happened to a file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Upload,
+    Update,
+    Download,
+    Expire,
+    /// A deleted file's stored copy was removed
+    Delete,
+}
+
+/// One journaled operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: i64,
+    pub repository: String,
+    pub operation: Operation,
+    pub path: String,
+    pub size: u64,
+}
+
+impl JournalEntry {
+    /// Entries for the transfers in a sync report
+    pub fn from_report(repository: &str, report: &SyncReport, timestamp: i64) -> Vec<Self> {
+        report
+            .actions
+            .iter()
+            .filter_map(|action| {
+                let operation = match action.action {
+                    Action::Uploaded => Operation::Upload,
+                    Action::Updated => Operation::Update,
+                    Action::Downloaded => Operation::Download,
+                    Action::Deleted => Operation::Delete,
+                    Action::Tracked | Action::Adopted | Action::Skipped => return None,
+                };
+                Some(Self {
+                    timestamp,
+                    repository: repository.to_string(),
+                    operation,
+                    path: action.path.clone(),
+                    size: action.size.unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Path of the journal file
+fn journal_path() -> Result<PathBuf> {
+    Ok(GlobalConfig::config_dir()?.join("journal.jsonl"))
+}
+
+/// Append entries to the journal
+pub fn append(entries: &[JournalEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let path = journal_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    Ok(())
+}
+
+/// Read every journal entry, skipping lines that don't parse
+pub fn read_all() -> Result<Vec<JournalEntry>> {
+    let path = journal_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("Skipping unreadable journal line: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Usage in one calendar month (UTC)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MonthlyStats {
+    pub uploaded_bytes: u64,
+    pub downloaded_bytes: u64,
+    pub uploads: usize,
+    pub downloads: usize,
+    /// Bytes added to remote storage minus bytes expired. Updates count in
+    /// full because earlier versions are kept.
+    pub storage_growth: i64,
+    /// Uploads and updates per path
+    pub churn: HashMap<String, usize>,
+}
+
+impl MonthlyStats {
+    /// The `limit` paths uploaded most often, most first
+    pub fn most_churned(&self, limit: usize) -> Vec<(&str, usize)> {
+        let mut churned: Vec<_> = self.churn.iter().map(|(path, n)| (path.as_str(), *n)).collect();
+        churned.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        churned.truncate(limit);
+        churned
+    }
+}
+
+/// Aggregate entries into stats keyed by month, such as "2026-10"
+pub fn monthly_stats<'a>(entries: impl IntoIterator<Item = &'a JournalEntry>) -> BTreeMap<String, MonthlyStats> {
+    let mut months: BTreeMap<String, MonthlyStats> = BTreeMap::new();
+
+    for entry in entries {
+        let month = DateTime::from_timestamp(entry.timestamp, 0)
+            .map(|t| t.format("%Y-%m").to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let stats = months.entry(month).or_default();
+
+        match entry.operation {
+            Operation::Upload | Operation::Update => {
+                stats.uploaded_bytes += entry.size;
+                stats.uploads += 1;
+                stats.storage_growth += entry.size as i64;
+                *stats.churn.entry(entry.path.clone()).or_default() += 1;
+            }
+            Operation::Download => {
+                stats.downloaded_bytes += entry.size;
+                stats.downloads += 1;
+            }
+            Operation::Expire | Operation::Delete => stats.storage_growth -= entry.size as i64,
+        }
+    }
+
+    months
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: i64, operation: Operation, path: &str, size: u64) -> JournalEntry {
+        JournalEntry {
+            timestamp,
+            repository: "game".to_string(),
+            operation,
+            path: path.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_monthly_stats() {
+        // 2026-09-15 and 2026-10-15
+        let september = 1_789_430_400;
+        let october = 1_792_022_400;
+        let entries = vec![
+            entry(september, Operation::Upload, "hero.png", 100),
+            entry(october, Operation::Upload, "level.bin", 1000),
+            entry(october, Operation::Update, "level.bin", 1200),
+            entry(october, Operation::Download, "hero.png", 100),
+            entry(october, Operation::Expire, "scratch.exr", 300),
+        ];
+
+        let months = monthly_stats(&entries);
+        assert_eq!(months.keys().collect::<Vec<_>>(), vec!["2026-09", "2026-10"]);
+
+        let stats = &months["2026-10"];
+        assert_eq!(stats.uploaded_bytes, 2200);
+        assert_eq!(stats.downloaded_bytes, 100);
+        assert_eq!(stats.storage_growth, 1900);
+        assert_eq!(stats.most_churned(5), vec![("level.bin", 2)]);
+    }
+}
@@ -0,0 +1,326 @@
+//! Importing files from Git LFS
+//!
+//! `ditrive import-lfs` moves a repository off LFS bandwidth quotas. Files
+//! matching the LFS patterns in `.gitattributes` are uploaded to the storage
+//! backend and tracked like any other managed file. Files still checked out
+//! as LFS pointers are fetched first: from git-lfs's local object store when
+//! it has them, otherwise from the remote's LFS batch API.
+//!
+//! `ditrive export-lfs` goes the other way, handing every managed file back
+//! to LFS with its own line in `.gitattributes`.
+
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+use crate::error::{DitriveError, Result};
+use crate::http;
+use crate::pointer;
+use crate::trace::TracedSend;
+
+/// First line of every LFS pointer file
+pub const LFS_POINTER_VERSION: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// Attributes git-lfs adds to a pattern; `-text` is only dropped along with them
+const LFS_ATTRIBUTES: [&str; 3] = ["filter=lfs", "diff=lfs", "merge=lfs"];
+
+/// LFS pointers are a few lines; anything larger is real content
+const MAX_POINTER_SIZE: u64 = 1024;
+
+const LFS_MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// What an LFS pointer file records about the object it stands for
+#[derive(Debug, Clone, PartialEq)]
+pub struct LfsPointer {
+    /// SHA-256 of the content
+    pub oid: String,
+    pub size: u64,
+}
+
+impl LfsPointer {
+    /// Parse LFS pointer content, returning None for anything else
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        if content.len() as u64 > MAX_POINTER_SIZE {
+            return None;
+        }
+
+        let text = std::str::from_utf8(content).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != LFS_POINTER_VERSION {
+            return None;
+        }
+
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            match line.split_once(' ')? {
+                ("oid", value) => oid = Some(value.strip_prefix("sha256:")?.to_string()),
+                ("size", value) => size = Some(value.parse().ok()?),
+                _ => {}
+            }
+        }
+        Some(Self { oid: oid?, size: size? })
+    }
+
+    /// Read an LFS pointer from a working tree file, if it is one
+    pub fn read_file(path: &Path) -> Option<Self> {
+        if fs::metadata(path).ok()?.len() > MAX_POINTER_SIZE {
+            return None;
+        }
+        Self::parse(&fs::read(path).ok()?)
+    }
+
+    /// Where git-lfs keeps this object once downloaded
+    pub fn object_path(&self, git_dir: &Path) -> PathBuf {
+        let oid = &self.oid;
+        git_dir
+            .join("lfs/objects")
+            .join(oid.get(..2).unwrap_or_default())
+            .join(oid.get(2..4).unwrap_or_default())
+            .join(oid)
+    }
+}
+
+/// Patterns `.gitattributes` routes through the LFS filter
+pub fn lfs_patterns(gitattributes: &str) -> Vec<String> {
+    gitattributes
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pattern = fields.next().filter(|p| !p.starts_with('#'))?;
+            fields.any(|attr| attr == "filter=lfs").then(|| pattern.to_string())
+        })
+        .collect()
+}
+
+/// `.gitattributes` with the LFS attributes removed, dropping lines left with nothing else
+pub fn strip_lfs_attributes(gitattributes: &str) -> String {
+    let mut stripped = String::new();
+    for line in gitattributes.lines() {
+        let is_lfs = !line.trim_start().starts_with('#') && line.split_whitespace().any(|attr| attr == "filter=lfs");
+        if !is_lfs {
+            stripped.push_str(line);
+            stripped.push('\n');
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let pattern = fields.next().unwrap_or_default();
+        let rest: Vec<&str> = fields.filter(|attr| !LFS_ATTRIBUTES.contains(attr)).collect();
+        if rest.iter().any(|attr| *attr != "-text") {
+            stripped.push_str(&format!("{} {}\n", pattern, rest.join(" ")));
+        }
+    }
+    stripped
+}
+
+/// Route a single file through the LFS filter
+pub fn add_attribute(repo_path: &Path, rel_path: &str) -> Result<()> {
+    let line = format!("{} {} -text", pointer::attribute_pattern(rel_path), LFS_ATTRIBUTES.join(" "));
+    pointer::add_attribute_line(repo_path, &line)
+}
+
+/// LFS server URL for a git remote, following git-lfs's default of `<remote>.git/info/lfs`
+pub fn endpoint_for_remote(remote_url: &str) -> Option<String> {
+    let url = remote_url.trim_end_matches('/');
+    let https = if url.starts_with("https://") || url.starts_with("http://") {
+        url.to_string()
+    } else if let Some(rest) = url.strip_prefix("ssh://") {
+        // ssh://git@host/owner/repo
+        let rest = rest.split_once('@').map_or(rest, |(_, host_path)| host_path);
+        format!("https://{}", rest)
+    } else {
+        // scp-like git@host:owner/repo
+        let (host, path) = url.split_once('@').map_or(url, |(_, host_path)| host_path).split_once(':')?;
+        format!("https://{}/{}", host, path)
+    };
+
+    let base = if https.ends_with(".git") { https } else { format!("{}.git", https) };
+    Some(format!("{}/info/lfs", base))
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    #[serde(default)]
+    objects: Vec<BatchObject>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchObject {
+    oid: String,
+    actions: Option<BatchActions>,
+    error: Option<BatchError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchActions {
+    download: Option<BatchAction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchAction {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchError {
+    code: u16,
+    message: String,
+}
+
+/// Downloads objects through an LFS server's batch API
+pub struct LfsClient {
+    client: Client,
+    endpoint: String,
+    credentials: Option<(String, String)>,
+}
+
+impl LfsClient {
+    pub fn new(endpoint: &str, credentials: Option<(&str, &str)>) -> Self {
+        Self {
+            client: http::client(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            credentials: credentials.map(|(user, pass)| (user.to_string(), pass.to_string())),
+        }
+    }
+
+    /// Download an object to `destination`, streaming it to disk and checking its hash
+    pub async fn download(&self, pointer: &LfsPointer, destination: &Path) -> Result<()> {
+        let action = self.download_action(pointer).await?;
+        let mut request = self.client.get(&action.href);
+        for (name, value) in &action.header {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let mut response = request
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Lfs(format!("Failed to download {}: {}", pointer.oid, e)))?;
+        if !response.status().is_success() {
+            return Err(DitriveError::Lfs(format!(
+                "Failed to download {}: HTTP {}",
+                pointer.oid,
+                response.status()
+            )));
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = tokio::fs::File::create(destination).await?;
+        let mut hasher = Sha256::new();
+        let mut size: u64 = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| DitriveError::Lfs(format!("Failed to read {}: {}", pointer.oid, e)))?
+        {
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        let hash = hex::encode(hasher.finalize());
+        if hash != pointer.oid {
+            drop(file);
+            let _ = fs::remove_file(destination);
+            return Err(DitriveError::Lfs(format!("Downloaded object {} has hash {}", pointer.oid, hash)));
+        }
+        debug!("Downloaded LFS object {} ({} bytes)", pointer.oid, size);
+        Ok(())
+    }
+
+    /// Ask the server where to download an object from
+    async fn download_action(&self, pointer: &LfsPointer) -> Result<BatchAction> {
+        let body = serde_json::json!({
+            "operation": "download",
+            "transfers": ["basic"],
+            "objects": [{ "oid": pointer.oid, "size": pointer.size }],
+        });
+
+        let mut request = self
+            .client
+            .post(format!("{}/objects/batch", self.endpoint))
+            .header(reqwest::header::ACCEPT, LFS_MEDIA_TYPE)
+            .header(reqwest::header::CONTENT_TYPE, LFS_MEDIA_TYPE)
+            .json(&body);
+        if let Some((user, pass)) = &self.credentials {
+            request = request.basic_auth(user, Some(pass));
+        }
+
+        let response = request
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Lfs(format!("LFS batch request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Lfs(format!("LFS batch request failed: {}", error)));
+        }
+
+        let batch: BatchResponse = response
+            .json()
+            .await
+            .map_err(|e| DitriveError::Lfs(format!("Failed to parse LFS batch response: {}", e)))?;
+        let object = batch
+            .objects
+            .into_iter()
+            .find(|object| object.oid == pointer.oid)
+            .ok_or_else(|| DitriveError::Lfs(format!("Server didn't return object {}", pointer.oid)))?;
+
+        if let Some(error) = object.error {
+            return Err(DitriveError::Lfs(format!(
+                "Object {}: {} ({})",
+                pointer.oid, error.message, error.code
+            )));
+        }
+        object
+            .actions
+            .and_then(|actions| actions.download)
+            .ok_or_else(|| DitriveError::Lfs(format!("Server has no download for object {}", pointer.oid)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lfs_pointer() {
+        let oid = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+        let content = format!("{}\noid sha256:{}\nsize 12345\n", LFS_POINTER_VERSION, oid);
+        let pointer = LfsPointer::parse(content.as_bytes()).unwrap();
+        assert_eq!(pointer, LfsPointer { oid: oid.to_string(), size: 12345 });
+        assert_eq!(
+            pointer.object_path(Path::new(".git")),
+            Path::new(".git/lfs/objects/4d/7a").join(oid)
+        );
+
+        assert!(LfsPointer::parse(b"plain content").is_none());
+        assert!(LfsPointer::parse(format!("{}\nsize 1\n", LFS_POINTER_VERSION).as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_gitattributes_patterns() {
+        let attributes = "*.psd filter=lfs diff=lfs merge=lfs -text\n\
+                          # comment filter=lfs\n\
+                          assets/*.bin filter=lfs diff=lfs merge=lfs -text eol=lf\n\
+                          *.sh text eol=lf\n";
+        assert_eq!(lfs_patterns(attributes), vec!["*.psd", "assets/*.bin"]);
+        assert_eq!(strip_lfs_attributes(attributes), "# comment filter=lfs\nassets/*.bin -text eol=lf\n*.sh text eol=lf\n");
+
+        assert_eq!(
+            endpoint_for_remote("git@github.com:owner/game.git").as_deref(),
+            Some("https://github.com/owner/game.git/info/lfs")
+        );
+        assert_eq!(
+            endpoint_for_remote("https://github.com/owner/game").as_deref(),
+            Some("https://github.com/owner/game.git/info/lfs")
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Ditrive - Git Drive Sync
+//!
+//! Automatically manage large files in Git with Google Drive. The `ditrive`
+//! binary is a thin CLI over this library; embedders drive [`Ditrive`] directly
+//! and answer its questions through their own [`Prompter`].
+
+pub mod app;
+pub mod backend;
+pub mod bench;
+pub mod bucket;
+pub mod chunking;
+pub mod cli;
+pub mod config;
+pub mod conflict;
+pub mod deletion;
+pub mod ditriveignore;
+pub mod download_cache;
+pub mod drive;
+pub mod drive_cache;
+pub mod error;
+pub mod expiry;
+pub mod export;
+pub mod git;
+pub mod github;
+pub mod hash_cache;
+pub mod history;
+pub mod http;
+pub mod hydration;
+pub mod i18n;
+pub mod index;
+pub mod journal;
+pub mod jwt;
+pub mod lfs;
+pub mod local;
+pub mod lock;
+pub mod log_file;
+pub mod migration;
+#[cfg(feature = "mount")]
+pub mod mount;
+pub mod oauth;
+pub mod onedrive;
+pub mod paths;
+pub mod placeholder;
+pub mod pointer;
+pub mod pr_comment;
+pub mod preview;
+pub mod progress;
+pub mod prompt;
+pub mod recover;
+pub mod releases;
+pub mod report;
+pub mod rpc;
+pub mod scan;
+pub mod selection;
+pub mod signing;
+pub mod symlink;
+pub mod team_cache;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod thumbnail;
+pub mod token_store;
+pub mod trace;
+pub mod tracker;
+pub mod trash;
+
+pub use crate::app::Ditrive;
+pub use crate::error::{DitriveError, Result};
+pub use crate::prompt::Prompter;
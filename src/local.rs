@@ -0,0 +1,310 @@
+//! Local filesystem backend for NAS shares and external drives
+//!
+//! Files are copied into `<root>/<repo name>/`, mirroring their path in the
+//! repository. A file's ID is its repository-relative path, so the store stays
+//! browsable and can be backed up or moved with ordinary tools. With the flat
+//! layout, files are stored once per content under `objects/` instead.
+
+use async_trait::async_trait;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, info};
+use walkdir::WalkDir;
+
+use crate::backend::{RemoteEntry, StorageBackend};
+use crate::config::StorageLayout;
+use crate::drive::{calculate_file_hash, object_path, upload_name, FileMetadata};
+use crate::error::{DitriveError, Result};
+use crate::paths;
+use crate::trace;
+
+/// Backend that stores managed files in a local or mounted directory
+pub struct LocalBackend {
+    repo_folder: PathBuf,
+    repo_folder_id: String,
+    hash_suffix_names: bool,
+    layout: StorageLayout,
+}
+
+impl LocalBackend {
+    /// Create a LocalBackend, creating the repository folder under `root` if needed
+    pub fn new(root: &str, repo_name: &str) -> Result<Self> {
+        if root.is_empty() {
+            return Err(DitriveError::Config(
+                "Local storage path is not configured. Run 'ditrive configure'.".to_string(),
+            ));
+        }
+
+        let root = Path::new(root);
+        if !root.is_dir() {
+            return Err(DitriveError::Config(format!(
+                "Local storage path {:?} does not exist or is not mounted",
+                root
+            )));
+        }
+
+        let repo_folder = root.join(repo_name);
+        fs::create_dir_all(&repo_folder)?;
+        info!("Using local storage folder {:?}", repo_folder);
+
+        Ok(Self {
+            repo_folder_id: repo_folder.display().to_string(),
+            repo_folder,
+            hash_suffix_names: false,
+            layout: StorageLayout::Mirror,
+        })
+    }
+
+    /// Append a short content hash to stored file names (see `upload_name`)
+    pub fn set_hash_suffix_names(&mut self, enabled: bool) {
+        self.hash_suffix_names = enabled;
+    }
+
+    /// Mirror the repository's folders, or store content-addressed objects (see `object_path`)
+    pub fn set_storage_layout(&mut self, layout: StorageLayout) {
+        self.layout = layout;
+    }
+
+    /// Resolve a file ID to its path in the store, refusing IDs that escape it
+    fn resolve(&self, file_id: &str) -> Result<PathBuf> {
+        let rel_path = Path::new(file_id);
+        let is_contained = rel_path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+
+        if file_id.is_empty() || !is_contained {
+            return Err(DitriveError::FileNotFound(file_id.to_string()));
+        }
+
+        Ok(self.repo_folder.join(rel_path))
+    }
+
+    /// Store a file as the object for its content, unless that object already exists
+    fn store_object(&self, file_path: &Path) -> Result<FileMetadata> {
+        let hash = calculate_file_hash(file_path)?;
+        let file_id = object_path(&hash);
+        if !self.resolve(&file_id)?.is_file() {
+            return self.store(&file_id, file_path);
+        }
+
+        debug!("{} is already in local storage", file_id);
+        Ok(FileMetadata {
+            id: file_id,
+            hash,
+            size: fs::metadata(file_path)?.len(),
+            uploaded_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        })
+    }
+
+    /// Copy a file into the store, replacing any previous content atomically
+    fn store(&self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let destination = self.resolve(file_id)?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Copy next to the target and rename, so readers never see a partial file
+        let partial = destination.with_extension("ditrive-partial");
+        let size = fs::copy(file_path, &partial)?;
+        fs::rename(&partial, &destination)?;
+
+        Ok(FileMetadata {
+            id: file_id.to_string(),
+            hash: calculate_file_hash(file_path)?,
+            size,
+            uploaded_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        })
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        "Local storage"
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        &self.repo_folder_id
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        if self.layout == StorageLayout::Flat {
+            return self.store_object(file_path);
+        }
+        let rel_path = paths::relative(repo_path, file_path)
+            .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?;
+        let file_id = if self.hash_suffix_names {
+            upload_name(&rel_path, &calculate_file_hash(file_path)?, true)
+        } else {
+            rel_path
+        };
+
+        let metadata = self.store(&file_id, file_path)?;
+        info!("Copied {} ({} bytes) to local storage", file_id, metadata.size);
+        Ok(metadata)
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        if self.layout == StorageLayout::Flat {
+            // Objects are never rewritten; other paths and older versions may point at them
+            return self.store_object(file_path);
+        }
+
+        // Previous content is overwritten; local storage keeps no version history
+        let metadata = self.store(file_id, file_path)?;
+        debug!("Updated {} in local storage", file_id);
+        Ok(metadata)
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let _timer = trace::phase("download", destination.display().to_string());
+        let source = self.resolve(file_id)?;
+        if !source.is_file() {
+            return Err(DitriveError::FileNotFound(source.display().to_string()));
+        }
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, destination)?;
+
+        info!("Copied {:?} from local storage", destination);
+        Ok(())
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        self.resolve(file_id).map(|p| p.is_file()).unwrap_or(false)
+    }
+
+    async fn content_hash(&self, file_id: &str) -> Result<Option<String>> {
+        let path = self.resolve(file_id)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(calculate_file_hash(&path)?))
+    }
+
+    async fn list_files(&self) -> Result<Option<Vec<RemoteEntry>>> {
+        let mut entries = Vec::new();
+        for entry in WalkDir::new(&self.repo_folder).sort_by_file_name() {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type().is_file() || path.extension().is_some_and(|ext| ext == "ditrive-partial") {
+                continue;
+            }
+
+            let rel_path = paths::repo_relative(&self.repo_folder, path);
+            let metadata = entry.metadata()?;
+            let modified_at = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|age| age.as_secs() as i64)
+                .unwrap_or(0);
+            entries.push(RemoteEntry {
+                id: rel_path.clone(),
+                path: rel_path,
+                size: metadata.len(),
+                hash: Some(calculate_file_hash(path)?),
+                modified_at,
+            });
+        }
+        Ok(Some(entries))
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        fs::remove_file(self.resolve(file_id)?)?;
+        debug!("Deleted {} from local storage", file_id);
+        Ok(())
+    }
+
+    async fn move_file(&mut self, file_id: &str, rel_path: &str) -> Result<String> {
+        let source = self.resolve(file_id)?;
+        let destination = self.resolve(rel_path)?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&source, &destination)?;
+        debug!("Moved {} to {} in local storage", file_id, rel_path);
+        Ok(rel_path.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_rejects_escaping_ids() {
+        let store = tempdir().unwrap();
+        let backend = LocalBackend::new(&store.path().to_string_lossy(), "game").unwrap();
+
+        assert!(backend.resolve("assets/hero.png").is_ok());
+        assert!(backend.resolve("../other/secret.bin").is_err());
+        assert!(backend.resolve("/etc/passwd").is_err());
+        assert!(backend.resolve("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_and_download_round_trip() {
+        let store = tempdir().unwrap();
+        let repo = tempdir().unwrap();
+        let source = repo.path().join("assets").join("level.bin");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, b"level data").unwrap();
+
+        let mut backend = LocalBackend::new(&store.path().to_string_lossy(), "game").unwrap();
+        let metadata = backend.upload_file(&source, repo.path()).await.unwrap();
+        assert_eq!(metadata.id, "assets/level.bin");
+        assert!(backend.file_exists(&metadata.id).await);
+
+        let listed = backend.list_files().await.unwrap().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].path, "assets/level.bin");
+        assert_eq!(listed[0].hash.as_deref(), Some(metadata.hash.as_str()));
+
+        let restored = repo.path().join("restored.bin");
+        backend.download_file(&metadata.id, &restored).await.unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), b"level data");
+
+        let moved = backend.move_to_trash(&metadata.id, ".trash/assets/level.bin").await.unwrap();
+        assert_eq!(moved, ".trash/assets/level.bin");
+        assert!(!backend.file_exists(&metadata.id).await);
+        assert!(backend.file_exists(&moved).await);
+    }
+
+    #[tokio::test]
+    async fn test_flat_layout_shares_objects() {
+        let store = tempdir().unwrap();
+        let repo = tempdir().unwrap();
+        let first = repo.path().join("a").join("level.bin");
+        let second = repo.path().join("b").join("copy.bin");
+        for path in [&first, &second] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"level data").unwrap();
+        }
+
+        let mut backend = LocalBackend::new(&store.path().to_string_lossy(), "game").unwrap();
+        backend.set_storage_layout(StorageLayout::Flat);
+        let original = backend.upload_file(&first, repo.path()).await.unwrap();
+        assert_eq!(original.id, object_path(&original.hash));
+        assert!(original.id.starts_with(&format!("objects/{}/", &original.hash[..2])));
+        assert_eq!(backend.upload_file(&second, repo.path()).await.unwrap().id, original.id);
+
+        // New content is a new object; the old one stays for the other path
+        fs::write(&second, b"edited level").unwrap();
+        let updated = backend.update_file(&original.id, &second).await.unwrap();
+        assert_ne!(updated.id, original.id);
+        assert_eq!(backend.list_files().await.unwrap().unwrap().len(), 2);
+
+        let restored = repo.path().join("restored.bin");
+        backend.download_file(&original.id, &restored).await.unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), b"level data");
+    }
+}
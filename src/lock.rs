@@ -0,0 +1,134 @@
+//! Repository lock that keeps two ditrive runs from working on one repository
+//!
+//! Commands that change trackers or stored files take an advisory lock on
+//! `.git/ditrive.lock` for as long as they run. The lock is an OS file lock,
+//! so it is released when its holder exits, even by crashing; the file itself
+//! stays behind and only records who held it last. A lock file nobody holds
+//! is stale and is simply taken over.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::error::{DitriveError, Result};
+
+/// Lock file, relative to the repository root
+pub const LOCK_PATH: &str = ".git/ditrive.lock";
+
+/// Who holds, or last held, the lock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    /// The command line, e.g. "ditrive sync --two-way"
+    pub command: String,
+    /// Unix timestamp the lock was taken at
+    pub since: i64,
+}
+
+impl LockHolder {
+    fn describe(&self) -> String {
+        let since = DateTime::from_timestamp(self.since, 0)
+            .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        format!("process {} is running `{}` since {}", self.pid, self.command, since)
+    }
+}
+
+/// Held repository lock, released when dropped
+#[derive(Debug)]
+pub struct RepoLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Take the repository's lock, failing if another process holds it.
+    ///
+    /// Outside a git repository, and on filesystems without file locks,
+    /// there is nothing to lock and None is returned.
+    pub fn acquire(repo_path: &Path, command: &str) -> Result<Option<Self>> {
+        if !repo_path.join(".git").is_dir() {
+            return Ok(None);
+        }
+
+        let path = repo_path.join(LOCK_PATH);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = read_holder(&path)
+                    .map(|holder| holder.describe())
+                    .unwrap_or_else(|| "another ditrive process is running".to_string());
+                return Err(DitriveError::Locked(format!(
+                    "{} ({}). Wait for it to finish, or stop it, and try again",
+                    holder,
+                    path.display()
+                )));
+            }
+            Err(TryLockError::Error(e)) => {
+                warn!("Couldn't lock {:?}, continuing without the repository lock: {}", path, e);
+                return Ok(None);
+            }
+        }
+
+        if let Some(stale) = read_holder(&path) {
+            debug!("Taking over the stale lock left by process {} (`{}`)", stale.pid, stale.command);
+        }
+        let holder = LockHolder {
+            pid: std::process::id(),
+            command: command.to_string(),
+            since: chrono::Utc::now().timestamp(),
+        };
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(serde_json::to_string(&holder)?.as_bytes())?;
+        file.flush()?;
+        debug!("Locked {:?}", path);
+        Ok(Some(Self { file, path }))
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        // The file stays: removing it would let a process waiting on the old
+        // one and a newcomer creating a fresh one both think they hold the lock
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+        debug!("Unlocked {:?}", self.path);
+    }
+}
+
+/// Who holds, or last held, the lock at `path`
+fn read_holder(path: &Path) -> Option<LockHolder> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(RepoLock::acquire(dir.path(), "ditrive sync").unwrap().is_none());
+
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let lock = RepoLock::acquire(dir.path(), "ditrive sync").unwrap().unwrap();
+        let error = RepoLock::acquire(dir.path(), "ditrive push").unwrap_err();
+        assert!(matches!(error, DitriveError::Locked(ref message) if message.contains("`ditrive sync`")));
+
+        drop(lock);
+        let holder = LockHolder {
+            pid: u32::MAX,
+            command: "ditrive pull".to_string(),
+            since: 0,
+        };
+        fs::write(dir.path().join(LOCK_PATH), serde_json::to_string(&holder).unwrap()).unwrap();
+        let lock = RepoLock::acquire(dir.path(), "ditrive push").unwrap();
+        assert!(lock.is_some(), "an unheld lock file is stale and gets taken over");
+        assert_eq!(read_holder(&dir.path().join(LOCK_PATH)).unwrap().command, "ditrive push");
+    }
+}
@@ -0,0 +1,108 @@
+//! Rotating log file for `--log-file`
+//!
+//! The log file gets every debug-level event as one JSON object per line,
+//! whatever the console shows, so intermittent API failures in long `watch`
+//! or `serve` runs can be diagnosed after the fact. When the file outgrows
+//! its size limit it is renamed to `<name>.1`, older files shift up to
+//! `<name>.<ROTATED_FILES>`, and the oldest is dropped.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Rotated files kept next to the live one
+pub const ROTATED_FILES: usize = 5;
+
+/// Path of the `index`th rotated file of the log at `path`, `<path>.<index>`
+pub fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Log file that rotates itself once it reaches `max_bytes`
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    /// Open `path` for appending, creating it and its folder as needed
+    pub fn open(path: &Path, max_bytes: u64) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            file,
+            written,
+        })
+    }
+
+    /// Path of the `index`th rotated file
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        rotated_path(&self.path, index)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated_path(ROTATED_FILES));
+        for index in (1..ROTATED_FILES).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Each event arrives as one write, so lines are never split across files
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("ditrive.log");
+        let mut log = RotatingFile::open(&path, 10).unwrap();
+
+        for line in 0..(ROTATED_FILES + 3) {
+            log.write_all(format!("line {}\n", line).as_bytes()).unwrap();
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), format!("line {}\n", ROTATED_FILES + 2));
+        assert_eq!(read(log.rotated_path(1)), format!("line {}\n", ROTATED_FILES + 1));
+        assert_eq!(read(log.rotated_path(ROTATED_FILES)), "line 2\n");
+        assert!(!log.rotated_path(ROTATED_FILES + 1).exists());
+
+        // Reopening continues from the current size
+        let reopened = RotatingFile::open(&path, 10).unwrap();
+        assert_eq!(reopened.written, 7);
+    }
+}
@@ -2,28 +2,40 @@
 //!
 //! Automatically manage large files in Git with Google Drive
 
-mod app;
-mod cli;
-mod config;
-mod drive;
-mod error;
-mod git;
-mod github;
-mod oauth;
-mod tracker;
-
 use anyhow::Result;
 use clap::Parser;
-use tracing::error;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{error, trace};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
-use crate::app::Ditrive;
-use crate::cli::{Cli, Commands};
+use ditrive::app::Ditrive;
+use ditrive::cli::{CacheCommand, Cli, Commands, ConfigCommand, TrashCommand};
+use ditrive::config::GlobalConfig;
+use ditrive::error::DitriveError;
+use ditrive::history::HistoryFilter;
+use ditrive::i18n::{self, tr};
+use ditrive::lock::RepoLock;
+use ditrive::log_file::RotatingFile;
+use ditrive::{http, rpc, team_cache, token_store, trace};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Pick the message language before anything is printed
+    let config = GlobalConfig::load().ok();
+    let settings = config.as_ref().map(|c| &c.settings);
+    i18n::init(settings.map(|s| s.language.as_str()).unwrap_or_default());
+    token_store::init(settings.map(|s| s.token_encryption).unwrap_or_default());
+    if let Some(config) = &config {
+        http::init(&config.network)?;
+    }
+
     // Initialize logging
     let filter = if cli.verbose {
         EnvFilter::new("debug")
@@ -31,71 +43,378 @@ async fn main() -> Result<()> {
         EnvFilter::new("info")
     };
 
+    // API and timing events only go to the trace file, when requested
+    let trace_layer = match cli.trace_api {
+        Some(ref path) => {
+            let file = File::create(path)?;
+            let targets = Targets::new()
+                .with_target(trace::API_TARGET, LevelFilter::TRACE)
+                .with_target(trace::TIMING_TARGET, LevelFilter::TRACE);
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .with_filter(targets),
+            )
+        }
+        None => None,
+    };
+
+    // The git filters answer git over stdout, and JSON output must stay
+    // parseable, so their logs go to stderr
+    let stdout_reserved = cli.json
+        || matches!(cli.command, Commands::FilterClean { .. } | Commands::FilterSmudge { .. });
+    let log_writer = if stdout_reserved {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    // Everything at debug level, API calls included, as JSON lines
+    let log_file = cli
+        .log_file
+        .clone()
+        .or_else(|| settings.filter(|s| !s.log_file.is_empty()).map(|s| PathBuf::from(&s.log_file)));
+    let file_layer = match log_file {
+        Some(path) => {
+            let max_mb = settings.map(|s| s.log_file_max_mb).unwrap_or(10).max(1);
+            let file = RotatingFile::open(&path, max_mb * 1024 * 1024)?;
+            let targets = Targets::new()
+                .with_target("ditrive", LevelFilter::DEBUG)
+                .with_target(trace::API_TARGET, LevelFilter::TRACE)
+                .with_default(LevelFilter::WARN);
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_writer(Mutex::new(file))
+                    .with_filter(targets),
+            )
+        }
+        None => None,
+    };
+
     tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(filter)
+        .with(fmt::layer().with_writer(log_writer).with_filter(filter))
+        .with(trace_layer)
+        .with(file_layer)
         .init();
 
+    let command_name = format!("{:?}", cli.command);
+    let started = Instant::now();
+
+    let json = cli.json;
+    let non_interactive = cli.non_interactive;
+
     // Resolve repository path
     let repo_path = cli.repo.canonicalize().unwrap_or(cli.repo.clone());
 
+    // Keep other runs off the trackers and stored files until this one is done
+    let _lock = if takes_repo_lock(&cli.command) {
+        let command_line = std::env::args().collect::<Vec<_>>().join(" ");
+        RepoLock::acquire(&repo_path, &command_line).unwrap_or_else(|e| exit_with_error(&e))
+    } else {
+        None
+    };
+
+    // Every command runs against the repository with the same prompting mode
+    let open = || Ditrive::new(&repo_path).map(|ditrive| ditrive.with_non_interactive(non_interactive));
+
     // Execute command
     let result = match cli.command {
         Commands::Configure => {
-            let mut ditrive = Ditrive::new(&repo_path)?;
-            ditrive.configure()
+            let mut ditrive = open()?;
+            ditrive.configure().await
+        }
+        Commands::Config { command } => {
+            let mut ditrive = open()?;
+            match command {
+                ConfigCommand::Get { key, repo } => ditrive.config_get(&key, repo),
+                ConfigCommand::Set { key, value, repo } => ditrive.config_set(&key, &value, repo),
+                ConfigCommand::Unset { key, repo } => ditrive.config_unset(&key, repo),
+            }
         }
-        Commands::Login => {
-            let ditrive = Ditrive::new(&repo_path)?;
-            ditrive.login().await
+        Commands::Login { profile, no_browser } => {
+            let ditrive = open()?.with_profile(profile);
+            ditrive.login(no_browser).await
         }
-        Commands::Logout => {
-            let ditrive = Ditrive::new(&repo_path)?;
+        Commands::Logout { profile } => {
+            let ditrive = open()?.with_profile(profile);
             ditrive.logout().await
         }
         Commands::QuickSetup {
             name,
             description,
             public,
+            org,
+            teams,
         } => {
-            let mut ditrive = Ditrive::new(&repo_path)?;
+            let mut ditrive = open()?;
             ditrive
-                .quick_setup(name.as_deref(), &description, !public)
+                .quick_setup(name.as_deref(), &description, !public, org.as_deref(), &teams)
                 .await
         }
-        Commands::Init => {
-            let mut ditrive = Ditrive::new(&repo_path)?;
-            ditrive.initialize().await
+        Commands::Init { pointers, overrides } => {
+            let mut ditrive = open()?.with_overrides(&overrides)?;
+            ditrive.initialize(pointers).await
         }
-        Commands::Sync => {
-            let mut ditrive = Ditrive::new(&repo_path)?;
-            ditrive.sync().await
+        Commands::Sync { pull, push, two_way, prune, commit, overrides } => {
+            let mut ditrive = open()?
+                .with_json_output(json)
+                .with_overrides(&overrides)?;
+            ditrive.sync(pull, push, two_way, prune, commit).await
         }
         Commands::Status => {
-            let ditrive = Ditrive::new(&repo_path)?;
+            let ditrive = open()?.with_json_output(json);
             ditrive.status().await
         }
-        Commands::Pull => {
-            let ditrive = Ditrive::new(&repo_path)?;
-            ditrive.sync_missing_files().await
+        Commands::Diff { prune, overrides } => {
+            let ditrive = open()?
+                .with_json_output(json)
+                .with_overrides(&overrides)?;
+            ditrive.diff(prune).await
+        }
+        Commands::Pull { paths, include, exclude } => {
+            let ditrive = open()?;
+            let filter = ditrive.pull_filter(&paths, include, exclude);
+            ditrive.sync_missing_files(&filter).await
+        }
+        Commands::Hydrate { paths } => {
+            let ditrive = open()?;
+            ditrive.hydrate(&paths).await
+        }
+        Commands::Mount { mountpoint } => {
+            let ditrive = open()?;
+            ditrive.mount(&mountpoint).await
+        }
+        Commands::Push { paths, force, commit, overrides } => {
+            let mut ditrive = open()?.with_overrides(&overrides)?;
+            ditrive.push(&paths, force, commit).await
+        }
+        Commands::Rm { paths } => {
+            let mut ditrive = open()?;
+            ditrive.rm(&paths).await
+        }
+        Commands::Trash { command } => {
+            let mut ditrive = open()?;
+            match command {
+                TrashCommand::List => ditrive.trash_list(),
+                TrashCommand::Restore { path } => ditrive.trash_restore(&path).await,
+                TrashCommand::Empty { all } => ditrive.trash_empty(all).await,
+            }
+        }
+        Commands::Track { patterns } => {
+            let mut ditrive = open()?;
+            ditrive.track(&patterns)
+        }
+        Commands::Watch => {
+            let ditrive = open()?;
+            ditrive.watch().await
+        }
+        Commands::List { long } => {
+            let ditrive = open()?.with_json_output(json);
+            ditrive.list_managed(long).await
+        }
+        Commands::LsRemote => {
+            let ditrive = open()?.with_json_output(json);
+            ditrive.ls_remote().await
+        }
+        Commands::Adopt { commit } => {
+            let mut ditrive = open()?;
+            ditrive.adopt(commit).await
+        }
+        Commands::Stats { top } => {
+            let ditrive = open()?.with_json_output(json);
+            ditrive.stats(top).await
+        }
+        Commands::Versions { path } => {
+            let ditrive = open()?;
+            ditrive.list_versions(&path).await
+        }
+        Commands::Restore { path, version, to } => {
+            let ditrive = open()?;
+            ditrive.restore_version(&path, version, to.as_deref()).await
         }
-        Commands::Push => {
-            let mut ditrive = Ditrive::new(&repo_path)?;
-            ditrive.process_new_files().await
+        Commands::ExportLinks {
+            format,
+            output,
+            share,
+            email,
+            expires_in_days,
+        } => {
+            let ditrive = open()?;
+            ditrive
+                .export_links(format, output.as_deref(), share, email.as_deref(), expires_in_days)
+                .await
+        }
+        Commands::Publish { target } => {
+            let ditrive = open()?;
+            ditrive.publish(&target).await
+        }
+        Commands::Share { email, role, list, revoke } => {
+            let ditrive = open()?;
+            ditrive.share(email.as_deref(), role, list, revoke.as_deref()).await
+        }
+        Commands::RestoreTrashed { path } => {
+            let ditrive = open()?;
+            ditrive.restore_trashed(&path).await
+        }
+        Commands::MigrateFolder { to } => {
+            let mut ditrive = open()?;
+            ditrive.migrate_folder(&to).await
+        }
+        Commands::MigrateBackend { from, to } => {
+            let mut ditrive = open()?;
+            ditrive.migrate_backend(from, to).await
+        }
+        Commands::ImportLfs => {
+            let mut ditrive = open()?;
+            ditrive.import_lfs().await
+        }
+        Commands::ExportLfs => {
+            let mut ditrive = open()?;
+            ditrive.export_lfs().await
+        }
+        Commands::Migrate { manifest } => {
+            let mut ditrive = open()?;
+            ditrive.migrate(manifest).await
+        }
+        Commands::MigrateTracker { to } => {
+            let mut ditrive = open()?;
+            ditrive.migrate_tracker(to)
+        }
+        Commands::Gc { dry_run } => {
+            let ditrive = open()?;
+            ditrive.gc(dry_run).await
+        }
+        Commands::Recover => {
+            let mut ditrive = open()?;
+            ditrive.recover().await
+        }
+        Commands::Report { months, all_repos } => {
+            let ditrive = open()?;
+            ditrive.usage_report(months, all_repos)
+        }
+        Commands::Log {
+            path,
+            action,
+            user,
+            since,
+            all_repos,
+            limit,
+        } => {
+            let ditrive = open()?.with_json_output(json);
+            let filter = HistoryFilter {
+                path,
+                actions: action,
+                user,
+                ..Default::default()
+            };
+            ditrive.history_log(filter, since.as_deref(), all_repos, limit)
         }
-        Commands::List => {
-            let ditrive = Ditrive::new(&repo_path)?;
-            ditrive.list_managed().await
+        Commands::Bench { sizes, concurrency } => {
+            let ditrive = open()?;
+            ditrive.bench(&sizes, &concurrency).await
         }
+        Commands::Serve { port, rpc: true, .. } => {
+            // Nobody is at a terminal to answer prompts for RPC clients
+            let mut ditrive = open()?.with_non_interactive(true);
+            ditrive.serve_rpc(port.unwrap_or(rpc::DEFAULT_PORT)).await
+        }
+        Commands::Serve { bind, port, cache_dir, rpc: false } => {
+            let ditrive = open()?;
+            ditrive
+                .serve(bind, port.unwrap_or(team_cache::DEFAULT_PORT), cache_dir.as_deref())
+                .await
+        }
+        Commands::Cache { command } => {
+            let ditrive = open()?;
+            match command {
+                CacheCommand::Stats => ditrive.cache_stats(),
+                CacheCommand::Clean => ditrive.cache_clean(),
+            }
+        }
+        Commands::PrComment { pr, base, repository } => {
+            let ditrive = open()?;
+            ditrive
+                .pr_comment(pr, base.as_deref(), repository.as_deref())
+                .await
+        }
+        Commands::FilterClean { path } => {
+            let ditrive = open()?;
+            ditrive.filter_clean(&path).await
+        }
+        Commands::FilterSmudge { path } => {
+            let ditrive = open()?;
+            ditrive.filter_smudge(&path).await
+        }
+        Commands::Check { install_hook } => {
+            let ditrive = open()?;
+            if install_hook {
+                ditrive.install_check_hook()
+            } else {
+                ditrive.check()
+            }
+        }
+        Commands::Messages { language } => Ditrive::print_message_template(&language),
     };
 
+    trace!(
+        target: trace::TIMING_TARGET,
+        command = %command_name,
+        duration_ms = started.elapsed().as_millis() as u64,
+        "command complete"
+    );
+
     if let Err(e) = result {
-        error!("Error: {}", e);
-        eprintln!("\nError: {}", e);
-        eprintln!("\nUse 'ditrive --help' for usage information.");
-        std::process::exit(1);
+        exit_with_error(&e);
     }
 
     Ok(())
 }
 
+/// Report a failed command in the configured language and exit
+fn exit_with_error(e: &DitriveError) -> ! {
+    error!("Error: {}", e);
+    eprintln!("\n{}: {}", tr("error-prefix"), e.localized());
+    eprintln!("\n{}", tr("error-help"));
+    std::process::exit(1);
+}
+
+/// Whether a command changes trackers or stored files, and so must not run
+/// alongside another one in the same repository
+fn takes_repo_lock(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::QuickSetup { .. }
+            | Commands::Init { .. }
+            | Commands::Sync { .. }
+            | Commands::Pull { .. }
+            | Commands::Hydrate { .. }
+            | Commands::Mount { .. }
+            | Commands::Push { .. }
+            | Commands::Rm { .. }
+            | Commands::Config {
+                command: ConfigCommand::Set { repo: true, .. } | ConfigCommand::Unset { repo: true, .. }
+            }
+            | Commands::Trash {
+                command: TrashCommand::Restore { .. } | TrashCommand::Empty { .. }
+            }
+            | Commands::Track { .. }
+            | Commands::Adopt { .. }
+            | Commands::Restore { .. }
+            | Commands::ExportLinks { share: true, .. }
+            | Commands::ExportLinks { email: Some(_), .. }
+            | Commands::Publish { .. }
+            | Commands::Share { list: false, .. }
+            | Commands::RestoreTrashed { .. }
+            | Commands::MigrateFolder { .. }
+            | Commands::MigrateBackend { .. }
+            | Commands::ImportLfs
+            | Commands::ExportLfs
+            | Commands::Migrate { .. }
+            | Commands::MigrateTracker { .. }
+            | Commands::Gc { dry_run: false }
+            | Commands::Recover
+    )
+}
+
@@ -0,0 +1,144 @@
+//! Record of a repository moving to another storage backend
+//!
+//! `ditrive migrate-backend` copies every managed file to the new backend and
+//! writes `.woilah-migration.json` next to the repo config. It maps each old
+//! file ID to its new one, which lets an interrupted migration resume and lets
+//! clones with outdated tracker files fail with a clear message instead of
+//! looking for files in the old backend.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::DriveAuthType;
+use crate::error::{DitriveError, Result};
+use crate::tracker;
+
+/// A backend migration and the file IDs it has moved so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationMap {
+    pub from: DriveAuthType,
+    pub to: DriveAuthType,
+    pub started_at: i64,
+    /// Set once every managed file has been moved
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+    /// Old file ID to new file ID
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+impl MigrationMap {
+    const FILENAME: &'static str = ".woilah-migration.json";
+
+    /// Start a new migration record
+    pub fn new(from: DriveAuthType, to: DriveAuthType) -> Self {
+        Self {
+            from,
+            to,
+            started_at: Utc::now().timestamp(),
+            completed_at: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Path of the migration record in a repository
+    pub fn path(repo_path: &Path) -> PathBuf {
+        repo_path.join(Self::FILENAME)
+    }
+
+    /// Load the repository's migration record, if it has one
+    pub fn load(repo_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path(repo_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Save the migration record
+    pub fn save(&self, repo_path: &Path) -> Result<()> {
+        // Saved after every moved file, so an interrupted run must not leave it truncated
+        let content = serde_json::to_string_pretty(self)? + "\n";
+        tracker::write_atomically(&Self::path(repo_path), content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Whether old and new IDs can be the same string, as between two
+    /// backends that both name files by path
+    fn ids_overlap(&self) -> bool {
+        self.from.ids_are_paths() && self.to.ids_are_paths()
+    }
+
+    /// Whether a file ID already belongs to the new backend
+    pub fn is_migrated(&self, file_id: &str) -> bool {
+        match self.files.get(file_id) {
+            // An old ID can also be a new one when both backends name files by path
+            Some(new_id) => new_id == file_id,
+            None => self.files.values().any(|id| id == file_id),
+        }
+    }
+
+    /// Fail if `file_id` is an old ID, meaning the caller's tracker predates the migration.
+    ///
+    /// Skipped when both backends name files by path: an old ID can then be
+    /// the new one too, and a current tracker looks just like an outdated one.
+    pub fn check_current(&self, file_id: &str, file_path: &Path) -> Result<()> {
+        if self.ids_overlap() {
+            return Ok(());
+        }
+        if self.files.contains_key(file_id) {
+            return Err(DitriveError::Config(format!(
+                "{} was moved from {} to {}, but this checkout still tracks its old \
+                 location. Re-pull required: update the repository with 'git pull', \
+                 then run 'ditrive pull'.",
+                file_path.display(),
+                self.from.service_name(),
+                self.to.service_name()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_migration_map_round_trip_and_stale_ids() {
+        let dir = tempdir().unwrap();
+        assert!(MigrationMap::load(dir.path()).unwrap().is_none());
+
+        let mut map = MigrationMap::new(DriveAuthType::OAuth, DriveAuthType::B2);
+        map.files.insert("drive-id".to_string(), "assets/hero.png".to_string());
+        map.save(dir.path()).unwrap();
+
+        let loaded = MigrationMap::load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.to, DriveAuthType::B2);
+        assert!(loaded.is_migrated("assets/hero.png"));
+        assert!(!loaded.is_migrated("drive-id"));
+
+        let path = Path::new("assets/hero.png");
+        assert!(loaded.check_current("assets/hero.png", path).is_ok());
+        let err = loaded.check_current("drive-id", path).unwrap_err();
+        assert!(err.to_string().contains("Re-pull required"));
+    }
+
+    #[test]
+    fn test_path_ids_shared_by_both_backends() {
+        let mut map = MigrationMap::new(DriveAuthType::Local, DriveAuthType::B2);
+        map.files.insert("objects/ab/abcd".to_string(), "objects/ab/abcd".to_string());
+        map.files.insert("assets/old.png".to_string(), "assets/new.png".to_string());
+
+        let path = Path::new("assets/hero.png");
+        assert!(map.check_current("objects/ab/abcd", path).is_ok());
+        assert!(map.is_migrated("objects/ab/abcd"));
+        assert!(map.is_migrated("assets/new.png"));
+        assert!(!map.is_migrated("assets/old.png"));
+    }
+}
@@ -0,0 +1,441 @@
+//! `ditrive mount`: managed files as a read-only virtual filesystem
+//!
+//! Built with the `mount` feature. The mount shows every managed file at its
+//! repository path with its recorded size, without downloading anything.
+//! The first read of a file fetches it from the backend into the download
+//! cache (`~/.ditrive/cache`, within `download_cache_max_mb`), and later reads
+//! are served from there. With the cache off, downloads stay in a scratch
+//! folder removed on unmount. Files already present in the working tree are
+//! read in place. Every copy is checked against its recorded hash before its
+//! first read is served.
+
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, Request};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::TempDir;
+use tokio::runtime::Handle;
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+
+use crate::backend::StorageBackend;
+use crate::config::GlobalConfig;
+use crate::download_cache::DownloadCache;
+use crate::drive::{calculate_file_hash, FileMetadata};
+use crate::error::{DitriveError, Result};
+use crate::scan::{ScanOutcome, Scanner};
+
+/// Inode of the mount's root folder
+const ROOT_INODE: u64 = 1;
+
+/// How long the kernel may cache attributes; the tree never changes while mounted
+const ATTR_TTL: Duration = Duration::from_secs(60);
+
+/// A folder or managed file in the mounted tree
+#[derive(Debug)]
+enum Node {
+    Folder {
+        /// Child names and inodes, sorted for stable listings
+        children: BTreeMap<String, u64>,
+    },
+    File {
+        /// Path relative to the repository root, with forward slashes
+        rel_path: String,
+        metadata: Box<FileMetadata>,
+    },
+}
+
+/// The managed files of a repository, numbered as inodes
+#[derive(Debug)]
+pub struct MountTree {
+    /// Node of each inode, indexed from `ROOT_INODE`
+    nodes: Vec<Node>,
+    /// Parent of each inode, for `..`
+    parents: Vec<u64>,
+}
+
+impl MountTree {
+    /// Build the tree from repository-relative paths and their tracker entries
+    pub fn new(files: impl IntoIterator<Item = (String, FileMetadata)>) -> Self {
+        let mut tree = Self {
+            nodes: vec![Node::Folder { children: BTreeMap::new() }],
+            parents: vec![ROOT_INODE],
+        };
+        for (rel_path, metadata) in files {
+            tree.insert(rel_path, metadata);
+        }
+        tree
+    }
+
+    fn insert(&mut self, rel_path: String, metadata: FileMetadata) {
+        let segments: Vec<String> = rel_path.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect();
+        let Some((name, folders)) = segments.split_last() else {
+            return;
+        };
+
+        let mut parent = ROOT_INODE;
+        for folder in folders {
+            parent = match self.child(parent, folder) {
+                Some(inode) if matches!(self.node(inode), Some(Node::Folder { .. })) => inode,
+                Some(_) => {
+                    warn!("Not mounting {}: {} is also a file", rel_path, folder);
+                    return;
+                }
+                None => self.add(parent, folder, Node::Folder { children: BTreeMap::new() }),
+            };
+        }
+        if self.child(parent, name).is_some() {
+            warn!("Not mounting {}: the name is already taken", rel_path);
+            return;
+        }
+        self.add(parent, name, Node::File { rel_path: rel_path.clone(), metadata: Box::new(metadata) });
+    }
+
+    /// Add a node under `parent`, returning its inode
+    fn add(&mut self, parent: u64, name: &str, node: Node) -> u64 {
+        self.nodes.push(node);
+        self.parents.push(parent);
+        let inode = self.nodes.len() as u64;
+        if let Some(Node::Folder { children }) = self.node_mut(parent) {
+            children.insert(name.to_string(), inode);
+        }
+        inode
+    }
+
+    fn node(&self, inode: u64) -> Option<&Node> {
+        self.nodes.get(inode.checked_sub(ROOT_INODE)? as usize)
+    }
+
+    fn node_mut(&mut self, inode: u64) -> Option<&mut Node> {
+        self.nodes.get_mut(inode.checked_sub(ROOT_INODE)? as usize)
+    }
+
+    /// Inode of `name` in the folder `parent`
+    pub fn child(&self, parent: u64, name: &str) -> Option<u64> {
+        match self.node(parent)? {
+            Node::Folder { children } => children.get(name).copied(),
+            Node::File { .. } => None,
+        }
+    }
+
+    /// Number of managed files in the tree
+    pub fn file_count(&self) -> usize {
+        self.nodes.iter().filter(|node| matches!(node, Node::File { .. })).count()
+    }
+
+    /// Attributes the kernel sees for an inode
+    fn attr(&self, inode: u64) -> Option<FileAttr> {
+        let (kind, size, perm, mtime) = match self.node(inode)? {
+            Node::Folder { .. } => (FileType::Directory, 0, 0o555, UNIX_EPOCH),
+            Node::File { metadata, .. } => {
+                let mtime = u64::try_from(metadata.uploaded_at)
+                    .map_or(UNIX_EPOCH, |secs| UNIX_EPOCH + Duration::from_secs(secs));
+                (FileType::RegularFile, metadata.size, 0o444, mtime)
+            }
+        };
+        Some(FileAttr {
+            ino: inode,
+            size,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            // SAFETY: getuid and getgid have no preconditions and can't fail
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+/// The mounted filesystem, downloading files on first read
+pub struct MountFs {
+    tree: MountTree,
+    fetcher: Arc<Fetcher>,
+    runtime: Handle,
+}
+
+impl MountFs {
+    pub fn new(
+        tree: MountTree,
+        repo_path: &Path,
+        backend: Box<dyn StorageBackend>,
+        scanner: Option<Scanner>,
+        cache: Option<DownloadCache>,
+    ) -> Result<Self> {
+        let config_dir = GlobalConfig::config_dir()?;
+        fs::create_dir_all(&config_dir)?;
+        let staging = tempfile::Builder::new().prefix("mount-").tempdir_in(config_dir)?;
+        let fetcher = Fetcher {
+            repo_path: repo_path.to_path_buf(),
+            cache,
+            staging,
+            backend,
+            scanner,
+            copies: Mutex::new(HashMap::new()),
+        };
+        Ok(Self {
+            tree,
+            fetcher: Arc::new(fetcher),
+            runtime: Handle::current(),
+        })
+    }
+
+    /// Serve the filesystem at `mountpoint` until it is unmounted. Blocks, so
+    /// run it off the async runtime.
+    pub fn serve(self, mountpoint: &Path) -> Result<()> {
+        let options = [
+            MountOption::RO,
+            MountOption::FSName("ditrive".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        fuser::mount2(self, mountpoint, &options)
+            .map_err(|e| DitriveError::Config(format!("Failed to mount at {}: {}", mountpoint.display(), e)))
+    }
+}
+
+/// Finds or downloads the local copies reads are served from, on the async
+/// runtime so a download never holds up the FUSE loop
+struct Fetcher {
+    repo_path: PathBuf,
+    /// The shared download cache, when it is on
+    cache: Option<DownloadCache>,
+    /// Downloads the cache can't hold, removed on unmount
+    staging: TempDir,
+    backend: Box<dyn StorageBackend>,
+    scanner: Option<Scanner>,
+    /// Local copy of each file inode, found once however many reads wait on it
+    copies: Mutex<HashMap<u64, Arc<OnceCell<PathBuf>>>>,
+}
+
+impl Fetcher {
+    /// Read up to `size` bytes at `offset` of a managed file
+    async fn read(
+        self: Arc<Self>,
+        inode: u64,
+        rel_path: &str,
+        metadata: &FileMetadata,
+        offset: u64,
+        size: usize,
+    ) -> std::result::Result<Vec<u8>, i32> {
+        let path = self.local_copy(inode, rel_path, metadata).await.map_err(|e| {
+            warn!("Couldn't read {}: {}", rel_path, e);
+            libc::EIO
+        })?;
+
+        let read = || -> std::io::Result<Vec<u8>> {
+            let mut file = File::open(&path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut buffer = Vec::with_capacity(size);
+            file.take(size as u64).read_to_end(&mut buffer)?;
+            Ok(buffer)
+        };
+        read().map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                // Evicted from the cache or removed from the tree; the next read fetches it again
+                self.copies.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&inode);
+            }
+            e.raw_os_error().unwrap_or(libc::EIO)
+        })
+    }
+
+    /// A verified local copy of a managed file, downloading it on first use
+    async fn local_copy(self: &Arc<Self>, inode: u64, rel_path: &str, metadata: &FileMetadata) -> Result<PathBuf> {
+        let copy = Arc::clone(self.copies.lock().unwrap_or_else(|e| e.into_inner()).entry(inode).or_default());
+        copy.get_or_try_init(|| self.fetch(inode, rel_path, metadata)).await.cloned()
+    }
+
+    async fn fetch(self: &Arc<Self>, inode: u64, rel_path: &str, metadata: &FileMetadata) -> Result<PathBuf> {
+        let in_tree = self.repo_path.join(rel_path);
+        if in_tree.is_file() && fs::metadata(&in_tree)?.len() == metadata.size {
+            let hash = metadata.hash.clone();
+            let path = in_tree.clone();
+            if blocking(move || matches_hash(&path, &hash)).await? {
+                return Ok(in_tree);
+            }
+            debug!("{} was changed in the working tree; serving the managed content", rel_path);
+        }
+        if let Some(cache) = self.cache.clone() {
+            let hash = metadata.hash.clone();
+            if let Some(cached) = blocking(move || cache.lookup(&hash)).await? {
+                return Ok(cached);
+            }
+        }
+
+        info!("Fetching {} on first read", rel_path);
+        let staged = self.staging.path().join(inode.to_string());
+        self.backend.download_version(metadata, &staged).await?;
+
+        let fetcher = Arc::clone(self);
+        let (rel_path, metadata) = (rel_path.to_string(), metadata.clone());
+        blocking(move || {
+            if !matches_hash(&staged, &metadata.hash)? {
+                let _ = fs::remove_file(&staged);
+                return Err(DitriveError::Drive(format!("{} doesn't match its recorded hash", rel_path)));
+            }
+            if let Some(ref scanner) = fetcher.scanner {
+                if let ScanOutcome::Flagged(report) = scanner.scan(&staged)? {
+                    let _ = fs::remove_file(&staged);
+                    return Err(DitriveError::Quarantined(format!("{} was flagged by the scanner: {}", rel_path, report)));
+                }
+            }
+
+            // Serve from the cache when it keeps the file, so the size limit covers it
+            if let Some(ref cache) = fetcher.cache {
+                cache.store(&metadata.hash, &staged)?;
+                if let Some(cached) = cache.path_for(&metadata.hash).filter(|path| path.is_file()) {
+                    let _ = fs::remove_file(&staged);
+                    return Ok(cached);
+                }
+            }
+            Ok(staged)
+        })
+        .await
+    }
+}
+
+/// Whether a file holds the content with `hash`; files without a recorded hash can only be trusted on size
+fn matches_hash(path: &Path, hash: &str) -> Result<bool> {
+    Ok(hash.is_empty() || calculate_file_hash(path)? == hash)
+}
+
+/// Run hashing and other blocking work off the async workers
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| DitriveError::Config(format!("Mount worker stopped: {}", e)))?
+}
+
+impl Filesystem for MountFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match name.to_str().and_then(|name| self.tree.child(parent, name)).and_then(|inode| self.tree.attr(inode)) {
+            Some(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.tree.attr(ino) {
+            Some(attr) => reply.attr(&ATTR_TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            reply.error(libc::EROFS);
+        } else if self.tree.node(ino).is_some() {
+            reply.opened(0, 0);
+        } else {
+            reply.error(libc::ENOENT);
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(Node::File { rel_path, metadata }) = self.tree.node(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+
+        // Reply from the runtime, so the loop keeps serving other requests during a download
+        let fetcher = Arc::clone(&self.fetcher);
+        let (rel_path, metadata) = (rel_path.clone(), metadata.clone());
+        self.runtime.spawn(async move {
+            match fetcher.read(ino, &rel_path, &metadata, offset.max(0) as u64, size as usize).await {
+                Ok(data) => reply.data(&data),
+                Err(errno) => reply.error(errno),
+            }
+        });
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let Some(Node::Folder { children }) = self.tree.node(ino) else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+
+        let parent = self.tree.parents[(ino - ROOT_INODE) as usize];
+        let mut entries = vec![(ino, FileType::Directory, ".".to_string()), (parent, FileType::Directory, "..".to_string())];
+        for (name, &child) in children {
+            let kind = match self.tree.node(child) {
+                Some(Node::Folder { .. }) => FileType::Directory,
+                _ => FileType::RegularFile,
+            };
+            entries.push((child, kind, name.clone()));
+        }
+
+        for (index, (inode, kind, name)) in entries.into_iter().enumerate().skip(offset.max(0) as usize) {
+            // The offset handed back is where the next call resumes
+            if reply.add(inode, index as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        debug!("Listed folder inode {}", ino);
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(size: u64) -> FileMetadata {
+        FileMetadata {
+            id: format!("id-{}", size),
+            hash: format!("hash-{}", size),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tree_nests_files_in_folders() {
+        let tree = MountTree::new(vec![
+            ("assets/levels/one.bin".to_string(), metadata(1)),
+            ("assets/hero.psd".to_string(), metadata(2)),
+            ("intro.mp4".to_string(), metadata(3)),
+        ]);
+        assert_eq!(tree.file_count(), 3);
+
+        let assets = tree.child(ROOT_INODE, "assets").unwrap();
+        let levels = tree.child(assets, "levels").unwrap();
+        let one = tree.child(levels, "one.bin").unwrap();
+        assert_eq!(tree.attr(levels).unwrap().kind, FileType::Directory);
+        assert_eq!(tree.attr(one).unwrap().size, 1);
+        assert_eq!(tree.attr(one).unwrap().perm, 0o444);
+        assert_eq!(tree.parents[(levels - ROOT_INODE) as usize], assets);
+        assert!(tree.child(one, "anything").is_none());
+        assert!(tree.child(ROOT_INODE, "missing.bin").is_none());
+    }
+
+    #[test]
+    fn test_tree_skips_clashing_paths() {
+        let tree = MountTree::new(vec![
+            ("assets".to_string(), metadata(1)),
+            ("assets/hero.psd".to_string(), metadata(2)),
+        ]);
+        assert_eq!(tree.file_count(), 1);
+        let assets = tree.child(ROOT_INODE, "assets").unwrap();
+        assert_eq!(tree.attr(assets).unwrap().kind, FileType::RegularFile);
+    }
+}
@@ -1,34 +1,100 @@
-//! OAuth2 authentication for Google Drive
+//! OAuth2 authentication for Google Drive and OneDrive
 //! 
 //! Supports user OAuth flow with token persistence for collaboration
 
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpListener;
 use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
 use tracing::{debug, info};
 
 use crate::error::{DitriveError, Result};
+use crate::http;
+use crate::token_store;
+use crate::trace::TracedSend;
+
+/// Access tokens are refreshed once they are this close to expiring
+pub const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Port the sign-in callback listens on unless configured otherwise, so a
+/// client registered with http://localhost:8085 keeps working
+pub const DEFAULT_CALLBACK_PORT: u16 = 8085;
+
+/// How long login waits for the browser to come back before giving up
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How long a single callback connection may take to send its request line
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// OAuth2 client credentials (from Google Cloud Console)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OAuthCredentials {
     pub client_id: String,
     pub client_secret: String,
-    pub redirect_uri: String,
+    /// Port the sign-in callback listens on; 0 picks a free one
+    #[serde(default = "default_callback_port")]
+    pub callback_port: u16,
+    /// Named profile whose tokens are used; empty for the default tokens
+    #[serde(default)]
+    pub profile: String,
 }
 
-impl Default for OAuthCredentials {
-    fn default() -> Self {
-        Self {
-            client_id: String::new(),
-            client_secret: String::new(),
-            redirect_uri: "http://localhost:8085".to_string(),
-        }
+fn default_callback_port() -> u16 {
+    DEFAULT_CALLBACK_PORT
+}
+
+/// Loopback redirect URI for a callback port. Providers accept any port on
+/// localhost for desktop clients, so none is needed when nothing listens.
+fn redirect_uri(port: u16) -> String {
+    if port == 0 {
+        "http://localhost".to_string()
+    } else {
+        format!("http://localhost:{}", port)
     }
 }
 
+/// Where the browser's redirect is received: the IPv4 loopback and, where
+/// available, the IPv6 one on the same port, since "localhost" may resolve
+/// to either
+struct CallbackListeners {
+    v4: TcpListener,
+    v6: Option<TcpListener>,
+    port: u16,
+}
+
+impl CallbackListeners {
+    /// Listen on `port`, or on a free port if it is 0
+    async fn bind(port: u16) -> Result<Self> {
+        let v4 = TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| {
+            DitriveError::Auth(format!(
+                "Failed to start callback server on port {}: {}. Set settings.oauth_callback_port to another port, or 0 for any free one.",
+                port, e
+            ))
+        })?;
+        let port = v4.local_addr()?.port();
+        let v6 = match TcpListener::bind(("::1", port)).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                debug!("Not listening on [::1]:{}: {}", port, e);
+                None
+            }
+        };
+        Ok(Self { v4, v6, port })
+    }
+}
+
+/// Whether a profile name is usable as a directory under ~/.ditrive/profiles
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
 /// Stored OAuth tokens
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredTokens {
@@ -47,61 +113,203 @@ struct TokenResponse {
     token_type: String,
 }
 
-/// OAuth2 manager for Google Drive
+/// OAuth2 endpoints and scopes of an identity provider
+#[derive(Debug, Clone)]
+pub struct OAuthProvider {
+    pub display_name: &'static str,
+    pub auth_url: String,
+    pub token_url: String,
+    pub revoke_url: Option<String>,
+    pub scopes: &'static str,
+    /// Provider-specific query parameters appended to the authorization URL
+    pub extra_auth_params: &'static str,
+    /// File name of the token store under ~/.ditrive
+    pub tokens_file: &'static str,
+}
+
+impl OAuthProvider {
+    /// Google accounts, for Google Drive
+    pub fn google() -> Self {
+        Self {
+            display_name: "Google Drive",
+            auth_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            revoke_url: Some("https://oauth2.googleapis.com/revoke".to_string()),
+            scopes: "https://www.googleapis.com/auth/drive",
+            extra_auth_params: "&access_type=offline&prompt=consent",
+            tokens_file: "tokens.json",
+        }
+    }
+
+    /// Microsoft identity platform, for OneDrive and SharePoint
+    pub fn microsoft(tenant: &str) -> Self {
+        let tenant = if tenant.is_empty() { "common" } else { tenant };
+        Self {
+            display_name: "OneDrive",
+            auth_url: format!("https://login.microsoftonline.com/{}/oauth2/v2.0/authorize", tenant),
+            token_url: format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", tenant),
+            revoke_url: None,
+            scopes: "Files.ReadWrite.All offline_access",
+            extra_auth_params: "&prompt=select_account",
+            tokens_file: "tokens-onedrive.json",
+        }
+    }
+}
+
+/// Proof Key for Code Exchange (RFC 7636), so an intercepted authorization
+/// code is useless without the verifier that never leaves this process
+struct Pkce {
+    verifier: String,
+    challenge: String,
+}
+
+impl Pkce {
+    /// A fresh verifier and its S256 challenge
+    fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self::from_verifier(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+/// A sign-in started without a callback listener, for machines the browser's
+/// redirect can't reach. It is finished with the address the browser ended up
+/// on, pasted back in.
+pub struct ManualAuthorization {
+    pub auth_url: String,
+    redirect_uri: String,
+    pkce: Pkce,
+}
+
+/// Authorization code in a pasted redirect address, or the pasted text itself
+/// when it is just the code
+fn code_from_pasted(pasted: &str) -> Result<String> {
+    let pasted = pasted.trim();
+    if pasted.contains('?') || pasted.contains("code=") {
+        if let Some(error) = query_param(pasted, "error") {
+            return Err(DitriveError::Auth(format!("Authorization was refused: {}", error)));
+        }
+        return query_param(pasted, "code")
+            .ok_or_else(|| DitriveError::Auth("The pasted address has no authorization code".to_string()));
+    }
+    if pasted.is_empty() {
+        return Err(DitriveError::Auth("No authorization code was entered".to_string()));
+    }
+    urlencoding::decode(pasted)
+        .map(|code| code.into_owned())
+        .map_err(|e| DitriveError::Auth(format!("Invalid authorization code: {}", e)))
+}
+
+/// Decoded value of a query parameter in a URL or request path
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let query = url.split_once('?').map_or(url, |(_, query)| query);
+    let query = query.split('#').next().unwrap_or_default();
+    query.split('&').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if key != name {
+            return None;
+        }
+        urlencoding::decode(value).ok().map(|value| value.into_owned())
+    })
+}
+
+/// OAuth2 manager for Google Drive and other providers
 pub struct OAuthManager {
     credentials: OAuthCredentials,
+    provider: OAuthProvider,
     tokens_path: PathBuf,
     client: reqwest::Client,
 }
 
 impl OAuthManager {
-    const AUTH_URL: &'static str = "https://accounts.google.com/o/oauth2/v2/auth";
-    const TOKEN_URL: &'static str = "https://oauth2.googleapis.com/token";
-    const SCOPES: &'static str = "https://www.googleapis.com/auth/drive";
-
-    /// Create a new OAuthManager
+    /// Create a new OAuthManager for Google Drive
     pub fn new(credentials: OAuthCredentials) -> Self {
-        let tokens_path = dirs::home_dir()
+        Self::with_provider(credentials, OAuthProvider::google())
+    }
+
+    /// Create a new OAuthManager for a specific identity provider.
+    ///
+    /// Tokens of a named profile live in `~/.ditrive/profiles/<profile>/`.
+    pub fn with_provider(credentials: OAuthCredentials, provider: OAuthProvider) -> Self {
+        let mut tokens_dir = dirs::home_dir()
             .unwrap_or_else(|| PathBuf::from("."))
-            .join(".ditrive")
-            .join("tokens.json");
+            .join(".ditrive");
+        if !credentials.profile.is_empty() {
+            // Never let a profile name point outside the profiles directory
+            let profile: String = credentials
+                .profile
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                .collect();
+            tokens_dir = tokens_dir.join("profiles").join(profile);
+        }
+        let tokens_path = tokens_dir.join(provider.tokens_file);
 
         Self {
             credentials,
+            provider,
             tokens_path,
-            client: reqwest::Client::new(),
+            client: http::client(),
+        }
+    }
+
+    /// Display name of the identity provider's service
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.display_name
+    }
+
+    /// Path of the token store
+    pub fn tokens_path(&self) -> &std::path::Path {
+        &self.tokens_path
+    }
+
+    /// Client credentials as form fields; public clients have no secret
+    fn client_form_fields(&self) -> Vec<(&str, &str)> {
+        let mut fields = vec![("client_id", self.credentials.client_id.as_str())];
+        if !self.credentials.client_secret.is_empty() {
+            fields.push(("client_secret", self.credentials.client_secret.as_str()));
         }
+        fields
     }
 
     /// Get a valid access token (refreshing if needed)
     pub async fn get_access_token(&self) -> Result<String> {
+        Ok(self.get_tokens().await?.access_token)
+    }
+
+    /// Get valid tokens, refreshing them if needed, along with when they expire
+    pub async fn get_tokens(&self) -> Result<StoredTokens> {
         // Try to load existing tokens
         if let Ok(tokens) = self.load_tokens() {
             // Check if token is still valid (with 5 min buffer)
             let now = chrono::Utc::now().timestamp();
-            if tokens.expires_at > now + 300 {
+            if tokens.expires_at > now + REFRESH_MARGIN_SECS {
                 debug!("Using cached access token");
-                return Ok(tokens.access_token);
+                return Ok(tokens);
             }
 
             // Try to refresh the token
             if let Some(refresh_token) = &tokens.refresh_token {
                 info!("Refreshing access token...");
                 if let Ok(new_tokens) = self.refresh_token(refresh_token).await {
-                    return Ok(new_tokens.access_token);
+                    return Ok(new_tokens);
                 }
             }
         }
 
         // Need to do full OAuth flow
         info!("Starting OAuth authorization flow...");
-        let tokens = self.authorize().await?;
-        Ok(tokens.access_token)
+        self.authorize().await
     }
 
     /// Load tokens from disk
     fn load_tokens(&self) -> Result<StoredTokens> {
-        let content = fs::read_to_string(&self.tokens_path)?;
+        let content = token_store::read(&self.tokens_path)?;
         let tokens: StoredTokens = serde_json::from_str(&content)?;
         Ok(tokens)
     }
@@ -112,84 +320,135 @@ impl OAuthManager {
             fs::create_dir_all(parent)?;
         }
         let content = serde_json::to_string_pretty(tokens)?;
-        fs::write(&self.tokens_path, content)?;
+        token_store::write(&self.tokens_path, &content)?;
         debug!("Saved tokens to {:?}", self.tokens_path);
         Ok(())
     }
 
+    /// Authorization URL the user signs in at
+    fn auth_url(&self, pkce: &Pkce, redirect_uri: &str) -> String {
+        format!(
+            "{}?client_id={}&redirect_uri={}&response_type=code&scope={}&code_challenge={}&code_challenge_method=S256{}",
+            self.provider.auth_url,
+            urlencoding::encode(&self.credentials.client_id),
+            urlencoding::encode(redirect_uri),
+            urlencoding::encode(self.provider.scopes),
+            pkce.challenge,
+            self.provider.extra_auth_params,
+        )
+    }
+
+    /// Start a sign-in that is finished by pasting the redirect address
+    /// instead of through the local callback listener
+    pub fn begin_manual(&self) -> ManualAuthorization {
+        let pkce = Pkce::generate();
+        let redirect_uri = redirect_uri(self.credentials.callback_port);
+        ManualAuthorization {
+            auth_url: self.auth_url(&pkce, &redirect_uri),
+            redirect_uri,
+            pkce,
+        }
+    }
+
+    /// Finish a manual sign-in with the pasted redirect address or code
+    pub async fn finish_manual(&self, authorization: &ManualAuthorization, pasted: &str) -> Result<StoredTokens> {
+        let code = code_from_pasted(pasted)?;
+        let tokens = self
+            .exchange_code(&code, &authorization.pkce.verifier, &authorization.redirect_uri)
+            .await?;
+        println!("✓ Successfully authenticated with {}!\n", self.provider.display_name);
+        Ok(tokens)
+    }
+
     /// Start the OAuth authorization flow
     pub async fn authorize(&self) -> Result<StoredTokens> {
-        // Build authorization URL
-        let auth_url = format!(
-            "{}?client_id={}&redirect_uri={}&response_type=code&scope={}&access_type=offline&prompt=consent",
-            Self::AUTH_URL,
-            urlencoding::encode(&self.credentials.client_id),
-            urlencoding::encode(&self.credentials.redirect_uri),
-            urlencoding::encode(Self::SCOPES),
-        );
+        let pkce = Pkce::generate();
+        let listeners = CallbackListeners::bind(self.credentials.callback_port).await?;
+        let redirect_uri = redirect_uri(listeners.port);
+        let auth_url = self.auth_url(&pkce, &redirect_uri);
 
-        println!("\n🔐 Google Drive Authorization Required\n");
+        println!("\n🔐 {} Authorization Required\n", self.provider.display_name);
         println!("Please open this URL in your browser:\n");
         println!("  {}\n", auth_url);
 
         // Try to open browser automatically
-        if let Err(_) = open::that(&auth_url) {
+        if open::that(&auth_url).is_err() {
             println!("(Could not open browser automatically)");
         }
 
         // Start local server to receive callback
-        let code = self.wait_for_callback()?;
+        let code = self.wait_for_callback(&listeners).await?;
         
         println!("\n✓ Authorization code received!");
 
         // Exchange code for tokens
-        let tokens = self.exchange_code(&code).await?;
+        let tokens = self.exchange_code(&code, &pkce.verifier, &redirect_uri).await?;
         
-        println!("✓ Successfully authenticated with Google Drive!\n");
+        println!("✓ Successfully authenticated with {}!\n", self.provider.display_name);
 
         Ok(tokens)
     }
 
-    /// Wait for OAuth callback on local server
-    fn wait_for_callback(&self) -> Result<String> {
-        // Parse port from redirect URI
-        let port: u16 = self.credentials.redirect_uri
-            .split(':')
-            .last()
-            .and_then(|s| s.trim_matches('/').parse().ok())
-            .unwrap_or(8085);
-
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
-            .map_err(|e| DitriveError::Auth(format!("Failed to start callback server: {}", e)))?;
-
-        println!("Waiting for authorization (listening on port {})...", port);
-
-        for stream in listener.incoming() {
-            if let Ok(mut stream) = stream {
-                let mut reader = BufReader::new(&stream);
-                let mut request_line = String::new();
-                reader.read_line(&mut request_line)?;
-
-                // Parse the authorization code from the request
-                if let Some(code) = self.parse_code_from_request(&request_line) {
-                    // Send success response
-                    let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n\
-                        <html><body style='font-family: sans-serif; text-align: center; padding: 50px;'>\
-                        <h1>✓ Authorization Successful!</h1>\
-                        <p>You can close this window and return to the terminal.</p>\
-                        </body></html>";
-                    stream.write_all(response.as_bytes())?;
-                    
-                    return Ok(code);
-                }
+    /// Wait for the OAuth callback on either loopback listener
+    async fn wait_for_callback(&self, listeners: &CallbackListeners) -> Result<String> {
+        println!("Waiting for authorization (listening on port {})...", listeners.port);
+
+        let accepted = async {
+            match listeners.v6 {
+                Some(ref v6) => tokio::select! {
+                    code = self.accept_callback(&listeners.v4) => code,
+                    code = self.accept_callback(v6) => code,
+                },
+                None => self.accept_callback(&listeners.v4).await,
+            }
+        };
+        tokio::time::timeout(CALLBACK_TIMEOUT, accepted)
+            .await
+            .map_err(|_| {
+                DitriveError::Auth(format!(
+                    "No authorization received within {} minutes. Run 'ditrive login' to try again.",
+                    CALLBACK_TIMEOUT.as_secs() / 60
+                ))
+            })?
+    }
 
-                // Send error response for other requests
-                let response = "HTTP/1.1 400 Bad Request\r\n\r\nMissing authorization code";
-                let _ = stream.write_all(response.as_bytes());
+    /// Accept connections until one carries the authorization code
+    async fn accept_callback(&self, listener: &TcpListener) -> Result<String> {
+        loop {
+            let (mut stream, peer) = listener.accept().await?;
+
+            // Browsers open speculative connections they never send on, so
+            // don't let one of those hold up the real callback
+            let request_line = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+                Ok(Ok(line)) => line,
+                Ok(Err(e)) => {
+                    debug!("Ignoring unreadable callback connection from {}: {}", peer, e);
+                    continue;
+                }
+                Err(_) => {
+                    debug!("Ignoring idle callback connection from {}", peer);
+                    continue;
+                }
+            };
+
+            // Parse the authorization code from the request
+            if let Some(code) = self.parse_code_from_request(&request_line) {
+                // Send success response
+                let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n\
+                    <html><body style='font-family: sans-serif; text-align: center; padding: 50px;'>\
+                    <h1>✓ Authorization Successful!</h1>\
+                    <p>You can close this window and return to the terminal.</p>\
+                    </body></html>";
+                stream.write_all(response.as_bytes()).await?;
+                let _ = stream.shutdown().await;
+
+                return Ok(code);
             }
-        }
 
-        Err(DitriveError::Auth("Failed to receive authorization callback".to_string()))
+            // Send error response for other requests
+            let response = "HTTP/1.1 400 Bad Request\r\n\r\nMissing authorization code";
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
     }
 
     /// Parse authorization code from HTTP request
@@ -201,34 +460,26 @@ impl OAuthManager {
         }
 
         let path = parts[1];
-        if !path.contains("code=") {
+        if !path.contains('?') {
             return None;
         }
-
-        // Parse query parameters
-        let query = path.split('?').nth(1)?;
-        for param in query.split('&') {
-            let mut kv = param.split('=');
-            if let (Some("code"), Some(code)) = (kv.next(), kv.next()) {
-                return Some(urlencoding::decode(code).ok()?.into_owned());
-            }
-        }
-
-        None
+        query_param(path, "code")
     }
 
     /// Exchange authorization code for tokens
-    async fn exchange_code(&self, code: &str) -> Result<StoredTokens> {
+    async fn exchange_code(&self, code: &str, code_verifier: &str, redirect_uri: &str) -> Result<StoredTokens> {
+        let mut form = self.client_form_fields();
+        form.extend([
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", redirect_uri),
+        ]);
+
         let response = self.client
-            .post(Self::TOKEN_URL)
-            .form(&[
-                ("client_id", self.credentials.client_id.as_str()),
-                ("client_secret", self.credentials.client_secret.as_str()),
-                ("code", code),
-                ("grant_type", "authorization_code"),
-                ("redirect_uri", self.credentials.redirect_uri.as_str()),
-            ])
-            .send()
+            .post(&self.provider.token_url)
+            .form(&form)
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Auth(format!("Token exchange failed: {}", e)))?;
 
@@ -254,15 +505,16 @@ impl OAuthManager {
 
     /// Refresh an expired access token
     async fn refresh_token(&self, refresh_token: &str) -> Result<StoredTokens> {
+        let mut form = self.client_form_fields();
+        form.extend([
+            ("refresh_token", refresh_token),
+            ("grant_type", "refresh_token"),
+        ]);
+
         let response = self.client
-            .post(Self::TOKEN_URL)
-            .form(&[
-                ("client_id", self.credentials.client_id.as_str()),
-                ("client_secret", self.credentials.client_secret.as_str()),
-                ("refresh_token", refresh_token),
-                ("grant_type", "refresh_token"),
-            ])
-            .send()
+            .post(&self.provider.token_url)
+            .form(&form)
+            .send_traced()
             .await
             .map_err(|e| DitriveError::Auth(format!("Token refresh failed: {}", e)))?;
 
@@ -289,12 +541,12 @@ impl OAuthManager {
 
     /// Revoke tokens and clear stored credentials
     pub async fn logout(&self) -> Result<()> {
-        if let Ok(tokens) = self.load_tokens() {
+        if let (Ok(tokens), Some(revoke_url)) = (self.load_tokens(), &self.provider.revoke_url) {
             // Revoke the token
             let _ = self.client
-                .post("https://oauth2.googleapis.com/revoke")
+                .post(revoke_url)
                 .form(&[("token", tokens.access_token.as_str())])
-                .send()
+                .send_traced()
                 .await;
         }
 
@@ -319,6 +571,13 @@ impl OAuthManager {
     }
 }
 
+/// Read the request line of an HTTP request
+async fn read_request_line(stream: &mut TcpStream) -> Result<String> {
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line).await?;
+    Ok(request_line)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -332,4 +591,83 @@ mod tests {
         assert!(code.is_some());
         assert!(code.unwrap().starts_with("4/0AfJohXn"));
     }
+
+    #[tokio::test]
+    async fn test_callback_listeners_pick_a_free_port() {
+        let listeners = CallbackListeners::bind(0).await.unwrap();
+        assert_ne!(listeners.port, 0);
+        assert_eq!(listeners.v4.local_addr().unwrap().port(), listeners.port);
+        if let Some(ref v6) = listeners.v6 {
+            assert_eq!(v6.local_addr().unwrap().port(), listeners.port);
+        }
+        assert_eq!(redirect_uri(listeners.port), format!("http://localhost:{}", listeners.port));
+        assert_eq!(redirect_uri(0), "http://localhost");
+    }
+
+    #[test]
+    fn test_code_from_pasted() {
+        let redirect = "http://localhost:8085/?state=x&code=4%2F0AfJohXn&scope=drive";
+        assert_eq!(code_from_pasted(redirect).unwrap(), "4/0AfJohXn");
+        assert_eq!(code_from_pasted("  4/0AfJohXn\n").unwrap(), "4/0AfJohXn");
+        assert_eq!(code_from_pasted("4%2F0AfJohXn").unwrap(), "4/0AfJohXn");
+        assert!(code_from_pasted("http://localhost:8085/?error=access_denied").is_err());
+        assert!(code_from_pasted("http://localhost:8085/?scope=drive").is_err());
+        assert!(code_from_pasted("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_callback_skips_requests_without_code() {
+        let oauth = OAuthManager::new(OAuthCredentials::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let mut favicon = TcpStream::connect(addr).await.unwrap();
+            favicon.write_all(b"GET /favicon.ico HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut callback = TcpStream::connect(addr).await.unwrap();
+            callback.write_all(b"GET /?code=abc%2F123 HTTP/1.1\r\n\r\n").await.unwrap();
+        });
+
+        let code = oauth.accept_callback(&listener).await.unwrap();
+        assert_eq!(code, "abc/123");
+        client.await.unwrap();
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mJ0vr9ahTU5HIbSPbGQkQ2T_4Rz4VE".to_string());
+        assert_eq!(pkce.challenge, "P7Q_tMJV5XMxkLrAeMeVzHllaT3D9hSJQrmDgo6v5m0");
+
+        let generated = Pkce::generate();
+        assert_eq!(generated.verifier.len(), 43);
+        assert_ne!(generated.verifier, Pkce::generate().verifier);
+    }
+
+    #[test]
+    fn test_profile_tokens_path() {
+        let default = OAuthManager::new(OAuthCredentials::default());
+        let work = OAuthManager::new(OAuthCredentials {
+            profile: "work".to_string(),
+            ..Default::default()
+        });
+        assert!(work.tokens_path().ends_with(".ditrive/profiles/work/tokens.json"));
+        assert_eq!(work.tokens_path().parent().unwrap().parent().unwrap().parent(), default.tokens_path().parent());
+
+        let escaping = OAuthManager::new(OAuthCredentials {
+            profile: "../../etc".to_string(),
+            ..Default::default()
+        });
+        assert!(escaping.tokens_path().ends_with("profiles/______etc/tokens.json"));
+        assert!(is_valid_profile_name("work_2") && !is_valid_profile_name("../x"));
+    }
+
+    #[test]
+    fn test_microsoft_provider_tenant() {
+        let provider = OAuthProvider::microsoft("");
+        assert!(provider.auth_url.contains("/common/"));
+
+        let provider = OAuthProvider::microsoft("contoso.onmicrosoft.com");
+        assert!(provider.token_url.contains("/contoso.onmicrosoft.com/oauth2/v2.0/token"));
+        assert_ne!(provider.tokens_file, OAuthProvider::google().tokens_file);
+    }
 }
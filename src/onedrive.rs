@@ -0,0 +1,465 @@
+//! OneDrive / SharePoint backend using the Microsoft Graph API
+
+use async_trait::async_trait;
+use reqwest::{header, Client, Response};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::backend::StorageBackend;
+use crate::chunking::{self, ChunkTuner};
+use crate::drive::{calculate_file_hash, transfer_progress_bar, upload_name, FileMetadata};
+use crate::error::{DitriveError, Result};
+use crate::http;
+use crate::oauth::{OAuthCredentials, OAuthManager, OAuthProvider};
+use crate::paths;
+use crate::progress::Transfer;
+use crate::trace::{self, TracedSend};
+
+/// Drive item returned by the Graph API
+#[derive(Debug, Deserialize)]
+struct GraphItem {
+    id: String,
+    #[serde(default)]
+    size: u64,
+}
+
+/// Upload session created for large files
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadSession {
+    upload_url: String,
+}
+
+/// OneDrive / SharePoint document library manager
+pub struct OneDriveManager {
+    client: Client,
+    access_token: String,
+    drive_base: String,
+    root_path: String,
+    repo_folder_id: String,
+    folder_cache: HashMap<String, String>,
+    hash_suffix_names: bool,
+}
+
+impl OneDriveManager {
+    const GRAPH_BASE: &'static str = "https://graph.microsoft.com/v1.0";
+    /// Largest file sent as a single PUT; bigger files use an upload session
+    const SIMPLE_UPLOAD_LIMIT: u64 = 4 * 1024 * 1024;
+    /// Graph requires upload session chunks to be a multiple of 320 KiB
+    const CHUNK_ALIGN: usize = 320 * 1024;
+    /// Largest chunk Graph accepts in one request
+    const MAX_CHUNK_SIZE: usize = 60 * 1024 * 1024;
+    /// Attempts per chunk before the upload is given up
+    const CHUNK_ATTEMPTS: u32 = 4;
+
+    /// Create a new OneDriveManager and open the repository folder.
+    ///
+    /// An empty `drive_id` uses the signed-in user's OneDrive; a SharePoint
+    /// document library is selected by its drive ID.
+    pub async fn new(
+        credentials: OAuthCredentials,
+        tenant: &str,
+        drive_id: &str,
+        root_path: &str,
+        repo_name: &str,
+    ) -> Result<Self> {
+        let oauth = OAuthManager::with_provider(credentials, OAuthProvider::microsoft(tenant));
+        let access_token = oauth.get_access_token().await?;
+
+        let drive_base = if drive_id.is_empty() {
+            format!("{}/me/drive", Self::GRAPH_BASE)
+        } else {
+            format!("{}/drives/{}", Self::GRAPH_BASE, drive_id)
+        };
+
+        let mut manager = Self {
+            client: http::client(),
+            access_token,
+            drive_base,
+            root_path: root_path.trim_matches('/').to_string(),
+            repo_folder_id: String::new(),
+            folder_cache: HashMap::new(),
+            hash_suffix_names: false,
+        };
+
+        let root_path = manager.root_path.clone();
+        let mut folder_id = "root".to_string();
+        for name in root_path.split('/').filter(|s| !s.is_empty()) {
+            folder_id = manager.get_or_create_folder(name, &folder_id).await?;
+        }
+        manager.repo_folder_id = manager.get_or_create_folder(repo_name, &folder_id).await?;
+
+        info!(
+            "OneDriveManager initialized for repo '{}' with folder ID: {}",
+            repo_name, manager.repo_folder_id
+        );
+
+        Ok(manager)
+    }
+
+    /// Get or create a child folder
+    async fn get_or_create_folder(&mut self, name: &str, parent_id: &str) -> Result<String> {
+        let cache_key = format!("{}/{}", parent_id, name);
+        if let Some(id) = self.folder_cache.get(&cache_key) {
+            return Ok(id.clone());
+        }
+
+        let response = self
+            .client
+            .get(format!(
+                "{}/items/{}:/{}",
+                self.drive_base,
+                parent_id,
+                urlencoding::encode(name)
+            ))
+            .bearer_auth(&self.access_token)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to look up folder: {}", e)))?;
+
+        let folder_id = if response.status().is_success() {
+            let item: GraphItem = response.json().await
+                .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+            item.id
+        } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+            self.create_folder(name, parent_id).await?
+        } else {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to look up folder: {}", error)));
+        };
+
+        self.folder_cache.insert(cache_key, folder_id.clone());
+        Ok(folder_id)
+    }
+
+    /// Folder a repository-relative path goes in, creating any that are missing
+    async fn folder_for(&mut self, rel_path: &Path) -> Result<String> {
+        let mut folder_id = self.repo_folder_id.clone();
+        if let Some(parent) = rel_path.parent() {
+            for component in parent.components() {
+                if let std::path::Component::Normal(name) = component {
+                    folder_id = self
+                        .get_or_create_folder(&name.to_string_lossy(), &folder_id)
+                        .await?;
+                }
+            }
+        }
+        Ok(folder_id)
+    }
+
+    /// Create a child folder
+    async fn create_folder(&self, name: &str, parent_id: &str) -> Result<String> {
+        let response = self
+            .client
+            .post(format!("{}/items/{}/children", self.drive_base, parent_id))
+            .bearer_auth(&self.access_token)
+            .json(&serde_json::json!({
+                "name": name,
+                "folder": {},
+                "@microsoft.graph.conflictBehavior": "fail"
+            }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to create folder: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to create folder: {}", error)));
+        }
+
+        let item: GraphItem = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        info!("Created folder '{}' with ID: {}", name, item.id);
+        Ok(item.id)
+    }
+
+    /// Upload file content to an item address (`.../items/{id}` or `.../items/{parent}:/{name}:`)
+    async fn upload_content(&self, item_url: &str, file_path: &Path) -> Result<GraphItem> {
+        let file_name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let file_size = fs::metadata(file_path)?.len();
+        let pb = transfer_progress_bar(file_size, format!("Uploading {}", file_name));
+
+        let response = if file_size <= Self::SIMPLE_UPLOAD_LIMIT {
+            self.client
+                .put(format!("{}/content", item_url))
+                .bearer_auth(&self.access_token)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(fs::read(file_path)?)
+                .send_traced()
+                .await
+                .map_err(|e| DitriveError::Drive(format!("Failed to upload file: {}", e)))?
+        } else {
+            let session = self.create_upload_session(item_url).await?;
+            let mut tuner = ChunkTuner::load(
+                "onedrive",
+                Self::CHUNK_ALIGN,
+                Self::MAX_CHUNK_SIZE.min(chunking::MAX_CHUNK_SIZE),
+                Self::CHUNK_ALIGN,
+            );
+            let result = self
+                .upload_chunks(&session, file_path, file_size, &mut tuner, &pb)
+                .await;
+            tuner.save();
+            result?
+        };
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Upload failed: {}", error)));
+        }
+
+        let item: GraphItem = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+
+        pb.finish_with_message(format!("Uploaded {}", file_name));
+        Ok(item)
+    }
+
+    /// Send a file through an upload session in chunks sized by `tuner`,
+    /// returning the response to the last chunk sent.
+    ///
+    /// A chunk that fails with a network or server error is sent again,
+    /// smaller, from the same offset.
+    async fn upload_chunks(
+        &self,
+        session: &UploadSession,
+        file_path: &Path,
+        file_size: u64,
+        tuner: &mut ChunkTuner,
+        pb: &Transfer,
+    ) -> Result<Response> {
+        let mut file = fs::File::open(file_path)?;
+        let mut offset = 0u64;
+        let mut failures = 0;
+
+        loop {
+            let mut chunk = Vec::with_capacity(tuner.chunk_size());
+            file.seek(SeekFrom::Start(offset))?;
+            (&mut file).take(tuner.chunk_size() as u64).read_to_end(&mut chunk)?;
+            if chunk.is_empty() {
+                return Err(DitriveError::Drive(format!(
+                    "{} changed size during upload",
+                    file_path.display()
+                )));
+            }
+            let end = offset + chunk.len() as u64;
+
+            // Upload URLs are pre-authenticated; Graph rejects a bearer token here
+            let started = Instant::now();
+            let result = self
+                .client
+                .put(&session.upload_url)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", offset, end - 1, file_size),
+                )
+                .body(chunk)
+                .send_traced()
+                .await;
+
+            let response = match result {
+                Ok(response) if !response.status().is_server_error() => response,
+                failed => {
+                    failures += 1;
+                    if failures >= Self::CHUNK_ATTEMPTS {
+                        return failed
+                            .map_err(|e| DitriveError::Drive(format!("Failed to upload chunk: {}", e)));
+                    }
+                    tuner.record_failure();
+                    warn!(
+                        "Chunk at byte {} failed, retrying with {} byte chunks",
+                        offset,
+                        tuner.chunk_size()
+                    );
+                    continue;
+                }
+            };
+
+            failures = 0;
+            tuner.record_success(started.elapsed());
+            pb.set_position(end);
+            offset = end;
+
+            if offset >= file_size || !response.status().is_success() {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Start a resumable upload session for an item address
+    async fn create_upload_session(&self, item_url: &str) -> Result<UploadSession> {
+        let response = self
+            .client
+            .post(format!("{}/createUploadSession", item_url))
+            .bearer_auth(&self.access_token)
+            .json(&serde_json::json!({
+                "item": { "@microsoft.graph.conflictBehavior": "replace" }
+            }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to create upload session: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to create upload session: {}", error)));
+        }
+
+        response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))
+    }
+
+    /// Append a short content hash to uploaded file names (see `upload_name`)
+    pub fn set_hash_suffix_names(&mut self, enabled: bool) {
+        self.hash_suffix_names = enabled;
+    }
+
+    /// Build the metadata stored in the tracker for an uploaded item
+    fn file_metadata(item: GraphItem, file_path: &Path) -> Result<FileMetadata> {
+        Ok(FileMetadata {
+            id: item.id,
+            hash: calculate_file_hash(file_path)?,
+            size: item.size,
+            uploaded_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        })
+    }
+}
+
+#[async_trait]
+impl StorageBackend for OneDriveManager {
+    fn name(&self) -> &'static str {
+        "OneDrive"
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        &self.repo_folder_id
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        let rel_path = paths::relative(repo_path, file_path)
+            .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?;
+        let rel_path = Path::new(&rel_path);
+        let folder_id = self.folder_for(rel_path).await?;
+
+        let file_name = rel_path.file_name().unwrap_or_default().to_string_lossy();
+        let stored_name = if self.hash_suffix_names {
+            upload_name(&file_name, &calculate_file_hash(file_path)?, true)
+        } else {
+            file_name.to_string()
+        };
+        let item_url = format!(
+            "{}/items/{}:/{}:",
+            self.drive_base,
+            folder_id,
+            urlencoding::encode(&stored_name)
+        );
+
+        let item = self.upload_content(&item_url, file_path).await?;
+        info!("Uploaded {} ({} bytes) to OneDrive", file_name, item.size);
+        Self::file_metadata(item, file_path)
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        let item_url = format!("{}/items/{}", self.drive_base, file_id);
+
+        // OneDrive keeps the previous content in the item's version history
+        let item = self.upload_content(&item_url, file_path).await?;
+        debug!("Updated OneDrive item {}", file_id);
+        Self::file_metadata(item, file_path)
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let _timer = trace::phase("download", destination.display().to_string());
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/items/{}/content", self.drive_base, file_id))
+            .bearer_auth(&self.access_token)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to download file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Download failed: {}", error)));
+        }
+
+        let bytes = response.bytes().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to read response: {}", e)))?;
+        fs::write(destination, &bytes)?;
+
+        info!("Downloaded {:?} from OneDrive", destination);
+        Ok(())
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        self.client
+            .get(format!("{}/items/{}", self.drive_base, file_id))
+            .bearer_auth(&self.access_token)
+            .query(&[("select", "id")])
+            .send_traced()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/items/{}", self.drive_base, file_id))
+            .bearer_auth(&self.access_token)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to delete file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to delete file: {}", error)));
+        }
+
+        debug!("Deleted {} from OneDrive", file_id);
+        Ok(())
+    }
+
+    async fn move_file(&mut self, file_id: &str, rel_path: &str) -> Result<String> {
+        // Moving keeps the item and its ID; only its folder and name change
+        let folder_id = self.folder_for(Path::new(rel_path)).await?;
+        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        let response = self
+            .client
+            .patch(format!("{}/items/{}", self.drive_base, file_id))
+            .bearer_auth(&self.access_token)
+            .json(&serde_json::json!({
+                "parentReference": { "id": folder_id },
+                "name": name,
+            }))
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to move file: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Failed to move file: {}", error)));
+        }
+
+        let item: GraphItem = response.json().await
+            .map_err(|e| DitriveError::Drive(format!("Failed to parse response: {}", e)))?;
+        debug!("Moved OneDrive item {} to {}", file_id, rel_path);
+        Ok(item.id)
+    }
+}
@@ -0,0 +1,173 @@
+//! Path normalization shared by the trackers, the .gitignore parser and the
+//! backends' folder mapping
+//!
+//! Paths recorded in `.woilah` files, the manifest and `.gitignore` always
+//! use forward slashes, whatever the platform. On Windows one folder can be
+//! spelled `C:\repo`, `c:/repo` or `\\?\C:\repo` (what `canonicalize`
+//! returns), and a share `\\server\share` or `\\?\UNC\server\share`, so
+//! paths are compared in their [`normalize`]d form. Paths longer than
+//! `MAX_PATH` go through [`long_path`] before they are opened.
+
+use std::path::{Path, PathBuf};
+
+/// Longest path Windows APIs accept without the `\\?\` prefix
+pub const MAX_PATH: usize = 260;
+
+/// A path with forward slashes, Windows verbatim prefixes removed, the drive
+/// letter upper-cased, and empty and `.` segments dropped
+pub fn normalize(path: &str) -> String {
+    let slashed = path.replace('\\', "/");
+    let unprefixed = if let Some(rest) = slashed.strip_prefix("//?/UNC/").or_else(|| slashed.strip_prefix("//./UNC/")) {
+        format!("//{}", rest)
+    } else if let Some(rest) = slashed.strip_prefix("//?/").or_else(|| slashed.strip_prefix("//./")) {
+        rest.to_string()
+    } else {
+        slashed
+    };
+
+    let (root, rest) = if let Some(rest) = unprefixed.strip_prefix("//") {
+        ("//", rest)
+    } else if let Some(rest) = unprefixed.strip_prefix('/') {
+        ("/", rest)
+    } else {
+        ("", unprefixed.as_str())
+    };
+    let mut normalized = root.to_string();
+    normalized.push_str(&rest.split('/').filter(|segment| !segment.is_empty() && *segment != ".").collect::<Vec<_>>().join("/"));
+
+    if has_drive_letter(&normalized) {
+        normalized[..1].make_ascii_uppercase();
+    }
+    normalized
+}
+
+/// Whether a normalized path starts with a drive letter, like `C:/`
+fn has_drive_letter(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// A path with forward slashes, as recorded in trackers and `.gitignore`
+pub fn to_slash(path: &Path) -> String {
+    normalize(&path.to_string_lossy())
+}
+
+/// `path` relative to `base`, with forward slashes, or None if it isn't
+/// inside it. Comparison ignores ASCII case when `ignore_case` is set.
+fn relative_str(base: &str, path: &str, ignore_case: bool) -> Option<String> {
+    let base = normalize(base);
+    let path = normalize(path);
+    let base = base.trim_end_matches('/');
+    if path.len() < base.len() || !path.is_char_boundary(base.len()) {
+        return None;
+    }
+
+    let (head, rest) = path.split_at(base.len());
+    let same = if ignore_case { head.eq_ignore_ascii_case(base) } else { head == base };
+    match rest.strip_prefix('/') {
+        _ if !same => None,
+        Some(rest) => Some(rest.to_string()),
+        None if rest.is_empty() => Some(String::new()),
+        None if base.is_empty() || base.ends_with(':') => Some(rest.to_string()),
+        None => None,
+    }
+}
+
+/// `path` relative to `base`, with forward slashes, or None if it isn't inside it.
+///
+/// Unlike `Path::strip_prefix`, `C:\repo\a.bin` is found inside `\\?\c:\repo`.
+pub fn relative(base: &Path, path: &Path) -> Option<String> {
+    relative_str(&base.to_string_lossy(), &path.to_string_lossy(), cfg!(windows))
+}
+
+/// Path of a file relative to the repository root, with forward slashes;
+/// paths outside the repository come back whole
+pub fn repo_relative(repo_path: &Path, path: &Path) -> String {
+    relative(repo_path, path).unwrap_or_else(|| to_slash(path))
+}
+
+/// The `\\?\` form of an absolute Windows path too long for `MAX_PATH`, or
+/// None if it can be used as it is
+fn long_path_str(path: &str) -> Option<String> {
+    if path.len() < MAX_PATH || path.starts_with(r"\\?\") {
+        return None;
+    }
+    let normalized = normalize(path);
+    let verbatim = if let Some(share) = normalized.strip_prefix("//") {
+        format!(r"\\?\UNC\{}", share)
+    } else if has_drive_letter(&normalized) && normalized[2..].starts_with('/') {
+        format!(r"\\?\{}", normalized)
+    } else {
+        return None;
+    };
+    Some(verbatim.replace('/', r"\"))
+}
+
+/// A path Windows can open even when it is longer than `MAX_PATH`; other
+/// platforms get it back unchanged
+pub fn long_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        if let Some(long) = long_path_str(&path.to_string_lossy()) {
+            return PathBuf::from(long);
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("assets/./hero.bin"), "assets/hero.bin");
+        assert_eq!(normalize("/home/ana//repo/"), "/home/ana/repo");
+        assert_eq!(normalize(r"assets\art\hero.psd"), "assets/art/hero.psd");
+        assert_eq!(normalize(r"c:\Users\ana\repo"), "C:/Users/ana/repo");
+        assert_eq!(normalize(r"\\?\C:\Users\ana\repo"), "C:/Users/ana/repo");
+        assert_eq!(normalize(r"\\server\share\repo"), "//server/share/repo");
+        assert_eq!(normalize(r"\\?\UNC\server\share\repo"), "//server/share/repo");
+    }
+
+    #[test]
+    fn test_relative_windows() {
+        let relative = |base: &str, path: &str| relative_str(base, path, true);
+        assert_eq!(relative(r"C:\repo", r"C:\repo\art\hero.psd").as_deref(), Some("art/hero.psd"));
+        assert_eq!(relative(r"\\?\C:\repo", r"c:\REPO\art\hero.psd").as_deref(), Some("art/hero.psd"));
+        assert_eq!(relative(r"\\?\UNC\nas\projects\game", r"\\nas\projects\game\level.bin").as_deref(), Some("level.bin"));
+        assert_eq!(relative(r"C:\repo", r"C:\repo").as_deref(), Some(""));
+        assert_eq!(relative(r"C:\", r"C:\level.bin").as_deref(), Some("level.bin"));
+        assert_eq!(relative(r"C:\repo", r"C:\repository\level.bin"), None);
+        assert_eq!(relative(r"C:\repo", r"D:\repo\level.bin"), None);
+    }
+
+    #[test]
+    fn test_relative_is_case_sensitive_elsewhere() {
+        assert_eq!(relative_str("/home/ana/repo", "/home/ana/repo/a.bin", false).as_deref(), Some("a.bin"));
+        assert_eq!(relative_str("/home/ana/repo", "/home/ana/Repo/a.bin", false), None);
+        assert_eq!(relative_str("/", "/a.bin", false).as_deref(), Some("a.bin"));
+    }
+
+    #[test]
+    fn test_long_path() {
+        let deep = format!(r"C:\repo\{}\hero.psd", "nested\\".repeat(40));
+        assert!(deep.len() > MAX_PATH);
+        let long = long_path_str(&deep).unwrap();
+        assert!(long.starts_with(r"\\?\C:\repo\nested\"));
+        assert!(!long.contains('/'));
+
+        let share = format!(r"\\nas\projects\{}\hero.psd", "nested\\".repeat(40));
+        assert!(long_path_str(&share).unwrap().starts_with(r"\\?\UNC\nas\projects\nested\"));
+
+        assert_eq!(long_path_str(r"C:\repo\hero.psd"), None);
+        assert_eq!(long_path_str(&long), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path_is_applied_on_windows() {
+        let deep = PathBuf::from(format!(r"C:\repo\{}\hero.psd", "nested\\".repeat(40)));
+        assert!(long_path(&deep).to_string_lossy().starts_with(r"\\?\C:\"));
+        assert_eq!(relative(Path::new(r"\\?\C:\repo"), Path::new(r"c:\repo\a.bin")).as_deref(), Some("a.bin"));
+    }
+}
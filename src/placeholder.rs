@@ -0,0 +1,198 @@
+//! Placeholders for managed files that aren't downloaded
+//!
+//! When `pull` leaves a managed file out, a tiny `<name>.ditrive` text file
+//! takes its place, so someone browsing the tree sees what exists, how big
+//! it is, and where it is stored. `ditrive hydrate <path>` swaps placeholders
+//! for the real content. Placeholders are only ever local, so each one is
+//! kept out of git by its own line in `.git/info/exclude`, which goes again
+//! with the placeholder.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::drive::FileMetadata;
+use crate::error::Result;
+use crate::paths;
+
+/// Suffix added to a managed file's name for its placeholder
+pub const PLACEHOLDER_SUFFIX: &str = ".ditrive";
+
+/// Pattern older versions excluded every placeholder with, catching
+/// unrelated files that happen to end in `.ditrive`
+const LEGACY_EXCLUDE_PATTERN: &str = "*?.ditrive";
+
+/// Placeholders are tiny; anything larger is a real file
+const MAX_PLACEHOLDER_SIZE: u64 = 2048;
+
+/// Line that marks a file as a placeholder
+const MARKER: &str = "# Ditrive placeholder";
+
+/// Path of the placeholder for a managed file
+pub fn placeholder_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(PLACEHOLDER_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// The managed file a placeholder path stands for, or None if it isn't one
+pub fn managed_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let managed = name.strip_suffix(PLACEHOLDER_SUFFIX).filter(|managed| !managed.is_empty())?;
+    Some(path.with_file_name(managed))
+}
+
+/// Whether a file is a placeholder written by `write`
+pub fn is_placeholder(path: &Path) -> bool {
+    managed_path(path).is_some()
+        && fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() <= MAX_PLACEHOLDER_SIZE)
+        && fs::read_to_string(path).is_ok_and(|content| content.starts_with(MARKER))
+}
+
+/// Placeholder content for a managed file
+pub fn render(rel_path: &str, metadata: &FileMetadata) -> String {
+    format!(
+        "{}\n# {} is stored remotely and hasn't been downloaded.\n# Run 'ditrive hydrate {}' to download it.\npath {}\nsize {}\nhash sha256:{}\nid {}\n",
+        MARKER, rel_path, rel_path, rel_path, metadata.size, metadata.hash, metadata.id
+    )
+}
+
+/// Write the placeholder for a managed file missing at `path`, unless it is already there
+pub fn write(path: &Path, rel_path: &str, metadata: &FileMetadata) -> Result<()> {
+    let placeholder = placeholder_path(path);
+    let content = render(rel_path, metadata);
+    if fs::read_to_string(&placeholder).is_ok_and(|existing| existing == content) {
+        return Ok(());
+    }
+    if let Some(parent) = placeholder.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&placeholder, content)?;
+    debug!("Wrote placeholder {:?}", placeholder);
+    Ok(())
+}
+
+/// Remove the placeholder of a managed file at `path`, if it has one, and its exclude line
+pub fn remove(repo_path: &Path, path: &Path) -> Result<()> {
+    let placeholder = placeholder_path(path);
+    if is_placeholder(&placeholder) {
+        fs::remove_file(&placeholder)?;
+        debug!("Removed placeholder {:?}", placeholder);
+    }
+    let line = exclude_line(repo_path, path);
+    update_exclude(repo_path, |lines| lines.retain(|existing| existing.trim() != line))
+}
+
+/// Keep the placeholder of the managed file at `path` out of git with an
+/// exact line in `.git/info/exclude`
+pub fn exclude_from_git(repo_path: &Path, path: &Path) -> Result<()> {
+    let line = exclude_line(repo_path, path);
+    update_exclude(repo_path, |lines| {
+        if !lines.iter().any(|existing| existing.trim() == line) {
+            lines.push(line);
+        }
+    })
+}
+
+/// Exclude line matching just the placeholder of the managed file at `path`
+fn exclude_line(repo_path: &Path, path: &Path) -> String {
+    format!("/{}", paths::repo_relative(repo_path, &placeholder_path(path)))
+}
+
+/// Edit the lines of `.git/info/exclude`, dropping the old catch-all
+/// pattern, and write them back if anything changed
+fn update_exclude(repo_path: &Path, edit: impl FnOnce(&mut Vec<String>)) -> Result<()> {
+    if !repo_path.join(".git").is_dir() {
+        return Ok(());
+    }
+
+    let exclude_path = repo_path.join(".git/info/exclude");
+    let content = fs::read_to_string(&exclude_path).unwrap_or_default();
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let original = lines.clone();
+    lines.retain(|line| line.trim() != LEGACY_EXCLUDE_PATTERN && line.trim() != MARKER);
+    edit(&mut lines);
+    if lines == original {
+        return Ok(());
+    }
+
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    if let Some(parent) = exclude_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&exclude_path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn metadata() -> FileMetadata {
+        FileMetadata {
+            id: "drive-id".to_string(),
+            hash: "abc123".to_string(),
+            size: 4096,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_placeholder_paths() {
+        assert_eq!(placeholder_path(Path::new("assets/hero.psd")), PathBuf::from("assets/hero.psd.ditrive"));
+        assert_eq!(managed_path(Path::new("assets/hero.psd.ditrive")), Some(PathBuf::from("assets/hero.psd")));
+        assert_eq!(managed_path(Path::new("assets/hero.psd")), None);
+        assert_eq!(managed_path(Path::new(".ditrive")), None);
+    }
+
+    #[test]
+    fn test_write_and_remove() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("assets/hero.psd");
+
+        write(&path, "assets/hero.psd", &metadata()).unwrap();
+        let placeholder = placeholder_path(&path);
+        assert!(is_placeholder(&placeholder));
+        let content = fs::read_to_string(&placeholder).unwrap();
+        assert!(content.contains("size 4096\n"));
+        assert!(content.contains("hash sha256:abc123\n"));
+        assert!(content.contains("id drive-id\n"));
+
+        remove(dir.path(), &path).unwrap();
+        assert!(!placeholder.exists());
+    }
+
+    #[test]
+    fn test_remove_leaves_other_files_alone() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(placeholder_path(&path), "my own file").unwrap();
+
+        remove(dir.path(), &path).unwrap();
+        assert!(placeholder_path(&path).exists());
+    }
+
+    #[test]
+    fn test_exclude_lines_per_placeholder() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join(".git/info")).unwrap();
+        fs::write(repo.join(".git/info/exclude"), format!("local.bin\n{}\n{}\n", MARKER, LEGACY_EXCLUDE_PATTERN)).unwrap();
+
+        let hero = repo.join("assets/hero.psd");
+        exclude_from_git(repo, &hero).unwrap();
+        exclude_from_git(repo, &hero).unwrap();
+        exclude_from_git(repo, &repo.join("level.bin")).unwrap();
+
+        let content = fs::read_to_string(repo.join(".git/info/exclude")).unwrap();
+        assert_eq!(content, "local.bin\n/assets/hero.psd.ditrive\n/level.bin.ditrive\n");
+
+        remove(repo, &hero).unwrap();
+        let content = fs::read_to_string(repo.join(".git/info/exclude")).unwrap();
+        assert_eq!(content, "local.bin\n/level.bin.ditrive\n");
+    }
+}
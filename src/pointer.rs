@@ -0,0 +1,185 @@
+//! Pointer files and the git clean/smudge filter that maintains them
+//!
+//! In pointer mode managed files stay in the working tree and are committed
+//! as small text pointers instead of being ignored. Git runs `ditrive
+//! filter-clean` when staging a file, which uploads the content and hands git
+//! the pointer, and `ditrive filter-smudge` on checkout, which swaps the
+//! pointer back for the content. Old commits can then be checked out with
+//! the content that belonged to them.
+
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+use crate::drive::FileMetadata;
+use crate::error::Result;
+
+/// First line of every pointer file
+pub const POINTER_VERSION: &str = "version https://github.com/frostfire1/Ditrive/pointer/v1";
+
+/// Name of the git filter driver
+pub const FILTER_NAME: &str = "ditrive";
+
+/// Pointer files are tiny; anything larger is real content
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// What a committed pointer file records about the content it stands for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pointer {
+    pub id: String,
+    pub hash: String,
+    pub size: u64,
+}
+
+impl Pointer {
+    /// Build a pointer from an upload's tracker entry
+    pub fn from_metadata(metadata: &FileMetadata) -> Self {
+        Self {
+            id: metadata.id.clone(),
+            hash: metadata.hash.clone(),
+            size: metadata.size,
+        }
+    }
+
+    /// Parse pointer file content, returning None for anything else
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        if content.len() as u64 > MAX_POINTER_SIZE {
+            return None;
+        }
+
+        let text = std::str::from_utf8(content).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != POINTER_VERSION {
+            return None;
+        }
+
+        let mut pointer = Self {
+            id: String::new(),
+            hash: String::new(),
+            size: 0,
+        };
+        for line in lines {
+            match line.split_once(' ')? {
+                ("id", value) => pointer.id = value.to_string(),
+                ("hash", value) => pointer.hash = value.strip_prefix("sha256:")?.to_string(),
+                ("size", value) => pointer.size = value.parse().ok()?,
+                // Unknown keys are ignored so newer pointers stay readable
+                _ => {}
+            }
+        }
+
+        if pointer.id.is_empty() || pointer.hash.is_empty() {
+            return None;
+        }
+        Some(pointer)
+    }
+
+    /// Read a pointer from a working tree file, if it is one
+    pub fn read_file(path: &Path) -> Option<Self> {
+        if fs::metadata(path).ok()?.len() > MAX_POINTER_SIZE {
+            return None;
+        }
+        Self::parse(&fs::read(path).ok()?)
+    }
+
+    /// The pointer file content
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\nid {}\nhash sha256:{}\nsize {}\n",
+            POINTER_VERSION, self.id, self.hash, self.size
+        )
+    }
+}
+
+/// Route a file through the ditrive filter by adding it to `.gitattributes`
+pub fn add_attribute(repo_path: &Path, rel_path: &str) -> Result<()> {
+    add_attribute_line(repo_path, &format!("{} filter={} -text", attribute_pattern(rel_path), FILTER_NAME))
+}
+
+/// Take a file out of the ditrive filter, returning whether it was routed through it
+pub fn remove_attribute(repo_path: &Path, rel_path: &str) -> Result<bool> {
+    let attributes_path = repo_path.join(".gitattributes");
+    let line = format!("{} filter={} -text", attribute_pattern(rel_path), FILTER_NAME);
+
+    let Ok(existing) = fs::read_to_string(&attributes_path) else {
+        return Ok(false);
+    };
+    if !existing.lines().any(|l| l.trim() == line) {
+        return Ok(false);
+    }
+
+    let content: String = existing
+        .lines()
+        .filter(|l| l.trim() != line)
+        .map(|l| format!("{}\n", l))
+        .collect();
+    fs::write(&attributes_path, content)?;
+
+    debug!("Removed {} from .gitattributes", rel_path);
+    Ok(true)
+}
+
+/// A `.gitattributes` pattern matching exactly one file.
+/// Spaces would end the pattern, so they are matched with a character class instead.
+pub fn attribute_pattern(rel_path: &str) -> String {
+    rel_path.replace(' ', "[[:space:]]")
+}
+
+/// Append a line to `.gitattributes` unless it is already there
+pub fn add_attribute_line(repo_path: &Path, line: &str) -> Result<()> {
+    let attributes_path = repo_path.join(".gitattributes");
+    let existing = fs::read_to_string(&attributes_path).unwrap_or_default();
+    if existing.lines().any(|l| l.trim() == line) {
+        return Ok(());
+    }
+
+    let mut content = existing;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(line);
+    content.push('\n');
+    fs::write(&attributes_path, content)?;
+
+    debug!("Added '{}' to .gitattributes", line);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pointer_round_trip() {
+        let pointer = Pointer {
+            id: "1AbC".to_string(),
+            hash: "deadbeef".to_string(),
+            size: 4096,
+        };
+
+        let text = pointer.to_text();
+        assert_eq!(Pointer::parse(text.as_bytes()), Some(pointer));
+        assert_eq!(Pointer::parse(b"\x89PNG binary content"), None);
+        assert_eq!(Pointer::parse(format!("{}\nsize 1\n", POINTER_VERSION).as_bytes()), None);
+    }
+
+    #[test]
+    fn test_add_attribute_is_idempotent() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(".gitattributes"), "*.sh text eol=lf").unwrap();
+
+        add_attribute(dir.path(), "assets/big level.bin").unwrap();
+        add_attribute(dir.path(), "assets/big level.bin").unwrap();
+
+        let content = fs::read_to_string(dir.path().join(".gitattributes")).unwrap();
+        assert_eq!(
+            content,
+            "*.sh text eol=lf\nassets/big[[:space:]]level.bin filter=ditrive -text\n"
+        );
+
+        assert!(remove_attribute(dir.path(), "assets/big level.bin").unwrap());
+        assert!(!remove_attribute(dir.path(), "assets/big level.bin").unwrap());
+        assert_eq!(fs::read_to_string(dir.path().join(".gitattributes")).unwrap(), "*.sh text eol=lf\n");
+    }
+}
@@ -0,0 +1,210 @@
+//! Pull request summaries of managed-file changes
+//!
+//! `ditrive pr-comment` compares the `.woilah` trackers at the PR's base with
+//! those at its head and posts the difference as a single PR comment. The
+//! comment carries a hidden marker, so later runs on the same PR edit it
+//! instead of adding another one.
+
+use std::collections::BTreeMap;
+
+use crate::drive::FileMetadata;
+
+/// Hidden marker identifying the comment Ditrive maintains on a PR
+pub const COMMENT_MARKER: &str = "<!-- ditrive:pr-comment -->";
+
+/// How a managed file changed between two commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl ChangeKind {
+    fn label(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "Added",
+            ChangeKind::Modified => "Modified",
+            ChangeKind::Removed => "Removed",
+        }
+    }
+}
+
+/// One managed file that changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// File ID after the change, or before it for removed files
+    pub id: String,
+    pub size: u64,
+    /// Size before the change, for modified files
+    pub old_size: Option<u64>,
+}
+
+/// Compare two sets of managed files keyed by repository-relative path
+pub fn diff_managed_files(
+    base: &BTreeMap<String, FileMetadata>,
+    head: &BTreeMap<String, FileMetadata>,
+) -> Vec<AssetChange> {
+    let mut changes = Vec::new();
+
+    for (path, new) in head {
+        match base.get(path) {
+            None => changes.push(AssetChange {
+                path: path.clone(),
+                kind: ChangeKind::Added,
+                id: new.id.clone(),
+                size: new.size,
+                old_size: None,
+            }),
+            Some(old) if old.id != new.id || old.hash != new.hash => changes.push(AssetChange {
+                path: path.clone(),
+                kind: ChangeKind::Modified,
+                id: new.id.clone(),
+                size: new.size,
+                old_size: Some(old.size),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (path, old) in base {
+        if !head.contains_key(path) {
+            changes.push(AssetChange {
+                path: path.clone(),
+                kind: ChangeKind::Removed,
+                id: old.id.clone(),
+                size: old.size,
+                old_size: None,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| (a.kind, &a.path).cmp(&(b.kind, &b.path)));
+    changes
+}
+
+/// Pull request number from a GitHub Actions ref such as `refs/pull/42/merge`
+pub fn pr_number_from_ref(github_ref: &str) -> Option<u64> {
+    github_ref.strip_prefix("refs/pull/")?.split('/').next()?.parse().ok()
+}
+
+/// `(owner, name)` from `owner/name` or a repository URL on any forge
+pub fn parse_repository(repository: &str) -> Option<(String, String)> {
+    let trimmed = repository.trim().trim_end_matches('/');
+    let trimmed = trimmed.strip_suffix(".git").unwrap_or(trimmed);
+    // Any host, so remotes on self-hosted forges work too
+    let path = match trimmed.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None => match trimmed.split_once(':') {
+            Some((host, path)) if host.contains('@') => path,
+            _ => trimmed,
+        },
+    };
+
+    match path.split('/').collect::<Vec<_>>()[..] {
+        [owner, name] if !owner.is_empty() && !name.is_empty() => Some((owner.to_string(), name.to_string())),
+        _ => None,
+    }
+}
+
+/// Render the PR comment body. `link` gives a browser link for a file ID, if the backend has one.
+pub fn render_comment(changes: &[AssetChange], link: impl Fn(&str) -> Option<String>) -> String {
+    let mut body = format!("{}\n### Ditrive managed files\n\n", COMMENT_MARKER);
+
+    if changes.is_empty() {
+        body.push_str("No managed files changed in this pull request.\n");
+        return body;
+    }
+
+    let count = |kind| changes.iter().filter(|c| c.kind == kind).count();
+    body.push_str(&format!(
+        "{} added, {} modified, {} removed\n\n",
+        count(ChangeKind::Added),
+        count(ChangeKind::Modified),
+        count(ChangeKind::Removed)
+    ));
+    body.push_str("| Change | File | Size |\n|---|---|---|\n");
+
+    for change in changes {
+        let name = format!("`{}`", change.path.replace('|', "\\|"));
+        let file = match link(&change.id) {
+            Some(url) if change.kind != ChangeKind::Removed => format!("[{}]({})", name, url),
+            _ => name,
+        };
+        let size = match change.old_size {
+            Some(old) => format!("{} → {}", format_size(old), format_size(change.size)),
+            None => format_size(change.size),
+        };
+        body.push_str(&format!("| {} | {} | {} |\n", change.kind.label(), file, size));
+    }
+
+    body
+}
+
+/// Human-readable size in MB, matching `ditrive list`
+fn format_size(size: u64) -> String {
+    if size > 0 {
+        format!("{:.2} MB", size as f64 / 1024.0 / 1024.0)
+    } else {
+        "Unknown".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(id: &str, hash: &str, size: u64) -> FileMetadata {
+        FileMetadata {
+            id: id.to_string(),
+            hash: hash.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_and_render_managed_file_changes() {
+        let mut base = BTreeMap::new();
+        base.insert("models/a.onnx".to_string(), metadata("a1", "h1", 1024 * 1024));
+        base.insert("videos/old.mp4".to_string(), metadata("v1", "h2", 2 * 1024 * 1024));
+        base.insert("same.bin".to_string(), metadata("s1", "h3", 10));
+
+        let mut head = base.clone();
+        head.remove("videos/old.mp4");
+        head.insert("models/a.onnx".to_string(), metadata("a2", "h4", 3 * 1024 * 1024));
+        head.insert("assets/new.png".to_string(), metadata("n1", "h5", 0));
+
+        let changes = diff_managed_files(&base, &head);
+        let kinds: Vec<_> = changes.iter().map(|c| (c.kind, c.path.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChangeKind::Added, "assets/new.png"),
+                (ChangeKind::Modified, "models/a.onnx"),
+                (ChangeKind::Removed, "videos/old.mp4"),
+            ]
+        );
+
+        let body = render_comment(&changes, |id| Some(format!("https://example.com/{}", id)));
+        assert!(body.starts_with(COMMENT_MARKER));
+        assert!(body.contains("1 added, 1 modified, 1 removed"));
+        assert!(body.contains("| Modified | [`models/a.onnx`](https://example.com/a2) | 1.00 MB → 3.00 MB |"));
+        assert!(body.contains("| Removed | `videos/old.mp4` | 2.00 MB |"));
+    }
+
+    #[test]
+    fn test_pr_and_repository_parsing() {
+        assert_eq!(pr_number_from_ref("refs/pull/42/merge"), Some(42));
+        assert_eq!(pr_number_from_ref("refs/heads/main"), None);
+
+        let expected = Some(("octo".to_string(), "assets".to_string()));
+        assert_eq!(parse_repository("octo/assets"), expected);
+        assert_eq!(parse_repository("https://github.com/octo/assets.git"), expected);
+        assert_eq!(parse_repository("git@github.com:octo/assets.git"), expected);
+        assert_eq!(parse_repository("https://git.example.com/octo/assets"), expected);
+        assert_eq!(parse_repository("octo"), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! Lightweight preview metadata extraction for managed files
+//!
+//! Extractors only read file headers (or stream text once for models), so
+//! they are cheap enough to run on every upload.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use tracing::debug;
+
+use crate::error::Result;
+
+/// Extracts preview metadata from files of the types it supports
+pub trait MetadataExtractor: Send + Sync {
+    /// Short name used in log messages
+    fn name(&self) -> &'static str;
+
+    /// Check if this extractor handles the given file
+    fn supports(&self, path: &Path) -> bool;
+
+    /// Extract metadata as key/value pairs
+    fn extract(&self, path: &Path) -> Result<BTreeMap<String, String>>;
+}
+
+/// The built-in extractors
+pub fn default_extractors() -> Vec<Box<dyn MetadataExtractor>> {
+    vec![
+        Box::new(ImageExtractor),
+        Box::new(WavExtractor),
+        Box::new(Mp4Extractor),
+        Box::new(ObjExtractor),
+    ]
+}
+
+/// Run every extractor that supports the file, merging their results.
+///
+/// Extractor failures are logged and skipped; preview metadata is best-effort.
+pub fn extract_metadata(
+    path: &Path,
+    extractors: &[Box<dyn MetadataExtractor>],
+) -> BTreeMap<String, String> {
+    let mut extra = BTreeMap::new();
+
+    for extractor in extractors.iter().filter(|e| e.supports(path)) {
+        match extractor.extract(path) {
+            Ok(values) => extra.extend(values),
+            Err(e) => debug!("{} extractor failed on {:?}: {}", extractor.name(), path, e),
+        }
+    }
+
+    extra
+}
+
+/// Lowercased extension of a path
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Read up to `len` bytes from the start of a file
+fn read_header(path: &Path, len: usize) -> Result<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(len);
+    File::open(path)?.take(len as u64).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Format a duration in seconds as e.g. "83.25s"
+fn format_seconds(seconds: f64) -> String {
+    format!("{:.2}s", seconds)
+}
+
+/// Image dimensions for PNG, GIF and JPEG
+pub struct ImageExtractor;
+
+impl ImageExtractor {
+    /// Parse width and height from the header of a supported image
+    fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.len() >= 24 {
+            let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+            let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+            return Some((width, height));
+        }
+
+        if data.starts_with(b"GIF8") && data.len() >= 10 {
+            let width = u16::from_le_bytes([data[6], data[7]]) as u32;
+            let height = u16::from_le_bytes([data[8], data[9]]) as u32;
+            return Some((width, height));
+        }
+
+        if data.starts_with(&[0xFF, 0xD8]) {
+            // Walk JPEG segments until a start-of-frame marker
+            let mut i = 2;
+            while i + 9 < data.len() {
+                if data[i] != 0xFF {
+                    return None;
+                }
+                let marker = data[i + 1];
+                let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+                let is_sof = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+                if is_sof {
+                    let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+                    let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+                    return Some((width, height));
+                }
+                i += 2 + length;
+            }
+        }
+
+        None
+    }
+}
+
+impl MetadataExtractor for ImageExtractor {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn supports(&self, path: &Path) -> bool {
+        matches!(extension(path).as_str(), "png" | "gif" | "jpg" | "jpeg")
+    }
+
+    fn extract(&self, path: &Path) -> Result<BTreeMap<String, String>> {
+        // JPEG metadata segments can be large; 256 KiB covers typical EXIF blocks
+        let data = read_header(path, 256 * 1024)?;
+        let mut values = BTreeMap::new();
+        if let Some((width, height)) = Self::dimensions(&data) {
+            values.insert("dimensions".to_string(), format!("{}x{}", width, height));
+        }
+        Ok(values)
+    }
+}
+
+/// Audio length for RIFF/WAVE files
+pub struct WavExtractor;
+
+impl WavExtractor {
+    /// Parse the duration in seconds from a WAVE file's chunks
+    fn duration(data: &[u8], file_len: u64) -> Option<f64> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return None;
+        }
+
+        let mut byte_rate = None;
+        let mut i = 12;
+        while i + 8 <= data.len() {
+            let id = &data[i..i + 4];
+            let size = u32::from_le_bytes(data[i + 4..i + 8].try_into().ok()?) as u64;
+            if id == b"fmt " && i + 20 <= data.len() {
+                byte_rate = Some(u32::from_le_bytes(data[i + 16..i + 20].try_into().ok()?));
+            } else if id == b"data" {
+                // Clamp to the real file length for truncated or streamed files
+                let available = file_len.saturating_sub(i as u64 + 8);
+                let rate = byte_rate.filter(|r| *r > 0)?;
+                return Some(size.min(available) as f64 / rate as f64);
+            }
+            i += 8 + size as usize + (size as usize % 2);
+        }
+
+        None
+    }
+}
+
+impl MetadataExtractor for WavExtractor {
+    fn name(&self) -> &'static str {
+        "wav"
+    }
+
+    fn supports(&self, path: &Path) -> bool {
+        extension(path) == "wav"
+    }
+
+    fn extract(&self, path: &Path) -> Result<BTreeMap<String, String>> {
+        let data = read_header(path, 64 * 1024)?;
+        let file_len = std::fs::metadata(path)?.len();
+        let mut values = BTreeMap::new();
+        if let Some(seconds) = Self::duration(&data, file_len) {
+            values.insert("duration".to_string(), format_seconds(seconds));
+        }
+        Ok(values)
+    }
+}
+
+/// Video duration for MP4/MOV files, read from the `mvhd` box
+pub struct Mp4Extractor;
+
+impl Mp4Extractor {
+    /// Find a child box by type within `[start, end)`, returning its content range.
+    /// Boxes that claim to run past `end` end the search, as a damaged file would.
+    fn find_box(file: &mut File, start: u64, end: u64, kind: &[u8; 4]) -> Result<Option<(u64, u64)>> {
+        let mut offset = start;
+        while end.saturating_sub(offset) >= 8 {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut header = [0u8; 16];
+            file.read_exact(&mut header[..8])?;
+            let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+            let mut header_len = 8;
+            if size == 1 {
+                file.read_exact(&mut header[8..16])?;
+                size = u64::from_be_bytes(header[8..16].try_into().unwrap_or_default());
+                header_len = 16;
+            } else if size == 0 {
+                size = end - offset;
+            }
+            if size < header_len {
+                return Ok(None);
+            }
+            let Some(box_end) = offset.checked_add(size).filter(|&box_end| box_end <= end) else {
+                return Ok(None);
+            };
+            if &header[4..8] == kind {
+                return Ok(Some((offset + header_len, box_end)));
+            }
+            offset = box_end;
+        }
+        Ok(None)
+    }
+}
+
+impl MetadataExtractor for Mp4Extractor {
+    fn name(&self) -> &'static str {
+        "mp4"
+    }
+
+    fn supports(&self, path: &Path) -> bool {
+        matches!(extension(path).as_str(), "mp4" | "m4v" | "mov")
+    }
+
+    fn extract(&self, path: &Path) -> Result<BTreeMap<String, String>> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut values = BTreeMap::new();
+
+        let Some((moov_start, moov_end)) = Self::find_box(&mut file, 0, file_len, b"moov")? else {
+            return Ok(values);
+        };
+        let Some((mvhd_start, _)) = Self::find_box(&mut file, moov_start, moov_end, b"mvhd")? else {
+            return Ok(values);
+        };
+
+        file.seek(SeekFrom::Start(mvhd_start))?;
+        let mut header = [0u8; 32];
+        file.read_exact(&mut header)?;
+
+        // Version 1 uses 64-bit creation/modification times and duration
+        let (timescale, duration) = if header[0] == 1 {
+            (
+                u32::from_be_bytes(header[20..24].try_into().unwrap_or_default()) as u64,
+                u64::from_be_bytes(header[24..32].try_into().unwrap_or_default()),
+            )
+        } else {
+            (
+                u32::from_be_bytes(header[12..16].try_into().unwrap_or_default()) as u64,
+                u32::from_be_bytes(header[16..20].try_into().unwrap_or_default()) as u64,
+            )
+        };
+
+        if timescale > 0 {
+            values.insert(
+                "duration".to_string(),
+                format_seconds(duration as f64 / timescale as f64),
+            );
+        }
+        Ok(values)
+    }
+}
+
+/// Vertex and polygon counts for Wavefront OBJ models
+pub struct ObjExtractor;
+
+impl MetadataExtractor for ObjExtractor {
+    fn name(&self) -> &'static str {
+        "obj"
+    }
+
+    fn supports(&self, path: &Path) -> bool {
+        extension(path) == "obj"
+    }
+
+    fn extract(&self, path: &Path) -> Result<BTreeMap<String, String>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut vertices = 0u64;
+        let mut polygons = 0u64;
+
+        for line in reader.split(b'\n') {
+            let line = line?;
+            if line.starts_with(b"v ") {
+                vertices += 1;
+            } else if line.starts_with(b"f ") {
+                polygons += 1;
+            }
+        }
+
+        let mut values = BTreeMap::new();
+        values.insert("vertices".to_string(), vertices.to_string());
+        values.insert("polygons".to_string(), polygons.to_string());
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_image_dimensions_png_and_gif() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(ImageExtractor::dimensions(&png), Some((640, 480)));
+
+        let gif = b"GIF89a\x20\x00\x10\x00";
+        assert_eq!(ImageExtractor::dimensions(gif), Some((32, 16)));
+    }
+
+    #[test]
+    fn test_extract_metadata_wav_and_obj() {
+        let dir = tempdir().unwrap();
+
+        // 1 second of 8 kHz mono 8-bit audio
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(b"\x01\0\x08\0data");
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&vec![0u8; 8000]);
+        let wav_path = dir.path().join("tone.wav");
+        std::fs::write(&wav_path, wav).unwrap();
+
+        let obj_path = dir.path().join("tri.obj");
+        std::fs::write(&obj_path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let extractors = default_extractors();
+        let wav_meta = extract_metadata(&wav_path, &extractors);
+        assert_eq!(wav_meta.get("duration").map(String::as_str), Some("1.00s"));
+
+        let obj_meta = extract_metadata(&obj_path, &extractors);
+        assert_eq!(obj_meta.get("vertices").map(String::as_str), Some("3"));
+        assert_eq!(obj_meta.get("polygons").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn test_mp4_boxes_running_past_the_end_are_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("broken.mp4");
+        // A 64-bit box size that would overflow the offset, then the box being looked for
+        let mut mp4 = 1u32.to_be_bytes().to_vec();
+        mp4.extend_from_slice(b"free");
+        mp4.extend_from_slice(&u64::MAX.to_be_bytes());
+        mp4.extend_from_slice(&8u32.to_be_bytes());
+        mp4.extend_from_slice(b"moov");
+        std::fs::write(&path, &mp4).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let len = mp4.len() as u64;
+        assert_eq!(Mp4Extractor::find_box(&mut file, 0, len, b"moov").unwrap(), None);
+        assert_eq!(Mp4Extractor::find_box(&mut file, 16, len, b"moov").unwrap(), Some((24, 24)));
+    }
+}
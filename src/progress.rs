@@ -0,0 +1,206 @@
+//! Progress display for file transfers
+//!
+//! Every transfer bar is drawn through one shared `MultiProgress`, so bars of
+//! concurrent transfers stack instead of overwriting each other. While a
+//! batch is running, an aggregate bar below them counts the bytes and files
+//! of the whole batch. Terminals narrower than `COMPACT_WIDTH` get a single
+//! line instead: the aggregate bar, naming the file currently transferring.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Narrowest terminal that gets a bar per file
+pub const COMPACT_WIDTH: u16 = 80;
+
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Aggregate of the batch running now, if any
+static BATCH: Mutex<Option<Arc<Batch>>> = Mutex::new(None);
+
+fn multi() -> &'static MultiProgress {
+    MULTI.get_or_init(MultiProgress::new)
+}
+
+/// Whether the terminal is too narrow for a bar per file. Output that isn't
+/// a terminal isn't drawn at all, so it counts as wide.
+pub fn is_compact() -> bool {
+    console::Term::stderr()
+        .size_checked()
+        .is_some_and(|(_, columns)| columns < COMPACT_WIDTH)
+}
+
+fn file_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}")
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+fn batch_style(compact: bool) -> ProgressStyle {
+    let template = if compact {
+        "[{bar:20.green/white}] {bytes}/{total_bytes} {wide_msg}"
+    } else {
+        "{prefix:.bold} [{elapsed_precise}] [{bar:40.green/white}] {bytes}/{total_bytes} ({eta}) {msg}"
+    };
+    ProgressStyle::default_bar()
+        .template(template)
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+/// Bytes and files transferred by a batch, drawn as one bar
+pub struct Batch {
+    bar: ProgressBar,
+    files: usize,
+    done: AtomicUsize,
+    compact: bool,
+}
+
+impl Batch {
+    fn new(bar: ProgressBar, files: usize, compact: bool) -> Self {
+        bar.set_style(batch_style(compact));
+        bar.set_prefix("Total");
+        let batch = Self {
+            bar,
+            files,
+            done: AtomicUsize::new(0),
+            compact,
+        };
+        batch.show(None);
+        batch
+    }
+
+    /// Update the file count, plus the current file in compact mode
+    fn show(&self, current: Option<&str>) {
+        let counted = format!("{}/{} files", self.done.load(Ordering::Relaxed), self.files);
+        match current.filter(|_| self.compact) {
+            Some(current) => self.bar.set_message(format!("{} {}", counted, current)),
+            None => self.bar.set_message(counted),
+        }
+    }
+
+    fn finish_file(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.show(None);
+    }
+}
+
+/// Keeps a batch's aggregate bar up until dropped
+pub struct BatchGuard {
+    batch: Arc<Batch>,
+}
+
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        self.batch.bar.finish_and_clear();
+        let mut current = BATCH.lock().unwrap_or_else(|e| e.into_inner());
+        if current.as_ref().is_some_and(|batch| Arc::ptr_eq(batch, &self.batch)) {
+            *current = None;
+        }
+    }
+}
+
+/// Show an aggregate bar for `files` transfers totalling `total_bytes` until
+/// the returned guard is dropped. Transfers started meanwhile count towards it.
+pub fn batch(files: usize, total_bytes: u64) -> BatchGuard {
+    let compact = is_compact();
+    let batch = Arc::new(Batch::new(multi().add(ProgressBar::new(total_bytes)), files, compact));
+    *BATCH.lock().unwrap_or_else(|e| e.into_inner()) = Some(batch.clone());
+    BatchGuard { batch }
+}
+
+/// Progress of one file transfer, also counted in the running batch
+pub struct Transfer {
+    bar: ProgressBar,
+    batch: Option<Arc<Batch>>,
+}
+
+impl Transfer {
+    /// Start a transfer of `total` bytes in the running batch, if any
+    pub fn start(total: u64, message: String) -> Self {
+        let batch = BATCH.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let bar = match &batch {
+            // The batch line stands in for every file
+            Some(batch) if batch.compact => ProgressBar::hidden(),
+            Some(batch) => multi().insert_before(&batch.bar, ProgressBar::new(total)),
+            None => multi().add(ProgressBar::new(total)),
+        };
+        Self::with_batch(bar, total, message, batch)
+    }
+
+    fn with_batch(bar: ProgressBar, total: u64, message: String, batch: Option<Arc<Batch>>) -> Self {
+        bar.set_length(total);
+        bar.set_style(file_style());
+        if let Some(batch) = &batch {
+            batch.show(Some(&message));
+        }
+        bar.set_message(message);
+        Self { bar, batch }
+    }
+
+    pub fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+        if let Some(batch) = &self.batch {
+            batch.bar.inc(delta);
+        }
+    }
+
+    pub fn set_position(&self, position: u64) {
+        let delta = position.saturating_sub(self.bar.position());
+        self.bar.set_position(position);
+        if let Some(batch) = &self.batch {
+            batch.bar.inc(delta);
+        }
+    }
+
+    /// Mark the transfer done, counting any bytes not reported along the way
+    pub fn finish_with_message(&self, message: String) {
+        let remaining = self.bar.length().unwrap_or_default().saturating_sub(self.bar.position());
+        match &self.batch {
+            Some(batch) => {
+                batch.bar.inc(remaining);
+                batch.finish_file();
+                self.bar.finish_and_clear();
+            }
+            None => self.bar.finish_with_message(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfers_add_up_in_batch() {
+        let batch = Arc::new(Batch::new(ProgressBar::hidden(), 2, false));
+        batch.bar.set_length(300);
+
+        let first = Transfer::with_batch(ProgressBar::hidden(), 100, "a.bin".to_string(), Some(batch.clone()));
+        first.set_position(40);
+        first.set_position(70);
+        // Bytes not reported before finishing still count
+        first.finish_with_message("done".to_string());
+        assert_eq!(batch.bar.position(), 100);
+        assert_eq!(batch.bar.message(), "1/2 files");
+
+        let second = Transfer::with_batch(ProgressBar::hidden(), 200, "b.bin".to_string(), Some(batch.clone()));
+        second.inc(150);
+        assert_eq!(batch.bar.position(), 250);
+        second.finish_with_message("done".to_string());
+        assert_eq!(batch.bar.position(), 300);
+        assert_eq!(batch.bar.message(), "2/2 files");
+    }
+
+    #[test]
+    fn test_compact_batch_names_current_file() {
+        let batch = Arc::new(Batch::new(ProgressBar::hidden(), 3, true));
+        let _transfer = Transfer::with_batch(ProgressBar::hidden(), 10, "Uploading a.bin".to_string(), Some(batch.clone()));
+        assert_eq!(batch.bar.message(), "0/3 files Uploading a.bin");
+
+        let wide = Arc::new(Batch::new(ProgressBar::hidden(), 3, false));
+        let _transfer = Transfer::with_batch(ProgressBar::hidden(), 10, "Uploading a.bin".to_string(), Some(wide.clone()));
+        assert_eq!(wide.bar.message(), "0/3 files");
+    }
+}
@@ -0,0 +1,129 @@
+//! Interactive prompts behind a pluggable interface
+//!
+//! Every question Ditrive asks goes through a `Prompter`, so embedders (a GUI
+//! or a background daemon) can answer them without a terminal. Each question
+//! has a stable key such as `github.username` that presets can match on.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::error::Result;
+
+/// Asks the user questions and shows the text around them
+pub trait Prompter: Send + Sync {
+    /// Show informational text such as headings and hints
+    fn say(&self, text: &str);
+
+    /// Ask for a line of text. An empty answer means "keep the current value".
+    fn ask(&self, key: &str, question: &str) -> Result<String>;
+
+    /// Ask for a secret such as a token or client secret
+    fn ask_secret(&self, key: &str, question: &str) -> Result<String> {
+        self.ask(key, question)
+    }
+
+    /// Ask a yes/no question; empty or unrecognized answers pick `default`
+    fn confirm(&self, key: &str, question: &str, default: bool) -> Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        let answer = self.ask(key, &format!("{} [{}]", question, hint))?;
+        Ok(match answer.to_lowercase().as_str() {
+            "y" | "yes" => true,
+            "n" | "no" => false,
+            _ => default,
+        })
+    }
+
+    /// Pick one of `options`, returning its index; unrecognized answers pick `default`
+    fn choose(&self, key: &str, question: &str, options: &[&str], default: usize) -> Result<usize> {
+        for (i, option) in options.iter().enumerate() {
+            self.say(&format!("{}. {}", i + 1, option));
+        }
+        let answer = self.ask(key, &format!("{} (1-{})", question, options.len()))?;
+        Ok(answer
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=options.len()).contains(n))
+            .map(|n| n - 1)
+            .unwrap_or(default))
+    }
+}
+
+/// Prompts on stdout and reads answers from stdin
+pub struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn say(&self, text: &str) {
+        println!("{}", text);
+    }
+
+    fn ask(&self, _key: &str, question: &str) -> Result<String> {
+        print!("{}: ", question);
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim().to_string())
+    }
+}
+
+/// Answers every question with an empty string, keeping current values and defaults
+pub struct NonInteractivePrompter;
+
+impl Prompter for NonInteractivePrompter {
+    fn say(&self, _text: &str) {}
+
+    fn ask(&self, _key: &str, _question: &str) -> Result<String> {
+        Ok(String::new())
+    }
+}
+
+/// Answers questions from a set of presets by key, deferring the rest
+pub struct PresetPrompter {
+    answers: HashMap<String, String>,
+    fallback: Box<dyn Prompter>,
+}
+
+impl PresetPrompter {
+    /// Create a PresetPrompter that asks `fallback` for keys without a preset
+    pub fn new(answers: HashMap<String, String>, fallback: Box<dyn Prompter>) -> Self {
+        Self { answers, fallback }
+    }
+}
+
+impl Prompter for PresetPrompter {
+    fn say(&self, text: &str) {
+        self.fallback.say(text);
+    }
+
+    fn ask(&self, key: &str, question: &str) -> Result<String> {
+        match self.answers.get(key) {
+            Some(answer) => Ok(answer.clone()),
+            None => self.fallback.ask(key, question),
+        }
+    }
+
+    fn ask_secret(&self, key: &str, question: &str) -> Result<String> {
+        match self.answers.get(key) {
+            Some(answer) => Ok(answer.clone()),
+            None => self.fallback.ask_secret(key, question),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_prompter_answers_by_key() {
+        let mut answers = HashMap::new();
+        answers.insert("github.username".to_string(), "octocat".to_string());
+        answers.insert("ignored_large_file".to_string(), "1".to_string());
+        let prompter = PresetPrompter::new(answers, Box::new(NonInteractivePrompter));
+
+        assert_eq!(prompter.ask("github.username", "GitHub username").unwrap(), "octocat");
+        assert_eq!(prompter.ask("github.token", "GitHub token").unwrap(), "");
+        assert_eq!(prompter.choose("ignored_large_file", "Choice", &["Manage", "Skip"], 1).unwrap(), 0);
+        assert_eq!(prompter.choose("other", "Choice", &["Manage", "Skip"], 1).unwrap(), 1);
+    }
+}
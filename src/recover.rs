@@ -0,0 +1,177 @@
+//! Checks and repairs behind `ditrive recover`
+//!
+//! Recovery runs in three steps: fsck checks trackers and local files,
+//! check-remote confirms every tracked file still exists in the backend,
+//! and reconcile fixes what the first two found. This module holds the
+//! pieces that don't touch the network; `Ditrive::recover` drives the steps.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::drive::{FileMetadata, FileVersion};
+
+const CONFLICT_OURS: &str = "<<<<<<<";
+const CONFLICT_BASE: &str = "|||||||";
+const CONFLICT_SEPARATOR: &str = "=======";
+const CONFLICT_THEIRS: &str = ">>>>>>>";
+
+/// Something wrong with a repository's managed files
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// A tracker file still contains merge conflict markers
+    ConflictedTracker(PathBuf),
+    /// A tracker file isn't valid JSON
+    CorruptTracker(PathBuf),
+    /// A tracked file is missing from the working tree
+    MissingLocal(PathBuf),
+    /// A tracked file's content differs from what was uploaded
+    ModifiedLocal(PathBuf),
+    /// The backend no longer has a tracked file; `local` says whether the working tree still has its content
+    MissingRemote { path: PathBuf, local: bool },
+}
+
+impl Issue {
+    /// One-line explanation for people who don't know the internals
+    pub fn describe(&self, repo_path: &Path) -> String {
+        let display = |path: &PathBuf| path.strip_prefix(repo_path).unwrap_or(path).display().to_string();
+        match self {
+            Issue::ConflictedTracker(path) => {
+                format!("{}: merge conflict left in the file list", display(path))
+            }
+            Issue::CorruptTracker(path) => format!("{}: file list is unreadable", display(path)),
+            Issue::MissingLocal(path) => format!("{}: not downloaded yet", display(path)),
+            Issue::ModifiedLocal(path) => format!("{}: changed locally but not uploaded", display(path)),
+            Issue::MissingRemote { path, local: true } => {
+                format!("{}: missing from storage, but this machine has it", display(path))
+            }
+            Issue::MissingRemote { path, local: false } => {
+                format!("{}: missing from storage and from this machine", display(path))
+            }
+        }
+    }
+}
+
+/// Whether content still has merge conflict markers
+pub fn has_conflict_markers(content: &str) -> bool {
+    content.lines().any(|line| line.starts_with(CONFLICT_OURS))
+}
+
+/// Split content with conflict markers into the "ours" and "theirs" versions.
+///
+/// Returns None if the markers are unbalanced.
+pub fn split_conflict(content: &str) -> Option<(String, String)> {
+    #[derive(PartialEq)]
+    enum Section {
+        Common,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut section = Section::Common;
+
+    for line in content.lines() {
+        if line.starts_with(CONFLICT_OURS) && section == Section::Common {
+            section = Section::Ours;
+        } else if line.starts_with(CONFLICT_BASE) && section == Section::Ours {
+            section = Section::Base;
+        } else if line.starts_with(CONFLICT_SEPARATOR) && matches!(section, Section::Ours | Section::Base) {
+            section = Section::Theirs;
+        } else if line.starts_with(CONFLICT_THEIRS) && section == Section::Theirs {
+            section = Section::Common;
+        } else {
+            let targets: &mut [&mut String] = match section {
+                Section::Common => &mut [&mut ours, &mut theirs],
+                Section::Ours => &mut [&mut ours],
+                Section::Base => &mut [],
+                Section::Theirs => &mut [&mut theirs],
+            };
+            for target in targets {
+                target.push_str(line);
+                target.push('\n');
+            }
+        }
+    }
+
+    (section == Section::Common).then_some((ours, theirs))
+}
+
+/// Merge both sides of a conflicted tracker.
+///
+/// Files on only one side are kept. For files on both sides the newer upload
+/// wins and the older one is kept in its version history, so nothing that was
+/// uploaded becomes unreachable.
+pub fn merge_trackers(
+    ours: HashMap<String, FileMetadata>,
+    mut theirs: HashMap<String, FileMetadata>,
+) -> HashMap<String, FileMetadata> {
+    let mut merged = HashMap::new();
+
+    for (filename, our_entry) in ours {
+        let entry = match theirs.remove(&filename) {
+            Some(their_entry) if their_entry.hash != our_entry.hash => {
+                let (mut newer, older) = if their_entry.uploaded_at > our_entry.uploaded_at {
+                    (their_entry, our_entry)
+                } else {
+                    (our_entry, their_entry)
+                };
+                for version in older.versions.iter().cloned().chain([FileVersion::from(&older)]) {
+                    if !newer.versions.iter().any(|v| v.hash == version.hash) {
+                        newer.versions.push(version);
+                    }
+                }
+                newer.versions.sort_by_key(|v| v.uploaded_at);
+                newer
+            }
+            _ => our_entry,
+        };
+        merged.insert(filename, entry);
+    }
+
+    merged.extend(theirs);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(id: &str, hash: &str, uploaded_at: i64) -> FileMetadata {
+        FileMetadata {
+            id: id.to_string(),
+            hash: hash.to_string(),
+            uploaded_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_conflict() {
+        let content = "{\n<<<<<<< HEAD\n  \"a\": 1\n||||||| base\n  \"a\": 0\n=======\n  \"a\": 2\n>>>>>>> theirs\n}\n";
+        assert!(has_conflict_markers(content));
+
+        let (ours, theirs) = split_conflict(content).unwrap();
+        assert_eq!(ours, "{\n  \"a\": 1\n}\n");
+        assert_eq!(theirs, "{\n  \"a\": 2\n}\n");
+        assert!(split_conflict("<<<<<<< HEAD\nx\n").is_none());
+    }
+
+    #[test]
+    fn test_merge_trackers_keeps_both_sides() {
+        let mut ours = HashMap::new();
+        ours.insert("shared.bin".to_string(), metadata("id-1", "old", 100));
+        ours.insert("mine.bin".to_string(), metadata("id-2", "m", 100));
+        let mut theirs = HashMap::new();
+        theirs.insert("shared.bin".to_string(), metadata("id-1", "new", 200));
+        theirs.insert("yours.bin".to_string(), metadata("id-3", "y", 100));
+
+        let merged = merge_trackers(ours, theirs);
+        assert_eq!(merged.len(), 3);
+        let shared = &merged["shared.bin"];
+        assert_eq!(shared.hash, "new");
+        assert_eq!(shared.versions.len(), 1);
+        assert_eq!(shared.versions[0].hash, "old");
+    }
+}
@@ -0,0 +1,445 @@
+//! GitHub Releases backend for public projects
+//!
+//! Managed files are uploaded as assets of one release (tag `ditrive-assets`
+//! by default). A file's ID is `<tag>/<asset name>`, so anyone can download
+//! it from the release's public URL without credentials; a token is only
+//! needed to upload, or to read assets of a private repository.
+//!
+//! Asset names are flat and GitHub rewrites unusual characters, so a file is
+//! stored under its sanitized file name prefixed with a hash of its
+//! repository path. Assets can't be overwritten, so an update is uploaded
+//! under a temporary name first, and only then replaces the old asset, which
+//! is deleted; releases keep no version history.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tracing::{debug, info};
+
+use crate::backend::StorageBackend;
+use crate::drive::{calculate_file_hash, transfer_progress_bar, FileMetadata};
+use crate::error::{DitriveError, Result};
+use crate::http;
+use crate::paths;
+use crate::trace::{self, TracedSend};
+
+/// Tag of the release assets go to unless configured otherwise
+pub const DEFAULT_TAG: &str = "ditrive-assets";
+
+/// Largest asset GitHub accepts
+const MAX_ASSET_SIZE: u64 = 2 * 1024 * 1024 * 1024 - 1;
+
+/// Hex digits of the path hash that prefixes asset names
+const PATH_HASH_LEN: usize = 8;
+
+/// Suffix of an asset while it is uploaded, before it replaces the old one
+const UPLOADING_SUFFIX: &str = ".uploading";
+
+/// Release response
+#[derive(Debug, Clone, Deserialize)]
+struct Release {
+    id: u64,
+    upload_url: String,
+}
+
+/// Release asset response
+#[derive(Debug, Deserialize)]
+struct Asset {
+    id: u64,
+    name: String,
+    /// "sha256:<hex>", on assets uploaded since GitHub started computing it
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+/// Backend that stores managed files as assets of a GitHub release
+pub struct ReleasesBackend {
+    client: Client,
+    api_base: String,
+    web_base: String,
+    owner: String,
+    repo: String,
+    tag: String,
+    authenticated: bool,
+    repo_folder_id: String,
+    /// Releases looked up or created so far, by tag
+    releases: HashMap<String, Release>,
+}
+
+impl ReleasesBackend {
+    const API_BASE: &'static str = "https://api.github.com";
+
+    /// Create a ReleasesBackend for `owner/name`. Without a token, files can only be downloaded.
+    pub fn new(repository: &str, tag: &str, token: Option<&str>, api_url: &str) -> Result<Self> {
+        let (owner, repo) = match repository.split_once('/') {
+            Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() && !repo.contains('/') => (owner, repo),
+            _ => {
+                return Err(DitriveError::Config(format!(
+                    "Invalid releases repository '{}': use owner/name",
+                    repository
+                )))
+            }
+        };
+
+        let api_base = match api_url.trim_end_matches('/') {
+            "" => Self::API_BASE.to_string(),
+            url => url.to_string(),
+        };
+        // GitHub Enterprise Server serves its API under /api/v3 of the web host
+        let web_base = if api_base == Self::API_BASE {
+            "https://github.com".to_string()
+        } else {
+            api_base.trim_end_matches("/api/v3").to_string()
+        };
+
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            headers.insert(
+                header::AUTHORIZATION,
+                header::HeaderValue::from_str(&format!("token {}", token))
+                    .map_err(|e| DitriveError::Auth(format!("Invalid token: {}", e)))?,
+            );
+        }
+        headers.insert(header::ACCEPT, header::HeaderValue::from_static("application/vnd.github+json"));
+        headers.insert(header::USER_AGENT, header::HeaderValue::from_static("ditrive/0.1.0"));
+        let client = http::client_builder()
+            .default_headers(headers)
+            .build()
+            .map_err(DitriveError::Http)?;
+
+        let tag = if tag.is_empty() { DEFAULT_TAG } else { tag };
+        info!("Using release '{}' of {}/{}", tag, owner, repo);
+
+        Ok(Self {
+            client,
+            repo_folder_id: format!("{}/{}@{}", owner, repo, tag),
+            api_base,
+            web_base,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            tag: tag.to_string(),
+            authenticated: token.is_some(),
+            releases: HashMap::new(),
+        })
+    }
+
+    /// Repository API URL for a path
+    fn api(&self, path: &str) -> String {
+        format!("{}/repos/{}/{}/{}", self.api_base, self.owner, self.repo, path)
+    }
+
+    /// Send a request, failing with the response body unless it succeeded
+    async fn check(request: RequestBuilder, action: &str) -> Result<Response> {
+        Self::check_response(request.send_traced().await?, action).await
+    }
+
+    async fn check_response(response: Response, action: &str) -> Result<Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let error = response.text().await.unwrap_or_default();
+        Err(DitriveError::Drive(format!("Failed to {} ({}): {}", action, status, error)))
+    }
+
+    fn require_token(&self) -> Result<()> {
+        if self.authenticated {
+            Ok(())
+        } else {
+            Err(DitriveError::Auth(
+                "Uploading release assets needs a GitHub token. Set GITHUB_TOKEN or run 'ditrive configure'.".to_string(),
+            ))
+        }
+    }
+
+    /// Look up the release for a tag
+    async fn find_release(&self, tag: &str) -> Result<Option<Release>> {
+        let response = self
+            .client
+            .get(self.api(&format!("releases/tags/{}", urlencoding::encode(tag))))
+            .send_traced()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Self::check_response(response, "look up release").await?;
+        Ok(Some(response.json().await?))
+    }
+
+    /// The release for a tag, creating it (and the tag) on first upload
+    async fn release_for(&mut self, tag: &str) -> Result<Release> {
+        if let Some(release) = self.releases.get(tag) {
+            return Ok(release.clone());
+        }
+
+        let release = match self.find_release(tag).await? {
+            Some(release) => release,
+            None => {
+                info!("Creating release '{}' in {}/{}", tag, self.owner, self.repo);
+                let request = self.client.post(self.api("releases")).json(&serde_json::json!({
+                    "tag_name": tag,
+                    "name": "Ditrive assets",
+                    "body": "Large files managed by Ditrive. Checkouts download them from here, so don't delete these assets.",
+                    // Keep the project's real releases as "latest"
+                    "make_latest": "false",
+                }));
+                Self::check(request, "create release").await?.json().await?
+            }
+        };
+        self.releases.insert(tag.to_string(), release.clone());
+        Ok(release)
+    }
+
+    /// Find an asset of a tag's release by name
+    async fn find_asset(&self, tag: &str, name: &str) -> Result<Option<Asset>> {
+        let Some(release) = self.find_release(tag).await? else {
+            return Ok(None);
+        };
+
+        for page in 1.. {
+            let request = self
+                .client
+                .get(self.api(&format!("releases/{}/assets", release.id)))
+                .query(&[("per_page", "100".to_string()), ("page", page.to_string())]);
+            let assets: Vec<Asset> = Self::check(request, "list release assets").await?.json().await?;
+            let last_page = assets.len() < 100;
+            if let Some(asset) = assets.into_iter().find(|a| a.name == name) {
+                return Ok(Some(asset));
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    async fn delete_asset(&self, asset: &Asset) -> Result<()> {
+        let request = self.client.delete(self.api(&format!("releases/assets/{}", asset.id)));
+        Self::check(request, "delete release asset").await?;
+        Ok(())
+    }
+
+    async fn rename_asset(&self, asset: &Asset, name: &str) -> Result<()> {
+        let request = self
+            .client
+            .patch(self.api(&format!("releases/assets/{}", asset.id)))
+            .json(&serde_json::json!({ "name": name }));
+        Self::check(request, "rename release asset").await?;
+        Ok(())
+    }
+
+    /// Upload a file as an asset of a tag's release, replacing any asset with that name
+    async fn put_asset(&mut self, tag: &str, name: &str, file_path: &Path) -> Result<FileMetadata> {
+        self.require_token()?;
+        let file_size = fs::metadata(file_path)?.len();
+        if file_size > MAX_ASSET_SIZE {
+            return Err(DitriveError::Drive(format!(
+                "{} is larger than the 2 GiB GitHub allows per release asset",
+                file_path.display()
+            )));
+        }
+
+        let release = self.release_for(tag).await?;
+        // Left over from an upload that was cut short
+        let staged_name = format!("{}{}", name, UPLOADING_SUFFIX);
+        if let Some(stale) = self.find_asset(tag, &staged_name).await? {
+            self.delete_asset(&stale).await?;
+        }
+
+        let file_id = format!("{}/{}", tag, name);
+        let pb = transfer_progress_bar(file_size, format!("Uploading {}", file_id));
+        // upload_url is a URI template ending in "{?name,label}"
+        let upload_url = release.upload_url.split('{').next().unwrap_or_default();
+        let request = self
+            .client
+            .post(upload_url)
+            .query(&[("name", staged_name.as_str())])
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, file_size)
+            .body(tokio::fs::File::open(file_path).await?);
+        let uploaded: Asset = Self::check(request, "upload release asset").await?.json().await?;
+        pb.finish_with_message(format!("Uploaded {}", file_id));
+
+        // The old asset goes only once its replacement is stored
+        if let Some(existing) = self.find_asset(tag, name).await? {
+            self.delete_asset(&existing).await?;
+        }
+        self.rename_asset(&uploaded, name).await?;
+
+        Ok(FileMetadata {
+            id: file_id,
+            hash: calculate_file_hash(file_path)?,
+            size: file_size,
+            uploaded_at: Utc::now().timestamp(),
+            release_tag: tag.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Split a file ID into its release tag and asset name
+fn parse_file_id(file_id: &str) -> Result<(&str, &str)> {
+    file_id
+        .split_once('/')
+        .filter(|(tag, name)| !tag.is_empty() && !name.is_empty())
+        .ok_or_else(|| DitriveError::FileNotFound(file_id.to_string()))
+}
+
+/// Asset name for a repository-relative path
+fn asset_name(rel_path: &str) -> String {
+    let path_hash = hex::encode(Sha256::digest(rel_path.as_bytes()));
+    let file_name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+    let sanitized: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    format!("{}-{}", &path_hash[..PATH_HASH_LEN], sanitized.trim_matches('.'))
+}
+
+#[async_trait]
+impl StorageBackend for ReleasesBackend {
+    fn name(&self) -> &'static str {
+        "GitHub Releases"
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        &self.repo_folder_id
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        let rel_path = paths::relative(repo_path, file_path)
+            .ok_or_else(|| DitriveError::FileNotFound(file_path.display().to_string()))?;
+
+        let tag = self.tag.clone();
+        let metadata = self.put_asset(&tag, &asset_name(&rel_path), file_path).await?;
+        info!("Uploaded {} ({} bytes) to GitHub Releases", metadata.id, metadata.size);
+        Ok(metadata)
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let _timer = trace::phase("upload", file_path.display().to_string());
+        let (tag, name) = parse_file_id(file_id)?;
+
+        // Stays in the release it was first uploaded to, so the ID doesn't change
+        let metadata = self.put_asset(tag, name, file_path).await?;
+        debug!("Updated {} in GitHub Releases", file_id);
+        Ok(metadata)
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let _timer = trace::phase("download", destination.display().to_string());
+        let (tag, name) = parse_file_id(file_id)?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The public URL needs no token; private repositories go through the API
+        let request = if self.authenticated {
+            let asset = self
+                .find_asset(tag, name)
+                .await?
+                .ok_or_else(|| DitriveError::FileNotFound(file_id.to_string()))?;
+            self.client
+                .get(self.api(&format!("releases/assets/{}", asset.id)))
+                .header(header::ACCEPT, "application/octet-stream")
+        } else {
+            self.client.get(format!(
+                "{}/{}/{}/releases/download/{}/{}",
+                self.web_base, self.owner, self.repo, tag, name
+            ))
+        };
+        let mut response = Self::check(request, "download release asset").await?;
+
+        let pb = transfer_progress_bar(
+            response.content_length().unwrap_or_default(),
+            format!("Downloading {}", file_id),
+        );
+        let mut file = fs::File::create(destination)?;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)?;
+            pb.inc(chunk.len() as u64);
+        }
+        pb.finish_with_message(format!("Downloaded {}", file_id));
+
+        info!("Downloaded {:?} from GitHub Releases", destination);
+        Ok(())
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        let Ok((tag, name)) = parse_file_id(file_id) else {
+            return false;
+        };
+        matches!(self.find_asset(tag, name).await, Ok(Some(_)))
+    }
+
+    async fn content_hash(&self, file_id: &str) -> Result<Option<String>> {
+        let (tag, name) = parse_file_id(file_id)?;
+        Ok(self
+            .find_asset(tag, name)
+            .await?
+            .and_then(|asset| asset.digest)
+            .and_then(|digest| digest.strip_prefix("sha256:").map(str::to_string)))
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        self.require_token()?;
+        let (tag, name) = parse_file_id(file_id)?;
+        match self.find_asset(tag, name).await? {
+            Some(asset) => self.delete_asset(&asset).await?,
+            None => return Err(DitriveError::FileNotFound(file_id.to_string())),
+        }
+        debug!("Deleted {} from GitHub Releases", file_id);
+        Ok(())
+    }
+
+    async fn move_file(&mut self, file_id: &str, rel_path: &str) -> Result<String> {
+        // Renamed in place, so the asset stays in the release it was uploaded to
+        self.require_token()?;
+        let (tag, name) = parse_file_id(file_id)?;
+        let asset = self
+            .find_asset(tag, name)
+            .await?
+            .ok_or_else(|| DitriveError::FileNotFound(file_id.to_string()))?;
+        let new_name = asset_name(rel_path);
+        if let Some(existing) = self.find_asset(tag, &new_name).await? {
+            self.delete_asset(&existing).await?;
+        }
+        self.rename_asset(&asset, &new_name).await?;
+        debug!("Renamed {} to {} in GitHub Releases", file_id, new_name);
+        Ok(format!("{}/{}", tag, new_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_names_are_flat_and_distinct() {
+        let hero = asset_name("assets/hero image.png");
+        assert!(hero.ends_with("-hero_image.png"));
+        assert_eq!(hero.len(), PATH_HASH_LEN + 1 + "hero_image.png".len());
+        assert_ne!(asset_name("a/level.bin"), asset_name("b/level.bin"));
+
+        assert_eq!(parse_file_id(&format!("ditrive-assets/{}", hero)).unwrap(), ("ditrive-assets", hero.as_str()));
+        assert!(parse_file_id("no-tag").is_err());
+    }
+
+    #[test]
+    fn test_new_validates_repository() {
+        let backend = ReleasesBackend::new("studio/game", "", None, "").unwrap();
+        assert_eq!(backend.repo_folder_id(), "studio/game@ditrive-assets");
+        assert_eq!(backend.web_base, "https://github.com");
+
+        let enterprise = ReleasesBackend::new("studio/game", "v1", None, "https://git.corp/api/v3").unwrap();
+        assert_eq!(enterprise.web_base, "https://git.corp");
+        assert!(ReleasesBackend::new("game", "", None, "").is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! Machine-readable output for `--json`
+//!
+//! These mirror what `status`, `list`, and `sync` print for people, in a
+//! shape CI scripts can parse. Field names are part of the interface, so
+//! extend them rather than renaming.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::backend::StorageQuota;
+use crate::config::DriveAuthType;
+use crate::drive::FileMetadata;
+use crate::error::Result;
+
+/// Output of `ditrive status --json`
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub configuration: ConfigurationStatus,
+    pub repository: RepositoryStatus,
+}
+
+/// Global configuration as seen by the repository
+#[derive(Debug, Serialize)]
+pub struct ConfigurationStatus {
+    pub github_username: String,
+    pub github_token_set: bool,
+    pub backend: DriveAuthType,
+    pub service: String,
+    /// Folder ID, directory, or bucket the files are stored under
+    pub location: String,
+    /// Only present for backends that sign in with OAuth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logged_in: Option<bool>,
+    /// Auth profile the repository signs in with; omitted for the default
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub profile: String,
+    /// Only present for backends that report their storage quota
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageQuota>,
+}
+
+/// State of the repository itself
+#[derive(Debug, Serialize)]
+pub struct RepositoryStatus {
+    pub name: String,
+    pub path: String,
+    pub git_initialized: bool,
+    pub tracked_files: usize,
+    pub pending_files: usize,
+    pub files: Vec<FileStatus>,
+}
+
+/// How a managed file compares with its tracker entry and the backend
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    UpToDate,
+    /// Changed locally since the last upload
+    Modified,
+    /// Not downloaded, or still a pointer file
+    MissingLocally,
+    /// The backend no longer has the tracked object
+    MissingRemotely,
+    /// Neither this checkout nor the backend has the file, so pulling can't bring it back
+    MissingEverywhere,
+}
+
+/// One managed file in `status`
+#[derive(Debug, Serialize)]
+pub struct FileStatus {
+    pub path: String,
+    pub state: FileState,
+}
+
+/// Output of `ditrive list --json`
+#[derive(Debug, Serialize)]
+pub struct ListReport {
+    pub repository: String,
+    pub path: String,
+    pub drive_folder_id: String,
+    pub github_url: String,
+    pub large_file_threshold_mb: u64,
+    pub files: Vec<FileEntry>,
+}
+
+/// One managed file
+#[derive(Debug, Serialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub id: String,
+    pub size: u64,
+    pub sha256: String,
+    pub local: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub uploaded_by: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub commit: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Output of `ditrive ls-remote --json`
+#[derive(Debug, Serialize)]
+pub struct RemoteListReport {
+    pub service: String,
+    /// Folder ID, directory, or bucket the files are stored under
+    pub location: String,
+    pub files: Vec<RemoteFileEntry>,
+    /// Tracked files the backend didn't list
+    pub missing: Vec<String>,
+}
+
+/// One file as the backend stores it
+#[derive(Debug, Serialize)]
+pub struct RemoteFileEntry {
+    pub path: String,
+    pub id: String,
+    pub size: u64,
+    /// Unix timestamp; 0 when the backend doesn't say
+    pub modified_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// What the trackers hold this file as, e.g. "assets/hero.png"; empty if nothing
+    pub tracked_as: Vec<String>,
+}
+
+/// What `sync` did to a file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// A new large file was uploaded
+    Uploaded,
+    /// A managed file's changed content was uploaded
+    Updated,
+    /// A missing file was downloaded
+    Downloaded,
+    /// A new large file was routed through the pointer filter
+    Tracked,
+    /// A large ignored file was left alone
+    Skipped,
+    /// A managed file deleted locally was removed from the backend and the trackers
+    Deleted,
+    /// A stored file no tracker knew about was tracked, and downloaded if missing
+    Adopted,
+}
+
+/// One action taken by `sync`
+#[derive(Debug, Serialize)]
+pub struct SyncAction {
+    pub action: Action,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// Output of `ditrive sync --json`
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub actions: Vec<SyncAction>,
+}
+
+impl SyncReport {
+    /// Record an action on a file
+    pub fn record(&mut self, action: Action, path: String, id: Option<String>, size: Option<u64>) {
+        self.actions.push(SyncAction { action, path, id, size });
+    }
+
+    /// Number of recorded actions of one kind
+    pub fn count(&self, action: Action) -> usize {
+        self.actions.iter().filter(|a| a.action == action).count()
+    }
+
+    /// What the run changed in the trackers, like "manage a.bin, b.bin; update c.bin";
+    /// empty when it changed nothing
+    pub fn commit_summary(&self) -> String {
+        let verbs = [
+            ("manage", &[Action::Uploaded, Action::Tracked, Action::Adopted][..]),
+            ("update", &[Action::Updated][..]),
+            ("remove", &[Action::Deleted][..]),
+        ];
+        let mut parts = Vec::new();
+        for (verb, actions) in verbs {
+            let paths: Vec<&str> = self
+                .actions
+                .iter()
+                .filter(|a| actions.contains(&a.action))
+                .map(|a| a.path.as_str())
+                .collect();
+            match paths.len() {
+                0 => {}
+                1..=3 => parts.push(format!("{} {}", verb, paths.join(", "))),
+                count => parts.push(format!("{} {} files", verb, count)),
+            }
+        }
+        parts.join("; ")
+    }
+}
+
+/// Why a file is in the `diff` plan
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanReason {
+    /// A large file that isn't managed yet
+    New,
+    /// Changed locally since the last upload
+    Modified,
+    /// Managed, but not downloaded here
+    Missing,
+    /// Another clone uploaded newer content
+    OutOfDate,
+    /// Deleted locally since the last sync
+    Deleted,
+    /// Stored remotely, but in no tracker
+    Untracked,
+}
+
+/// One file in the `diff` plan
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedFile {
+    pub path: String,
+    pub reason: PlanReason,
+    pub size: u64,
+}
+
+/// Whether a `.gitignore` line would be added or removed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitignoreChange {
+    Add,
+    Remove,
+}
+
+/// One `.gitignore` edit in the `diff` plan
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitignoreEdit {
+    pub change: GitignoreChange,
+    pub pattern: String,
+}
+
+/// Output of `ditrive diff --json`: what `sync` would do, without doing it
+#[derive(Debug, Default, Serialize)]
+pub struct DiffReport {
+    pub upload: Vec<PlannedFile>,
+    pub download: Vec<PlannedFile>,
+    /// Deleted locally; the stored copies follow the deletion policy
+    pub remove: Vec<PlannedFile>,
+    /// Stored remotely but not tracked, left alone by sync unless it's two-way
+    pub orphaned: Vec<PlannedFile>,
+    pub gitignore: Vec<GitignoreEdit>,
+    /// False when the backend couldn't be reached, so out-of-date and
+    /// orphaned files are unknown
+    pub remote_checked: bool,
+}
+
+impl DiffReport {
+    /// Whether nothing diverges
+    pub fn is_empty(&self) -> bool {
+        self.upload.is_empty()
+            && self.download.is_empty()
+            && self.remove.is_empty()
+            && self.orphaned.is_empty()
+            && self.gitignore.is_empty()
+    }
+}
+
+/// Output of `ditrive stats --json`
+#[derive(Debug, Default, Serialize)]
+pub struct StatsReport {
+    pub managed_files: usize,
+    /// Current content of every managed file
+    pub managed_bytes: u64,
+    /// Managed content downloaded into this working tree
+    pub local_bytes: u64,
+    /// Everything kept out of git: current content plus earlier versions
+    pub offloaded_bytes: u64,
+    /// Top-level directories, largest first; "." holds files at the root
+    pub directories: Vec<DirectoryStats>,
+    /// Largest managed files, largest first
+    pub largest_files: Vec<SizedFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageQuota>,
+}
+
+/// Managed files under one top-level directory
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DirectoryStats {
+    pub path: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// A managed file and its size
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SizedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+impl StatsReport {
+    /// Add up managed files, given as repository-relative path, metadata and
+    /// whether the file is present locally, keeping the `top` largest
+    pub fn tally<'a>(files: impl IntoIterator<Item = (String, &'a FileMetadata, bool)>, top: usize) -> Self {
+        let mut report = Self::default();
+        let mut directories: BTreeMap<String, DirectoryStats> = BTreeMap::new();
+        let mut largest = Vec::new();
+
+        for (path, metadata, local) in files {
+            report.managed_files += 1;
+            report.managed_bytes += metadata.size;
+            report.offloaded_bytes += metadata.size + metadata.versions.iter().map(|v| v.size).sum::<u64>();
+            if local {
+                report.local_bytes += metadata.size;
+            }
+
+            let directory = match path.split_once('/') {
+                Some((directory, _)) => directory.to_string(),
+                None => ".".to_string(),
+            };
+            let stats = directories.entry(directory.clone()).or_insert(DirectoryStats {
+                path: directory,
+                files: 0,
+                bytes: 0,
+            });
+            stats.files += 1;
+            stats.bytes += metadata.size;
+            largest.push(SizedFile { path, size: metadata.size });
+        }
+
+        report.directories = directories.into_values().collect();
+        report.directories.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        largest.truncate(top);
+        report.largest_files = largest;
+        report
+    }
+}
+
+/// Print a report as pretty-printed JSON on stdout
+pub fn print_json<T: Serialize>(report: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_report_serialization() {
+        let mut report = SyncReport::default();
+        report.record(Action::Uploaded, "assets/hero.png".to_string(), Some("1AbC".to_string()), Some(42));
+        report.record(Action::Skipped, "build/cache.bin".to_string(), None, None);
+
+        assert_eq!(report.count(Action::Uploaded), 1);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["actions"][0]["action"], "uploaded");
+        assert_eq!(json["actions"][0]["id"], "1AbC");
+        assert!(json["actions"][1].get("id").is_none());
+    }
+
+    #[test]
+    fn test_commit_summary() {
+        let mut report = SyncReport::default();
+        assert_eq!(report.commit_summary(), "");
+
+        report.record(Action::Downloaded, "intro.mp4".to_string(), None, None);
+        report.record(Action::Uploaded, "assets/big.bin".to_string(), None, None);
+        assert_eq!(report.commit_summary(), "manage assets/big.bin");
+
+        for name in ["a.bin", "b.bin", "c.bin", "d.bin"] {
+            report.record(Action::Updated, name.to_string(), None, None);
+        }
+        report.record(Action::Deleted, "old.bin".to_string(), None, None);
+        assert_eq!(report.commit_summary(), "manage assets/big.bin; update 4 files; remove old.bin");
+    }
+
+    #[test]
+    fn test_stats_tally() {
+        let file = |size: u64, earlier: &[u64]| FileMetadata {
+            size,
+            versions: earlier
+                .iter()
+                .map(|&size| crate::drive::FileVersion { size, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let hero = file(300, &[250, 200]);
+        let theme = file(100, &[]);
+        let manual = file(50, &[]);
+        let files = [
+            ("assets/art/hero.psd".to_string(), &hero, true),
+            ("assets/theme.ogg".to_string(), &theme, false),
+            ("manual.pdf".to_string(), &manual, true),
+        ];
+
+        let report = StatsReport::tally(files, 2);
+        assert_eq!(report.managed_files, 3);
+        assert_eq!(report.managed_bytes, 450);
+        assert_eq!(report.local_bytes, 350);
+        assert_eq!(report.offloaded_bytes, 900);
+        assert_eq!(
+            report.directories,
+            [
+                DirectoryStats { path: "assets".to_string(), files: 2, bytes: 400 },
+                DirectoryStats { path: ".".to_string(), files: 1, bytes: 50 },
+            ]
+        );
+        assert_eq!(
+            report.largest_files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+            ["assets/art/hero.psd", "assets/theme.ogg"]
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! JSON-RPC interface of `ditrive serve --rpc`
+//!
+//! The daemon signs in once and keeps its storage backend, folder cache
+//! included, for every request, so IDE plugins and scripts don't pay for a
+//! fresh sign-in per command. It listens on 127.0.0.1 only and speaks
+//! JSON-RPC 2.0 with one message per line:
+//!
+//! ```text
+//! {"jsonrpc":"2.0","id":1,"token":"…","method":"sync","params":{"two_way":true}}
+//! {"jsonrpc":"2.0","id":1,"result":{"actions":[...]}}
+//! ```
+//!
+//! Methods are `status`, `sync`, `push`, `pull` and `shutdown`. Requests are
+//! handled one at a time, in the order they arrive.
+//!
+//! Every request carries the daemon's token, a random string it writes to
+//! `.git/ditrive/rpc.json` (with its port) when it starts. Only processes
+//! that can read the repository can talk to it, so a web page posting to
+//! the port gets nowhere; the first line that isn't a valid request closes
+//! the connection. `ditrive watch` finds the daemon the same way and hands
+//! it new large files to push.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use crate::error::{DitriveError, Result};
+use crate::team_cache::token_matches;
+
+/// Port `ditrive serve --rpc` listens on by default
+pub const DEFAULT_PORT: u16 = 7879;
+
+/// The request isn't valid JSON
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON isn't a JSON-RPC request
+pub const INVALID_REQUEST: i64 = -32600;
+/// No such method
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The method's parameters don't fit
+pub const INVALID_PARAMS: i64 = -32602;
+/// The operation itself failed
+pub const OPERATION_FAILED: i64 = -32000;
+/// The request's token is missing or wrong
+pub const UNAUTHORIZED: i64 = -32001;
+
+/// Where a running daemon records how to reach it, relative to the repository root
+pub const STATE_PATH: &str = ".git/ditrive/rpc.json";
+
+/// How long `call_running` waits for the daemon to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address the daemon listens on; only this machine may connect
+pub fn bind_address(port: u16) -> String {
+    format!("127.0.0.1:{}", port)
+}
+
+/// How clients reach the repository's daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub port: u16,
+    pub token: String,
+}
+
+impl DaemonInfo {
+    /// Details for a daemon on `port`, with a fresh random token
+    pub fn new(port: u16) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self {
+            port,
+            token: hex::encode(bytes),
+        }
+    }
+
+    /// The details the repository's daemon recorded, if it is running
+    pub fn load(repo_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(repo_path.join(STATE_PATH)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Record the details for clients, readable by this user only
+    pub fn save(&self, repo_path: &Path) -> Result<()> {
+        let path = repo_path.join(STATE_PATH);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let _ = fs::remove_file(&path);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&path)?.write_all(serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Forget the details once the daemon stops
+    pub fn remove(repo_path: &Path) {
+        let _ = fs::remove_file(repo_path.join(STATE_PATH));
+    }
+}
+
+/// Parameters of `sync`, each defaulting to the repository setting
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncParams {
+    pub pull: bool,
+    pub push: bool,
+    pub two_way: bool,
+    pub prune: bool,
+    pub commit: bool,
+}
+
+/// An operation a client asked for
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    Status,
+    Sync(SyncParams),
+    Push,
+    Pull,
+    /// Stop the daemon after answering
+    Shutdown,
+}
+
+/// A parsed request, with the ID to answer it under
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    /// None for notifications, which get no response
+    pub id: Option<Value>,
+    pub method: Method,
+}
+
+#[derive(Deserialize)]
+struct RawRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    token: Option<String>,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+impl Request {
+    /// Parse one request line, checking it carries `token`, or the error
+    /// response to send instead
+    pub fn parse(line: &str, token: &str) -> std::result::Result<Self, Response> {
+        let value: Value = serde_json::from_str(line)
+            .map_err(|e| Response::error(Value::Null, PARSE_ERROR, &e.to_string()))?;
+        let raw: RawRequest = serde_json::from_value(value)
+            .map_err(|e| Response::error(Value::Null, INVALID_REQUEST, &e.to_string()))?;
+        let id = raw.id.clone().unwrap_or(Value::Null);
+        if raw.jsonrpc != "2.0" {
+            return Err(Response::error(id, INVALID_REQUEST, "Only JSON-RPC 2.0 is supported"));
+        }
+        if !raw.token.as_deref().is_some_and(|given| token_matches(given, token)) {
+            return Err(Response::error(id, UNAUTHORIZED, "Missing or wrong token"));
+        }
+
+        let method = match raw.method.as_str() {
+            "status" => Method::Status,
+            "sync" => {
+                let params = match raw.params {
+                    None | Some(Value::Null) => SyncParams::default(),
+                    Some(params) => serde_json::from_value(params)
+                        .map_err(|e| Response::error(id.clone(), INVALID_PARAMS, &e.to_string()))?,
+                };
+                Method::Sync(params)
+            }
+            "push" => Method::Push,
+            "pull" => Method::Pull,
+            "shutdown" => Method::Shutdown,
+            other => {
+                return Err(Response::error(id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", other)));
+            }
+        };
+        Ok(Self { id: raw.id, method })
+    }
+}
+
+/// Error member of a response
+#[derive(Debug, Serialize)]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+/// A response line
+#[derive(Debug, Serialize)]
+pub struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorObject>,
+}
+
+impl Response {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Value, code: i64, message: &str) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(ErrorObject {
+                code,
+                message: message.to_string(),
+            }),
+        }
+    }
+
+    /// The response for an operation that failed
+    pub fn failure(id: Value, error: &DitriveError) -> Self {
+        Self::error(id, OPERATION_FAILED, &error.to_string())
+    }
+
+    /// The response as one line, without the trailing newline
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// A request waiting for the daemon, with where to send its response
+pub type Queued = (Request, oneshot::Sender<Response>);
+
+/// Read requests from one client until it disconnects or sends a line that
+/// isn't a valid request, queueing each for the daemon and writing back its response
+pub async fn handle_connection(stream: TcpStream, peer: SocketAddr, token: String, queue: mpsc::Sender<Queued>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                debug!("Reading from RPC client {} failed: {}", peer, e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, rejected) = match Request::parse(&line, &token) {
+            Ok(request) => {
+                let notification = request.id.is_none();
+                let (reply, response) = oneshot::channel();
+                if queue.send((request, reply)).await.is_err() {
+                    break;
+                }
+                match response.await {
+                    Ok(_) if notification => continue,
+                    Ok(response) => (response, false),
+                    Err(_) => break,
+                }
+            }
+            Err(response) => (response, true),
+        };
+
+        let mut line = response.to_line();
+        line.push('\n');
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            debug!("Writing to RPC client {} failed: {}", peer, e);
+            break;
+        }
+        // Whatever sent that isn't a client of ours, such as a browser form
+        if rejected {
+            debug!("Closing connection from {} after an invalid request", peer);
+            break;
+        }
+    }
+    debug!("RPC client {} disconnected", peer);
+}
+
+/// Send one request to the repository's running daemon and wait for its
+/// result. None when no daemon is recorded or it can't be reached.
+pub fn call_running(repo_path: &Path, method: &str) -> Option<Result<Value>> {
+    let daemon = DaemonInfo::load(repo_path)?;
+    let address: SocketAddr = bind_address(daemon.port).parse().ok()?;
+    let mut stream = std::net::TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok()?;
+
+    let request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "token": daemon.token, "method": method});
+    let mut exchange = || -> Result<Value> {
+        writeln!(stream, "{}", request)?;
+        let mut line = String::new();
+        std::io::BufReader::new(&stream).read_line(&mut line)?;
+        let mut response: Value = serde_json::from_str(&line)?;
+        match response.get("error") {
+            Some(error) => Err(DitriveError::Config(format!(
+                "RPC daemon answered with an error: {}",
+                error["message"].as_str().unwrap_or_default()
+            ))),
+            None => Ok(response["result"].take()),
+        }
+    };
+    Some(exchange())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "secret";
+
+    #[test]
+    fn test_request_parsing() {
+        let request =
+            Request::parse(r#"{"jsonrpc":"2.0","id":7,"token":"secret","method":"sync","params":{"two_way":true}}"#, TOKEN)
+                .unwrap();
+        assert_eq!(request.id, Some(Value::from(7)));
+        assert_eq!(
+            request.method,
+            Method::Sync(SyncParams {
+                two_way: true,
+                ..Default::default()
+            })
+        );
+
+        let notification = Request::parse(r#"{"jsonrpc":"2.0","token":"secret","method":"status"}"#, TOKEN).unwrap();
+        assert_eq!(notification.id, None);
+        assert_eq!(notification.method, Method::Status);
+
+        let error = |line: &str| Request::parse(line, TOKEN).unwrap_err().error.unwrap().code;
+        assert_eq!(error("not json"), PARSE_ERROR);
+        assert_eq!(error("POST / HTTP/1.1"), PARSE_ERROR);
+        assert_eq!(error(r#"{"jsonrpc":"1.0","id":1,"token":"secret","method":"status"}"#), INVALID_REQUEST);
+        assert_eq!(error(r#"{"jsonrpc":"2.0","id":1,"method":"sync"}"#), UNAUTHORIZED);
+        assert_eq!(error(r#"{"jsonrpc":"2.0","id":1,"token":"guess","method":"sync"}"#), UNAUTHORIZED);
+        assert_eq!(error(r#"{"jsonrpc":"2.0","id":1,"token":"secret","method":"deploy"}"#), METHOD_NOT_FOUND);
+        assert_eq!(
+            error(r#"{"jsonrpc":"2.0","id":1,"token":"secret","method":"sync","params":{"force":true}}"#),
+            INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn test_response_lines() {
+        let ok = Response::success(Value::from(1), serde_json::json!({"actions": []}));
+        assert_eq!(ok.to_line(), r#"{"jsonrpc":"2.0","id":1,"result":{"actions":[]}}"#);
+
+        let failed = Response::error(Value::from("a"), METHOD_NOT_FOUND, "Unknown method 'x'");
+        assert_eq!(
+            failed.to_line(),
+            r#"{"jsonrpc":"2.0","id":"a","error":{"code":-32601,"message":"Unknown method 'x'"}}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_closes_after_invalid_line() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (queue, mut queued) = mpsc::channel::<Queued>(1);
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            handle_connection(stream, peer, TOKEN.to_string(), queue).await;
+        });
+
+        // A browser form post: header lines, then a body that would run sync
+        let mut client = TcpStream::connect(address).await.unwrap();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"sync\"}\n")
+            .await
+            .unwrap();
+        let mut answer = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut answer).await.unwrap();
+        assert_eq!(answer.lines().count(), 1);
+        assert!(answer.contains(&PARSE_ERROR.to_string()));
+        assert!(queued.try_recv().is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Malware scanning of downloaded files
+//!
+//! With `scan_command` set in the global settings (for example
+//! `clamscan --no-summary {}`), every file Ditrive downloads is passed to that
+//! command before it is placed in the working tree. `{}` is replaced with the
+//! file's path, which is appended if the command has no `{}`. A non-zero exit
+//! moves the file to `~/.ditrive/quarantine` instead and fails the download.
+
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::debug;
+
+use crate::config::GlobalConfig;
+use crate::error::{DitriveError, Result};
+
+/// Placeholder replaced with the path of the file to scan
+const PATH_PLACEHOLDER: &str = "{}";
+
+/// What the scanner made of a file
+#[derive(Debug, PartialEq)]
+pub enum ScanOutcome {
+    Clean,
+    /// The scanner exited non-zero; holds what it printed
+    Flagged(String),
+}
+
+/// A configured scan command
+#[derive(Debug)]
+pub struct Scanner {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Scanner {
+    /// Parse a scan command, split on whitespace. None if no command is set.
+    pub fn from_command(command: &str) -> Option<Self> {
+        let mut parts = command.split_whitespace().map(str::to_string);
+        let program = parts.next()?;
+        let mut args: Vec<String> = parts.collect();
+        if !args.iter().any(|arg| arg.contains(PATH_PLACEHOLDER)) {
+            args.push(PATH_PLACEHOLDER.to_string());
+        }
+        Some(Self { program, args })
+    }
+
+    /// Arguments for scanning `path`
+    fn args_for(&self, path: &Path) -> Vec<String> {
+        let path = path.to_string_lossy();
+        self.args.iter().map(|arg| arg.replace(PATH_PLACEHOLDER, &path)).collect()
+    }
+
+    /// Scan a file. Failing to run the scanner is an error, not a clean result.
+    pub fn scan(&self, path: &Path) -> Result<ScanOutcome> {
+        debug!("Scanning {:?} with {}", path, self.program);
+        let output = Command::new(&self.program)
+            .args(self.args_for(path))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| DitriveError::Config(format!("Failed to run scan command '{}': {}", self.program, e)))?;
+
+        if output.status.success() {
+            return Ok(ScanOutcome::Clean);
+        }
+
+        let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(ScanOutcome::Flagged(report.trim().to_string()))
+    }
+}
+
+/// Move a flagged file into the quarantine folder, returning where it went.
+///
+/// Quarantined files are grouped by repository and prefixed with the time, so
+/// repeated downloads of the same file don't overwrite each other.
+pub fn quarantine(file: &Path, repo_name: &str, rel_path: &str) -> Result<PathBuf> {
+    let folder = GlobalConfig::config_dir()?.join("quarantine").join(repo_name);
+    fs::create_dir_all(&folder)?;
+
+    let name = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S"), rel_path.replace(['/', '\\'], "_"));
+    let destination = folder.join(name);
+    if fs::rename(file, &destination).is_err() {
+        // Across filesystems rename fails, so fall back to copy and delete
+        fs::copy(file, &destination)?;
+        fs::remove_file(file)?;
+    }
+    Ok(destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_command_arguments() {
+        let scanner = Scanner::from_command("clamscan --no-summary {}").unwrap();
+        assert_eq!(scanner.program, "clamscan");
+        assert_eq!(scanner.args_for(Path::new("/tmp/a.bin")), vec!["--no-summary", "/tmp/a.bin"]);
+
+        let appended = Scanner::from_command("scan-file -q").unwrap();
+        assert_eq!(appended.args_for(Path::new("x.bin")), vec!["-q", "x.bin"]);
+
+        assert!(Scanner::from_command("  ").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_outcome_follows_exit_status() {
+        let clean = Scanner::from_command("true").unwrap();
+        assert_eq!(clean.scan(Path::new("any")).unwrap(), ScanOutcome::Clean);
+
+        let flagged = Scanner::from_command("false").unwrap();
+        assert!(matches!(flagged.scan(Path::new("any")).unwrap(), ScanOutcome::Flagged(_)));
+
+        assert!(Scanner::from_command("no-such-scanner-ditrive").unwrap().scan(Path::new("any")).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Which missing files `pull` downloads, and which files `push` uploads
+//!
+//! `ditrive pull assets/levels --include "*.bin" --exclude "assets/levels/old/**"`
+//! downloads only missing files under `assets/levels` that match an include
+//! glob and no exclude glob. Globs match like `.gitattributes`: one without a
+//! slash matches file names at any depth, one with a slash matches from the
+//! repository root.
+
+use crate::git;
+
+/// Paths and globs narrowing down the missing files to download
+#[derive(Debug, Clone, Default)]
+pub struct PullFilter {
+    /// Repository-relative files or folders, with forward slashes
+    paths: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PullFilter {
+    /// A filter for `paths` relative to the repository root; no paths and no
+    /// include globs let everything through
+    pub fn new(paths: Vec<String>, include: Vec<String>, exclude: Vec<String>) -> Self {
+        let paths = paths
+            .into_iter()
+            .map(|path| path.trim_end_matches('/').to_string())
+            .collect();
+        Self { paths, include, exclude }
+    }
+
+    /// Whether the filter lets every file through
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check a repository-relative path against the filter
+    pub fn matches(&self, rel_path: &str) -> bool {
+        let under_paths = self.paths.is_empty()
+            || self.paths.iter().any(|path| {
+                path.is_empty()
+                    || rel_path == path
+                    || rel_path.strip_prefix(path.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            });
+        let included = self.include.is_empty() || self.include.iter().any(|glob| git::pattern_matches(glob, rel_path));
+        let excluded = self.exclude.iter().any(|glob| git::pattern_matches(glob, rel_path));
+        under_paths && included && !excluded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_empty_filter_matches_everything() {
+        let filter = PullFilter::default();
+        assert!(filter.is_empty());
+        assert!(filter.matches("assets/hero.bin"));
+    }
+
+    #[test]
+    fn test_paths_match_files_and_folders() {
+        let filter = PullFilter::new(strings(&["assets/levels/", "intro.mp4"]), vec![], vec![]);
+        assert!(filter.matches("assets/levels/one.bin"));
+        assert!(filter.matches("assets/levels/deep/two.bin"));
+        assert!(filter.matches("intro.mp4"));
+        assert!(!filter.matches("assets/levels-old/one.bin"));
+        assert!(!filter.matches("assets/hero.bin"));
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let filter = PullFilter::new(strings(&["assets"]), strings(&["*.bin"]), strings(&["/assets/old/**"]));
+        assert!(filter.matches("assets/levels/one.bin"));
+        assert!(!filter.matches("assets/levels/one.psd"));
+        assert!(!filter.matches("assets/old/one.bin"));
+        assert!(!filter.matches("videos/one.bin"));
+    }
+}
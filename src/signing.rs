@@ -0,0 +1,258 @@
+//! Signed tracker entries
+//!
+//! With the repository's `signing` setting at "gpg" or "minisign", every push
+//! signs a listing of the managed files (path, hash, size and stored ID) into
+//! `.ditrive/manifest.sig`, to be committed along with the trackers. Pulls
+//! check that signature before downloading anything, so someone with access to
+//! the Drive account or the trackers can't swap the binaries teammates get.
+//! `signing_public_key` pins who may sign: the minisign public key, or the GPG
+//! key's fingerprint, since any key in the local keyring would otherwise pass.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
+use tracing::debug;
+
+use crate::config::SigningTool;
+use crate::drive::FileMetadata;
+use crate::error::{DitriveError, Result};
+
+/// Location of the signature, relative to the repository root
+pub const SIGNATURE_PATH: &str = ".ditrive/manifest.sig";
+
+/// First line of every listing, so a signature over anything else can't pass for one
+const LISTING_HEADER: &str = "ditrive signed manifest v1";
+
+/// The text that gets signed: one "path hash size id" line per managed file, sorted by path
+pub fn listing<'a>(entries: impl IntoIterator<Item = (String, &'a FileMetadata)>) -> String {
+    let lines: BTreeMap<String, String> = entries
+        .into_iter()
+        .map(|(rel_path, metadata)| {
+            let line = format!("{} {} {} {}", rel_path, metadata.hash, metadata.size, metadata.id);
+            (rel_path, line)
+        })
+        .collect();
+
+    let mut text = format!("{}\n", LISTING_HEADER);
+    for line in lines.values() {
+        text.push_str(line);
+        text.push('\n');
+    }
+    text
+}
+
+/// A signing tool with the keys to sign and check with
+#[derive(Debug)]
+pub struct Signer {
+    tool: SigningTool,
+    /// GPG key ID or minisign secret key file; empty uses the tool's default
+    secret_key: String,
+    /// Minisign public key, or the fingerprint of the GPG key signatures must come from
+    public_key: String,
+}
+
+impl Signer {
+    /// None when signing is off
+    pub fn new(tool: SigningTool, secret_key: &str, public_key: &str) -> Option<Self> {
+        (tool != SigningTool::Off).then(|| Self {
+            tool,
+            secret_key: secret_key.to_string(),
+            public_key: public_key.to_string(),
+        })
+    }
+
+    fn program(&self) -> &'static str {
+        match self.tool {
+            SigningTool::Gpg => "gpg",
+            SigningTool::Off | SigningTool::Minisign => "minisign",
+        }
+    }
+
+    /// Arguments for signing `data` into `signature`
+    fn sign_args(&self, data: &Path, signature: &Path) -> Vec<String> {
+        let data = data.to_string_lossy().to_string();
+        let signature = signature.to_string_lossy().to_string();
+        let mut args = match self.tool {
+            SigningTool::Gpg => vec!["--yes".into(), "--armor".into(), "--output".into(), signature],
+            SigningTool::Off | SigningTool::Minisign => vec!["-S".into(), "-m".into(), data.clone(), "-x".into(), signature],
+        };
+        if !self.secret_key.is_empty() {
+            let flag = if self.tool == SigningTool::Gpg { "--local-user" } else { "-s" };
+            args.extend([flag.to_string(), self.secret_key.clone()]);
+        }
+        if self.tool == SigningTool::Gpg {
+            args.extend(["--detach-sign".to_string(), data]);
+        }
+        args
+    }
+
+    /// Arguments for checking `signature` over `data`
+    fn verify_args(&self, data: &Path, signature: &Path) -> Result<Vec<String>> {
+        if self.public_key.is_empty() {
+            return Err(DitriveError::Config(match self.tool {
+                SigningTool::Gpg => "signing_public_key must be set to the signing key's fingerprint to check GPG signatures",
+                SigningTool::Off | SigningTool::Minisign => "signing_public_key must be set to check minisign signatures",
+            }
+            .to_string()));
+        }
+        let data = data.to_string_lossy().to_string();
+        let signature = signature.to_string_lossy().to_string();
+        match self.tool {
+            // The status lines name the key that made the signature, which `verify` checks against the pin
+            SigningTool::Gpg => Ok(vec!["--batch".into(), "--status-fd".into(), "1".into(), "--verify".into(), signature, data]),
+            SigningTool::Off | SigningTool::Minisign => {
+                Ok(vec!["-V".into(), "-q".into(), "-P".into(), self.public_key.clone(), "-m".into(), data, "-x".into(), signature])
+            }
+        }
+    }
+
+    /// Sign a listing into `signature`. The tool may ask for a passphrase.
+    pub fn sign(&self, listing: &str, signature: &Path) -> Result<()> {
+        if let Some(parent) = signature.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let data = write_listing(listing)?;
+        debug!("Signing {:?} with {}", signature, self.program());
+        let status = Command::new(self.program())
+            .args(self.sign_args(data.path(), signature))
+            .status()
+            .map_err(|e| self.run_error(e))?;
+        if !status.success() {
+            return Err(DitriveError::Signature(format!("{} couldn't sign the trackers ({})", self.program(), status)));
+        }
+        Ok(())
+    }
+
+    /// Check that `signature` is a good signature over a listing
+    pub fn verify(&self, listing: &str, signature: &Path) -> Result<()> {
+        if !signature.exists() {
+            return Err(DitriveError::Signature(format!(
+                "{} is missing; push from a clone with signing set up first",
+                SIGNATURE_PATH
+            )));
+        }
+        let data = write_listing(listing)?;
+        let args = self.verify_args(data.path(), signature)?;
+        debug!("Checking {:?} with {}", signature, self.program());
+        let output = Command::new(self.program())
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| self.run_error(e))?;
+        let status = String::from_utf8_lossy(&output.stdout);
+        if output.status.success() {
+            if self.tool != SigningTool::Gpg || signed_by(&status, &self.public_key) {
+                return Ok(());
+            }
+            return Err(DitriveError::Signature(format!(
+                "{} was not made by the key signing_public_key names ({})",
+                SIGNATURE_PATH, self.public_key
+            )));
+        }
+
+        let mut report = status.into_owned();
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+        Err(DitriveError::Signature(format!(
+            "the trackers don't match {}: {}",
+            SIGNATURE_PATH,
+            report.trim()
+        )))
+    }
+
+    fn run_error(&self, e: std::io::Error) -> DitriveError {
+        DitriveError::Config(format!("Failed to run {}: {}", self.program(), e))
+    }
+}
+
+/// Write a listing to a fresh temporary file for the signing tool; it is removed on drop
+fn write_listing(listing: &str) -> Result<NamedTempFile> {
+    let mut file = tempfile::Builder::new().prefix("ditrive-manifest-").suffix(".txt").tempfile()?;
+    file.write_all(listing.as_bytes())?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// Whether GPG's status output reports a good signature by the key with `fingerprint`.
+///
+/// VALIDSIG lines carry the signing (sub)key's fingerprint first and the
+/// primary key's last; either may be pinned.
+fn signed_by(status: &str, fingerprint: &str) -> bool {
+    let pinned: String = fingerprint.chars().filter(|c| !c.is_whitespace()).collect();
+    status.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some("[GNUPG:]") || fields.next() != Some("VALIDSIG") {
+            return false;
+        }
+        let fields: Vec<&str> = fields.collect();
+        let matches = |key: Option<&&str>| key.is_some_and(|key| key.eq_ignore_ascii_case(&pinned));
+        matches(fields.first()) || matches(fields.last())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(id: &str, hash: &str, size: u64) -> FileMetadata {
+        FileMetadata {
+            id: id.to_string(),
+            hash: hash.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_listing_is_sorted_by_path() {
+        let hero = metadata("id-1", "aaa", 10);
+        let level = metadata("id-2", "bbb", 20);
+        let listing = listing([("maps/level.bin".to_string(), &level), ("art/hero.psd".to_string(), &hero)]);
+        assert_eq!(
+            listing,
+            "ditrive signed manifest v1\nart/hero.psd aaa 10 id-1\nmaps/level.bin bbb 20 id-2\n"
+        );
+    }
+
+    #[test]
+    fn test_tool_arguments() {
+        let data = Path::new("list.txt");
+        let signature = Path::new("manifest.sig");
+
+        let gpg = Signer::new(SigningTool::Gpg, "ABCD1234", "0123ABCD").unwrap();
+        assert_eq!(
+            gpg.sign_args(data, signature),
+            vec!["--yes", "--armor", "--output", "manifest.sig", "--local-user", "ABCD1234", "--detach-sign", "list.txt"]
+        );
+        assert_eq!(
+            gpg.verify_args(data, signature).unwrap(),
+            vec!["--batch", "--status-fd", "1", "--verify", "manifest.sig", "list.txt"]
+        );
+        assert!(Signer::new(SigningTool::Gpg, "", "").unwrap().verify_args(data, signature).is_err());
+
+        let minisign = Signer::new(SigningTool::Minisign, "", "RWQkey").unwrap();
+        assert_eq!(minisign.sign_args(data, signature), vec!["-S", "-m", "list.txt", "-x", "manifest.sig"]);
+        assert_eq!(
+            minisign.verify_args(data, signature).unwrap(),
+            vec!["-V", "-q", "-P", "RWQkey", "-m", "list.txt", "-x", "manifest.sig"]
+        );
+        assert!(Signer::new(SigningTool::Minisign, "", "").unwrap().verify_args(data, signature).is_err());
+
+        assert!(Signer::new(SigningTool::Off, "", "").is_none());
+    }
+
+    #[test]
+    fn test_signed_by_pinned_key() {
+        let status = "[GNUPG:] NEWSIG\n\
+            [GNUPG:] GOODSIG 89ABCDEF01234567 Dev <dev@example.com>\n\
+            [GNUPG:] VALIDSIG 1111222233334444555566667777888899990000 2026-01-01 1767225600 0 4 0 1 10 00 AAAABBBBCCCCDDDDEEEEFFFF0000111122223333\n";
+
+        // Subkey or primary key, spaces and case aside
+        assert!(signed_by(status, "1111222233334444555566667777888899990000"));
+        assert!(signed_by(status, "aaaa bbbb cccc dddd eeee ffff 0000 1111 2222 3333"));
+        assert!(!signed_by(status, "FFFF222233334444555566667777888899990000"));
+        assert!(!signed_by("[GNUPG:] GOODSIG 89ABCDEF01234567 Dev\n", "89ABCDEF01234567"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Symbolic links in repository scans
+//!
+//! The repository's `symlinks` setting decides what the large file scan and
+//! the trackers make of links: `skip` leaves them out, `follow` descends into
+//! linked folders, and `treat-as-file` manages a link to a file as a copy of
+//! it. Whatever the policy, a link whose target lies outside the repository
+//! is left out, so nothing outside it is ever uploaded.
+
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::config::SymlinkPolicy;
+
+/// Walk a repository's files and folders under `policy`, leaving out `.git`
+pub fn walk(repo_path: &Path, policy: SymlinkPolicy) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+    walk_pruned(repo_path, policy, |_| false)
+}
+
+/// Like [`walk`], also leaving out entries `prune` picks and everything under them
+pub fn walk_pruned(
+    repo_path: &Path,
+    policy: SymlinkPolicy,
+    mut prune: impl FnMut(&DirEntry) -> bool,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+    let git_dir = repo_path.join(".git");
+    let root = canonical_root(repo_path);
+    WalkDir::new(repo_path)
+        .follow_links(policy == SymlinkPolicy::Follow)
+        .into_iter()
+        .filter_entry(move |e| {
+            !e.path().starts_with(&git_dir)
+                && (!e.path_is_symlink() || admits_link(&root, e.path(), policy))
+                && !prune(e)
+        })
+        .filter(|entry| match entry {
+            // A link back up the tree; everything under it was already walked
+            Err(e) if e.loop_ancestor().is_some() => {
+                warn!("Skipping symlink loop at {:?}", e.path().unwrap_or(Path::new("")));
+                false
+            }
+            _ => true,
+        })
+}
+
+/// Whether a walked entry is a file to consider, counting links to files
+pub fn is_file(entry: &DirEntry) -> bool {
+    entry.file_type().is_file() || (entry.path_is_symlink() && entry.path().is_file())
+}
+
+/// Whether a managed file at `path` may be read under `policy`: not a link
+/// the policy skips, and not resolving to somewhere outside the repository
+pub fn admits_path(repo_path: &Path, path: &Path, policy: SymlinkPolicy) -> bool {
+    let root = canonical_root(repo_path);
+    if path.is_symlink() {
+        return admits_link(&root, path, policy);
+    }
+    match path.canonicalize() {
+        Ok(target) if !target.starts_with(&root) => {
+            warn!("Skipping {:?}: it resolves to {:?}, outside the repository", path, target);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// The repository root with links resolved, to compare link targets against
+fn canonical_root(repo_path: &Path) -> PathBuf {
+    repo_path.canonicalize().unwrap_or_else(|_| repo_path.to_path_buf())
+}
+
+/// Whether the link at `path` is included under `policy`
+fn admits_link(root: &Path, path: &Path, policy: SymlinkPolicy) -> bool {
+    if policy == SymlinkPolicy::Skip {
+        debug!("Skipping symlink {:?}", path);
+        return false;
+    }
+    match path.canonicalize() {
+        Ok(target) if target.starts_with(root) => true,
+        Ok(target) => {
+            warn!("Skipping symlink {:?}: its target {:?} is outside the repository", path, target);
+            false
+        }
+        Err(_) => {
+            debug!("Skipping broken symlink {:?}", path);
+            false
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    /// Repo-relative paths of the files a walk finds
+    fn files(repo: &Path, policy: SymlinkPolicy) -> Vec<String> {
+        let mut found: Vec<String> = walk(repo, policy)
+            .map(|entry| entry.unwrap())
+            .filter(is_file)
+            .map(|entry| entry.path().strip_prefix(repo).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn test_walk_policies() {
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("secret.bin"), "secret").unwrap();
+
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("assets")).unwrap();
+        fs::write(repo.join("assets/hero.bin"), "hero").unwrap();
+        symlink(repo.join("assets/hero.bin"), repo.join("hero-link.bin")).unwrap();
+        symlink(repo.join("assets"), repo.join("assets-link")).unwrap();
+        symlink(outside.path().join("secret.bin"), repo.join("secret.bin")).unwrap();
+        symlink(outside.path(), repo.join("outside")).unwrap();
+        symlink(repo, repo.join("assets/loop")).unwrap();
+
+        assert_eq!(files(repo, SymlinkPolicy::Skip), vec!["assets/hero.bin"]);
+        assert_eq!(files(repo, SymlinkPolicy::TreatAsFile), vec!["assets/hero.bin", "hero-link.bin"]);
+        assert_eq!(
+            files(repo, SymlinkPolicy::Follow),
+            vec!["assets-link/hero.bin", "assets/hero.bin", "hero-link.bin"]
+        );
+    }
+
+    #[test]
+    fn test_admits_path() {
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("secret.bin"), "secret").unwrap();
+
+        let dir = tempdir().unwrap();
+        let repo = dir.path();
+        fs::write(repo.join("level.bin"), "level").unwrap();
+        symlink(repo.join("level.bin"), repo.join("level-link.bin")).unwrap();
+        symlink(outside.path(), repo.join("outside")).unwrap();
+
+        assert!(admits_path(repo, &repo.join("level.bin"), SymlinkPolicy::Skip));
+        assert!(admits_path(repo, &repo.join("missing.bin"), SymlinkPolicy::Skip));
+        assert!(!admits_path(repo, &repo.join("level-link.bin"), SymlinkPolicy::Skip));
+        assert!(admits_path(repo, &repo.join("level-link.bin"), SymlinkPolicy::TreatAsFile));
+        assert!(!admits_path(repo, &repo.join("outside/secret.bin"), SymlinkPolicy::Follow));
+    }
+}
@@ -0,0 +1,308 @@
+//! LAN read-through cache for managed files
+//!
+//! One machine runs `ditrive serve --bind 0.0.0.0`. It answers
+//! `GET /files/<id>?hash=<sha256>` from its cache directory, downloading each
+//! version from the storage backend the first time it is asked for, so an
+//! office hydrating the same assets pays for the WAN transfer once. Cached
+//! files are kept per ID and hash, since backends like Drive keep a file's ID
+//! when its content changes. Clients set `team_cache_url` in their global
+//! config and try the cache before the backend; whatever they get is checked
+//! against the tracked hash, and any failure falls back to the backend.
+//!
+//! The server downloads with its user's credentials, so it only answers
+//! requests carrying the team's `team_cache_token` as a bearer token, and
+//! only for file IDs in its repository's trackers. Those are the trackers of
+//! its own checkout, so keep it pulled: files added in newer commits are
+//! refused until it has them. It listens on 127.0.0.1 unless given another
+//! address.
+//!
+//! The server speaks just enough HTTP/1.1 for that: one request per
+//! connection, no keep-alive.
+
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::drive::calculate_file_hash;
+use crate::error::{DitriveError, Result};
+use crate::http;
+use crate::trace::TracedSend;
+
+/// Port `ditrive serve` listens on by default
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// How long clients wait to reach the cache before using the backend
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Upper bound on request header size, so a bad client can't make us buffer forever
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// Address to listen on: this machine only unless another address is given
+pub fn bind_address(bind: Option<IpAddr>, port: u16) -> SocketAddr {
+    SocketAddr::new(bind.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)), port)
+}
+
+/// Where a version of a file is kept in the cache. IDs can contain slashes,
+/// so they are hashed; `hash` tells the versions of one ID apart.
+pub fn cache_path(cache_dir: &Path, file_id: &str, hash: Option<&str>) -> PathBuf {
+    let name = hex::encode(Sha256::digest(file_id.as_bytes()));
+    match hash {
+        Some(hash) => cache_dir.join(format!("{}.{}", name, hash)),
+        None => cache_dir.join(name),
+    }
+}
+
+/// Whether `hash` looks like a SHA-256 content hash, the only kind safe to put in a cache path
+fn is_content_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A request the cache server understands
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    /// `GET /health`, for checking a cache is reachable
+    Health,
+    /// `GET /files/<id>`, optionally with `?hash=<sha256>` naming the version wanted
+    File { id: String, hash: Option<String> },
+    /// A file request without the team token
+    Unauthorized,
+    Invalid,
+}
+
+impl Request {
+    /// Parse an HTTP request line such as `GET /files/abc HTTP/1.1`
+    pub fn parse(request_line: &str) -> Self {
+        let mut parts = request_line.split_whitespace();
+        let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+            return Request::Invalid;
+        };
+
+        if target == "/health" {
+            return Request::Health;
+        }
+        let Some(target) = target.strip_prefix("/files/") else {
+            return Request::Invalid;
+        };
+        let (id, query) = target.split_once('?').unwrap_or((target, ""));
+        let hash = match query {
+            "" => None,
+            query => match query.strip_prefix("hash=") {
+                Some(hash) if is_content_hash(hash) => Some(hash.to_ascii_lowercase()),
+                _ => return Request::Invalid,
+            },
+        };
+        match urlencoding::decode(id) {
+            Ok(id) if !id.is_empty() => Request::File { id: id.into_owned(), hash },
+            _ => Request::Invalid,
+        }
+    }
+
+    /// Read and parse a request from a connection, checking file requests
+    /// carry `token` in their `Authorization` header
+    pub async fn read(stream: &mut TcpStream, token: &str) -> Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+
+        let mut header_bytes = request_line.len();
+        let mut authorized = false;
+        loop {
+            let mut line = String::new();
+            let read = reader.read_line(&mut line).await?;
+            header_bytes += read;
+            if read == 0 || line.trim().is_empty() {
+                break;
+            }
+            if header_bytes > MAX_HEADER_BYTES {
+                return Ok(Request::Invalid);
+            }
+            authorized |= bearer_token(&line).is_some_and(|given| token_matches(given, token));
+        }
+
+        match Self::parse(&request_line) {
+            Request::File { .. } if !authorized => Ok(Request::Unauthorized),
+            request => Ok(request),
+        }
+    }
+}
+
+/// Whether a client's token is the expected one. Both are hashed first, and
+/// the digests compared in full, so timing says nothing about the token.
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    let (given, expected) = (Sha256::digest(given.as_bytes()), Sha256::digest(expected.as_bytes()));
+    given.iter().zip(expected.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The token of an `Authorization: Bearer <token>` header line
+fn bearer_token(header_line: &str) -> Option<&str> {
+    let (name, value) = header_line.split_once(':')?;
+    if !name.trim().eq_ignore_ascii_case("authorization") {
+        return None;
+    }
+    let value = value.trim();
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// Send a short plain-text response
+pub async fn write_status<W: AsyncWrite + Unpin>(stream: &mut W, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Send a cached file as the response body, returning the bytes sent
+pub async fn send_file<W: AsyncWrite + Unpin>(stream: &mut W, path: &Path) -> Result<u64> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let headers = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        size
+    );
+    stream.write_all(headers.as_bytes()).await?;
+    let sent = tokio::io::copy(&mut file, stream).await?;
+    stream.flush().await?;
+    Ok(sent)
+}
+
+/// One lock per file ID, so concurrent requests for the same file trigger a single download
+#[derive(Default)]
+pub struct FetchLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl FetchLocks {
+    /// The lock guarding downloads of `file_id`
+    pub fn lock_for(&self, file_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.entry(file_id.to_string()).or_default().clone()
+    }
+}
+
+/// Fetches files from a team cache server
+pub struct TeamCacheClient {
+    client: Client,
+    base_url: String,
+    token: String,
+}
+
+impl TeamCacheClient {
+    /// Create a client for the cache at `base_url`, e.g. `http://10.0.0.5:7878`,
+    /// authenticating with the team's `token`
+    pub fn new(base_url: &str, token: &str) -> Result<Self> {
+        let client = http::client_builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .map_err(DitriveError::Http)?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    /// Download a file from the cache, asking for and checking against `hash` when one is known
+    pub async fn fetch(&self, file_id: &str, hash: &str, destination: &Path) -> Result<()> {
+        let mut url = format!("{}/files/{}", self.base_url, urlencoding::encode(file_id));
+        if !hash.is_empty() {
+            url = format!("{}?hash={}", url, hash);
+        }
+        let mut response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.token)
+            .send_traced()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to reach team cache: {}", e)))?;
+
+        if response.status() != StatusCode::OK {
+            let status = response.status();
+            let error = response.text().await.unwrap_or_default();
+            return Err(DitriveError::Drive(format!("Team cache returned {}: {}", status, error)));
+        }
+
+        if let Some(parent) = destination.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(destination).await?;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| DitriveError::Drive(format!("Failed to read from team cache: {}", e)))?
+        {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        if !hash.is_empty() && calculate_file_hash(destination)? != hash {
+            let _ = tokio::fs::remove_file(destination).await;
+            return Err(DitriveError::Drive(format!(
+                "Team cache content for {} doesn't match its tracked hash",
+                file_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_parsing() {
+        assert_eq!(Request::parse("GET /health HTTP/1.1\r\n"), Request::Health);
+        assert_eq!(
+            Request::parse("GET /files/models%2Fbig.onnx.ab12 HTTP/1.1"),
+            Request::File { id: "models/big.onnx.ab12".to_string(), hash: None }
+        );
+        let hash = "ab".repeat(32);
+        assert_eq!(
+            Request::parse(&format!("GET /files/abc?hash={} HTTP/1.1", hash)),
+            Request::File { id: "abc".to_string(), hash: Some(hash.clone()) }
+        );
+        assert_eq!(Request::parse("GET /files/abc?hash=../../etc HTTP/1.1"), Request::Invalid);
+        assert_eq!(Request::parse("GET /files/abc?rev=2 HTTP/1.1"), Request::Invalid);
+        assert_eq!(Request::parse("GET /files/ HTTP/1.1"), Request::Invalid);
+        assert_eq!(Request::parse("POST /files/abc HTTP/1.1"), Request::Invalid);
+        assert_eq!(Request::parse(""), Request::Invalid);
+    }
+
+    #[test]
+    fn test_cache_path_keeps_versions_apart() {
+        let dir = Path::new("cache");
+        let (old, new) = ("aa".repeat(32), "bb".repeat(32));
+        assert_ne!(cache_path(dir, "abc", Some(&old)), cache_path(dir, "abc", Some(&new)));
+        assert_ne!(cache_path(dir, "abc", None), cache_path(dir, "abc", Some(&old)));
+        assert_eq!(cache_path(dir, "a/b", None).parent(), Some(dir));
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Authorization: Bearer s3cret\r\n"), Some("s3cret"));
+        assert_eq!(bearer_token("authorization: bearer s3cret"), Some("s3cret"));
+        assert_eq!(bearer_token("Authorization: Basic dXNlcg=="), None);
+        assert_eq!(bearer_token("X-Token: Bearer s3cret"), None);
+        assert_eq!(bearer_token("Authorization: Bearer"), None);
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cres", "s3cret"));
+        assert!(!token_matches("s3cret-and-more", "s3cret"));
+        assert!(!token_matches("", "s3cret"));
+    }
+}
@@ -0,0 +1,1170 @@
+//! Fixtures for testing sync scenarios without real accounts
+//!
+//! A [`Fixture`] is a throwaway git repository wired to a [`MemoryBackend`],
+//! so uploads and downloads happen in memory. A [`Scenario`] scripts changes
+//! and syncs against a fixture, and [`Fixture::assert_golden`] compares the
+//! resulting trackers, `.gitignore` and remote contents with a file under
+//! `tests/golden`. Run with `DITRIVE_UPDATE_GOLDEN=1` to rewrite the golden
+//! files after an intended change, and review the diff.
+//!
+//! Built for `cargo test` and with the `test-util` feature.
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+use crate::app::Ditrive;
+use crate::backend::{RemoteEntry, StorageBackend};
+use crate::config::{DriveAuthType, GlobalConfig, RepoConfig};
+use crate::drive::{calculate_file_hash, FileMetadata};
+use crate::error::{DitriveError, Result};
+use crate::git::GitManager;
+use crate::prompt::{NonInteractivePrompter, PresetPrompter};
+use crate::tracker;
+
+/// Set to rewrite golden files instead of comparing against them
+pub const UPDATE_GOLDEN_ENV: &str = "DITRIVE_UPDATE_GOLDEN";
+
+/// Size of files written with [`Fixture::write_large`], just over the 1 MB threshold
+pub const LARGE_FILE_SIZE: usize = 1_500_000;
+
+/// Remote content shared by every backend a fixture hands out
+#[derive(Debug, Default)]
+struct StoreState {
+    files: BTreeMap<String, Vec<u8>>,
+    /// Logical clock for `uploaded_at`, so snapshots don't depend on the time
+    clock: i64,
+    /// Report no content hashes, like backends that don't keep one
+    hide_hashes: bool,
+}
+
+/// In-memory storage shared between fixtures, standing in for a real backend
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    state: Arc<Mutex<StoreState>>,
+}
+
+impl MemoryStore {
+    /// IDs and sizes of everything stored
+    pub fn list(&self) -> Vec<(String, usize)> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.files.iter().map(|(id, content)| (id.clone(), content.len())).collect()
+    }
+
+    /// Stop reporting content hashes, as backends without one do
+    pub fn hide_hashes(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).hide_hashes = true;
+    }
+
+    /// Drop a file, as if the backend had lost it
+    pub fn remove(&self, file_id: &str) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.files.remove(file_id).is_some()
+    }
+
+    fn put(&self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        let content = fs::read(file_path)?;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.clock += 1;
+        let metadata = FileMetadata {
+            id: file_id.to_string(),
+            hash: calculate_file_hash(file_path)?,
+            size: content.len() as u64,
+            uploaded_at: state.clock,
+            ..Default::default()
+        };
+        state.files.insert(file_id.to_string(), content);
+        Ok(metadata)
+    }
+}
+
+/// Backend over a [`MemoryStore`]. IDs are repository-relative paths, like the local backend.
+pub struct MemoryBackend {
+    store: MemoryStore,
+}
+
+impl MemoryBackend {
+    pub fn new(store: MemoryStore) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "Memory"
+    }
+
+    fn repo_folder_id(&self) -> &str {
+        "memory"
+    }
+
+    async fn upload_file(&mut self, file_path: &Path, repo_path: &Path) -> Result<FileMetadata> {
+        let file_id = file_path
+            .strip_prefix(repo_path)
+            .map_err(|_| DitriveError::FileNotFound(file_path.display().to_string()))?
+            .to_string_lossy()
+            .replace('\\', "/");
+        self.store.put(&file_id, file_path)
+    }
+
+    async fn update_file(&mut self, file_id: &str, file_path: &Path) -> Result<FileMetadata> {
+        self.store.put(file_id, file_path)
+    }
+
+    async fn download_file(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let content = {
+            let state = self.store.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.files.get(file_id).cloned()
+        };
+        let content = content.ok_or_else(|| DitriveError::FileNotFound(file_id.to_string()))?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(destination, content)?;
+        Ok(())
+    }
+
+    async fn file_exists(&self, file_id: &str) -> bool {
+        let state = self.store.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.files.contains_key(file_id)
+    }
+
+    async fn content_hash(&self, file_id: &str) -> Result<Option<String>> {
+        let state = self.store.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.hide_hashes {
+            return Ok(None);
+        }
+        Ok(state.files.get(file_id).map(|content| hex::encode(Sha256::digest(content))))
+    }
+
+    async fn list_files(&self) -> Result<Option<Vec<RemoteEntry>>> {
+        let state = self.store.state.lock().unwrap_or_else(|e| e.into_inner());
+        let entries = state
+            .files
+            .iter()
+            .map(|(id, content)| RemoteEntry {
+                id: id.clone(),
+                path: id.clone(),
+                size: content.len() as u64,
+                hash: (!state.hide_hashes).then(|| hex::encode(Sha256::digest(content))),
+                modified_at: 0,
+            })
+            .collect();
+        Ok(Some(entries))
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<()> {
+        if self.store.remove(file_id) {
+            Ok(())
+        } else {
+            Err(DitriveError::FileNotFound(file_id.to_string()))
+        }
+    }
+}
+
+/// A temporary git repository set up for Ditrive, backed by a [`MemoryStore`]
+pub struct Fixture {
+    dir: TempDir,
+    store: MemoryStore,
+    global_config: GlobalConfig,
+}
+
+impl Fixture {
+    /// A fresh repository with a 1 MB threshold and its own store
+    pub fn new() -> Result<Self> {
+        Self::with_store(MemoryStore::default())
+    }
+
+    /// A fresh repository sharing `store`, like a second machine on the same backend
+    pub fn with_store(store: MemoryStore) -> Result<Self> {
+        let dir = tempfile::Builder::new().prefix("ditrive-fixture").tempdir()?;
+        let git = GitManager::init(dir.path())?;
+        git.configure_user("Fixture", "fixture@example.com")?;
+
+        let mut global_config = GlobalConfig::default();
+        global_config.drive.auth_type = DriveAuthType::Local;
+        global_config.settings.large_file_threshold_mb = 1;
+        global_config.settings.handle_ignored_large_files = "skip".to_string();
+        // Scenarios share nothing through the user's ~/.ditrive/cache, nor write to their history log
+        global_config.settings.download_cache_max_mb = 0;
+        global_config.settings.history_log = false;
+
+        // Written up front so loading it never falls back to the user's global config
+        let mut repo_config = RepoConfig::new_with_global(&global_config);
+        repo_config.settings.additional_ignore_patterns.clear();
+        repo_config.save(dir.path())?;
+
+        Ok(Self { dir, store, global_config })
+    }
+
+    /// A second repository with a copy of this one's working tree and the same store
+    pub fn fork(&self) -> Result<Self> {
+        let other = Self::with_store(self.store.clone())?;
+        for entry in WalkDir::new(self.path()).min_depth(1) {
+            let entry = entry?;
+            let rel_path = entry.path().strip_prefix(self.path()).unwrap_or(entry.path());
+            if rel_path.starts_with(".git") {
+                continue;
+            }
+            let target = other.path().join(rel_path);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&target)?;
+            } else {
+                fs::copy(entry.path(), &target)?;
+            }
+        }
+        Ok(other)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn store(&self) -> &MemoryStore {
+        &self.store
+    }
+
+    /// Ditrive for this repository, answering prompts with their defaults
+    pub fn ditrive(&self) -> Result<Ditrive> {
+        self.ditrive_with_answers(&[])
+    }
+
+    /// Ditrive for this repository, answering the given prompt keys and defaulting the rest
+    pub fn ditrive_with_answers(&self, answers: &[(&str, &str)]) -> Result<Ditrive> {
+        let store = self.store.clone();
+        let answers: HashMap<String, String> =
+            answers.iter().map(|(key, answer)| (key.to_string(), answer.to_string())).collect();
+
+        Ok(Ditrive::with_global_config(self.path(), self.global_config.clone())?
+            .with_non_interactive(true)
+            .with_prompter(Box::new(PresetPrompter::new(answers, Box::new(NonInteractivePrompter))))
+            .with_backend_factory(Arc::new(move || Box::new(MemoryBackend::new(store.clone())))))
+    }
+
+    /// Write a file above the large-file threshold. The same seed gives the same content.
+    pub fn write_large(&self, rel_path: &str, seed: u8) -> Result<()> {
+        let content: Vec<u8> = (0..LARGE_FILE_SIZE)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect();
+        self.write(rel_path, &content)
+    }
+
+    pub fn write(&self, rel_path: &str, content: &[u8]) -> Result<()> {
+        let path = self.path().join(rel_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    pub fn read(&self, rel_path: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(self.path().join(rel_path))?)
+    }
+
+    pub fn remove(&self, rel_path: &str) -> Result<()> {
+        fs::remove_file(self.path().join(rel_path))?;
+        Ok(())
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let target = self.path().join(to);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.path().join(from), target)?;
+        Ok(())
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        self.ditrive()?.sync(false, false, false, false, false).await
+    }
+
+    /// Sync that also takes what other fixtures on the same store uploaded
+    pub async fn sync_two_way(&self) -> Result<()> {
+        self.ditrive()?.sync(false, false, true, false, false).await
+    }
+
+    /// Sync that removes the stored copies of files deleted here
+    pub async fn sync_prune(&self) -> Result<()> {
+        self.ditrive()?.sync(false, false, false, true, false).await
+    }
+
+    /// Replace this repository's trackers with merge conflicts against `theirs`,
+    /// as a bad merge would leave them. Trackers that agree are left alone.
+    pub fn conflict_trackers_with(&self, theirs: &Fixture) -> Result<()> {
+        let ours = self.trackers()?;
+        for (rel_path, their_content) in theirs.trackers()? {
+            let path = self.path().join(&rel_path);
+            let conflicted = match ours.get(&rel_path) {
+                Some(our_content) if *our_content == their_content => continue,
+                Some(our_content) => format!(
+                    "<<<<<<< ours\n{}\n=======\n{}\n>>>>>>> theirs\n",
+                    our_content.trim_end(),
+                    their_content.trim_end()
+                ),
+                None => their_content,
+            };
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, conflicted)?;
+        }
+        Ok(())
+    }
+
+    /// Tracker files by repository-relative path
+    fn trackers(&self) -> Result<BTreeMap<String, String>> {
+        let mut trackers = BTreeMap::new();
+        for entry in WalkDir::new(self.path()).sort_by_file_name() {
+            let entry = entry?;
+            if !tracker::is_tracker_file(self.path(), entry.path()) {
+                continue;
+            }
+            let rel_path = entry.path().strip_prefix(self.path()).unwrap_or(entry.path());
+            trackers.insert(rel_path.to_string_lossy().replace('\\', "/"), fs::read_to_string(entry.path())?);
+        }
+        Ok(trackers)
+    }
+
+    /// Trackers, `.gitignore`, and remote contents as stable text for golden files
+    pub fn snapshot(&self) -> Result<String> {
+        let mut snapshot = String::new();
+
+        let gitignore = fs::read_to_string(self.path().join(".gitignore")).unwrap_or_default();
+        snapshot.push_str("--- .gitignore\n");
+        snapshot.push_str(gitignore.trim_end());
+        snapshot.push('\n');
+
+        for (rel_path, content) in self.trackers()? {
+            // Re-serialize through a Value so keys come out sorted
+            let normalized = match serde_json::from_str::<serde_json::Value>(&content) {
+                Ok(value) => serde_json::to_string_pretty(&value)?,
+                Err(_) => content.trim_end().to_string(),
+            };
+            snapshot.push_str(&format!("--- {}\n{}\n", rel_path, normalized));
+        }
+
+        snapshot.push_str("--- remote\n");
+        for (id, size) in self.store.list() {
+            snapshot.push_str(&format!("{} {}\n", id, size));
+        }
+        Ok(snapshot)
+    }
+
+    /// Compare the snapshot with `tests/golden/<name>.txt`, or rewrite it when
+    /// `DITRIVE_UPDATE_GOLDEN` is set
+    pub fn assert_golden(&self, name: &str) {
+        let golden_path = golden_path(name);
+        let actual = self.snapshot().expect("failed to snapshot fixture");
+
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            fs::write(&golden_path, &actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+            panic!("missing golden file {:?}; run with {}=1 to create it", golden_path, UPDATE_GOLDEN_ENV)
+        });
+        assert!(
+            expected == actual,
+            "snapshot differs from {:?}; run with {}=1 to update it\n--- expected\n{}\n--- actual\n{}",
+            golden_path,
+            UPDATE_GOLDEN_ENV,
+            expected,
+            actual
+        );
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.txt", name))
+}
+
+/// One scripted change to a fixture
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Write a large file with content from a seed
+    Add(&'static str, u8),
+    /// Overwrite a large file with content from another seed
+    Modify(&'static str, u8),
+    Rename(&'static str, &'static str),
+    Remove(&'static str),
+    Sync,
+    /// Sync that also takes remote changes from other fixtures
+    SyncTwoWay,
+    /// Sync that removes the stored copies of deleted files
+    SyncPrune,
+}
+
+/// A scripted sequence of changes and syncs
+#[derive(Debug, Default)]
+pub struct Scenario {
+    steps: Vec<Step>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Run the steps against `fixture` in order
+    pub async fn run(&self, fixture: &Fixture) -> Result<()> {
+        for step in &self.steps {
+            match *step {
+                Step::Add(path, seed) | Step::Modify(path, seed) => fixture.write_large(path, seed)?,
+                Step::Rename(from, to) => fixture.rename(from, to)?,
+                Step::Remove(path) => fixture.remove(path)?,
+                Step::Sync => fixture.sync().await?,
+                Step::SyncTwoWay => fixture.sync_two_way().await?,
+                Step::SyncPrune => fixture.sync_prune().await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::TrackerFormat;
+    use crate::config::{DeletionPolicy, SigningTool, StorageLayout, SymlinkPolicy};
+    use crate::download_cache::DownloadCache;
+    use crate::report::{FileState, GitignoreChange, PlanReason};
+    use crate::selection::PullFilter;
+
+    #[tokio::test]
+    async fn test_add_modify_rename_scenario() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .step(Step::Modify("level.bin", 3))
+            .step(Step::Rename("assets/hero.bin", "assets/hero-v2.bin"))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        fixture.assert_golden("add_modify_rename");
+    }
+
+    #[tokio::test]
+    async fn test_tracker_conflict_scenario() {
+        let ours = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("shared.bin", 1))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        let theirs = ours.fork().unwrap();
+        Scenario::new()
+            .step(Step::Modify("shared.bin", 2))
+            .step(Step::Add("theirs.bin", 3))
+            .step(Step::Sync)
+            .run(&theirs)
+            .await
+            .unwrap();
+        Scenario::new()
+            .step(Step::Add("ours.bin", 4))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        ours.conflict_trackers_with(&theirs).unwrap();
+        let mut ditrive = ours.ditrive_with_answers(&[("recover.modified", "2")]).unwrap();
+        ditrive.recover().await.unwrap();
+
+        // Their newer shared.bin won, and recover replaced ours with it
+        assert_eq!(ours.read("shared.bin").unwrap(), theirs.read("shared.bin").unwrap());
+        ours.assert_golden("tracker_conflict");
+    }
+
+    #[tokio::test]
+    async fn test_recover_keeps_entries_and_uploads_only_modified_files() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .step(Step::Modify("hero.bin", 3))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        // Storage loses hero.bin, level.bin is edited, and a new large file appears
+        assert!(fixture.store().remove("hero.bin"));
+        fixture.write_large("level.bin", 4).unwrap();
+        fixture.write_large("new.bin", 5).unwrap();
+
+        let mut ditrive = fixture.ditrive_with_answers(&[("recover.modified", "1")]).unwrap();
+        ditrive.recover().await.unwrap();
+
+        let tracker = tracker::open(fixture.path(), SymlinkPolicy::default());
+        let hero = tracker.get_file_info(fixture.path(), "hero.bin").unwrap().unwrap();
+        assert_eq!(hero.versions.len(), 1);
+        assert!(fixture.store().list().iter().any(|(id, _)| id == "hero.bin"));
+        let level = tracker.get_file_info(fixture.path(), "level.bin").unwrap().unwrap();
+        assert_eq!(level.hash, calculate_file_hash(&fixture.path().join("level.bin")).unwrap());
+        assert!(!tracker.is_managed(&fixture.path().join("new.bin")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_remote_change_conflict_scenario() {
+        let ours = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("shared.bin", 1))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        let theirs = ours.fork().unwrap();
+        Scenario::new()
+            .step(Step::Modify("shared.bin", 2))
+            .step(Step::Sync)
+            .run(&theirs)
+            .await
+            .unwrap();
+        let mine = ours.read("shared.bin").unwrap();
+        Scenario::new()
+            .step(Step::Modify("shared.bin", 3))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        // Non-interactive sync keeps both: their upload survives, ours is saved aside
+        assert_eq!(ours.read("shared.bin").unwrap(), theirs.read("shared.bin").unwrap());
+        assert_ne!(ours.read("shared.bin").unwrap(), mine);
+        ours.assert_golden("remote_change_conflict");
+    }
+
+    #[tokio::test]
+    async fn test_manifest_migration_scenario() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        fixture.ditrive().unwrap().migrate_tracker(TrackerFormat::Manifest).unwrap();
+        Scenario::new()
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Modify("assets/hero.bin", 3))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        // One manifest holds both files, and no .woilah is left behind
+        fixture.assert_golden("manifest_migration");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_tracker_migration_scenario() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        // Early versions stored just the file ID; one of the files is stored remotely only
+        fixture.write(".woilah", br#"{"level.bin": "level.bin"}"#).unwrap();
+        fixture.write("assets/.woilah", br#"{"hero.bin": "assets/hero.bin"}"#).unwrap();
+        fixture.remove("level.bin").unwrap();
+
+        fixture.ditrive().unwrap().migrate(true).await.unwrap();
+        fixture.assert_golden("legacy_tracker_migration");
+    }
+
+    /// A passphrase-less GPG key in a keyring under `home`, or None without gpg
+    #[cfg(unix)]
+    fn gpg_test_key(home: &Path) -> Option<String> {
+        let created = std::process::Command::new("gpg")
+            .env("GNUPGHOME", home)
+            .args(["--batch", "--passphrase", "", "--quick-gen-key", "Fixture <fixture@example.com>", "ed25519", "sign", "never"])
+            .output()
+            .ok()?;
+        if !created.status.success() {
+            return None;
+        }
+        let listed = std::process::Command::new("gpg")
+            .env("GNUPGHOME", home)
+            .args(["--with-colons", "--list-secret-keys"])
+            .output()
+            .ok()?;
+        String::from_utf8_lossy(&listed.stdout)
+            .lines()
+            .find_map(|line| line.strip_prefix("fpr:"))
+            .map(|rest| rest.trim_matches(':').to_string())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_migrate_signs_trackers_again_so_clones_can_pull() {
+        let home = tempfile::Builder::new()
+            .prefix("ditrive-gnupg")
+            .permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700))
+            .tempdir()
+            .unwrap();
+        let Some(fingerprint) = gpg_test_key(home.path()) else {
+            eprintln!("gpg is not available; skipping");
+            return;
+        };
+        // Only this test runs gpg, so pointing the process at its keyring is safe
+        std::env::set_var("GNUPGHOME", home.path());
+
+        let mut fixture = Fixture::new().unwrap();
+        fixture.global_config.settings.signing_key = fingerprint.clone();
+        let mut repo_config = RepoConfig::load(fixture.path()).unwrap();
+        repo_config.settings.signing = SigningTool::Gpg;
+        repo_config.settings.signing_public_key = fingerprint;
+        repo_config.save(fixture.path()).unwrap();
+
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        // Signed while the entries were still bare IDs, which `track` signs again
+        fixture.write(".woilah", br#"{"level.bin": "level.bin"}"#).unwrap();
+        fixture.write("assets/.woilah", br#"{"hero.bin": "assets/hero.bin"}"#).unwrap();
+        fixture.ditrive().unwrap().track(&["*.psd".to_string()]).unwrap();
+
+        fixture.ditrive().unwrap().migrate(false).await.unwrap();
+
+        let clone = fixture.fork().unwrap();
+        clone.remove("level.bin").unwrap();
+        clone.ditrive().unwrap().sync_missing_files(&PullFilter::default()).await.unwrap();
+        assert_eq!(clone.read("level.bin").unwrap(), fixture.read("level.bin").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_lfs_scenario() {
+        let fixture = Fixture::new().unwrap();
+        fixture
+            .write(".gitattributes", b"*.bin filter=lfs diff=lfs merge=lfs -text\n*.sh text eol=lf\n")
+            .unwrap();
+        // One file git-lfs already smudged, one still a pointer with its object in the local store
+        fixture.write_large("assets/hero.bin", 1).unwrap();
+        fixture.write_large("level.bin", 2).unwrap();
+        let level = fixture.read("level.bin").unwrap();
+        let oid = hex::encode(Sha256::digest(&level));
+        fixture.write(&format!(".git/lfs/objects/{}/{}/{}", &oid[..2], &oid[2..4], oid), &level).unwrap();
+        let pointer = format!("{}\noid sha256:{}\nsize {}\n", crate::lfs::LFS_POINTER_VERSION, oid, level.len());
+        fixture.write("level.bin", pointer.as_bytes()).unwrap();
+        GitManager::open(fixture.path()).unwrap().stage_files(&["assets/hero.bin", "level.bin"]).unwrap();
+        // A pointer git doesn't track was never in LFS and stays as it is
+        fixture.write("scratch/copy.bin", pointer.as_bytes()).unwrap();
+
+        fixture.ditrive().unwrap().import_lfs().await.unwrap();
+
+        assert_eq!(fixture.read("level.bin").unwrap(), level);
+        assert_eq!(fixture.read("scratch/copy.bin").unwrap(), pointer.as_bytes());
+        assert_eq!(fixture.read(".gitattributes").unwrap(), b"*.sh text eol=lf\n");
+        let index = GitManager::open(fixture.path()).unwrap().repository().index().unwrap();
+        assert!(index.get_path(Path::new("level.bin"), 0).is_none());
+        fixture.assert_golden("import_lfs");
+    }
+
+    #[tokio::test]
+    async fn test_export_lfs_scenario() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .step(Step::Remove("level.bin"))
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        fixture.ditrive().unwrap().export_lfs().await.unwrap();
+
+        // The missing copy came back, and both files now go through LFS instead
+        assert!(fixture.path().join("level.bin").exists());
+        assert_eq!(
+            fixture.read(".gitattributes").unwrap(),
+            b"assets/hero.bin filter=lfs diff=lfs merge=lfs -text\nlevel.bin filter=lfs diff=lfs merge=lfs -text\n"
+        );
+        fixture.assert_golden("export_lfs");
+    }
+
+    #[tokio::test]
+    async fn test_status_file_states_scenario() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("clean.bin", 1))
+            .step(Step::Add("edited.bin", 2))
+            .step(Step::Add("gone.bin", 3))
+            .step(Step::Add("lost.bin", 4))
+            .step(Step::Add("vanished.bin", 6))
+            .step(Step::Sync)
+            .step(Step::Modify("edited.bin", 5))
+            .step(Step::Remove("gone.bin"))
+            .step(Step::Remove("vanished.bin"))
+            .run(&fixture)
+            .await
+            .unwrap();
+        assert!(fixture.store().remove("lost.bin"));
+        assert!(fixture.store().remove("vanished.bin"));
+
+        let backend = MemoryBackend::new(fixture.store().clone());
+        let ditrive = fixture.ditrive().unwrap();
+        let states: Vec<(String, FileState)> = ditrive
+            .file_states(Some(&backend))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.path, file.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("clean.bin".to_string(), FileState::UpToDate),
+                ("edited.bin".to_string(), FileState::Modified),
+                ("gone.bin".to_string(), FileState::MissingLocally),
+                ("lost.bin".to_string(), FileState::MissingRemotely),
+                ("vanished.bin".to_string(), FileState::MissingEverywhere),
+            ]
+        );
+
+        // Without a backend, remote copies are assumed present
+        let states = ditrive.file_states(None).await.unwrap();
+        assert_eq!(states[3].state, FileState::UpToDate);
+    }
+
+    #[tokio::test]
+    async fn test_diff_plan_scenario() {
+        let ours = Fixture::new().unwrap();
+        let mut config = RepoConfig::load(ours.path()).unwrap();
+        config.settings.deletion_policy = DeletionPolicy::Trash;
+        config.save(ours.path()).unwrap();
+        Scenario::new()
+            .step(Step::Add("clean.bin", 1))
+            .step(Step::Add("edited.bin", 2))
+            .step(Step::Add("deleted.bin", 3))
+            .step(Step::Add("stale.bin", 4))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        let theirs = ours.fork().unwrap();
+        Scenario::new()
+            .step(Step::Modify("stale.bin", 5))
+            .step(Step::Add("theirs.bin", 6))
+            .step(Step::Sync)
+            .run(&theirs)
+            .await
+            .unwrap();
+        Scenario::new()
+            .step(Step::Modify("edited.bin", 7))
+            .step(Step::Remove("deleted.bin"))
+            .step(Step::Add("new.bin", 8))
+            .run(&ours)
+            .await
+            .unwrap();
+        let before = ours.snapshot().unwrap();
+
+        let backend = MemoryBackend::new(ours.store().clone());
+        let plan = ours.ditrive().unwrap().plan(Some(&backend), true).await.unwrap();
+        fn summary(files: &[crate::report::PlannedFile]) -> Vec<(&str, PlanReason)> {
+            files.iter().map(|f| (f.path.as_str(), f.reason)).collect()
+        }
+        assert_eq!(
+            summary(&plan.upload),
+            vec![("edited.bin", PlanReason::Modified), ("new.bin", PlanReason::New)]
+        );
+        assert_eq!(summary(&plan.download), vec![("stale.bin", PlanReason::OutOfDate)]);
+        assert_eq!(summary(&plan.remove), vec![("deleted.bin", PlanReason::Deleted)]);
+        assert_eq!(summary(&plan.orphaned), vec![("theirs.bin", PlanReason::Untracked)]);
+        let edits: Vec<_> = plan.gitignore.iter().map(|e| (e.change, e.pattern.as_str())).collect();
+        assert_eq!(
+            edits,
+            vec![(GitignoreChange::Remove, "deleted.bin"), (GitignoreChange::Add, "new.bin")]
+        );
+
+        // Planning is read-only
+        assert_eq!(ours.snapshot().unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn test_deletion_policy_scenario() {
+        let fixture = Fixture::new().unwrap();
+        let mut config = RepoConfig::load(fixture.path()).unwrap();
+        config.settings.deletion_policy = DeletionPolicy::Delete;
+        config.save(fixture.path()).unwrap();
+        Scenario::new()
+            .step(Step::Add("kept.bin", 1))
+            .step(Step::Add("deleted.bin", 2))
+            .step(Step::Add("removed.bin", 3))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+
+        // A clone that never had the file downloads it rather than deleting it
+        let clone = fixture.fork().unwrap();
+        clone.remove("kept.bin").unwrap();
+        clone.sync().await.unwrap();
+        assert!(clone.path().join("kept.bin").exists());
+
+        // A plain sync treats the deletion as a mistake and restores the file
+        let stored = fixture.store().list().len();
+        fixture.remove("deleted.bin").unwrap();
+        fixture.sync().await.unwrap();
+        assert!(fixture.path().join("deleted.bin").exists());
+        assert_eq!(fixture.store().list().len(), stored);
+
+        fixture.remove("deleted.bin").unwrap();
+        fixture.sync_prune().await.unwrap();
+        fixture.ditrive().unwrap().rm(&[PathBuf::from("removed.bin")]).await.unwrap();
+
+        assert!(!fixture.path().join("deleted.bin").exists());
+        assert!(!fixture.path().join("removed.bin").exists());
+        fixture.assert_golden("deletion_policy");
+    }
+
+    #[tokio::test]
+    async fn test_flat_layout_keeps_objects_older_versions_refer_to() {
+        let fixture = Fixture::new().unwrap();
+        let mut config = RepoConfig::load(fixture.path()).unwrap();
+        config.settings.deletion_policy = DeletionPolicy::Delete;
+        config.settings.storage_layout = StorageLayout::Flat;
+        config.save(fixture.path()).unwrap();
+
+        // a.bin's first version has the content b.bin holds now
+        fixture.write_large("a.bin", 1).unwrap();
+        fixture.sync().await.unwrap();
+        fixture.write_large("a.bin", 2).unwrap();
+        fixture.sync().await.unwrap();
+        fixture.write_large("b.bin", 1).unwrap();
+        fixture.sync().await.unwrap();
+        assert_eq!(fixture.store().list().len(), 2);
+
+        fixture.ditrive().unwrap().rm(&[PathBuf::from("b.bin")]).await.unwrap();
+        assert_eq!(fixture.store().list().len(), 2);
+
+        // Once nothing refers to that content, removing a.bin reclaims it too
+        fixture.ditrive().unwrap().rm(&[PathBuf::from("a.bin")]).await.unwrap();
+        assert!(fixture.store().list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_trash_scenario() {
+        let fixture = Fixture::new().unwrap();
+        let mut config = RepoConfig::load(fixture.path()).unwrap();
+        config.settings.deletion_policy = DeletionPolicy::Trash;
+        config.save(fixture.path()).unwrap();
+        Scenario::new()
+            .step(Step::Add("art/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .step(Step::Remove("level.bin"))
+            .step(Step::SyncPrune)
+            .run(&fixture)
+            .await
+            .unwrap();
+        let hero = fixture.read("art/hero.bin").unwrap();
+        fixture.ditrive().unwrap().rm(&[PathBuf::from("art/hero.bin")]).await.unwrap();
+        assert_eq!(crate::trash::load(fixture.path()).unwrap().len(), 2);
+
+        // Trashed copies aren't remote additions for other clones
+        let clone = fixture.fork().unwrap();
+        clone.sync_two_way().await.unwrap();
+        assert!(!clone.path().join(".trash").exists());
+
+        let mut ditrive = fixture.ditrive().unwrap();
+        ditrive.trash_restore(Path::new("art/hero.bin")).await.unwrap();
+        assert_eq!(fixture.read("art/hero.bin").unwrap(), hero);
+        // level.bin was trashed just now, well within the retention period
+        ditrive.trash_empty(false).await.unwrap();
+        assert_eq!(crate::trash::load(fixture.path()).unwrap().len(), 1);
+        fixture.assert_golden("trash_before_empty");
+
+        ditrive.trash_empty(true).await.unwrap();
+        assert!(crate::trash::load(fixture.path()).unwrap().is_empty());
+        assert_eq!(fixture.store().list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_track_pattern_scenario() {
+        let fixture = Fixture::new().unwrap();
+        fixture.write("art/hero.psd", b"small but tracked").unwrap();
+        fixture.write("notes.txt", b"small and left to git").unwrap();
+
+        fixture.ditrive().unwrap().track(&["*.psd".to_string()]).unwrap();
+        fixture.sync().await.unwrap();
+
+        fixture.assert_golden("track_pattern");
+    }
+
+    #[tokio::test]
+    async fn test_download_cache_shared_between_checkouts() {
+        let ours = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/level.bin", 1))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(cache_dir.path().to_path_buf(), 100);
+
+        let first = ours.fork().unwrap();
+        first.remove("assets/level.bin").unwrap();
+        let ditrive = first.ditrive().unwrap().with_download_cache(cache.clone());
+        ditrive.sync_missing_files(&ditrive.pull_filter(&[], Vec::new(), Vec::new())).await.unwrap();
+        assert_eq!(cache.stats().unwrap().files, 1);
+
+        // The second checkout never reaches the backend, which has since lost the file
+        assert!(ours.store().remove("assets/level.bin"));
+        let second = ours.fork().unwrap();
+        second.remove("assets/level.bin").unwrap();
+        let ditrive = second.ditrive().unwrap().with_download_cache(cache);
+        ditrive.sync_missing_files(&ditrive.pull_filter(&[], Vec::new(), Vec::new())).await.unwrap();
+        assert_eq!(second.read("assets/level.bin").unwrap(), ours.read("assets/level.bin").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ls_remote_matches_stored_files_to_trackers() {
+        let fixture = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/hero.bin", 1))
+            .step(Step::Add("level.bin", 2))
+            .step(Step::Sync)
+            .run(&fixture)
+            .await
+            .unwrap();
+        assert!(fixture.store().remove("level.bin"));
+        fixture.write("stray.bin", b"left behind").unwrap();
+        fixture.store().put("stray.bin", &fixture.path().join("stray.bin")).unwrap();
+
+        let listing = fixture.ditrive().unwrap().remote_listing().await.unwrap();
+        let files: Vec<(&str, Vec<String>)> =
+            listing.files.iter().map(|file| (file.path.as_str(), file.tracked_as.clone())).collect();
+        assert_eq!(
+            files,
+            [("assets/hero.bin", vec!["assets/hero.bin".to_string()]), ("stray.bin", Vec::new())]
+        );
+        assert_eq!(listing.missing, ["level.bin"]);
+    }
+
+    #[tokio::test]
+    async fn test_adopt_tracks_stored_files_by_path() {
+        let fixture = Fixture::new().unwrap();
+        for (rel_path, content) in [("assets/hero.bin", "hero"), ("level.bin", "level"), ("notes.bin", "notes")] {
+            fixture.write(rel_path, content.as_bytes()).unwrap();
+            fixture.store().put(rel_path, &fixture.path().join(rel_path)).unwrap();
+        }
+        fixture.remove("level.bin").unwrap();
+        fixture.write("notes.bin", b"edited locally").unwrap();
+
+        let mut ditrive = fixture.ditrive().unwrap();
+        ditrive.adopt(false).await.unwrap();
+
+        // The missing file came down; the edited one is left for the user to sort out
+        assert_eq!(fixture.read("level.bin").unwrap(), b"level");
+        assert_eq!(fixture.read("notes.bin").unwrap(), b"edited locally");
+        assert!(ditrive.is_managed(&fixture.path().join("assets/hero.bin")).unwrap());
+        assert!(ditrive.is_managed(&fixture.path().join("level.bin")).unwrap());
+        assert!(!ditrive.is_managed(&fixture.path().join("notes.bin")).unwrap());
+        let gitignore = String::from_utf8(fixture.read(".gitignore").unwrap()).unwrap();
+        assert!(gitignore.contains("hero.bin") && gitignore.contains("level.bin"));
+        assert!(!gitignore.contains("notes.bin"));
+    }
+
+    #[tokio::test]
+    async fn test_adopt_compares_content_when_the_backend_has_no_hashes() {
+        let fixture = Fixture::new().unwrap();
+        fixture.store().hide_hashes();
+        for (rel_path, content) in [("same.bin", "same"), ("edited.bin", "old!")] {
+            fixture.write(rel_path, content.as_bytes()).unwrap();
+            fixture.store().put(rel_path, &fixture.path().join(rel_path)).unwrap();
+        }
+        fixture.write("edited.bin", b"new!").unwrap();
+
+        let mut ditrive = fixture.ditrive().unwrap();
+        ditrive.adopt(false).await.unwrap();
+
+        // Equal sizes aren't enough; only the file whose content matches is adopted
+        assert!(ditrive.is_managed(&fixture.path().join("same.bin")).unwrap());
+        assert!(!ditrive.is_managed(&fixture.path().join("edited.bin")).unwrap());
+        assert_eq!(fixture.read("edited.bin").unwrap(), b"new!");
+    }
+
+    #[tokio::test]
+    async fn test_selective_pull_and_hydrate_scenario() {
+        let ours = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("assets/levels/one.bin", 1))
+            .step(Step::Add("assets/levels/old/two.bin", 2))
+            .step(Step::Add("videos/intro.bin", 3))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        let theirs = ours.fork().unwrap();
+        for rel_path in ["assets/levels/one.bin", "assets/levels/old/two.bin", "videos/intro.bin"] {
+            theirs.remove(rel_path).unwrap();
+        }
+
+        let ditrive = theirs.ditrive().unwrap();
+        let filter = ditrive.pull_filter(
+            &[PathBuf::from("assets")],
+            vec!["*.bin".to_string()],
+            vec!["/assets/levels/old/**".to_string()],
+        );
+        ditrive.sync_missing_files(&filter).await.unwrap();
+
+        // Only the selected file came down; the others are stood in for
+        assert_eq!(theirs.read("assets/levels/one.bin").unwrap(), ours.read("assets/levels/one.bin").unwrap());
+        assert!(!theirs.path().join("videos/intro.bin").exists());
+        let placeholder = theirs.read("videos/intro.bin.ditrive").unwrap();
+        assert!(String::from_utf8(placeholder).unwrap().contains(&format!("size {}\n", LARGE_FILE_SIZE)));
+        assert!(theirs.path().join("assets/levels/old/two.bin.ditrive").exists());
+
+        ditrive.hydrate(&[PathBuf::from("videos/intro.bin.ditrive")]).await.unwrap();
+        assert_eq!(theirs.read("videos/intro.bin").unwrap(), ours.read("videos/intro.bin").unwrap());
+        assert!(!theirs.path().join("videos/intro.bin.ditrive").exists());
+        assert!(theirs.path().join("assets/levels/old/two.bin.ditrive").exists());
+
+        // Removing a file that was never downloaded takes its placeholder and exclude line with it
+        theirs.ditrive().unwrap().rm(&[PathBuf::from("assets/levels/old/two.bin")]).await.unwrap();
+        assert!(!theirs.path().join("assets/levels/old/two.bin.ditrive").exists());
+        let exclude = String::from_utf8(theirs.read(".git/info/exclude").unwrap()).unwrap();
+        assert!(!exclude.contains("two.bin.ditrive"));
+    }
+
+    #[tokio::test]
+    async fn test_targeted_and_forced_push_scenario() {
+        let fixture = Fixture::new().unwrap();
+        fixture.write_large("art/hero.bin", 1).unwrap();
+        fixture.write_large("level.bin", 2).unwrap();
+
+        let mut ditrive = fixture.ditrive().unwrap();
+        ditrive.push(&[PathBuf::from("art")], false, false).await.unwrap();
+        let stored: Vec<String> = fixture.store().list().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(stored, vec!["art/hero.bin".to_string()]);
+
+        // A stored copy lost behind the tracker's back only comes back with --force
+        assert!(fixture.store().remove("art/hero.bin"));
+        ditrive.push(&[PathBuf::from("art/hero.bin")], false, false).await.unwrap();
+        assert!(fixture.store().list().is_empty());
+        ditrive.push(&[PathBuf::from("art/hero.bin")], true, false).await.unwrap();
+        let stored: Vec<String> = fixture.store().list().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(stored, vec!["art/hero.bin".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_commit_scenario() {
+        let fixture = Fixture::new().unwrap();
+        fixture.write_large("assets/big.bin", 1).unwrap();
+        fixture.write("notes.txt", b"not ditrive's to commit").unwrap();
+
+        fixture.ditrive().unwrap().sync(false, false, false, false, true).await.unwrap();
+
+        let git = GitManager::open(fixture.path()).unwrap();
+        let head = git.repository().head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("ditrive: manage assets/big.bin"));
+        let tree = head.tree().unwrap();
+        assert!(tree.get_path(Path::new("assets/.woilah")).is_ok());
+        assert!(tree.get_path(Path::new(".gitignore")).is_ok());
+        assert!(tree.get_path(Path::new("notes.txt")).is_err());
+        assert!(tree.get_path(Path::new("assets/big.bin")).is_err());
+
+        // A sync that changes nothing makes no commit
+        fixture.ditrive().unwrap().sync(false, false, false, false, true).await.unwrap();
+        assert_eq!(git.head_commit(), Some(head.id().to_string()));
+    }
+
+    #[test]
+    fn test_check_rejects_staged_large_files() {
+        let fixture = Fixture::new().unwrap();
+        let git = GitManager::open(fixture.path()).unwrap();
+        fixture.write("notes.txt", b"small").unwrap();
+        git.stage_files(&["notes.txt"]).unwrap();
+        fixture.ditrive().unwrap().check().unwrap();
+
+        fixture.write_large("assets/big.bin", 1).unwrap();
+        git.stage_files(&["assets/big.bin"]).unwrap();
+        let error = fixture.ditrive().unwrap().check().unwrap_err();
+        assert!(matches!(error, DitriveError::CheckFailed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_two_way_sync_scenario() {
+        let ours = Fixture::new().unwrap();
+        Scenario::new()
+            .step(Step::Add("shared.bin", 1))
+            .step(Step::Add("mine.bin", 2))
+            .step(Step::Sync)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        let theirs = ours.fork().unwrap();
+        Scenario::new()
+            .step(Step::Modify("shared.bin", 3))
+            .step(Step::Add("assets/theirs.bin", 4))
+            .step(Step::Sync)
+            .run(&theirs)
+            .await
+            .unwrap();
+        Scenario::new()
+            .step(Step::Modify("mine.bin", 5))
+            .step(Step::SyncTwoWay)
+            .run(&ours)
+            .await
+            .unwrap();
+
+        // Their update and their new file came down; our own edit still went up
+        assert_eq!(ours.read("shared.bin").unwrap(), theirs.read("shared.bin").unwrap());
+        assert_eq!(ours.read("assets/theirs.bin").unwrap(), theirs.read("assets/theirs.bin").unwrap());
+        ours.assert_golden("two_way_sync");
+    }
+
+    #[tokio::test]
+    async fn test_two_way_sync_leaves_untracked_copies_alone() {
+        let fixture = Fixture::new().unwrap();
+        let mut config = RepoConfig::load(fixture.path()).unwrap();
+        config.settings.deletion_policy = DeletionPolicy::Keep;
+        config.save(fixture.path()).unwrap();
+        fixture.write_large("old.bin", 1).unwrap();
+        fixture.sync().await.unwrap();
+
+        // The stored copy stays, but the file was removed on purpose
+        fixture.ditrive().unwrap().rm(&[PathBuf::from("old.bin")]).await.unwrap();
+        assert_eq!(fixture.store().list().len(), 1);
+        fixture.sync_two_way().await.unwrap();
+        assert!(!fixture.path().join("old.bin").exists());
+    }
+}
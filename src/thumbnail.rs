@@ -0,0 +1,116 @@
+//! Thumbnail sidecars for image and video assets
+//!
+//! Thumbnails are rendered with `ffmpeg`, which handles both still images and
+//! video frames. It is optional: when it is not installed, thumbnail
+//! generation is skipped with a warning and the upload proceeds as usual.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::debug;
+
+use crate::error::{DitriveError, Result};
+
+/// Directory (relative to the repository root) holding committed thumbnails
+pub const THUMBNAIL_DIR: &str = ".thumbnails";
+
+/// Longest edge of a generated thumbnail, in pixels
+const MAX_EDGE: u32 = 256;
+
+/// Check if a file is an image or video that thumbnails can be made for
+pub fn supports(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    matches!(
+        extension.as_str(),
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "tif" | "tiff" | "webp"
+            | "psd" | "exr" | "tga"
+            | "mp4" | "m4v" | "mov" | "mkv" | "avi" | "webm"
+    )
+}
+
+/// Path of the thumbnail for a managed file.
+///
+/// The managed file's relative path is mirrored under `base`, with `.jpg`
+/// appended so `assets/hero.png` becomes `<base>/assets/hero.png.jpg`.
+pub fn thumbnail_path(base: &Path, repo_path: &Path, file_path: &Path) -> PathBuf {
+    let rel_path = file_path.strip_prefix(repo_path).unwrap_or(file_path);
+    let mut name = rel_path.as_os_str().to_os_string();
+    name.push(".jpg");
+    base.join(name)
+}
+
+/// Where thumbnails are committed within the repository
+pub fn commit_dir(repo_path: &Path) -> PathBuf {
+    repo_path.join(THUMBNAIL_DIR)
+}
+
+/// Where upload-only thumbnails are staged, inside the repository's git directory.
+///
+/// Files here are uploaded relative to this directory, so they land in a
+/// `thumbnails` subfolder of the remote repository folder.
+pub fn staging_root(git_dir: &Path) -> PathBuf {
+    git_dir.join("ditrive")
+}
+
+/// Render a thumbnail of `source` into `destination` as a JPEG
+pub fn generate(source: &Path, destination: &Path) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // `thumbnail` picks a representative frame for videos and is a no-op for
+    // stills; the scale keeps the aspect ratio and never upscales
+    let filter = format!(
+        "thumbnail,scale='min({0},iw)':'min({0},ih)':force_original_aspect_ratio=decrease",
+        MAX_EDGE
+    );
+
+    debug!("Generating thumbnail for {:?}", source);
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(source)
+        .args(["-vf", &filter, "-frames:v", "1"])
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| DitriveError::Config(format!("Failed to run ffmpeg: {}", e)))?;
+
+    if !status.success() {
+        return Err(DitriveError::Config(format!(
+            "ffmpeg could not create a thumbnail for {:?}",
+            source
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports_images_and_videos() {
+        assert!(supports(Path::new("art/hero.PNG")));
+        assert!(supports(Path::new("clips/intro.mov")));
+        assert!(!supports(Path::new("data/dump.bin")));
+        assert!(!supports(Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_thumbnail_path_mirrors_repo_layout() {
+        let repo = Path::new("/work/game");
+        let file = repo.join("assets").join("hero.png");
+
+        let committed = thumbnail_path(&commit_dir(repo), repo, &file);
+        assert_eq!(committed, repo.join(".thumbnails/assets/hero.png.jpg"));
+
+        let staged = thumbnail_path(&staging_root(&repo.join(".git")).join("thumbnails"), repo, &file);
+        assert_eq!(staged, repo.join(".git/ditrive/thumbnails/assets/hero.png.jpg"));
+    }
+}
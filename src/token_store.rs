@@ -0,0 +1,185 @@
+//! Protection for OAuth token files at rest
+//!
+//! Token files are always written readable by the owner only. With
+//! `token_encryption` set in the global settings they are also sealed with
+//! AES-256-GCM, keyed either to this machine or to a passphrase from
+//! `DITRIVE_TOKEN_PASSPHRASE`. Reading detects the format, so plaintext
+//! files from before encryption was enabled keep working and are sealed the
+//! next time they are saved.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::TokenEncryption;
+use crate::error::{DitriveError, Result};
+
+/// Environment variable holding the passphrase for `passphrase` encryption
+pub const PASSPHRASE_ENV: &str = "DITRIVE_TOKEN_PASSPHRASE";
+
+/// Marks a token file as sealed by this module
+const ENVELOPE_FORMAT: &str = "ditrive-sealed-v1";
+
+const SALT_LEN: usize = 16;
+
+static ENCRYPTION: OnceLock<TokenEncryption> = OnceLock::new();
+
+/// Set how token files are written for the rest of the process
+pub fn init(encryption: TokenEncryption) {
+    let _ = ENCRYPTION.set(encryption);
+}
+
+fn encryption() -> TokenEncryption {
+    ENCRYPTION.get().copied().unwrap_or_default()
+}
+
+/// A sealed token file
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    key: TokenEncryption,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Read a token file, unsealing it if needed
+pub fn read(path: &Path) -> Result<String> {
+    let content = fs::read_to_string(path)?;
+    match serde_json::from_str::<Envelope>(&content) {
+        Ok(envelope) if envelope.format == ENVELOPE_FORMAT => open(&envelope),
+        _ => Ok(content),
+    }
+}
+
+/// Write a token file readable only by its owner, sealed if encryption is on
+pub fn write(path: &Path, content: &str) -> Result<()> {
+    let content = match encryption() {
+        TokenEncryption::Off => content.to_string(),
+        key => serde_json::to_string_pretty(&seal(content, key)?)?,
+    };
+    write_private(path, content.as_bytes())
+}
+
+/// Write a file with 0600 permissions where the platform has them
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to new files, so tighten existing ones too
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(content)?;
+    Ok(())
+}
+
+fn seal(plaintext: &str, key: TokenEncryption) -> Result<Envelope> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = cipher(key, &salt)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| DitriveError::Auth("Failed to encrypt token file".to_string()))?;
+
+    Ok(Envelope {
+        format: ENVELOPE_FORMAT.to_string(),
+        key,
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+fn open(envelope: &Envelope) -> Result<String> {
+    let decode = |field: &str| {
+        STANDARD
+            .decode(field)
+            .map_err(|e| DitriveError::Auth(format!("Corrupt token file: {}", e)))
+    };
+    let salt = decode(&envelope.salt)?;
+    let nonce = decode(&envelope.nonce)?;
+    if nonce.len() != 12 {
+        return Err(DitriveError::Auth("Corrupt token file: bad nonce".to_string()));
+    }
+
+    let plaintext = cipher(envelope.key, &salt)?
+        .decrypt(Nonce::from_slice(&nonce), decode(&envelope.ciphertext)?.as_ref())
+        .map_err(|_| {
+            DitriveError::Auth(match envelope.key {
+                TokenEncryption::Passphrase => format!("Could not decrypt tokens; check {}", PASSPHRASE_ENV),
+                _ => "Could not decrypt tokens; they were sealed on another machine. Run 'ditrive login' again.".to_string(),
+            })
+        })?;
+
+    String::from_utf8(plaintext).map_err(|e| DitriveError::Auth(format!("Corrupt token file: {}", e)))
+}
+
+/// Cipher for a key source and salt
+fn cipher(key: TokenEncryption, salt: &[u8]) -> Result<Aes256Gcm> {
+    let secret = match key {
+        TokenEncryption::Machine => machine_secret(),
+        TokenEncryption::Passphrase => std::env::var(PASSPHRASE_ENV)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| DitriveError::Auth(format!("Token encryption needs a passphrase in {}", PASSPHRASE_ENV)))?
+            .into_bytes(),
+        TokenEncryption::Off => return Err(DitriveError::Auth("Token encryption is off".to_string())),
+    };
+
+    let mut key_bytes = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(&secret, salt, &mut key_bytes)
+        .map_err(|e| DitriveError::Auth(format!("Failed to derive token key: {}", e)))?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes)))
+}
+
+/// Material identifying this machine and user, for machine-bound keys
+fn machine_secret() -> Vec<u8> {
+    let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+    let home = dirs::home_dir().unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"ditrive token store\0");
+    hasher.update(machine_id.trim().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(home.to_string_lossy().as_bytes());
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_tokens_round_trip() {
+        let envelope = seal(r#"{"access_token":"secret"}"#, TokenEncryption::Machine).unwrap();
+        assert!(!envelope.ciphertext.contains("secret"));
+        assert_eq!(open(&envelope).unwrap(), r#"{"access_token":"secret"}"#);
+
+        let mut tampered = envelope;
+        tampered.ciphertext = STANDARD.encode(b"not the ciphertext at all");
+        assert!(open(&tampered).is_err());
+    }
+}
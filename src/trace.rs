@@ -0,0 +1,89 @@
+//! API-call tracing and per-phase timing
+//!
+//! Events are emitted at TRACE level under the `ditrive::api` and
+//! `ditrive::timing` targets, and only reach the trace file enabled by `--trace-api`.
+
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use std::time::Instant;
+use tracing::trace;
+
+/// Target for per-request API events
+pub const API_TARGET: &str = "ditrive::api";
+
+/// Target for per-phase timing events
+pub const TIMING_TARGET: &str = "ditrive::timing";
+
+/// Extension for sending requests with API-call tracing
+#[async_trait]
+pub trait TracedSend {
+    /// Send the request, recording method, endpoint, status and duration
+    async fn send_traced(self) -> reqwest::Result<Response>;
+}
+
+#[async_trait]
+impl TracedSend for RequestBuilder {
+    async fn send_traced(self) -> reqwest::Result<Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let method = request.method().clone();
+        let endpoint = format!(
+            "{}{}",
+            request.url().host_str().unwrap_or_default(),
+            request.url().path()
+        );
+
+        let start = Instant::now();
+        let result = client.execute(request).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        match &result {
+            Ok(response) => trace!(
+                target: API_TARGET,
+                %method,
+                %endpoint,
+                status = response.status().as_u16(),
+                duration_ms,
+                "api call"
+            ),
+            Err(e) => trace!(
+                target: API_TARGET,
+                %method,
+                %endpoint,
+                error = %e,
+                duration_ms,
+                "api call failed"
+            ),
+        }
+
+        result
+    }
+}
+
+/// Records the duration of a phase when dropped
+pub struct PhaseTimer {
+    phase: &'static str,
+    detail: String,
+    start: Instant,
+}
+
+/// Start timing a named phase (scan, hash, upload, tracker write, ...)
+pub fn phase(phase: &'static str, detail: impl Into<String>) -> PhaseTimer {
+    PhaseTimer {
+        phase,
+        detail: detail.into(),
+        start: Instant::now(),
+    }
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        trace!(
+            target: TIMING_TARGET,
+            phase = self.phase,
+            detail = %self.detail,
+            duration_ms = self.start.elapsed().as_millis() as u64,
+            "phase complete"
+        );
+    }
+}
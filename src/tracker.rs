@@ -1,18 +1,163 @@
-//! Woilah tracker for managing file mappings to Google Drive
-
-use std::collections::HashMap;
+//! Trackers recording which files are managed and where their content lives
+//!
+//! By default every folder with managed files gets a `.woilah` file listing
+//! them by name. A repository can instead keep a single `.ditrive/manifest.json`
+//! keyed by repository-relative path, which keeps the tree clean and puts every
+//! change in one file. Both hold the same JSON map of key to metadata, and
+//! [`open`] picks the manifest once it exists, `.woilah` files otherwise.
+
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use tracing::{debug, warn};
 
-use crate::drive::{calculate_file_hash, FileMetadata};
+use crate::config::SymlinkPolicy;
+use crate::drive::{calculate_file_hash, FileMetadata, FileVersion};
 use crate::error::Result;
+use crate::paths;
+use crate::symlink;
+use crate::trace;
+
+pub const WOILAH_FILENAME: &str = ".woilah";
+
+/// Location of the single-file manifest, relative to the repository root
+pub const MANIFEST_PATH: &str = ".ditrive/manifest.json";
+
+/// Records managed files, addressed by the folder they are in and their name
+pub trait Tracker: Send + Sync {
+    /// Add or replace a file mapping
+    fn add_file_mapping(&self, folder_path: &Path, filename: &str, metadata: FileMetadata) -> Result<()>;
+
+    /// Remove a file mapping, if there is one
+    fn remove_file_mapping(&self, folder_path: &Path, filename: &str) -> Result<()>;
+
+    /// Get the file metadata for a local file
+    fn get_file_info(&self, folder_path: &Path, filename: &str) -> Result<Option<FileMetadata>>;
+
+    /// Get all managed files in the repository
+    fn get_all_managed_files(&self) -> Result<Vec<(PathBuf, FileMetadata)>>;
+
+    /// Replace a file mapping after a re-upload, moving the previous entry into its version history
+    fn add_file_version(&self, folder_path: &Path, filename: &str, mut metadata: FileMetadata) -> Result<()> {
+        if let Some(previous) = self.get_file_info(folder_path, filename)? {
+            metadata.versions = previous.versions.clone();
+            metadata.versions.push(FileVersion::from(&previous));
+        }
+        self.add_file_mapping(folder_path, filename, metadata)
+    }
+
+    /// Get the Drive file ID for a local file
+    fn get_file_id(&self, folder_path: &Path, filename: &str) -> Result<Option<String>> {
+        Ok(self.get_file_info(folder_path, filename)?.map(|m| m.id))
+    }
 
-const WOILAH_FILENAME: &str = ".woilah";
+    /// Check if a file is managed by woilah
+    fn is_managed(&self, file_path: &Path) -> Result<bool> {
+        let (folder_path, filename) = split_path(file_path);
+        Ok(self.get_file_id(folder_path, &filename)?.is_some())
+    }
+
+    /// Check if a file needs to be re-uploaded (hash changed)
+    fn file_needs_update(&self, file_path: &Path) -> Result<bool> {
+        let (folder_path, filename) = split_path(file_path);
+        match self.get_file_info(folder_path, &filename)? {
+            Some(metadata) if !metadata.hash.is_empty() => {
+                let current_hash = calculate_file_hash(file_path)?;
+                Ok(current_hash != metadata.hash)
+            }
+            _ => Ok(true), // File not tracked or no hash stored
+        }
+    }
+}
+
+/// The tracker a repository uses: its manifest if it has one, `.woilah` files otherwise
+pub fn open(repo_path: &Path, symlinks: SymlinkPolicy) -> Box<dyn Tracker> {
+    if repo_path.join(MANIFEST_PATH).is_file() {
+        Box::new(ManifestTracker::new(repo_path).with_symlinks(symlinks))
+    } else {
+        Box::new(WoilahTracker::new(repo_path).with_symlinks(symlinks))
+    }
+}
+
+/// Check if a path is a tracker file of either kind
+pub fn is_tracker_file(repo_path: &Path, path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == WOILAH_FILENAME) || path == repo_path.join(MANIFEST_PATH)
+}
+
+/// Folder the keys of a tracker file are relative to
+pub fn entry_base(repo_path: &Path, tracker_path: &Path) -> PathBuf {
+    if tracker_path == repo_path.join(MANIFEST_PATH) {
+        repo_path.to_path_buf()
+    } else {
+        tracker_path.parent().unwrap_or(repo_path).to_path_buf()
+    }
+}
+
+/// Write a tracker file with its keys sorted and one entry per block, so
+/// unrelated changes don't collide in merges and diffs show only what changed
+pub fn write_tracker_file(tracker_path: &Path, mappings: &HashMap<String, FileMetadata>) -> Result<()> {
+    let _timer = trace::phase("tracker write", tracker_path.display().to_string());
+    if let Some(parent) = tracker_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let sorted: BTreeMap<_, _> = mappings.iter().collect();
+    write_atomically(tracker_path, (serde_json::to_string_pretty(&sorted)? + "\n").as_bytes())?;
+    debug!("Updated tracker file at {:?}", tracker_path);
+    Ok(())
+}
+
+/// Write a file through a temporary file next to it and a rename, so a
+/// crash leaves either the old content or the new, never a truncated file
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let path = &paths::long_path(path);
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    sync_parent_dir(path)
+}
+
+/// Flush a rename into the parent directory so it survives a crash too;
+/// Windows has no way to open a directory for this and commits renames itself
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// A file's folder and name, as trackers address it
+fn split_path(file_path: &Path) -> (&Path, String) {
+    let folder_path = file_path.parent().unwrap_or(file_path);
+    let filename = file_path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (folder_path, filename)
+}
 
 /// Manages .woilah files for tracking Drive file mappings
 pub struct WoilahTracker {
     repo_path: PathBuf,
+    symlinks: SymlinkPolicy,
 }
 
 impl WoilahTracker {
@@ -20,9 +165,16 @@ impl WoilahTracker {
     pub fn new(repo_path: &Path) -> Self {
         Self {
             repo_path: repo_path.to_path_buf(),
+            symlinks: SymlinkPolicy::default(),
         }
     }
 
+    /// Find `.woilah` files and managed files under a symlink policy other than `skip`
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
     /// Get the path to the .woilah file in a specific folder
     fn woilah_path(&self, folder_path: &Path) -> PathBuf {
         folder_path.join(WOILAH_FILENAME)
@@ -30,16 +182,20 @@ impl WoilahTracker {
 
     /// Read the .woilah file in a folder
     pub fn read_woilah_file(&self, folder_path: &Path) -> Result<HashMap<String, FileMetadata>> {
-        let woilah_path = self.woilah_path(folder_path);
+        let woilah_path = paths::long_path(&self.woilah_path(folder_path));
 
         if !woilah_path.exists() {
             return Ok(HashMap::new());
         }
 
         let content = fs::read_to_string(&woilah_path)?;
+        Ok(Self::parse_woilah(&content, &woilah_path))
+    }
 
+    /// Parse the contents of a .woilah file; `origin` names it in warnings
+    pub fn parse_woilah(content: &str, origin: &Path) -> HashMap<String, FileMetadata> {
         // Handle both old format (string values) and new format (metadata objects)
-        match serde_json::from_str::<HashMap<String, serde_json::Value>>(&content) {
+        match serde_json::from_str::<HashMap<String, serde_json::Value>>(content) {
             Ok(raw_data) => {
                 let mut result = HashMap::new();
 
@@ -48,28 +204,21 @@ impl WoilahTracker {
                         // Old format: just the file ID as a string
                         FileMetadata {
                             id: value.as_str().unwrap_or_default().to_string(),
-                            hash: String::new(),
-                            size: 0,
-                            uploaded_at: 0,
+                            ..Default::default()
                         }
                     } else {
                         // New format: full metadata object
-                        serde_json::from_value(value).unwrap_or_else(|_| FileMetadata {
-                            id: String::new(),
-                            hash: String::new(),
-                            size: 0,
-                            uploaded_at: 0,
-                        })
+                        serde_json::from_value(value).unwrap_or_default()
                     };
 
                     result.insert(filename, metadata);
                 }
 
-                Ok(result)
+                result
             }
             Err(e) => {
-                warn!("Failed to parse .woilah file at {:?}: {}", woilah_path, e);
-                Ok(HashMap::new())
+                warn!("Failed to parse .woilah file at {:?}: {}", origin, e);
+                HashMap::new()
             }
         }
     }
@@ -80,27 +229,18 @@ impl WoilahTracker {
         folder_path: &Path,
         mappings: &HashMap<String, FileMetadata>,
     ) -> Result<()> {
-        let woilah_path = self.woilah_path(folder_path);
-        let content = serde_json::to_string_pretty(mappings)?;
-        fs::write(&woilah_path, content)?;
-        debug!("Updated .woilah file at {:?}", woilah_path);
-        Ok(())
+        write_tracker_file(&self.woilah_path(folder_path), mappings)
     }
+}
 
-    /// Add a file mapping to the .woilah file
-    pub fn add_file_mapping(
-        &self,
-        folder_path: &Path,
-        filename: &str,
-        metadata: FileMetadata,
-    ) -> Result<()> {
+impl Tracker for WoilahTracker {
+    fn add_file_mapping(&self, folder_path: &Path, filename: &str, metadata: FileMetadata) -> Result<()> {
         let mut mappings = self.read_woilah_file(folder_path)?;
         mappings.insert(filename.to_string(), metadata);
         self.write_woilah_file(folder_path, &mappings)
     }
 
-    /// Remove a file mapping from the .woilah file
-    pub fn remove_file_mapping(&self, folder_path: &Path, filename: &str) -> Result<()> {
+    fn remove_file_mapping(&self, folder_path: &Path, filename: &str) -> Result<()> {
         let mut mappings = self.read_woilah_file(folder_path)?;
         if mappings.remove(filename).is_some() {
             self.write_woilah_file(folder_path, &mappings)?;
@@ -109,68 +249,106 @@ impl WoilahTracker {
         Ok(())
     }
 
-    /// Get the file metadata for a local file
-    pub fn get_file_info(&self, folder_path: &Path, filename: &str) -> Result<Option<FileMetadata>> {
+    fn get_file_info(&self, folder_path: &Path, filename: &str) -> Result<Option<FileMetadata>> {
         let mappings = self.read_woilah_file(folder_path)?;
         Ok(mappings.get(filename).cloned())
     }
 
-    /// Get the Drive file ID for a local file
-    pub fn get_file_id(&self, folder_path: &Path, filename: &str) -> Result<Option<String>> {
-        Ok(self.get_file_info(folder_path, filename)?.map(|m| m.id))
+    fn get_all_managed_files(&self) -> Result<Vec<(PathBuf, FileMetadata)>> {
+        let mut result = Vec::new();
+
+        for entry in symlink::walk(&self.repo_path, self.symlinks) {
+            let entry = entry?;
+            if entry.file_name() == WOILAH_FILENAME && symlink::is_file(&entry) {
+                let folder_path = entry.path().parent().unwrap_or(entry.path());
+                let mappings = self.read_woilah_file(folder_path)?;
+
+                for (filename, metadata) in mappings {
+                    let file_path = folder_path.join(&filename);
+                    if symlink::admits_path(&self.repo_path, &file_path, self.symlinks) {
+                        result.push((file_path, metadata));
+                    }
+                }
+            }
+        }
+
+        Ok(result)
     }
+}
 
-    /// Check if a file is managed by woilah
-    pub fn is_managed(&self, file_path: &Path) -> Result<bool> {
-        let folder_path = file_path.parent().unwrap_or(file_path);
-        let filename = file_path
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_default();
+/// Manages the single `.ditrive/manifest.json`, keyed by repository-relative path
+pub struct ManifestTracker {
+    repo_path: PathBuf,
+    symlinks: SymlinkPolicy,
+}
 
-        Ok(self.get_file_id(folder_path, &filename)?.is_some())
+impl ManifestTracker {
+    pub fn new(repo_path: &Path) -> Self {
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            symlinks: SymlinkPolicy::default(),
+        }
     }
 
-    /// Check if a file needs to be re-uploaded (hash changed)
-    pub fn file_needs_update(&self, file_path: &Path) -> Result<bool> {
-        let folder_path = file_path.parent().unwrap_or(file_path);
-        let filename = file_path
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_default();
+    /// List managed files under a symlink policy other than `skip`
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
 
-        let info = self.get_file_info(folder_path, &filename)?;
+    pub fn manifest_path(&self) -> PathBuf {
+        self.repo_path.join(MANIFEST_PATH)
+    }
 
-        match info {
-            Some(metadata) if !metadata.hash.is_empty() => {
-                let current_hash = calculate_file_hash(file_path)?;
-                Ok(current_hash != metadata.hash)
-            }
-            _ => Ok(true), // File not tracked or no hash stored
+    /// Manifest key for a file: its path from the repository root, with forward slashes
+    fn key(&self, folder_path: &Path, filename: &str) -> String {
+        let path = folder_path.join(filename);
+        paths::repo_relative(&self.repo_path, &path)
+    }
+
+    /// Read the whole manifest; a missing one is empty
+    pub fn read(&self) -> Result<HashMap<String, FileMetadata>> {
+        let manifest_path = self.manifest_path();
+        if !manifest_path.exists() {
+            return Ok(HashMap::new());
         }
+        let content = fs::read_to_string(&manifest_path)?;
+        Ok(WoilahTracker::parse_woilah(&content, &manifest_path))
     }
 
-    /// Get all managed files in the repository
-    pub fn get_all_managed_files(&self) -> Result<Vec<(PathBuf, FileMetadata)>> {
-        let mut result = Vec::new();
+    pub fn write(&self, mappings: &HashMap<String, FileMetadata>) -> Result<()> {
+        write_tracker_file(&self.manifest_path(), mappings)
+    }
+}
 
-        for entry in walkdir::WalkDir::new(&self.repo_path)
-            .into_iter()
-            .filter_entry(|e| !e.path().starts_with(self.repo_path.join(".git")))
-        {
-            let entry = entry?;
-            if entry.file_name() == WOILAH_FILENAME {
-                let folder_path = entry.path().parent().unwrap_or(entry.path());
-                let mappings = self.read_woilah_file(folder_path)?;
+impl Tracker for ManifestTracker {
+    fn add_file_mapping(&self, folder_path: &Path, filename: &str, metadata: FileMetadata) -> Result<()> {
+        let mut mappings = self.read()?;
+        mappings.insert(self.key(folder_path, filename), metadata);
+        self.write(&mappings)
+    }
 
-                for (filename, metadata) in mappings {
-                    let file_path = folder_path.join(&filename);
-                    result.push((file_path, metadata));
-                }
-            }
+    fn remove_file_mapping(&self, folder_path: &Path, filename: &str) -> Result<()> {
+        let key = self.key(folder_path, filename);
+        let mut mappings = self.read()?;
+        if mappings.remove(&key).is_some() {
+            self.write(&mappings)?;
+            debug!("Removed mapping for '{}'", key);
         }
+        Ok(())
+    }
 
-        Ok(result)
+    fn get_file_info(&self, folder_path: &Path, filename: &str) -> Result<Option<FileMetadata>> {
+        Ok(self.read()?.remove(&self.key(folder_path, filename)))
+    }
+
+    fn get_all_managed_files(&self) -> Result<Vec<(PathBuf, FileMetadata)>> {
+        Ok(self
+            .read()?
+            .into_iter()
+            .map(|(rel_path, metadata)| (self.repo_path.join(rel_path), metadata))
+            .filter(|(path, _)| symlink::admits_path(&self.repo_path, path, self.symlinks))
+            .collect())
     }
 }
 
@@ -189,6 +367,7 @@ mod tests {
             hash: "abc123".to_string(),
             size: 1024,
             uploaded_at: 1234567890,
+            ..Default::default()
         };
 
         tracker
@@ -207,9 +386,7 @@ mod tests {
 
         let metadata = FileMetadata {
             id: "test-id".to_string(),
-            hash: String::new(),
-            size: 0,
-            uploaded_at: 0,
+            ..Default::default()
         };
 
         tracker
@@ -220,4 +397,104 @@ mod tests {
         let retrieved = tracker.get_file_info(dir.path(), "test.bin").unwrap();
         assert!(retrieved.is_none());
     }
+
+    #[test]
+    fn test_woilah_tracker_add_file_version() {
+        let dir = tempdir().unwrap();
+        let tracker = WoilahTracker::new(dir.path());
+
+        let first = FileMetadata {
+            id: "test-id".to_string(),
+            hash: "aaa".to_string(),
+            revision_id: "rev-1".to_string(),
+            ..Default::default()
+        };
+        let second = FileMetadata {
+            id: "test-id".to_string(),
+            hash: "bbb".to_string(),
+            revision_id: "rev-2".to_string(),
+            ..Default::default()
+        };
+
+        tracker.add_file_mapping(dir.path(), "test.bin", first).unwrap();
+        tracker.add_file_version(dir.path(), "test.bin", second).unwrap();
+
+        let info = tracker.get_file_info(dir.path(), "test.bin").unwrap().unwrap();
+        assert_eq!(info.revision_id, "rev-2");
+        assert_eq!(info.versions.len(), 1);
+        assert_eq!(info.versions[0].revision_id, "rev-1");
+        assert_eq!(info.versions[0].hash, "aaa");
+    }
+
+    #[test]
+    fn test_manifest_tracker_keys_by_relative_path() {
+        let dir = tempdir().unwrap();
+        let manifest = ManifestTracker::new(dir.path());
+        let metadata = FileMetadata {
+            id: "test-id".to_string(),
+            ..Default::default()
+        };
+
+        assert!(!dir.path().join(MANIFEST_PATH).exists());
+        manifest.add_file_mapping(&dir.path().join("assets"), "hero.bin", metadata).unwrap();
+
+        let mappings = manifest.read().unwrap();
+        assert_eq!(mappings["assets/hero.bin"].id, "test-id");
+        assert!(!dir.path().join("assets").join(WOILAH_FILENAME).exists());
+
+        // Once the manifest exists, it is the tracker the repository uses
+        let tracker = open(dir.path(), SymlinkPolicy::Skip);
+        assert!(tracker.is_managed(&dir.path().join("assets/hero.bin")).unwrap());
+        let managed = tracker.get_all_managed_files().unwrap();
+        assert_eq!(managed.len(), 1);
+        assert_eq!(managed[0].0, dir.path().join("assets/hero.bin"));
+    }
+
+    #[test]
+    fn test_tracker_file_is_sorted() {
+        let dir = tempdir().unwrap();
+        let tracker = WoilahTracker::new(dir.path());
+        for name in ["zebra.bin", "alpha.bin", "middle.bin"] {
+            let metadata = FileMetadata {
+                id: format!("id-{}", name),
+                ..Default::default()
+            };
+            tracker.add_file_mapping(dir.path(), name, metadata).unwrap();
+        }
+
+        let content = fs::read_to_string(dir.path().join(WOILAH_FILENAME)).unwrap();
+        let positions: Vec<_> = ["alpha.bin", "middle.bin", "zebra.bin"]
+            .iter()
+            .map(|name| content.find(name).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(content.ends_with("}\n"));
+
+        // Only the tracker file is left behind, not the temporary one it was written through
+        let files: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(files, vec![WOILAH_FILENAME]);
+    }
+
+    #[test]
+    fn test_file_needs_update() {
+        let dir = tempdir().unwrap();
+        let tracker = WoilahTracker::new(dir.path());
+        let file_path = dir.path().join("test.bin");
+        fs::write(&file_path, "original").unwrap();
+
+        let metadata = FileMetadata {
+            id: "test-id".to_string(),
+            hash: calculate_file_hash(&file_path).unwrap(),
+            size: 8,
+            ..Default::default()
+        };
+
+        tracker
+            .add_file_mapping(dir.path(), "test.bin", metadata)
+            .unwrap();
+        assert!(!tracker.file_needs_update(&file_path).unwrap());
+
+        fs::write(&file_path, "modified").unwrap();
+        assert!(tracker.file_needs_update(&file_path).unwrap());
+    }
 }
@@ -0,0 +1,170 @@
+//! Retention for files removed from the backend
+//!
+//! With `deletion_policy` set to `trash`, removing a managed file sets its
+//! stored copy aside under `.trash/` in the repository folder (Google Drive
+//! uses its own trash) instead of deleting it. `.ditrive/trash.json` records
+//! what was set aside and when; it is committed with the trackers, so any
+//! clone can restore a file. `ditrive trash empty` deletes what has been there
+//! longer than `trash_retention_days`. Google Drive empties its own trash
+//! after 30 days, so retention there is never longer than that.
+//!
+//! Stored copies left in place when their files stop being managed, by
+//! `deletion_policy = "keep"` or `export-lfs`, are listed by ID in
+//! `.ditrive/untracked.json`, so two-way sync and adopt don't bring them back.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::drive::FileMetadata;
+use crate::error::Result;
+use crate::tracker;
+
+/// Folder trashed copies are kept under, relative to the repository folder
+pub const TRASH_FOLDER: &str = ".trash";
+
+/// Record of trashed files, relative to the repository root
+pub const TRASH_RECORD_PATH: &str = ".ditrive/trash.json";
+
+/// Record of stored copies untracked on purpose, relative to the repository root
+pub const UNTRACKED_RECORD_PATH: &str = ".ditrive/untracked.json";
+
+/// How long trashed copies are kept unless the repository says otherwise
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// How long Google Drive keeps trashed files before deleting them itself
+pub const DRIVE_TRASH_DAYS: u32 = 30;
+
+/// Characters of the content hash that keep trashed copies of one path apart
+const NAME_HASH_LEN: usize = 8;
+
+/// A managed file whose stored copy was set aside
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Repo-relative path the file had
+    pub path: String,
+    pub trashed_at: i64,
+    /// Tracker entry at removal, with the ID of the trashed copy
+    pub metadata: FileMetadata,
+}
+
+impl TrashEntry {
+    /// When `trash empty` may delete the copy
+    pub fn expires_at(&self, retention_days: u32) -> i64 {
+        self.trashed_at + i64::from(retention_days) * 24 * 60 * 60
+    }
+}
+
+/// Where a file's copy goes in the trash: `.trash/1a2b3c4d/assets/hero.psd`
+pub fn trash_path(rel_path: &str, hash: &str) -> String {
+    let short_hash = hash.get(..NAME_HASH_LEN).unwrap_or(hash);
+    if short_hash.is_empty() {
+        format!("{}/{}", TRASH_FOLDER, rel_path)
+    } else {
+        format!("{}/{}/{}", TRASH_FOLDER, short_hash, rel_path)
+    }
+}
+
+/// Check if a path in the repository folder is inside the trash
+pub fn is_trash_path(path: &str) -> bool {
+    path == TRASH_FOLDER || path.starts_with(&format!("{}/", TRASH_FOLDER))
+}
+
+/// Read the trash record, oldest entry first
+pub fn load(repo_path: &Path) -> Result<Vec<TrashEntry>> {
+    match fs::read_to_string(repo_path.join(TRASH_RECORD_PATH)) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write the trash record, removing it once the trash is empty
+pub fn save(repo_path: &Path, entries: &[TrashEntry]) -> Result<()> {
+    let record_path = repo_path.join(TRASH_RECORD_PATH);
+    if entries.is_empty() {
+        if record_path.exists() {
+            fs::remove_file(&record_path)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = record_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    tracker::write_atomically(&record_path, (serde_json::to_string_pretty(entries)? + "\n").as_bytes())?;
+    Ok(())
+}
+
+/// IDs of stored copies whose files were untracked on purpose
+pub fn load_untracked(repo_path: &Path) -> Result<BTreeSet<String>> {
+    match fs::read_to_string(repo_path.join(UNTRACKED_RECORD_PATH)) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Add IDs to the record of stored copies untracked on purpose
+pub fn record_untracked(repo_path: &Path, ids: impl IntoIterator<Item = String>) -> Result<()> {
+    let mut untracked = load_untracked(repo_path)?;
+    let before = untracked.len();
+    untracked.extend(ids.into_iter().filter(|id| !id.is_empty()));
+    if untracked.len() == before {
+        return Ok(());
+    }
+
+    let record_path = repo_path.join(UNTRACKED_RECORD_PATH);
+    if let Some(parent) = record_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    tracker::write_atomically(&record_path, (serde_json::to_string_pretty(&untracked)? + "\n").as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_trash_paths() {
+        assert_eq!(trash_path("assets/hero.psd", "1a2b3c4d5e6f"), ".trash/1a2b3c4d/assets/hero.psd");
+        assert_eq!(trash_path("blob", ""), ".trash/blob");
+        assert!(is_trash_path(".trash/1a2b3c4d/assets/hero.psd"));
+        assert!(!is_trash_path(".trashed/hero.psd"));
+    }
+
+    #[test]
+    fn test_trash_record_round_trip() {
+        let dir = tempdir().unwrap();
+        assert!(load(dir.path()).unwrap().is_empty());
+
+        let entry = TrashEntry {
+            path: "level.bin".to_string(),
+            trashed_at: 1_000,
+            metadata: FileMetadata { id: ".trash/abc/level.bin".to_string(), ..Default::default() },
+        };
+        assert_eq!(entry.expires_at(1), 1_000 + 86_400);
+
+        save(dir.path(), &[entry]).unwrap();
+        let loaded = load(dir.path()).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].metadata.id, ".trash/abc/level.bin");
+
+        save(dir.path(), &[]).unwrap();
+        assert!(!dir.path().join(TRASH_RECORD_PATH).exists());
+    }
+
+    #[test]
+    fn test_untracked_record_accumulates() {
+        let dir = tempdir().unwrap();
+        assert!(load_untracked(dir.path()).unwrap().is_empty());
+
+        record_untracked(dir.path(), ["id-2".to_string(), String::new()]).unwrap();
+        record_untracked(dir.path(), ["id-1".to_string(), "id-2".to_string()]).unwrap();
+        let ids: Vec<_> = load_untracked(dir.path()).unwrap().into_iter().collect();
+        assert_eq!(ids, vec!["id-1", "id-2"]);
+    }
+}